@@ -1,97 +1,575 @@
+use super::classify::classify;
+#[cfg(target_os = "windows")]
+use super::clipboard::{clipboard_written_by_rae, read_clipboard_data};
+#[cfg(target_os = "linux")]
+use super::clipboard::{read_selection_data, selection_written_by_rae};
+use super::clipboard::{ClipboardChanged, ClipboardContent, ClipboardData};
+use super::clipboard_history::{
+    active_window_source, is_clipboard_history_enabled, record_clipboard_text,
+};
+#[cfg(target_os = "windows")]
+use super::inline_prompt::InlinePrompts;
+use super::keyboard;
+use super::secure_input::{
+    ensure_secure_input_monitor_started, pause_epoch, stop_unused_secure_input_monitor,
+    watchers_paused,
+};
+use super::selection_context::SelectionContext;
+#[cfg(target_os = "windows")]
+use super::selection_context::{context_wanted, uia_selection_context};
+#[cfg(target_os = "windows")]
+use super::selection_copy::{capture_selection_by_copy, is_simulated_copy_update};
+use super::selection_gesture::{current_selection_gesture_config, SelectionTrigger};
+#[cfg(target_os = "windows")]
+use super::selection_gesture::{InputEvent, Key, SelectionDetector};
+#[cfg(target_os = "windows")]
+use super::selection_text::selection_read_limit;
+use super::selection_text::SelectedText;
+#[cfg(target_os = "windows")]
+use super::sensitive::clipboard_marked_sensitive;
+#[cfg(target_os = "linux")]
+use super::sensitive::selection_marked_sensitive;
+use super::sensitive::{filter_clipboard_data, FilterOutcome};
+use super::triggers::{get_trigger_config, take_triggers_changed, TriggerMatcher};
+#[cfg(target_os = "windows")]
 use enigo::{Enigo, MouseControllable};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicU64, Ordering};
 use tauri::{AppHandle, Emitter};
 
 static AUTO_SHOW_ON_COPY: AtomicBool = AtomicBool::new(false);
-static CLIPBOARD_WATCHER_RUNNING: AtomicBool = AtomicBool::new(false);
+// Bumped by every stop request; a clipboard watcher thread exits as soon as the
+// generation it was started for is no longer current
+static CLIPBOARD_WATCHER_GENERATION: AtomicU64 = AtomicU64::new(1);
+// Generation of the live clipboard watcher thread, 0 when none runs
+static CLIPBOARD_WATCHER_ACTIVE: AtomicU64 = AtomicU64::new(0);
 static AUTO_SHOW_ON_SELECTION: AtomicBool = AtomicBool::new(false);
 static SELECTION_WATCHER_RUNNING: AtomicBool = AtomicBool::new(false);
 static RAE_WATCHER_ENABLED: AtomicBool = AtomicBool::new(false);
 static RAE_WATCHER_RUNNING: AtomicBool = AtomicBool::new(false);
 static NOTCH_WINDOW_DISPLAY_ENABLED: AtomicBool = AtomicBool::new(true);
+// Foreground window at the time of the last `text_selected` event
+static LAST_SELECTION_HWND: AtomicIsize = AtomicIsize::new(0);
+// Text, window and time of the last non-empty `text_selected` event
+static LAST_SELECTION_TEXT: std::sync::Mutex<Option<(String, isize, std::time::Instant)>> =
+    std::sync::Mutex::new(None);
+// Message-only window receiving WM_CLIPBOARDUPDATE while the clipboard watcher runs
+#[cfg(target_os = "windows")]
+static CLIPBOARD_LISTENER_HWND: AtomicIsize = AtomicIsize::new(0);
+#[cfg(target_os = "windows")]
+const WM_STOP_CLIPBOARD_LISTENER: u32 = winapi::um::winuser::WM_APP + 1;
+#[cfg(target_os = "linux")]
+static CLIPBOARD_STOP_HANDLE: std::sync::Mutex<Option<(u64, crate::x11::StopHandle)>> =
+    std::sync::Mutex::new(None);
+#[cfg(target_os = "linux")]
+static SELECTION_STOP_HANDLE: std::sync::Mutex<Option<crate::x11::StopHandle>> =
+    std::sync::Mutex::new(None);
 
-use winapi::um::winuser::{
-    GetAsyncKeyState, GetClipboardSequenceNumber, IsClipboardFormatAvailable, CF_UNICODETEXT,
-    VK_LBUTTON,
-};
+#[cfg(target_os = "windows")]
+use winapi::um::winuser::{GetAsyncKeyState, GetForegroundWindow, VK_LBUTTON};
 
-unsafe fn read_clipboard_unicode_text() -> Option<String> {
-    use std::ffi::OsString;
-    use std::os::windows::ffi::OsStringExt;
-    use winapi::shared::minwindef::HGLOBAL;
-    use winapi::um::winbase::{GlobalLock, GlobalUnlock};
-    use winapi::um::winuser::{CloseClipboard, GetClipboardData, OpenClipboard};
-    if IsClipboardFormatAvailable(CF_UNICODETEXT) == 0 {
-        return None;
-    }
-    if OpenClipboard(std::ptr::null_mut()) == 0 {
-        return None;
+/// Emits the clipboard events for freshly copied content, skipping repeats and
+/// very short text snippets.
+fn handle_clipboard_data(app: &AppHandle, last: &mut Option<u64>, data: Option<ClipboardData>) {
+    let Some(data) = data else {
+        return;
+    };
+    let fingerprint = data.fingerprint();
+    if *last == Some(fingerprint) {
+        return;
     }
-    let handle: HGLOBAL = GetClipboardData(CF_UNICODETEXT);
-    if handle.is_null() {
-        CloseClipboard();
-        return None;
+    *last = Some(fingerprint);
+
+    let data = match data {
+        ClipboardData::Text(text) => {
+            let text = text.trim().to_string();
+            if text.len() < 3 {
+                return;
+            }
+            ClipboardData::Text(text)
+        }
+        data => data,
+    };
+    // Secrets are filtered before anything is emitted or stored
+    let data = match filter_clipboard_data(data) {
+        FilterOutcome::Clean(data) => data,
+        FilterOutcome::Masked(data, detectors) => {
+            let _ = app.emit(
+                "clipboard_sensitive_filtered",
+                serde_json::json!({ "action": "mask", "detectors": detectors }),
+            );
+            data
+        }
+        FilterOutcome::Suppressed(detectors) => {
+            let _ = app.emit(
+                "clipboard_sensitive_filtered",
+                serde_json::json!({ "action": "suppress", "detectors": detectors }),
+            );
+            return;
+        }
+    };
+    let Some(content) = data.into_content() else {
+        return;
+    };
+
+    let auto_show = AUTO_SHOW_ON_COPY.load(Ordering::Relaxed);
+    if let Some(text) = content.text().map(|t| t.trim().to_string()) {
+        if text.len() >= 3 {
+            record_clipboard_text(app, &text);
+            // Only announced while copies summon the dot
+            if auto_show {
+                let _ = app.emit(
+                    "clipboard_text_copied",
+                    serde_json::json!({ "text": text, "classification": classify(&text) }),
+                );
+            }
+        }
     }
-    let locked = GlobalLock(handle) as *const u16;
-    if locked.is_null() {
-        CloseClipboard();
-        return None;
+    match &content {
+        ClipboardContent::Html {
+            html,
+            text,
+            source_url,
+        } => {
+            let _ = app.emit(
+                "clipboard_html_copied",
+                serde_json::json!({ "html": html, "text": text, "sourceUrl": source_url }),
+            );
+        }
+        ClipboardContent::Image {
+            image_id,
+            width,
+            height,
+            ..
+        } => {
+            let _ = app.emit(
+                "clipboard_image_copied",
+                serde_json::json!({ "imageId": image_id, "width": width, "height": height }),
+            );
+        }
+        ClipboardContent::Files { paths } => {
+            let _ = app.emit(
+                "clipboard_files_copied",
+                serde_json::json!({ "paths": paths }),
+            );
+        }
+        ClipboardContent::Text { .. } => {}
     }
-    let mut len: usize = 0;
-    while *locked.add(len) != 0 {
-        len += 1;
+    if auto_show {
+        crate::functions::overlay::show_magic_dot(app.clone());
     }
-    let slice = std::slice::from_raw_parts(locked, len);
-    let os_string = OsString::from_wide(slice);
-    let text = os_string.to_string_lossy().into_owned();
-    let _ = GlobalUnlock(handle);
-    CloseClipboard();
-    Some(text)
+    let _ = app.emit("clipboard_changed", ClipboardChanged::new(content));
+}
+
+/// Claims the current generation for a new clipboard watcher thread. Returns
+/// `None` when a thread for it is already running.
+fn claim_clipboard_watcher() -> Option<u64> {
+    let generation = CLIPBOARD_WATCHER_GENERATION.load(Ordering::SeqCst);
+    // A thread of an older generation may still be winding down; it no longer counts
+    (CLIPBOARD_WATCHER_ACTIVE.swap(generation, Ordering::SeqCst) != generation)
+        .then_some(generation)
+}
+
+fn clipboard_watcher_current(generation: u64) -> bool {
+    CLIPBOARD_WATCHER_GENERATION.load(Ordering::SeqCst) == generation && clipboard_watch_wanted()
+}
+
+fn release_clipboard_watcher(generation: u64) {
+    let _ = CLIPBOARD_WATCHER_ACTIVE.compare_exchange(
+        generation,
+        0,
+        Ordering::SeqCst,
+        Ordering::SeqCst,
+    );
 }
 
+#[cfg(target_os = "windows")]
 fn ensure_clipboard_watcher_started(app: &AppHandle) {
-    if CLIPBOARD_WATCHER_RUNNING.swap(true, Ordering::SeqCst) {
+    let Some(generation) = claim_clipboard_watcher() else {
         return;
+    };
+    ensure_secure_input_monitor_started(app);
+    let app_handle = app.clone();
+    std::thread::spawn(move || unsafe {
+        use winapi::um::winuser::{
+            AddClipboardFormatListener, CreateWindowExW, DestroyWindow, DispatchMessageW,
+            GetMessageW, RemoveClipboardFormatListener, TranslateMessage, HWND_MESSAGE, MSG,
+            WM_CLIPBOARDUPDATE,
+        };
+        // A message-only window is enough to receive WM_CLIPBOARDUPDATE
+        let class: Vec<u16> = "STATIC".encode_utf16().chain(std::iter::once(0)).collect();
+        let hwnd = CreateWindowExW(
+            0,
+            class.as_ptr(),
+            std::ptr::null(),
+            0,
+            0,
+            0,
+            0,
+            0,
+            HWND_MESSAGE,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        );
+        if hwnd.is_null() || AddClipboardFormatListener(hwnd) == 0 {
+            if !hwnd.is_null() {
+                DestroyWindow(hwnd);
+            }
+            release_clipboard_watcher(generation);
+            return;
+        }
+        // Published before the generation is checked, so a stop request either
+        // sees the window or is noticed by the loop condition
+        CLIPBOARD_LISTENER_HWND.store(hwnd as isize, Ordering::SeqCst);
+
+        let mut last_copied: Option<u64> = None;
+        let mut msg: MSG = std::mem::zeroed();
+        // The feature may have been turned off before the window existed
+        while clipboard_watcher_current(generation)
+            && GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) > 0
+        {
+            match msg.message {
+                WM_CLIPBOARDUPDATE => {
+                    if clipboard_watcher_current(generation)
+                        && !is_simulated_copy_update()
+                        && !clipboard_written_by_rae()
+                        && !clipboard_marked_sensitive()
+                        && !watchers_paused()
+                    {
+                        handle_clipboard_data(&app_handle, &mut last_copied, read_clipboard_data())
+                    }
+                }
+                WM_STOP_CLIPBOARD_LISTENER => break,
+                _ => {
+                    TranslateMessage(&msg);
+                    DispatchMessageW(&msg);
+                }
+            }
+        }
+
+        RemoveClipboardFormatListener(hwnd);
+        DestroyWindow(hwnd);
+        // A newer watcher may already have published its own window
+        let _ = CLIPBOARD_LISTENER_HWND.compare_exchange(
+            hwnd as isize,
+            0,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+        release_clipboard_watcher(generation);
+    });
+}
+
+#[cfg(target_os = "windows")]
+fn stop_clipboard_watcher() {
+    use winapi::um::winuser::PostMessageW;
+    CLIPBOARD_WATCHER_GENERATION.fetch_add(1, Ordering::SeqCst);
+    let hwnd = CLIPBOARD_LISTENER_HWND.load(Ordering::SeqCst);
+    if hwnd != 0 {
+        unsafe {
+            PostMessageW(hwnd as _, WM_STOP_CLIPBOARD_LISTENER, 0, 0);
+        }
     }
+}
+
+#[cfg(target_os = "linux")]
+fn ensure_clipboard_watcher_started(app: &AppHandle) {
+    let Some(generation) = claim_clipboard_watcher() else {
+        return;
+    };
+    ensure_secure_input_monitor_started(app);
     let app_handle = app.clone();
     std::thread::spawn(move || {
-        let mut last_text: Option<String> = None;
-        let mut last_seq: u32 = unsafe { GetClipboardSequenceNumber() };
-        loop {
-            if !AUTO_SHOW_ON_COPY.load(Ordering::Relaxed) {
-                CLIPBOARD_WATCHER_RUNNING.store(false, Ordering::SeqCst);
-                break;
-            }
-            let seq_now = unsafe { GetClipboardSequenceNumber() };
-            if seq_now == last_seq {
-                std::thread::sleep(std::time::Duration::from_millis(250));
-                continue;
+        let watcher = match crate::x11::SelectionWatcher::new("CLIPBOARD") {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                println!("Clipboard watcher unavailable: {}", e);
+                release_clipboard_watcher(generation);
+                return;
             }
-            last_seq = seq_now;
-            let current = unsafe { read_clipboard_unicode_text() }
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty());
-            if let Some(ref txt) = current {
-                let is_new = match last_text {
-                    Some(ref prev) => prev != txt,
-                    None => true,
-                };
-                if is_new && txt.len() >= 3 {
-                    crate::functions::overlay::show_magic_dot(app_handle.clone());
-                    let _ = app_handle
-                        .emit("clipboard_text_copied", serde_json::json!({ "text": txt }));
-                    last_text = current.clone();
+        };
+        // Published before the generation is checked, so a stop request either
+        // finds the handle or is noticed below
+        if let Ok(mut stop) = CLIPBOARD_STOP_HANDLE.lock() {
+            *stop = Some((generation, watcher.stop_handle()));
+        }
+        let mut last_copied: Option<u64> = None;
+        if clipboard_watcher_current(generation) {
+            let _ = watcher.run(|w| {
+                if clipboard_watcher_current(generation)
+                    && !selection_written_by_rae(w)
+                    && !selection_marked_sensitive(w)
+                    && !watchers_paused()
+                {
+                    handle_clipboard_data(&app_handle, &mut last_copied, read_selection_data(w))
                 }
+            });
+        }
+        if let Ok(mut stop) = CLIPBOARD_STOP_HANDLE.lock() {
+            if stop.as_ref().is_some_and(|(g, _)| *g == generation) {
+                *stop = None;
             }
-            std::thread::sleep(std::time::Duration::from_millis(250));
         }
+        release_clipboard_watcher(generation);
     });
 }
 
+#[cfg(target_os = "linux")]
+fn stop_clipboard_watcher() {
+    CLIPBOARD_WATCHER_GENERATION.fetch_add(1, Ordering::SeqCst);
+    if let Ok(stop) = CLIPBOARD_STOP_HANDLE.lock() {
+        if let Some((_, handle)) = stop.as_ref() {
+            handle.stop();
+        }
+    }
+}
+
+/// Where a selection was made, sent along with `text_selected`.
+#[derive(Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SelectionSource {
+    element_name: Option<String>,
+    control_type: Option<String>,
+    /// Screen rectangles of the selected range in physical pixels, one per line.
+    bounds: Vec<SelectionRect>,
+    /// Text around the selection, when enabled for the source app.
+    context: Option<SelectionContext>,
+}
+
+#[derive(Clone, Copy, serde::Serialize)]
+struct SelectionRect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+/// Emits `text_selected` and shows the magic dot just after the end of the
+/// selection, falling back to the cursor when the bounds are unknown.
+fn emit_text_selected(
+    app: &AppHandle,
+    selected: SelectedText,
+    hwnd: isize,
+    source: SelectionSource,
+    trigger: Option<SelectionTrigger>,
+) {
+    LAST_SELECTION_HWND.store(hwnd, Ordering::SeqCst);
+    if !selected.text.is_empty() {
+        if let Ok(mut last) = LAST_SELECTION_TEXT.lock() {
+            *last = Some((selected.text.clone(), hwnd, std::time::Instant::now()));
+        }
+    }
+    let anchor = source.bounds.last().map(|r| {
+        tauri::PhysicalPosition::new((r.x + r.width) as i32 + 6, (r.y + r.height) as i32 + 4)
+    });
+    crate::functions::overlay::show_magic_dot_at(app.clone(), anchor);
+    let (source_app, window_title) = active_window_source();
+    let _ = app.emit(
+        "text_selected",
+        serde_json::json!({
+            "text": selected.text,
+            "totalLength": selected.total_length,
+            "truncated": selected.truncated,
+            "ranges": selected.range_lengths,
+            "blobId": selected.blob_id,
+            "hwnd": hwnd,
+            "app": source_app,
+            "windowTitle": window_title,
+            "elementName": source.element_name,
+            "controlType": source.control_type,
+            "bounds": source.bounds,
+            "context": source.context,
+            "trigger": trigger,
+            "classification": classify(&selected.text),
+        }),
+    );
+}
+
+/// Reads the element details of a UIA selection and the bounds of all its ranges.
+#[cfg(target_os = "windows")]
+unsafe fn uia_selection_source(
+    element: &windows::Win32::UI::Accessibility::IUIAutomationElement,
+    ranges: &windows::Win32::UI::Accessibility::IUIAutomationTextRangeArray,
+) -> SelectionSource {
+    use windows::Win32::System::Ole::SafeArrayDestroy;
+    let mut bounds = Vec::new();
+    for i in 0..ranges.Length().unwrap_or(0) {
+        let Ok(range) = ranges.GetElement(i) else {
+            continue;
+        };
+        let Ok(array) = range.GetBoundingRectangles() else {
+            continue;
+        };
+        if array.is_null() {
+            continue;
+        }
+        let count = (*array).rgsabound[0].cElements as usize;
+        if count > 0 && !(*array).pvData.is_null() {
+            // Flat list of left, top, width, height per line of the range
+            let values = std::slice::from_raw_parts((*array).pvData as *const f64, count);
+            bounds.extend(
+                values
+                    .chunks_exact(4)
+                    .map(|r| SelectionRect {
+                        x: r[0],
+                        y: r[1],
+                        width: r[2],
+                        height: r[3],
+                    })
+                    .filter(|r| r.width > 0.0 || r.height > 0.0),
+            );
+        }
+        let _ = SafeArrayDestroy(array);
+    }
+    SelectionSource {
+        element_name: element
+            .CurrentName()
+            .ok()
+            .map(|name| name.to_string())
+            .filter(|name| !name.is_empty()),
+        control_type: element
+            .CurrentControlType()
+            .ok()
+            .map(|id| control_type_name(id.0).to_string()),
+        bounds,
+        context: None,
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn control_type_name(id: u32) -> &'static str {
+    match id {
+        50000 => "button",
+        50003 => "combo_box",
+        50004 => "edit",
+        50005 => "hyperlink",
+        50007 => "list_item",
+        50008 => "list",
+        50020 => "text",
+        50023 => "tree_item",
+        50025 => "custom",
+        50026 => "group",
+        50029 => "data_item",
+        50030 => "document",
+        50032 => "window",
+        50033 => "pane",
+        _ => "other",
+    }
+}
+
+/// Characters read per range while polling; enough to notice a change without
+/// copying huge selections every iteration.
+#[cfg(target_os = "windows")]
+const SELECTION_PROBE_CHARS: i32 = 4096;
+
+/// A cheap rendering of the focused element's selection, or `None` when the
+/// element exposes no text pattern. The ranges are kept so the full text is
+/// only read once the selection is reported.
+#[cfg(target_os = "windows")]
+unsafe fn focused_uia_selection(
+    automation: &windows::Win32::UI::Accessibility::IUIAutomation,
+) -> Option<(
+    String,
+    windows::Win32::UI::Accessibility::IUIAutomationElement,
+    windows::Win32::UI::Accessibility::IUIAutomationTextRangeArray,
+)> {
+    use windows::Win32::UI::Accessibility::{IUIAutomationTextPattern, UIA_TextPatternId};
+    let element = automation.GetFocusedElement().ok()?;
+    let pattern = element
+        .GetCurrentPatternAs::<IUIAutomationTextPattern>(UIA_TextPatternId)
+        .ok()?;
+    let ranges = pattern.GetSelection().ok()?;
+    let probe = uia_range_texts(&ranges, SELECTION_PROBE_CHARS).join("\n");
+    Some((probe.trim().to_string(), element, ranges))
+}
+
+/// Text of every selected range, each cut at `max_chars`.
+#[cfg(target_os = "windows")]
+unsafe fn uia_range_texts(
+    ranges: &windows::Win32::UI::Accessibility::IUIAutomationTextRangeArray,
+    max_chars: i32,
+) -> Vec<String> {
+    // Unreadable ranges stay as empty ones, so indices keep matching
+    (0..ranges.Length().unwrap_or(0))
+        .map(|i| {
+            ranges
+                .GetElement(i)
+                .and_then(|range| range.GetText(max_chars))
+                .map(|text| text.to_string())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Turns polled mouse and key states into detector events.
+#[cfg(target_os = "windows")]
+#[derive(Default)]
+struct PolledInput {
+    mouse_down: bool,
+    keys_down: std::collections::HashSet<i32>,
+}
+
+#[cfg(target_os = "windows")]
+impl PolledInput {
+    fn watched_keys() -> Vec<(i32, Key)> {
+        use winapi::um::winuser::{
+            VK_BACK, VK_CONTROL, VK_DELETE, VK_DOWN, VK_END, VK_HOME, VK_LEFT, VK_NEXT, VK_PRIOR,
+            VK_RETURN, VK_RIGHT, VK_SHIFT, VK_SPACE, VK_TAB, VK_UP,
+        };
+        let mut keys = vec![
+            (VK_SHIFT, Key::Shift),
+            (VK_CONTROL, Key::Control),
+            (0x41, Key::A),
+        ];
+        for vk in [
+            VK_LEFT, VK_RIGHT, VK_UP, VK_DOWN, VK_HOME, VK_END, VK_PRIOR, VK_NEXT,
+        ] {
+            keys.push((vk, Key::Navigation));
+        }
+        for vk in (0x30..=0x39).chain(0x42..=0x5A) {
+            keys.push((vk, Key::Other));
+        }
+        for vk in [VK_SPACE, VK_RETURN, VK_BACK, VK_DELETE, VK_TAB] {
+            keys.push((vk, Key::Other));
+        }
+        keys
+    }
+
+    unsafe fn poll(&mut self, mouse: &Enigo, at: u64) -> Vec<InputEvent> {
+        let is_down = |vk: i32| (GetAsyncKeyState(vk) as u16 & 0x8000u16) != 0;
+        let mut events = Vec::new();
+
+        let mouse_down = is_down(VK_LBUTTON);
+        if mouse_down || self.mouse_down {
+            let (x, y) = mouse.mouse_location();
+            events.push(match (self.mouse_down, mouse_down) {
+                (false, true) => InputEvent::MouseDown { x, y, at },
+                (true, true) => InputEvent::MouseMove { x, y, at },
+                _ => InputEvent::MouseUp { x, y, at },
+            });
+        }
+        self.mouse_down = mouse_down;
+
+        for (vk, key) in Self::watched_keys() {
+            let down = is_down(vk);
+            let was_down = self.keys_down.contains(&vk);
+            if down && !was_down {
+                self.keys_down.insert(vk);
+                events.push(InputEvent::KeyDown { key, at });
+            } else if !down && was_down {
+                self.keys_down.remove(&vk);
+                events.push(InputEvent::KeyUp { key, at });
+            }
+        }
+        events
+    }
+}
+
 fn ensure_selection_watcher_started(app: &AppHandle) {
     if SELECTION_WATCHER_RUNNING.swap(true, Ordering::SeqCst) {
         return;
     }
+    ensure_secure_input_monitor_started(app);
     let app_handle = app.clone();
     std::thread::spawn(move || {
         #[cfg(target_os = "windows")]
@@ -100,84 +578,34 @@ fn ensure_selection_watcher_started(app: &AppHandle) {
                 CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_INPROC_SERVER,
                 COINIT_APARTMENTTHREADED,
             };
-            use windows::Win32::UI::Accessibility::{
-                CUIAutomation, IUIAutomation, IUIAutomationTextPattern, UIA_TextPatternId,
-            };
+            use windows::Win32::UI::Accessibility::{CUIAutomation, IUIAutomation};
             let _hr = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
             let mut automation: Option<IUIAutomation> = None;
             let mut last_text: Option<String> = None;
-            let mut last_uia_emit_at: Option<std::time::Instant> = None;
             let mut last_fallback_emit_at: Option<std::time::Instant> = None;
-            let mut mouse_was_down: bool = false;
-            let mut drag_origin: Option<(i32, i32)> = None;
-            let mut did_drag: bool = false;
-            let mut press_started_at: Option<std::time::Instant> = None;
-            let mut pending_selected_text: Option<String> = None;
+            // Latest selection of the focused element; `None` if it exposes none
+            let mut current_selection: Option<(
+                String,
+                SelectionSource,
+                windows::Win32::UI::Accessibility::IUIAutomationElement,
+                windows::Win32::UI::Accessibility::IUIAutomationTextRangeArray,
+            )> = None;
+            let mut detector = SelectionDetector::new(current_selection_gesture_config());
+            let mut input = PolledInput::default();
+            let started = std::time::Instant::now();
             let enigo_mouse = Enigo::new();
             loop {
                 if !AUTO_SHOW_ON_SELECTION.load(Ordering::Relaxed) {
                     SELECTION_WATCHER_RUNNING.store(false, Ordering::SeqCst);
                     break;
                 }
-                let mouse_state: u16 = GetAsyncKeyState(VK_LBUTTON as i32) as u16;
-                let mouse_is_down = (mouse_state & 0x8000u16) != 0;
-                if mouse_is_down && !mouse_was_down {
-                    let (mx, my) = enigo_mouse.mouse_location();
-                    drag_origin = Some((mx, my));
-                    did_drag = false;
-                    press_started_at = Some(std::time::Instant::now());
-                } else if mouse_is_down && mouse_was_down {
-                    if let Some((ox, oy)) = drag_origin {
-                        let (mx, my) = enigo_mouse.mouse_location();
-                        let dx = mx - ox;
-                        let dy = my - oy;
-                        if (dx * dx + dy * dy) as f64 > 144.0 {
-                            did_drag = true;
-                        }
-                    }
-                } else if !mouse_is_down && mouse_was_down {
-                    let press_duration_ms = press_started_at
-                        .map(|t| t.elapsed().as_millis() as u64)
-                        .unwrap_or(0);
-                    if did_drag && press_duration_ms >= 120 {
-                        if let Some(final_text) = pending_selected_text
-                            .take()
-                            .filter(|s| !s.trim().is_empty())
-                        {
-                            let is_new = match &last_text {
-                                Some(prev) => prev != &final_text,
-                                None => true,
-                            };
-                            if is_new {
-                                crate::functions::overlay::show_magic_dot(app_handle.clone());
-                                let _ = app_handle.emit(
-                                    "text_selected",
-                                    serde_json::json!({ "text": final_text }),
-                                );
-                                last_text = Some(final_text);
-                                last_uia_emit_at = Some(std::time::Instant::now());
-                            }
-                        } else {
-                            let allow_fallback = match last_uia_emit_at {
-                                Some(t) => t.elapsed().as_millis() as u64 > 250,
-                                None => true,
-                            } && match last_fallback_emit_at {
-                                Some(t) => t.elapsed().as_millis() as u64 > 500,
-                                None => true,
-                            };
-                            if allow_fallback {
-                                crate::functions::overlay::show_magic_dot(app_handle.clone());
-                                let _ = app_handle
-                                    .emit("text_selected", serde_json::json!({ "text": "" }));
-                                last_fallback_emit_at = Some(std::time::Instant::now());
-                            }
-                        }
-                    }
-                    drag_origin = None;
-                    did_drag = false;
-                    press_started_at = None;
+                if watchers_paused() {
+                    // Not even the focused element's selection is read
+                    current_selection = None;
+                    detector = SelectionDetector::new(current_selection_gesture_config());
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                    continue;
                 }
-                mouse_was_down = mouse_is_down;
                 if automation.is_none() {
                     automation = CoCreateInstance(&CUIAutomation, None, CLSCTX_INPROC_SERVER).ok();
                     if automation.is_none() {
@@ -186,35 +614,95 @@ fn ensure_selection_watcher_started(app: &AppHandle) {
                     }
                 }
                 let Some(auto) = &automation else { continue };
-                let focused_element = auto.GetFocusedElement();
-                if let Ok(element) = focused_element {
-                    let pattern =
-                        element.GetCurrentPatternAs::<IUIAutomationTextPattern>(UIA_TextPatternId);
-                    if let Ok(text_pattern) = pattern {
-                        let selection = text_pattern.GetSelection();
-                        if let Ok(selection_array) = selection {
-                            let len = selection_array.Length();
-                            if let Ok(len) = len {
-                                if len > 0 {
-                                    let first_range = selection_array.GetElement(0);
-                                    if let Ok(range) = first_range {
-                                        let get_text = range.GetText(4096);
-                                        if let Ok(bstr) = get_text {
-                                            let text = bstr.to_string();
-                                            let trimmed = text.trim().to_string();
-                                            if !trimmed.is_empty() && trimmed.len() >= 1 {
-                                                let should_update_pending =
-                                                    match &pending_selected_text {
-                                                        Some(prev) => prev != &trimmed,
-                                                        None => true,
-                                                    };
-                                                if should_update_pending {
-                                                    pending_selected_text = Some(trimmed.clone());
-                                                }
-                                            }
-                                        }
-                                    }
+                detector.set_config(current_selection_gesture_config());
+                let now = started.elapsed().as_millis() as u64;
+                let mut events = input.poll(&enigo_mouse, now);
+
+                match focused_uia_selection(auto) {
+                    Some((text, element, ranges)) => {
+                        let changed = current_selection.as_ref().map(|(t, ..)| t) != Some(&text);
+                        if changed {
+                            events.push(InputEvent::SelectionChanged {
+                                len: text.chars().count(),
+                                at: now,
+                            });
+                            let source = if text.is_empty() {
+                                SelectionSource::default()
+                            } else {
+                                uia_selection_source(&element, &ranges)
+                            };
+                            current_selection = Some((text, source, element, ranges));
+                        }
+                    }
+                    None => {
+                        if current_selection.take().is_some() {
+                            events.push(InputEvent::SelectionUnavailable { at: now });
+                        }
+                    }
+                }
+                events.push(InputEvent::Tick { at: now });
+
+                for event in events {
+                    let Some(trigger) = detector.handle(event) else {
+                        continue;
+                    };
+                    let hwnd = GetForegroundWindow() as isize;
+                    match &current_selection {
+                        Some((text, source, element, ranges)) if !text.is_empty() => {
+                            if last_text.as_deref() != Some(text.as_str()) {
+                                // The full text is only read now; polling sees a probe
+                                let selected = SelectedText::from_ranges(uia_range_texts(
+                                    ranges,
+                                    selection_read_limit(),
+                                ));
+                                let mut source = source.clone();
+                                if context_wanted(&active_window_source().0) {
+                                    source.context = uia_selection_context(auto, element, ranges);
+                                }
+                                emit_text_selected(
+                                    &app_handle,
+                                    selected,
+                                    hwnd,
+                                    source,
+                                    Some(trigger),
+                                );
+                                last_text = Some(text.clone());
+                            }
+                        }
+                        // The app does not expose its selection; copy it, or
+                        // leave that to the frontend when the fallback is off.
+                        // Double and triple clicks are mostly on things that are
+                        // not text, so only a drag gets the empty event
+                        _ => {
+                            if let Some(text) = capture_selection_by_copy(hwnd) {
+                                if last_text.as_deref() != Some(text.as_str()) {
+                                    emit_text_selected(
+                                        &app_handle,
+                                        SelectedText::single(text.clone()),
+                                        hwnd,
+                                        SelectionSource::default(),
+                                        Some(trigger),
+                                    );
+                                    last_text = Some(text);
                                 }
+                                continue;
+                            }
+                            if trigger != SelectionTrigger::Drag {
+                                continue;
+                            }
+                            let allow_fallback = match last_fallback_emit_at {
+                                Some(t) => t.elapsed().as_millis() as u64 > 500,
+                                None => true,
+                            };
+                            if allow_fallback {
+                                emit_text_selected(
+                                    &app_handle,
+                                    SelectedText::default(),
+                                    hwnd,
+                                    SelectionSource::default(),
+                                    Some(trigger),
+                                );
+                                last_fallback_emit_at = Some(std::time::Instant::now());
                             }
                         }
                     }
@@ -223,143 +711,173 @@ fn ensure_selection_watcher_started(app: &AppHandle) {
             }
             CoUninitialize();
         }
+        #[cfg(target_os = "linux")]
+        run_primary_selection_watcher(&app_handle);
     });
 }
 
+/// On X11 the PRIMARY selection is the highlighted text, so owner-change
+/// notifications replace the UIA polling used on Windows.
+#[cfg(target_os = "linux")]
+fn run_primary_selection_watcher(app: &AppHandle) {
+    let watcher = match crate::x11::SelectionWatcher::new("PRIMARY") {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            println!("Selection watcher unavailable: {}", e);
+            SELECTION_WATCHER_RUNNING.store(false, Ordering::SeqCst);
+            return;
+        }
+    };
+    if let Ok(mut stop) = SELECTION_STOP_HANDLE.lock() {
+        *stop = Some(watcher.stop_handle());
+    }
+    let mut last_text: Option<String> = None;
+    if AUTO_SHOW_ON_SELECTION.load(Ordering::Relaxed) {
+        let _ = watcher.run(|w| {
+            if watchers_paused() {
+                return;
+            }
+            // Toolkits re-assert ownership while the drag is still going; wait
+            // for the button release so only the final selection is reported
+            let started = std::time::Instant::now();
+            while w.pointer_buttons_down() && started.elapsed().as_secs() < 10 {
+                std::thread::sleep(std::time::Duration::from_millis(30));
+            }
+            let Some(text) = w.read_text().map(|t| t.trim().to_string()) else {
+                return;
+            };
+            let min_length = current_selection_gesture_config().min_length.max(1);
+            if text.chars().count() < min_length || last_text.as_deref() == Some(text.as_str()) {
+                return;
+            }
+            let window = w.active_window().unwrap_or(0) as isize;
+            emit_text_selected(
+                app,
+                SelectedText::single(text.clone()),
+                window,
+                SelectionSource::default(),
+                None,
+            );
+            last_text = Some(text);
+        });
+    }
+    if let Ok(mut stop) = SELECTION_STOP_HANDLE.lock() {
+        *stop = None;
+    }
+    SELECTION_WATCHER_RUNNING.store(false, Ordering::SeqCst);
+}
+
+#[cfg(target_os = "windows")]
+fn stop_selection_watcher() {
+    // The polling loop notices the setting change on its own
+}
+
+#[cfg(target_os = "linux")]
+fn stop_selection_watcher() {
+    if let Ok(stop) = SELECTION_STOP_HANDLE.lock() {
+        if let Some(handle) = stop.as_ref() {
+            handle.stop();
+        }
+    }
+}
+
+/// Emits `rae_mentioned` whenever a configured trigger phrase is typed, and
+/// runs the inline prompt typed after it.
 fn ensure_rae_watcher_started(app: &AppHandle) {
     if RAE_WATCHER_RUNNING.swap(true, Ordering::SeqCst) {
         return;
     }
+    ensure_secure_input_monitor_started(app);
     let app_handle = app.clone();
     std::thread::spawn(move || {
+        let (subscription, keys) = keyboard::subscribe();
+        let mut matcher = TriggerMatcher::new(&get_trigger_config());
         #[cfg(target_os = "windows")]
-        unsafe {
-            use std::collections::VecDeque;
-            use winapi::shared::minwindef::{HINSTANCE, LPARAM, LRESULT, WPARAM};
-            use winapi::shared::windef::HHOOK;
-            use winapi::um::winuser::{
-                CallNextHookEx, DispatchMessageW, PeekMessageW, SetWindowsHookExW,
-                TranslateMessage, UnhookWindowsHookEx, HC_ACTION, KBDLLHOOKSTRUCT, MSG, PM_REMOVE,
-                WH_KEYBOARD_LL, WM_KEYDOWN, WM_QUIT, WM_SYSKEYDOWN,
+        let mut inline_prompts = InlinePrompts::default();
+        let mut seen_pause = pause_epoch();
+        while RAE_WATCHER_ENABLED.load(Ordering::Relaxed) {
+            let stroke = keys.recv_timeout(std::time::Duration::from_millis(250));
+            // Keys typed before a password field must not combine with keys after it
+            if pause_epoch() != seen_pause {
+                seen_pause = pause_epoch();
+                matcher.reset();
+                #[cfg(target_os = "windows")]
+                inline_prompts.interrupt(&app_handle, "secure_input");
+            }
+            let stroke = match stroke {
+                Ok(stroke) => stroke,
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
             };
-
-            let mut typed_chars = VecDeque::new();
-            let mut last_key_time = std::time::Instant::now();
-            let mut hook_handle: HHOOK = std::ptr::null_mut();
-
-            // We need to pass the app_handle to the hook procedure somehow
-            // Since we can't capture it in the extern "system" function, we'll use a different approach
-
-            // Store a reference to check if watcher is still enabled
-            let watcher_enabled = &RAE_WATCHER_ENABLED;
-            let app_for_emit = app_handle.clone();
-
-            unsafe extern "system" fn keyboard_hook_proc(
-                code: i32,
-                wparam: WPARAM,
-                lparam: LPARAM,
-            ) -> LRESULT {
-                // We can't access the VecDeque from here easily, so let's redesign this
-                CallNextHookEx(std::ptr::null_mut(), code, wparam, lparam)
+            if take_triggers_changed() {
+                matcher = TriggerMatcher::new(&get_trigger_config());
             }
-
-            println!("Starting rae watcher with improved polling...");
-
-            // Improved polling approach with better key detection
-            loop {
-                if !RAE_WATCHER_ENABLED.load(Ordering::Relaxed) {
-                    println!("Rae watcher disabled, stopping...");
-                    break;
-                }
-
-                use winapi::um::winuser::GetAsyncKeyState;
-
-                // Check for @ key (Shift+2 on US keyboards) - use different detection method
-                let shift_pressed = (GetAsyncKeyState(0x10) as u16 & 0x8000u16) != 0;
-                let two_pressed = (GetAsyncKeyState(0x32) as u16 & 0x8000u16) != 0;
-
-                // Check each character of "@rae" - use key down state instead of key press
-                let at_detected = shift_pressed && two_pressed;
-                let r_detected = (GetAsyncKeyState(0x52) as u16 & 0x8000u16) != 0;
-                let a_detected = (GetAsyncKeyState(0x41) as u16 & 0x8000u16) != 0;
-                let e_detected = (GetAsyncKeyState(0x45) as u16 & 0x8000u16) != 0;
-
-                let now = std::time::Instant::now();
-
-                // Reset sequence if too much time has passed (3 seconds)
-                if !typed_chars.is_empty()
-                    && now.duration_since(last_key_time) > std::time::Duration::from_secs(3)
-                {
-                    typed_chars.clear();
-                    println!("Sequence timeout, resetting...");
-                }
-
-                // Detect @ symbol (only when first typed)
-                if at_detected && (typed_chars.is_empty() || typed_chars.len() > 3) {
-                    typed_chars.clear();
-                    typed_chars.push_back('@');
-                    last_key_time = now;
-                    println!("Detected @ - starting sequence");
-                }
-                // Detect r after @
-                else if !typed_chars.is_empty()
-                    && r_detected
-                    && typed_chars.len() == 1
-                    && typed_chars[0] == '@'
-                {
-                    typed_chars.push_back('r');
-                    last_key_time = now;
-                    println!("Detected @r");
-                }
-                // Detect a after @r
-                else if !typed_chars.is_empty()
-                    && a_detected
-                    && typed_chars.len() == 2
-                    && typed_chars[1] == 'r'
-                {
-                    typed_chars.push_back('a');
-                    last_key_time = now;
-                    println!("Detected @ra");
-                }
-                // Detect e after @ra - this completes @rae
-                else if !typed_chars.is_empty()
-                    && e_detected
-                    && typed_chars.len() == 3
-                    && typed_chars[2] == 'a'
-                {
-                    typed_chars.push_back('e');
-                    let sequence: String = typed_chars.iter().collect();
-                    if sequence == "@rae" {
-                        println!("RAE DETECTED! Emitting event...");
-                        let _ = app_for_emit.emit("rae_mentioned", serde_json::json!({}));
-                        typed_chars.clear();
-                    }
-                }
-
-                // Reset on backspace, enter, or space
-                let backspace = (GetAsyncKeyState(0x08) as u16 & 0x8000u16) != 0;
-                let enter = (GetAsyncKeyState(0x0D) as u16 & 0x8000u16) != 0;
-                let space = (GetAsyncKeyState(0x20) as u16 & 0x8000u16) != 0;
-
-                if (backspace || enter || space) && !typed_chars.is_empty() {
-                    println!("Resetting sequence due to special key");
-                    typed_chars.clear();
-                }
-
-                std::thread::sleep(std::time::Duration::from_millis(50));
+            #[cfg(target_os = "windows")]
+            if inline_prompts.handle_key(&app_handle, &stroke) {
+                continue;
+            }
+            if let Some(found) = matcher.feed(&stroke) {
+                #[cfg(target_os = "windows")]
+                inline_prompts.start(&app_handle, &found);
+                let _ = app_handle.emit(
+                    "rae_mentioned",
+                    serde_json::json!({
+                        "phrase": found.phrase,
+                        "typedChars": found.typed_chars,
+                    }),
+                );
+                // Typing carries on in the app in front, so the dot must not take focus
+                crate::functions::overlay::show_magic_dot_unfocused(&app_handle);
             }
-
-            println!("Rae watcher stopped");
         }
+        keyboard::unsubscribe(subscription);
         RAE_WATCHER_RUNNING.store(false, Ordering::SeqCst);
     });
 }
 
+/// Whether any watcher in this module is switched on.
+pub(crate) fn any_watcher_enabled() -> bool {
+    clipboard_watch_wanted()
+        || AUTO_SHOW_ON_SELECTION.load(Ordering::Relaxed)
+        || RAE_WATCHER_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Returns the text of the last non-empty `text_selected` event if it came
+/// from `hwnd` no longer than `max_age` ago.
+#[cfg(target_os = "windows")]
+pub(crate) fn last_selected_text(hwnd: isize, max_age: std::time::Duration) -> Option<String> {
+    let last = LAST_SELECTION_TEXT.lock().ok()?;
+    let (text, source, at) = last.as_ref()?;
+    (*source == hwnd && at.elapsed() <= max_age).then(|| text.clone())
+}
+
+/// Returns the window the last `text_selected` event came from, if any.
+pub(crate) fn last_selection_hwnd() -> Option<isize> {
+    match LAST_SELECTION_HWND.load(Ordering::SeqCst) {
+        0 => None,
+        hwnd => Some(hwnd),
+    }
+}
+
 #[tauri::command]
 pub fn set_auto_show_on_copy_enabled(app: AppHandle, enabled: bool) {
     AUTO_SHOW_ON_COPY.store(enabled, Ordering::Relaxed);
-    if enabled {
-        ensure_clipboard_watcher_started(&app);
+    refresh_clipboard_watcher(&app);
+}
+
+/// The clipboard watcher also feeds the clipboard history, so it keeps running
+/// while either feature needs it.
+fn clipboard_watch_wanted() -> bool {
+    AUTO_SHOW_ON_COPY.load(Ordering::Relaxed) || is_clipboard_history_enabled()
+}
+
+/// Starts or stops the clipboard watcher to match the current settings.
+pub(crate) fn refresh_clipboard_watcher(app: &AppHandle) {
+    if clipboard_watch_wanted() {
+        ensure_clipboard_watcher_started(app);
+    } else {
+        stop_clipboard_watcher();
+        stop_unused_secure_input_monitor();
     }
 }
 
@@ -373,6 +891,9 @@ pub fn set_auto_show_on_selection_enabled(app: AppHandle, enabled: bool) {
     AUTO_SHOW_ON_SELECTION.store(enabled, Ordering::Relaxed);
     if enabled {
         ensure_selection_watcher_started(&app);
+    } else {
+        stop_selection_watcher();
+        stop_unused_secure_input_monitor();
     }
 }
 
@@ -0,0 +1,905 @@
+//! Offline, rule-based classification of copied and selected text so the
+//! overlay can offer fitting actions. The same input always yields the same
+//! label and confidence.
+
+/// What a piece of text most likely is.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentKind {
+    Code,
+    Url,
+    Email,
+    Json,
+    Yaml,
+    StackTrace,
+    ShellCommand,
+    Math,
+    ForeignProse,
+    Prose,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Classification {
+    pub kind: ContentKind,
+    /// Programming language for `code`, ISO 639-1 code for `foreign_prose`.
+    pub language: Option<String>,
+    /// Between 0 and 1.
+    pub confidence: f32,
+}
+
+impl Classification {
+    fn new(kind: ContentKind, confidence: f32) -> Self {
+        Self {
+            kind,
+            language: None,
+            confidence: confidence.clamp(0.0, 1.0),
+        }
+    }
+
+    fn with_language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_string());
+        self
+    }
+}
+
+/// Language the user reads natively; prose in any other language is
+/// reported as `foreign_prose`.
+const NATIVE_LANGUAGE: &str = "en";
+
+#[tauri::command]
+pub fn classify_text(text: String) -> Classification {
+    classify(&text)
+}
+
+pub fn classify(text: &str) -> Classification {
+    let text = text.trim();
+    if text.is_empty() {
+        return Classification::new(ContentKind::Prose, 0.0);
+    }
+    let checks: [fn(&str) -> Option<Classification>; 9] = [
+        classify_url,
+        classify_email,
+        classify_json,
+        classify_stack_trace,
+        classify_shell_command,
+        classify_math,
+        classify_yaml,
+        classify_code,
+        classify_foreign_prose,
+    ];
+    checks
+        .iter()
+        .find_map(|check| check(text))
+        .unwrap_or_else(|| Classification::new(ContentKind::Prose, prose_confidence(text)))
+}
+
+fn is_single_token(text: &str) -> bool {
+    !text.chars().any(char::is_whitespace)
+}
+
+fn classify_url(text: &str) -> Option<Classification> {
+    if !is_single_token(text) {
+        return None;
+    }
+    let lower = text.to_ascii_lowercase();
+    if ["http://", "https://", "ftp://", "file://"]
+        .iter()
+        .any(|scheme| lower.starts_with(scheme) && lower.len() > scheme.len() + 2)
+    {
+        return Some(Classification::new(ContentKind::Url, 0.98));
+    }
+    let host = lower.strip_prefix("www.").unwrap_or(&lower);
+    let host = host.split(['/', '?', '#']).next().unwrap_or("");
+    let labels: Vec<&str> = host.split('.').collect();
+    let tld = labels.last().copied().unwrap_or("");
+    let looks_like_host = labels.len() >= 2
+        && labels
+            .iter()
+            .all(|l| !l.is_empty() && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        && (2..=6).contains(&tld.len())
+        && tld.chars().all(|c| c.is_ascii_alphabetic());
+    if !looks_like_host || text.contains('@') {
+        return None;
+    }
+    if lower.starts_with("www.") {
+        return Some(Classification::new(ContentKind::Url, 0.9));
+    }
+    // `example.com/path` is a URL; a bare `file.rs` is more likely a file name
+    if lower.len() > host.len() || ["com", "org", "net", "io", "dev", "app"].contains(&tld) {
+        return Some(Classification::new(ContentKind::Url, 0.7));
+    }
+    None
+}
+
+fn classify_email(text: &str) -> Option<Classification> {
+    let address = text.strip_prefix("mailto:").unwrap_or(text);
+    if !is_single_token(address) {
+        return None;
+    }
+    let (local, domain) = address.split_once('@')?;
+    let valid_local = !local.is_empty()
+        && local
+            .chars()
+            .all(|c| c.is_alphanumeric() || "._%+-".contains(c));
+    let valid_domain = domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && domain
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '.' || c == '-');
+    if valid_local && valid_domain {
+        return Some(Classification::new(ContentKind::Email, 0.97));
+    }
+    None
+}
+
+fn classify_json(text: &str) -> Option<Classification> {
+    let wrapped = (text.starts_with('{') && text.ends_with('}'))
+        || (text.starts_with('[') && text.ends_with(']'));
+    if !wrapped {
+        return None;
+    }
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(serde_json::Value::Object(_)) | Ok(serde_json::Value::Array(_)) => {
+            Some(Classification::new(ContentKind::Json, 0.98))
+        }
+        // Truncated or slightly broken JSON still deserves JSON actions
+        _ if text.contains("\":") => Some(Classification::new(ContentKind::Json, 0.6)),
+        _ => None,
+    }
+}
+
+fn is_stack_frame(line: &str) -> bool {
+    let trimmed = line.trim();
+    // JavaScript / Java / C#: `at foo (file.js:10:5)`, `at com.x.Y.z(Y.java:42)`
+    if let Some(rest) = trimmed.strip_prefix("at ") {
+        return rest.contains(':') && (rest.ends_with(')') || rest.contains(".js:"))
+            || rest.contains(" in ") && rest.contains(":line ");
+    }
+    // Python: `File "x.py", line 3, in foo`
+    if trimmed.starts_with("File \"") && trimmed.contains("\", line ") {
+        return true;
+    }
+    // Rust backtraces: `  12: std::panicking::begin_panic` / `at ./src/main.rs:3:5`
+    if let Some((index, rest)) = trimmed.split_once(": ") {
+        if !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()) && rest.contains("::") {
+            return true;
+        }
+    }
+    // Go: `main.main()` followed by `\t/path/main.go:12 +0x1d`
+    trimmed.contains(".go:") && trimmed.contains(" +0x")
+}
+
+fn is_error_header(line: &str) -> bool {
+    let trimmed = line.trim();
+    if trimmed.starts_with("Traceback (most recent call last)")
+        || trimmed.starts_with("Exception in thread")
+        || trimmed.contains("panicked at")
+        || (trimmed.starts_with("goroutine ") && trimmed.ends_with(':'))
+        || trimmed.starts_with("Uncaught ")
+    {
+        return true;
+    }
+    // `ValueError: ...`, `java.lang.NullPointerException: ...`, `TypeError: ...`
+    let head = trimmed.split(':').next().unwrap_or("");
+    let name = head.rsplit('.').next().unwrap_or("");
+    !head.contains(' ')
+        && trimmed.len() > head.len()
+        && (name.ends_with("Error") || name.ends_with("Exception"))
+}
+
+fn classify_stack_trace(text: &str) -> Option<Classification> {
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    let frames = lines.iter().filter(|l| is_stack_frame(l)).count();
+    let headers = lines.iter().filter(|l| is_error_header(l)).count();
+    match (headers, frames) {
+        (0, 0) => None,
+        (_, f) if f >= 2 => {
+            let ratio = (f + headers) as f32 / lines.len() as f32;
+            Some(Classification::new(
+                ContentKind::StackTrace,
+                0.7 + 0.3 * ratio,
+            ))
+        }
+        (h, 1) if h >= 1 => Some(Classification::new(ContentKind::StackTrace, 0.8)),
+        // A lone `SomeError: message` line is an error log
+        (h, 0) if h >= 1 && lines.len() <= 3 => {
+            Some(Classification::new(ContentKind::StackTrace, 0.6))
+        }
+        _ => None,
+    }
+}
+
+const SHELL_COMMANDS: &[&str] = &[
+    "apt",
+    "apt-get",
+    "brew",
+    "cargo",
+    "cat",
+    "cd",
+    "chmod",
+    "chown",
+    "choco",
+    "cp",
+    "curl",
+    "docker",
+    "docker-compose",
+    "echo",
+    "export",
+    "find",
+    "gcc",
+    "git",
+    "go",
+    "grep",
+    "helm",
+    "kill",
+    "kubectl",
+    "ls",
+    "make",
+    "mkdir",
+    "mv",
+    "node",
+    "npm",
+    "npx",
+    "pip",
+    "pip3",
+    "pnpm",
+    "python",
+    "python3",
+    "rm",
+    "rustup",
+    "scp",
+    "sed",
+    "ssh",
+    "sudo",
+    "systemctl",
+    "tar",
+    "touch",
+    "wget",
+    "winget",
+    "yarn",
+];
+
+fn classify_shell_command(text: &str) -> Option<Classification> {
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    if lines.is_empty() || lines.len() > 5 {
+        return None;
+    }
+    let mut prompted = 0;
+    let mut known = 0;
+    for line in &lines {
+        let line = line.trim();
+        let (line, has_prompt) = match line.strip_prefix("$ ").or(line.strip_prefix("> ")) {
+            Some(rest) => (rest, true),
+            None => (line, false),
+        };
+        let first = line.split_whitespace().next().unwrap_or("");
+        let is_known = SHELL_COMMANDS.contains(&first)
+            || (first.contains('-') && first.starts_with(char::is_uppercase) && {
+                // PowerShell verbs: Get-ChildItem, Set-Location, ...
+                let verb = first.split('-').next().unwrap_or("");
+                ["Get", "Set", "New", "Remove", "Start", "Stop", "Install"].contains(&verb)
+            });
+        if has_prompt {
+            prompted += 1;
+        }
+        if is_known {
+            known += 1;
+        } else if !has_prompt && !line.ends_with('\\') {
+            return None;
+        }
+    }
+    // Sentences that merely start with "make" or "find" end with punctuation
+    if text.ends_with('.') && !text.contains(" -") && prompted == 0 {
+        return None;
+    }
+    let confidence = if prompted > 0 { 0.9 } else { 0.75 };
+    (known > 0 || prompted > 0).then(|| Classification::new(ContentKind::ShellCommand, confidence))
+}
+
+fn classify_math(text: &str) -> Option<Classification> {
+    const LATEX: &[&str] = &[
+        "\\frac", "\\sum", "\\int", "\\sqrt", "\\cdot", "\\alpha", "\\pi",
+    ];
+    if LATEX.iter().any(|cmd| text.contains(cmd)) && text.len() < 500 {
+        return Some(Classification::new(ContentKind::Math, 0.85));
+    }
+    if text.len() > 200 || text.lines().count() > 3 {
+        return None;
+    }
+    const FUNCTIONS: &[&str] = &[
+        "sin", "cos", "tan", "log", "ln", "sqrt", "exp", "abs", "pi", "mod",
+    ];
+    let mut digits = 0;
+    let mut operators = 0;
+    let mut word = String::new();
+    let mut words = Vec::new();
+    for c in text.chars().chain(std::iter::once(' ')) {
+        if c.is_alphabetic() {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        match c {
+            '0'..='9' | '.' | ',' => digits += usize::from(c.is_ascii_digit()),
+            '+' | '-' | '*' | '/' | '^' | '=' | '<' | '>' | '%' | '×' | '÷' | '·' | '√' | '≤'
+            | '≥' | '≠' => operators += 1,
+            '(' | ')' | '[' | ']' | '|' | '!' => {}
+            c if c.is_whitespace() => {}
+            _ => return None,
+        }
+    }
+    // Single-letter variables and well-known functions only
+    let plain_words = words
+        .iter()
+        .all(|w| w.chars().count() == 1 || FUNCTIONS.contains(&w.to_lowercase().as_str()));
+    if !plain_words || operators == 0 || digits + words.len() < 2 {
+        return None;
+    }
+    // Dates like 2024-01-02 and versions like 1.2-3 are not maths
+    let is_date_like = words.is_empty()
+        && text
+            .split(['-', '/'])
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()));
+    if is_date_like {
+        return None;
+    }
+    let confidence = if digits > 0 { 0.9 } else { 0.7 };
+    Some(Classification::new(ContentKind::Math, confidence))
+}
+
+fn is_yaml_line(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with('#') || trimmed == "---" {
+        return true;
+    }
+    // List items hold arbitrary scalars such as `- "8080:80"`
+    if trimmed.starts_with("- ") {
+        return true;
+    }
+    let Some((key, value)) = trimmed.split_once(':') else {
+        return false;
+    };
+    let key = key.trim_matches(|c| c == '"' || c == '\'');
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.' || c == ' ')
+        && !key.starts_with(' ')
+        && key.split_whitespace().count() <= 3
+        && (value.is_empty() || value.starts_with(' '))
+        && !value.trim_end().ends_with(';')
+        && !value.trim_end().ends_with('{')
+}
+
+fn classify_yaml(text: &str) -> Option<Classification> {
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    if lines.len() < 2 {
+        return None;
+    }
+    let yaml_lines = lines.iter().filter(|l| is_yaml_line(l)).count();
+    let has_mapping = lines.iter().any(|l| {
+        let t = l.trim_start().trim_start_matches("- ");
+        t.split_once(": ").is_some() || t.ends_with(':')
+    });
+    let ratio = yaml_lines as f32 / lines.len() as f32;
+    if !has_mapping || ratio < 0.85 {
+        return None;
+    }
+    // Prose written as "Label: sentence" lines is common; real YAML nests or
+    // at least keeps its values short
+    let nested = lines
+        .iter()
+        .any(|l| l.starts_with(' ') || l.starts_with("- "));
+    if !nested && !text.starts_with("---") {
+        let sentence_values = lines.iter().any(|l| {
+            let value = l.split_once(':').map(|(_, v)| v.trim()).unwrap_or("");
+            value.ends_with('.') || value.split_whitespace().count() > 4
+        });
+        if sentence_values {
+            return None;
+        }
+        return Some(Classification::new(ContentKind::Yaml, 0.5 + 0.2 * ratio));
+    }
+    let confidence = 0.6 + 0.35 * ratio;
+    Some(Classification::new(ContentKind::Yaml, confidence))
+}
+
+/// Keywords and idioms that point at one language, with their weights.
+const LANGUAGE_HINTS: &[(&str, &[(&str, u32)])] = &[
+    (
+        "rust",
+        &[
+            ("fn ", 3),
+            ("let mut ", 4),
+            ("impl ", 3),
+            ("pub fn ", 4),
+            ("use std::", 4),
+            ("println!", 4),
+            ("-> ", 1),
+            ("::", 1),
+            ("&self", 3),
+            ("match ", 1),
+            ("Some(", 2),
+            ("Ok(", 2),
+            ("#[derive", 4),
+        ],
+    ),
+    (
+        "python",
+        &[
+            ("def ", 3),
+            ("import ", 1),
+            ("from ", 1),
+            ("self.", 2),
+            ("elif ", 4),
+            ("print(", 2),
+            ("None", 1),
+            ("True", 1),
+            ("__init__", 4),
+            ("lambda ", 2),
+            (" in range(", 4),
+        ],
+    ),
+    (
+        "javascript",
+        &[
+            ("const ", 2),
+            ("function ", 3),
+            ("=> ", 2),
+            ("console.log", 4),
+            ("require(", 3),
+            ("===", 3),
+            ("let ", 1),
+            ("document.", 3),
+            ("export default", 3),
+            ("async ", 1),
+            ("await ", 1),
+        ],
+    ),
+    (
+        "typescript",
+        &[
+            ("interface ", 3),
+            (": string", 4),
+            (": number", 4),
+            (": boolean", 4),
+            ("type ", 1),
+            ("as const", 4),
+            ("readonly ", 2),
+            ("<T>", 2),
+        ],
+    ),
+    (
+        "java",
+        &[
+            ("public class ", 4),
+            ("private ", 1),
+            ("System.out.", 5),
+            ("public static void main", 5),
+            ("@Override", 4),
+            ("new ", 1),
+            ("extends ", 2),
+            ("import java.", 5),
+            ("String[]", 3),
+        ],
+    ),
+    (
+        "csharp",
+        &[
+            ("using System", 5),
+            ("namespace ", 3),
+            ("Console.WriteLine", 5),
+            ("public class ", 2),
+            ("var ", 1),
+            ("{ get; set; }", 5),
+            ("async Task", 4),
+        ],
+    ),
+    (
+        "cpp",
+        &[
+            ("#include", 4),
+            ("std::", 4),
+            ("int main(", 3),
+            ("cout <<", 5),
+            ("printf(", 2),
+            ("nullptr", 4),
+            ("template<", 4),
+        ],
+    ),
+    (
+        "go",
+        &[
+            ("func ", 4),
+            ("package ", 3),
+            (":= ", 3),
+            ("fmt.", 4),
+            ("err != nil", 5),
+            ("go ", 1),
+            ("chan ", 3),
+        ],
+    ),
+    (
+        "sql",
+        &[
+            ("SELECT ", 4),
+            (" FROM ", 3),
+            (" WHERE ", 3),
+            ("INSERT INTO", 5),
+            ("UPDATE ", 2),
+            ("CREATE TABLE", 5),
+            ("JOIN ", 3),
+            ("GROUP BY", 4),
+            ("ORDER BY", 4),
+        ],
+    ),
+    (
+        "html",
+        &[
+            ("<div", 4),
+            ("</", 2),
+            ("<html", 5),
+            ("class=\"", 3),
+            ("<span", 3),
+            ("href=\"", 3),
+            ("<!DOCTYPE", 5),
+        ],
+    ),
+    (
+        "css",
+        &[
+            ("px;", 3),
+            ("color:", 2),
+            ("margin:", 3),
+            ("padding:", 3),
+            ("display:", 3),
+            ("@media", 5),
+            ("font-size:", 3),
+        ],
+    ),
+    (
+        "php",
+        &[
+            ("<?php", 6),
+            ("$this->", 5),
+            ("echo ", 2),
+            ("->", 1),
+            ("function ", 1),
+        ],
+    ),
+    (
+        "ruby",
+        &[
+            ("def ", 2),
+            ("end\n", 2),
+            ("puts ", 4),
+            ("require '", 4),
+            ("do |", 5),
+            ("attr_accessor", 5),
+        ],
+    ),
+];
+
+fn guess_language(text: &str) -> Option<(&'static str, u32)> {
+    let mut best: Option<(&'static str, u32)> = None;
+    for (language, hints) in LANGUAGE_HINTS {
+        let score: u32 = hints
+            .iter()
+            .filter(|(hint, _)| text.contains(hint))
+            .map(|(_, weight)| weight)
+            .sum();
+        // Earlier entries win ties, which keeps the result deterministic
+        if score > 0 && best.is_none_or(|(_, s)| score > s) {
+            best = Some((language, score));
+        }
+    }
+    // TypeScript is a superset of JavaScript
+    if best.map(|(l, _)| l) == Some("javascript") {
+        let ts = LANGUAGE_HINTS
+            .iter()
+            .find(|(l, _)| *l == "typescript")
+            .map(|(_, hints)| {
+                hints
+                    .iter()
+                    .filter(|(h, _)| text.contains(h))
+                    .map(|(_, w)| w)
+                    .sum::<u32>()
+            })
+            .unwrap_or(0);
+        if ts >= 4 {
+            return best.map(|(_, s)| ("typescript", s + ts));
+        }
+    }
+    best
+}
+
+/// How much `text` looks like source code, independent of the language.
+fn code_structure_score(text: &str) -> f32 {
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    if lines.is_empty() {
+        return 0.0;
+    }
+    let code_like = lines
+        .iter()
+        .filter(|l| {
+            let t = l.trim();
+            t.ends_with(';')
+                || t.ends_with('{')
+                || t.ends_with('}')
+                || t.ends_with(')')
+                || t.ends_with("):")
+                || t.ends_with('>')
+                || t.starts_with("//")
+                || t.starts_with('#') && !t.starts_with("# ")
+                || t.contains(" = ")
+                || t.contains("==")
+        })
+        .count();
+    let symbols = text.chars().filter(|c| "{}()[];=<>&|".contains(*c)).count();
+    let letters = text.chars().filter(|c| c.is_alphabetic()).count().max(1);
+    let line_ratio = code_like as f32 / lines.len() as f32;
+    let symbol_ratio = (symbols as f32 / letters as f32 * 4.0).min(1.0);
+    0.6 * line_ratio + 0.4 * symbol_ratio
+}
+
+fn classify_code(text: &str) -> Option<Classification> {
+    let structure = code_structure_score(text);
+    let guess = guess_language(text);
+    let hint_score = guess.map(|(_, s)| s).unwrap_or(0);
+    // Prose mentions "function" or "new" too; require either real structure or
+    // several strong hints
+    let strength = structure + (hint_score as f32 / 10.0).min(0.6);
+    if strength < 0.55 || (structure < 0.2 && hint_score < 6) {
+        return None;
+    }
+    let classification = Classification::new(ContentKind::Code, 0.4 + strength / 2.0);
+    Some(match guess {
+        Some((language, score)) if score >= 3 => classification.with_language(language),
+        _ => classification,
+    })
+}
+
+/// Common short words per language, used for Latin-script prose.
+const STOPWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "and", "is", "are", "of", "to", "in", "that", "it", "with", "for", "this",
+            "was", "you", "not", "have",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "la", "los", "las", "de", "que", "y", "en", "un", "una", "es", "por", "con",
+            "para", "no", "se",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "de", "des", "et", "est", "un", "une", "que", "en", "du", "pour",
+            "pas", "je", "nous",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "ein", "eine", "zu", "den", "mit", "ich",
+            "sie", "es", "auf", "für",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "lo", "la", "che", "di", "e", "è", "un", "una", "per", "non", "sono", "gli",
+            "del", "della", "con",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "a", "os", "as", "de", "que", "e", "é", "um", "uma", "não", "para", "com", "do",
+            "da", "em",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "en", "van", "is", "niet", "dat", "op", "te", "zijn", "ik", "met",
+            "voor", "wij", "ook",
+        ],
+    ),
+];
+
+/// Languages recognised by their script alone.
+fn script_language(text: &str) -> Option<&'static str> {
+    let mut counts: [(&'static str, usize); 10] = [
+        ("ja", 0),
+        ("zh", 0),
+        ("ko", 0),
+        ("ru", 0),
+        ("ar", 0),
+        ("he", 0),
+        ("el", 0),
+        ("hi", 0),
+        ("th", 0),
+        ("latin", 0),
+    ];
+    let mut han = 0;
+    for c in text.chars() {
+        let index = match c as u32 {
+            0x3040..=0x30FF => 0,
+            0x4E00..=0x9FFF => {
+                han += 1;
+                continue;
+            }
+            0xAC00..=0xD7AF | 0x1100..=0x11FF => 2,
+            0x0400..=0x04FF => 3,
+            0x0600..=0x06FF => 4,
+            0x0590..=0x05FF => 5,
+            0x0370..=0x03FF => 6,
+            0x0900..=0x097F => 7,
+            0x0E00..=0x0E7F => 8,
+            _ if c.is_alphabetic() => 9,
+            _ => continue,
+        };
+        counts[index].1 += 1;
+    }
+    // Kanji mixed with kana is Japanese, Han alone is Chinese
+    if counts[0].1 > 0 {
+        counts[0].1 += han;
+    } else {
+        counts[1].1 = han;
+    }
+    let total: usize = counts.iter().map(|(_, n)| n).sum();
+    let (language, count) = counts.iter().max_by_key(|(_, n)| *n).copied()?;
+    (language != "latin" && total > 0 && count * 2 > total).then_some(language)
+}
+
+fn latin_language(text: &str) -> Option<(&'static str, f32)> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect();
+    if words.len() < 3 {
+        return None;
+    }
+    let mut scores: Vec<(&'static str, usize)> = STOPWORDS
+        .iter()
+        .map(|(language, stopwords)| {
+            let hits = words
+                .iter()
+                .filter(|w| stopwords.contains(&w.as_str()))
+                .count();
+            (*language, hits)
+        })
+        .collect();
+    // Stable sort keeps the table order for ties
+    scores.sort_by_key(|s| std::cmp::Reverse(s.1));
+    let (language, hits) = scores[0];
+    let runner_up = scores[1].1;
+    if hits == 0 {
+        return None;
+    }
+    let margin = (hits - runner_up) as f32 / hits as f32;
+    let coverage = (hits as f32 / words.len() as f32 * 3.0).min(1.0);
+    Some((language, 0.4 + 0.3 * margin + 0.25 * coverage))
+}
+
+fn detect_language(text: &str) -> Option<(&'static str, f32)> {
+    if let Some(language) = script_language(text) {
+        return Some((language, 0.9));
+    }
+    latin_language(text)
+}
+
+fn classify_foreign_prose(text: &str) -> Option<Classification> {
+    let (language, confidence) = detect_language(text)?;
+    (language != NATIVE_LANGUAGE)
+        .then(|| Classification::new(ContentKind::ForeignProse, confidence).with_language(language))
+}
+
+fn prose_confidence(text: &str) -> f32 {
+    match detect_language(text) {
+        Some((_, confidence)) => confidence,
+        None => 0.5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn kind_from_dir(name: &str) -> ContentKind {
+        match name {
+            "code" => ContentKind::Code,
+            "url" => ContentKind::Url,
+            "email" => ContentKind::Email,
+            "json" => ContentKind::Json,
+            "yaml" => ContentKind::Yaml,
+            "stack_trace" => ContentKind::StackTrace,
+            "shell_command" => ContentKind::ShellCommand,
+            "math" => ContentKind::Math,
+            "foreign_prose" => ContentKind::ForeignProse,
+            "prose" => ContentKind::Prose,
+            other => panic!("unknown fixture directory {}", other),
+        }
+    }
+
+    /// Fixtures live in `fixtures/classify/<kind>/`. For `code` and
+    /// `foreign_prose` the file name starts with the expected language,
+    /// e.g. `rust-1.txt` or `de-1.txt`.
+    #[test]
+    fn classifies_fixture_corpus() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/classify");
+        let mut dirs: Vec<_> = std::fs::read_dir(&root)
+            .expect("fixture corpus missing")
+            .map(|e| e.unwrap().path())
+            .collect();
+        dirs.sort();
+        let mut failures = Vec::new();
+        let mut total = 0;
+        for dir in dirs {
+            let expected_kind = kind_from_dir(dir.file_name().unwrap().to_str().unwrap());
+            let mut files: Vec<_> = std::fs::read_dir(&dir)
+                .unwrap()
+                .map(|e| e.unwrap().path())
+                .collect();
+            files.sort();
+            for file in files {
+                total += 1;
+                let text = std::fs::read_to_string(&file).unwrap();
+                let stem = file.file_stem().unwrap().to_str().unwrap();
+                let expected_language =
+                    matches!(expected_kind, ContentKind::Code | ContentKind::ForeignProse)
+                        .then(|| stem.split('-').next().unwrap().to_string());
+                let got = classify(&text);
+                if got.kind != expected_kind || got.language != expected_language {
+                    failures.push(format!(
+                        "{}: expected {:?} {:?}, got {:?} {:?}",
+                        file.display(),
+                        expected_kind,
+                        expected_language,
+                        got.kind,
+                        got.language
+                    ));
+                }
+            }
+        }
+        assert!(total > 0, "fixture corpus is empty");
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn is_deterministic() {
+        let text = "fn main() {\n    println!(\"hi\");\n}";
+        let first = classify(text);
+        for _ in 0..10 {
+            let again = classify(text);
+            assert_eq!(again.kind, first.kind);
+            assert_eq!(again.language, first.language);
+            assert_eq!(again.confidence, first.confidence);
+        }
+    }
+
+    #[test]
+    fn confidence_is_bounded() {
+        for text in [
+            "",
+            "x",
+            "https://example.com",
+            "SELECT * FROM t",
+            "hola que tal",
+        ] {
+            let c = classify(text).confidence;
+            assert!((0.0..=1.0).contains(&c), "{} -> {}", text, c);
+        }
+    }
+}
@@ -0,0 +1,965 @@
+//! Clipboard access: reading typed content (text, HTML, images and file lists)
+//! and writing one or more representations at once.
+
+#[cfg(target_os = "windows")]
+use super::markdown::format_for_window;
+use super::markdown::{convert, to_html, to_plain_text, to_rtf, OutputFormat};
+use base64::{engine::general_purpose, Engine as _};
+#[cfg(target_os = "windows")]
+use image::DynamicImage;
+use image::ImageFormat;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+#[cfg(target_os = "windows")]
+use std::io;
+use std::sync::Mutex;
+#[cfg(target_os = "windows")]
+use winapi::shared::windef::HWND as WinHWND;
+#[cfg(target_os = "windows")]
+use winapi::um::winbase::{GlobalAlloc, GlobalFree, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
+#[cfg(target_os = "windows")]
+use winapi::um::winuser::{
+    CloseClipboard, EmptyClipboard, OpenClipboard, RegisterClipboardFormatW, SetClipboardData,
+    CF_UNICODETEXT,
+};
+
+/// Number of copied images kept in memory for `get_clipboard_image`.
+const MAX_STORED_IMAGES: usize = 10;
+
+static CLIPBOARD_IMAGES: Mutex<VecDeque<(u64, Vec<u8>)>> = Mutex::new(VecDeque::new());
+static NEXT_IMAGE_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+
+/// Typed payload of the `clipboard_changed` event.
+#[derive(Clone, serde::Serialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "snake_case",
+    rename_all_fields = "camelCase"
+)]
+pub enum ClipboardContent {
+    Text {
+        text: String,
+    },
+    Html {
+        html: String,
+        /// Plain-text representation copied alongside the HTML, if any.
+        text: Option<String>,
+        source_url: Option<String>,
+    },
+    /// The PNG bytes are kept on the Rust side; fetch them with `get_clipboard_image`.
+    Image {
+        image_id: u64,
+        width: u32,
+        height: u32,
+        /// Plain text copied alongside the image, such as the cells Excel
+        /// also puts on the clipboard as a picture.
+        text: Option<String>,
+    },
+    Files {
+        paths: Vec<String>,
+    },
+}
+
+impl ClipboardContent {
+    /// The text a user would expect to paste, if the content has one.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            ClipboardContent::Text { text } => Some(text),
+            ClipboardContent::Html { text, .. } | ClipboardContent::Image { text, .. } => {
+                text.as_deref()
+            }
+            ClipboardContent::Files { .. } => None,
+        }
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipboardChanged {
+    pub content: ClipboardContent,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl ClipboardChanged {
+    pub fn new(content: ClipboardContent) -> Self {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        Self { content, timestamp }
+    }
+}
+
+/// Raw clipboard data before images are stored and given an id.
+pub(crate) enum ClipboardData {
+    Text(String),
+    Html {
+        html: String,
+        text: Option<String>,
+        source_url: Option<String>,
+    },
+    Png {
+        png: Vec<u8>,
+        text: Option<String>,
+    },
+    Files(Vec<String>),
+}
+
+impl ClipboardData {
+    /// Hash used by the watchers to skip repeated notifications for the same data.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        match self {
+            ClipboardData::Text(text) => ("text", text.trim()).hash(&mut hasher),
+            ClipboardData::Html { html, .. } => ("html", html).hash(&mut hasher),
+            ClipboardData::Png { png, .. } => ("png", png).hash(&mut hasher),
+            ClipboardData::Files(paths) => ("files", paths).hash(&mut hasher),
+        }
+        hasher.finish()
+    }
+
+    /// Stores image bytes and turns the data into an event payload.
+    pub fn into_content(self) -> Option<ClipboardContent> {
+        Some(match self {
+            ClipboardData::Text(text) => ClipboardContent::Text { text },
+            ClipboardData::Html {
+                html,
+                text,
+                source_url,
+            } => ClipboardContent::Html {
+                html,
+                text,
+                source_url,
+            },
+            ClipboardData::Png { png, text } => {
+                let (width, height) = png_dimensions(&png)?;
+                let image_id = store_clipboard_image(png);
+                ClipboardContent::Image {
+                    image_id,
+                    width,
+                    height,
+                    text,
+                }
+            }
+            ClipboardData::Files(paths) => ClipboardContent::Files { paths },
+        })
+    }
+}
+
+fn store_clipboard_image(png: Vec<u8>) -> u64 {
+    let id = NEXT_IMAGE_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    if let Ok(mut images) = CLIPBOARD_IMAGES.lock() {
+        images.push_back((id, png));
+        while images.len() > MAX_STORED_IMAGES {
+            images.pop_front();
+        }
+    }
+    id
+}
+
+/// Returns a copied image as a PNG data URL.
+#[tauri::command]
+pub fn get_clipboard_image(image_id: u64) -> Result<String, String> {
+    let images = CLIPBOARD_IMAGES.lock().map_err(|e| e.to_string())?;
+    let (_, png) = images
+        .iter()
+        .find(|(id, _)| *id == image_id)
+        .ok_or("Clipboard image not found")?;
+    Ok(format!(
+        "data:image/png;base64,{}",
+        general_purpose::STANDARD.encode(png)
+    ))
+}
+
+/// Copies a Markdown answer in the format that suits `hwnd`, or as plain text,
+/// HTML and RTF together when no target window is given.
+#[tauri::command]
+pub fn copy_markdown_to_clipboard(
+    markdown: String,
+    hwnd: Option<isize>,
+    format: Option<OutputFormat>,
+) -> Result<(), String> {
+    #[cfg(target_os = "windows")]
+    let format = format.or_else(|| hwnd.map(|h| format_for_window(h as WinHWND)));
+    #[cfg(not(target_os = "windows"))]
+    let _ = hwnd;
+    let content = match format.unwrap_or(OutputFormat::Rich) {
+        OutputFormat::Rich => ClipboardWrite {
+            text: Some(to_plain_text(&markdown)),
+            html: Some(to_html(&markdown)),
+            rtf: Some(to_rtf(&markdown)),
+            ..ClipboardWrite::default()
+        },
+        format => ClipboardWrite {
+            text: Some(convert(&markdown, format)),
+            ..ClipboardWrite::default()
+        },
+    };
+    clipboard_write(content)
+}
+
+/// Representations to put on the clipboard with `clipboard_write`.
+#[derive(Default, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ClipboardWrite {
+    pub text: Option<String>,
+    pub html: Option<String>,
+    pub rtf: Option<String>,
+    /// Base64 PNG, optionally as a `data:image/png;base64,` URL.
+    pub png: Option<String>,
+    pub files: Option<Vec<String>>,
+}
+
+/// Replaces the clipboard with every given representation at once. The write
+/// is tagged so Rae's own clipboard watcher ignores it.
+#[tauri::command]
+pub fn clipboard_write(content: ClipboardWrite) -> Result<(), String> {
+    let png = content.png.as_deref().map(decode_png_base64).transpose()?;
+    let files = content.files.clone().filter(|f| !f.is_empty());
+    if content.text.is_none()
+        && content.html.is_none()
+        && content.rtf.is_none()
+        && png.is_none()
+        && files.is_none()
+    {
+        return Err("Nothing to write to the clipboard".into());
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        let mut formats = Vec::new();
+        if let Some(text) = &content.text {
+            formats.push((CF_UNICODETEXT, unicode_text_bytes(text)));
+        }
+        if let Some(html) = &content.html {
+            formats.push((
+                register_clipboard_format("HTML Format"),
+                cf_html_bytes(html, None),
+            ));
+        }
+        if let Some(rtf) = &content.rtf {
+            formats.push((
+                register_clipboard_format("Rich Text Format"),
+                rtf.as_bytes().iter().copied().chain(Some(0)).collect(),
+            ));
+        }
+        if let Some(png) = png {
+            // Most apps only paste CF_DIB, newer ones prefer PNG for alpha
+            if let Some(dib) = png_to_dib(&png) {
+                formats.push((winapi::um::winuser::CF_DIB, dib));
+            }
+            formats.push((register_clipboard_format("PNG"), png));
+        }
+        if let Some(files) = &files {
+            formats.push((winapi::um::winuser::CF_HDROP, hdrop_bytes(files)));
+        }
+        if !unsafe { write_clipboard_formats(&formats) } {
+            return Err("Failed to write to clipboard".into());
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let mut offers: Vec<(String, Vec<u8>)> = Vec::new();
+        if let Some(text) = &content.text {
+            for target in ["UTF8_STRING", "text/plain;charset=utf-8", "text/plain"] {
+                offers.push((target.to_string(), text.as_bytes().to_vec()));
+            }
+            // ICCCM defines STRING as Latin-1, so it is only offered when the
+            // text fits
+            if let Some(latin1) = latin1_bytes(text) {
+                offers.push(("STRING".into(), latin1));
+            }
+        }
+        if let Some(html) = &content.html {
+            offers.push(("text/html".into(), html.as_bytes().to_vec()));
+        }
+        if let Some(rtf) = &content.rtf {
+            offers.push(("text/rtf".into(), rtf.as_bytes().to_vec()));
+        }
+        if let Some(png) = png {
+            offers.push(("image/png".into(), png));
+        }
+        if let Some(files) = &files {
+            let uris: Vec<String> = files.iter().map(|p| file_uri(p)).collect();
+            offers.push(("text/uri-list".into(), uris.join("\r\n").into_bytes()));
+            offers.push((
+                "x-special/gnome-copied-files".into(),
+                format!("copy\n{}", uris.join("\n")).into_bytes(),
+            ));
+        }
+        offers.push((RAE_CLIPBOARD_TAG.into(), vec![1]));
+        crate::x11::own_selection("CLIPBOARD", offers)
+    }
+}
+
+/// `text` encoded as Latin-1, or `None` when it has characters outside it.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn latin1_bytes(text: &str) -> Option<Vec<u8>> {
+    text.chars().map(|c| u8::try_from(c).ok()).collect()
+}
+
+fn decode_png_base64(data: &str) -> Result<Vec<u8>, String> {
+    let encoded = data.strip_prefix("data:image/png;base64,").unwrap_or(data);
+    let bytes = general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| e.to_string())?;
+    if png_dimensions(&bytes).is_none() {
+        return Err("Image is not a valid PNG".into());
+    }
+    Ok(bytes)
+}
+
+/// Marker format (Windows) or target (X11) added to every clipboard write made
+/// by Rae, so the watcher can tell them apart from the user's copies.
+pub(crate) const RAE_CLIPBOARD_TAG: &str = "application/x-rae-origin";
+
+/// Whether the current clipboard contents were written by Rae.
+#[cfg(target_os = "windows")]
+pub(crate) fn clipboard_written_by_rae() -> bool {
+    use winapi::um::winuser::IsClipboardFormatAvailable;
+    unsafe { IsClipboardFormatAvailable(register_clipboard_format(RAE_CLIPBOARD_TAG)) != 0 }
+}
+
+/// Whether the current selection owner is one of Rae's clipboard writes.
+#[cfg(target_os = "linux")]
+pub(crate) fn selection_written_by_rae(watcher: &crate::x11::SelectionWatcher) -> bool {
+    watcher.targets().iter().any(|t| t == RAE_CLIPBOARD_TAG)
+}
+
+/// Registers (or looks up) a named clipboard format such as `"HTML Format"`.
+#[cfg(target_os = "windows")]
+pub(crate) fn register_clipboard_format(name: &str) -> u32 {
+    let wide: Vec<u16> = name.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe { RegisterClipboardFormatW(wide.as_ptr()) }
+}
+
+/// Replaces the clipboard contents with the given `(format, bytes)` pairs in one
+/// open/close cycle, so readers never observe a partial update.
+#[cfg(target_os = "windows")]
+pub(crate) unsafe fn write_clipboard_formats(formats: &[(u32, Vec<u8>)]) -> bool {
+    if OpenClipboard(std::ptr::null_mut()) == 0 {
+        return false;
+    }
+    EmptyClipboard();
+    let mut ok = true;
+    let tag = (register_clipboard_format(RAE_CLIPBOARD_TAG), vec![1u8]);
+    for (format, bytes) in formats.iter().chain(std::iter::once(&tag)) {
+        if !set_clipboard_bytes(*format, bytes) {
+            ok = false;
+            break;
+        }
+    }
+    CloseClipboard();
+    ok
+}
+
+/// Hands a copy of `bytes` to the open clipboard (or to a delayed rendering
+/// request) as `format`.
+#[cfg(target_os = "windows")]
+unsafe fn set_clipboard_bytes(format: u32, bytes: &[u8]) -> bool {
+    let handle = GlobalAlloc(GMEM_MOVEABLE, bytes.len().max(1));
+    if handle.is_null() {
+        return false;
+    }
+    let locked = GlobalLock(handle) as *mut u8;
+    if locked.is_null() {
+        GlobalFree(handle);
+        return false;
+    }
+    std::ptr::copy_nonoverlapping(bytes.as_ptr(), locked, bytes.len());
+    let _ = GlobalUnlock(handle);
+    // On success the clipboard owns the memory
+    if SetClipboardData(format, handle as _).is_null() {
+        GlobalFree(handle);
+        return false;
+    }
+    true
+}
+
+#[cfg(target_os = "windows")]
+thread_local! {
+    /// Formats offered by [`offer_clipboard_formats`] on this thread, and
+    /// whether another app asked for one.
+    static OFFERED: std::cell::RefCell<(Vec<(u32, Vec<u8>)>, bool)> =
+        const { std::cell::RefCell::new((Vec::new(), false)) };
+}
+
+/// Puts `formats` on the clipboard with delayed rendering, runs `paste` and
+/// waits up to `timeout` for another app to read them, which tells a paste
+/// that arrived from one the target ignored. Returns whether anything was
+/// read. The clipboard is left empty afterwards; the caller restores it.
+#[cfg(target_os = "windows")]
+pub(crate) unsafe fn offer_clipboard_formats(
+    formats: Vec<(u32, Vec<u8>)>,
+    paste: impl FnOnce(),
+    timeout: std::time::Duration,
+) -> Result<bool, String> {
+    use std::time::{Duration, Instant};
+    use winapi::um::libloaderapi::GetModuleHandleW;
+    use winapi::um::winuser::{
+        CreateWindowExW, DestroyWindow, DispatchMessageW, PeekMessageW, RegisterClassW,
+        TranslateMessage, HWND_MESSAGE, MSG, PM_REMOVE, WNDCLASSW,
+    };
+    /// How long to keep serving formats after the first was read, since apps
+    /// often ask for several.
+    const LINGER: Duration = Duration::from_millis(300);
+    let class: Vec<u16> = "RaeClipboardOffer"
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect();
+    let instance = GetModuleHandleW(std::ptr::null());
+    let mut window_class: WNDCLASSW = std::mem::zeroed();
+    window_class.lpfnWndProc = Some(offer_window_proc);
+    window_class.hInstance = instance;
+    window_class.lpszClassName = class.as_ptr();
+    // Fails harmlessly once the class exists
+    RegisterClassW(&window_class);
+    let hwnd = CreateWindowExW(
+        0,
+        class.as_ptr(),
+        std::ptr::null(),
+        0,
+        0,
+        0,
+        0,
+        0,
+        HWND_MESSAGE,
+        std::ptr::null_mut(),
+        instance,
+        std::ptr::null_mut(),
+    );
+    if hwnd.is_null() {
+        return Err("Failed to create the clipboard window".into());
+    }
+    if OpenClipboard(hwnd) == 0 {
+        DestroyWindow(hwnd);
+        return Err("Failed to open the clipboard".into());
+    }
+    EmptyClipboard();
+    for (format, _) in &formats {
+        SetClipboardData(*format, std::ptr::null_mut());
+    }
+    // The tag is there right away, so the history never records the paste
+    set_clipboard_bytes(register_clipboard_format(RAE_CLIPBOARD_TAG), &[1]);
+    CloseClipboard();
+    OFFERED.with(|offered| *offered.borrow_mut() = (formats, false));
+
+    paste();
+    let started = Instant::now();
+    let mut read_at = None;
+    let mut msg: MSG = std::mem::zeroed();
+    loop {
+        while PeekMessageW(&mut msg, std::ptr::null_mut(), 0, 0, PM_REMOVE) != 0 {
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+        if read_at.is_none() && OFFERED.with(|offered| offered.borrow().1) {
+            read_at = Some(Instant::now());
+        }
+        let done = match read_at {
+            Some(at) => at.elapsed() >= LINGER,
+            None => started.elapsed() >= timeout,
+        };
+        if done {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    // Withdraws the offer before the window goes, which would otherwise
+    // have to render everything
+    if OpenClipboard(hwnd) != 0 {
+        EmptyClipboard();
+        CloseClipboard();
+    }
+    DestroyWindow(hwnd);
+    let read = OFFERED.with(|offered| std::mem::take(&mut *offered.borrow_mut()).1);
+    Ok(read)
+}
+
+#[cfg(target_os = "windows")]
+unsafe extern "system" fn offer_window_proc(
+    hwnd: WinHWND,
+    message: u32,
+    wparam: winapi::shared::minwindef::WPARAM,
+    lparam: winapi::shared::minwindef::LPARAM,
+) -> winapi::shared::minwindef::LRESULT {
+    use winapi::um::winuser::{DefWindowProcW, WM_RENDERFORMAT};
+    if message != WM_RENDERFORMAT {
+        return DefWindowProcW(hwnd, message, wparam, lparam);
+    }
+    OFFERED.with(|offered| {
+        let mut offered = offered.borrow_mut();
+        let format = wparam as u32;
+        let rendered = match offered.0.iter().find(|(f, _)| *f == format) {
+            Some((_, bytes)) => set_clipboard_bytes(format, bytes),
+            None => false,
+        };
+        offered.1 |= rendered;
+    });
+    0
+}
+
+/// Encodes text as a NUL-terminated UTF-16 buffer for `CF_UNICODETEXT`.
+#[cfg(target_os = "windows")]
+pub(crate) fn unicode_text_bytes(text: &str) -> Vec<u8> {
+    text.encode_utf16()
+        .chain(std::iter::once(0))
+        .flat_map(|u| u.to_le_bytes())
+        .collect()
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) unsafe fn write_clipboard_unicode_text(text: &str) -> bool {
+    write_clipboard_formats(&[(CF_UNICODETEXT, unicode_text_bytes(text))])
+}
+
+/// Plain text, HTML and RTF renderings of one piece of content.
+#[cfg(target_os = "windows")]
+pub(crate) fn rich_text_formats(text: &str, html: &str, rtf: &str) -> Vec<(u32, Vec<u8>)> {
+    vec![
+        (CF_UNICODETEXT, unicode_text_bytes(text)),
+        (
+            register_clipboard_format("HTML Format"),
+            cf_html_bytes(html, None),
+        ),
+        (
+            register_clipboard_format("Rich Text Format"),
+            rtf.as_bytes().iter().copied().chain(Some(0)).collect(),
+        ),
+    ]
+}
+
+/// Wraps an HTML fragment in the `CF_HTML` header that Windows expects.
+#[cfg(target_os = "windows")]
+pub(crate) fn cf_html_bytes(fragment: &str, source_url: Option<&str>) -> Vec<u8> {
+    const HEADER_LEN: usize = 105;
+    let source = source_url
+        .map(|url| format!("SourceURL:{}\r\n", url))
+        .unwrap_or_default();
+    let prefix = format!("{}<html><body>\r\n<!--StartFragment-->", source);
+    let suffix = "<!--EndFragment-->\r\n</body></html>";
+
+    // Offsets are byte offsets from the start of the header, which always has
+    // the fixed length of HEADER_LEN thanks to the zero-padded numbers.
+    let start_html = HEADER_LEN + source.len();
+    let start_fragment = HEADER_LEN + prefix.len();
+    let end_fragment = start_fragment + fragment.len();
+    let end_html = end_fragment + suffix.len();
+
+    let header = format!(
+        "Version:0.9\r\nStartHTML:{:010}\r\nEndHTML:{:010}\r\nStartFragment:{:010}\r\nEndFragment:{:010}\r\n",
+        start_html, end_html, start_fragment, end_fragment
+    );
+    debug_assert_eq!(header.len(), HEADER_LEN);
+
+    let mut bytes = header.into_bytes();
+    bytes.extend_from_slice(prefix.as_bytes());
+    bytes.extend_from_slice(fragment.as_bytes());
+    bytes.extend_from_slice(suffix.as_bytes());
+    bytes.push(0);
+    bytes
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) unsafe fn read_clipboard_unicode_text() -> Option<String> {
+    let bytes = read_clipboard_bytes(CF_UNICODETEXT)?;
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&u| u != 0)
+        .collect();
+    Some(String::from_utf16_lossy(&units))
+}
+
+/// Copies the raw bytes of one clipboard format.
+#[cfg(target_os = "windows")]
+pub(crate) unsafe fn read_clipboard_bytes(format: u32) -> Option<Vec<u8>> {
+    use winapi::um::winbase::GlobalSize;
+    use winapi::um::winuser::{GetClipboardData, IsClipboardFormatAvailable};
+    if format == 0 || IsClipboardFormatAvailable(format) == 0 {
+        return None;
+    }
+    if OpenClipboard(std::ptr::null_mut()) == 0 {
+        return None;
+    }
+    let handle = GetClipboardData(format);
+    if handle.is_null() {
+        CloseClipboard();
+        return None;
+    }
+    let size = GlobalSize(handle);
+    let locked = GlobalLock(handle) as *const u8;
+    if locked.is_null() {
+        CloseClipboard();
+        return None;
+    }
+    let bytes = std::slice::from_raw_parts(locked, size).to_vec();
+    let _ = GlobalUnlock(handle);
+    CloseClipboard();
+    Some(bytes)
+}
+
+/// Copies every memory-backed clipboard format so the clipboard can be put back
+/// with [`write_clipboard_formats`] later. GDI handle formats are skipped; their
+/// DIB renderings are captured instead.
+#[cfg(target_os = "windows")]
+pub(crate) unsafe fn snapshot_clipboard() -> Option<Vec<(u32, Vec<u8>)>> {
+    use winapi::um::winbase::GlobalSize;
+    use winapi::um::winuser::{
+        EnumClipboardFormats, GetClipboardData, CF_BITMAP, CF_DSPBITMAP, CF_DSPENHMETAFILE,
+        CF_DSPMETAFILEPICT, CF_ENHMETAFILE, CF_METAFILEPICT, CF_OWNERDISPLAY, CF_PALETTE,
+    };
+    const HANDLE_FORMATS: [u32; 8] = [
+        CF_BITMAP,
+        CF_METAFILEPICT,
+        CF_PALETTE,
+        CF_ENHMETAFILE,
+        CF_OWNERDISPLAY,
+        CF_DSPBITMAP,
+        CF_DSPMETAFILEPICT,
+        CF_DSPENHMETAFILE,
+    ];
+    if OpenClipboard(std::ptr::null_mut()) == 0 {
+        return None;
+    }
+    let tag = register_clipboard_format(RAE_CLIPBOARD_TAG);
+    let mut formats = Vec::new();
+    let mut format = EnumClipboardFormats(0);
+    while format != 0 {
+        if format != tag && !HANDLE_FORMATS.contains(&format) {
+            let handle = GetClipboardData(format);
+            let locked = if handle.is_null() {
+                std::ptr::null()
+            } else {
+                GlobalLock(handle) as *const u8
+            };
+            if !locked.is_null() {
+                let size = GlobalSize(handle);
+                formats.push((format, std::slice::from_raw_parts(locked, size).to_vec()));
+                let _ = GlobalUnlock(handle);
+            }
+        }
+        format = EnumClipboardFormats(format);
+    }
+    CloseClipboard();
+    Some(formats)
+}
+
+#[cfg(target_os = "windows")]
+unsafe fn read_clipboard_files() -> Option<Vec<String>> {
+    use winapi::um::shellapi::{DragQueryFileW, HDROP};
+    use winapi::um::winuser::{GetClipboardData, IsClipboardFormatAvailable, CF_HDROP};
+    if IsClipboardFormatAvailable(CF_HDROP) == 0 {
+        return None;
+    }
+    if OpenClipboard(std::ptr::null_mut()) == 0 {
+        return None;
+    }
+    let hdrop = GetClipboardData(CF_HDROP) as HDROP;
+    if hdrop.is_null() {
+        CloseClipboard();
+        return None;
+    }
+    let count = DragQueryFileW(hdrop, 0xFFFF_FFFF, std::ptr::null_mut(), 0);
+    let mut paths = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let len = DragQueryFileW(hdrop, i, std::ptr::null_mut(), 0);
+        let mut buf = vec![0u16; len as usize + 1];
+        let copied = DragQueryFileW(hdrop, i, buf.as_mut_ptr(), buf.len() as u32);
+        paths.push(String::from_utf16_lossy(&buf[..copied as usize]));
+    }
+    CloseClipboard();
+    Some(paths)
+}
+
+/// Reads the richest representation currently on the clipboard:
+/// files, then images, then HTML, then plain text. Plain text is picked up
+/// alongside images and HTML, which Office apps put on the clipboard together.
+#[cfg(target_os = "windows")]
+pub(crate) unsafe fn read_clipboard_data() -> Option<ClipboardData> {
+    use winapi::um::winuser::CF_DIB;
+    if let Some(paths) = read_clipboard_files().filter(|p| !p.is_empty()) {
+        return Some(ClipboardData::Files(paths));
+    }
+    let text = read_clipboard_unicode_text();
+    let png = read_clipboard_bytes(register_clipboard_format("PNG"))
+        .or_else(|| read_clipboard_bytes(CF_DIB).and_then(|dib| dib_to_png(&dib)));
+    if let Some(png) = png {
+        return Some(ClipboardData::Png { png, text });
+    }
+    if let Some(cf_html) = read_clipboard_bytes(register_clipboard_format("HTML Format")) {
+        if let Some((html, source_url)) = parse_cf_html(&cf_html) {
+            return Some(ClipboardData::Html {
+                html,
+                text,
+                source_url,
+            });
+        }
+    }
+    text.map(ClipboardData::Text)
+}
+
+/// Reads the richest representation a selection owner offers on X11.
+#[cfg(target_os = "linux")]
+pub(crate) fn read_selection_data(watcher: &crate::x11::SelectionWatcher) -> Option<ClipboardData> {
+    let targets = watcher.targets();
+    let offers = |name: &str| targets.iter().any(|t| t == name);
+
+    for target in ["x-special/gnome-copied-files", "text/uri-list"] {
+        if offers(target) {
+            let paths = watcher
+                .read_target_named(target)
+                .map(|bytes| parse_uri_list(&String::from_utf8_lossy(&bytes)))
+                .unwrap_or_default();
+            if !paths.is_empty() {
+                return Some(ClipboardData::Files(paths));
+            }
+        }
+    }
+    let text = watcher.read_text();
+    if offers("image/png") {
+        if let Some(png) = watcher.read_target_named("image/png") {
+            return Some(ClipboardData::Png { png, text });
+        }
+    }
+    if offers("text/html") {
+        if let Some(bytes) = watcher.read_target_named("text/html") {
+            let html = decode_x11_html(&bytes);
+            let source_url = if offers("text/x-moz-url-priv") {
+                watcher
+                    .read_target_named("text/x-moz-url-priv")
+                    .map(|b| decode_x11_html(&b).lines().next().unwrap_or("").to_string())
+                    .filter(|u| !u.is_empty())
+            } else {
+                None
+            };
+            return Some(ClipboardData::Html {
+                html,
+                text,
+                source_url,
+            });
+        }
+    }
+    text.map(ClipboardData::Text)
+}
+
+/// Firefox offers `text/html` as UTF-16 with a BOM, everyone else as UTF-8.
+#[cfg(target_os = "linux")]
+fn decode_x11_html(bytes: &[u8]) -> String {
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        return String::from_utf16_lossy(&units);
+    }
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Extracts the fragment and `SourceURL` from `CF_HTML` data.
+#[cfg(target_os = "windows")]
+fn parse_cf_html(bytes: &[u8]) -> Option<(String, Option<String>)> {
+    let data = String::from_utf8_lossy(bytes);
+    let header_value = |key: &str| -> Option<&str> {
+        data.lines()
+            .take_while(|l| !l.starts_with('<'))
+            .find_map(|l| l.strip_prefix(key))
+            .map(|v| v.trim())
+    };
+    let start: usize = header_value("StartFragment:")?.parse().ok()?;
+    let end: usize = header_value("EndFragment:")?.parse().ok()?;
+    let source_url = header_value("SourceURL:").map(|s| s.to_string());
+    // Offsets are byte offsets into the raw data
+    let fragment = bytes.get(start..end.min(bytes.len()))?;
+    Some((String::from_utf8_lossy(fragment).into_owned(), source_url))
+}
+
+/// Builds a `CF_HDROP` block: a `DROPFILES` header followed by the
+/// NUL-separated wide paths and a final NUL.
+#[cfg(target_os = "windows")]
+fn hdrop_bytes(paths: &[String]) -> Vec<u8> {
+    const DROPFILES_SIZE: u32 = 20;
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&DROPFILES_SIZE.to_le_bytes()); // pFiles
+    bytes.extend_from_slice(&[0u8; 8]); // pt
+    bytes.extend_from_slice(&0i32.to_le_bytes()); // fNC
+    bytes.extend_from_slice(&1i32.to_le_bytes()); // fWide
+    for path in paths {
+        bytes.extend(
+            path.encode_utf16()
+                .chain(std::iter::once(0))
+                .flat_map(|u| u.to_le_bytes()),
+        );
+    }
+    bytes.extend_from_slice(&[0, 0]);
+    bytes
+}
+
+#[cfg(target_os = "linux")]
+fn file_uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    uri
+}
+
+/// Parses `text/uri-list` or `x-special/gnome-copied-files` into local paths.
+#[cfg(target_os = "linux")]
+fn parse_uri_list(list: &str) -> Vec<String> {
+    list.lines()
+        .map(|l| l.trim())
+        .filter_map(|l| l.strip_prefix("file://"))
+        .map(|l| percent_decode(l.trim_start_matches("localhost")))
+        .collect()
+}
+
+/// Decodes `%XX` escapes. Works on bytes, so a `%` in front of a multi-byte
+/// character is kept as it is.
+#[cfg(target_os = "linux")]
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let hex = |i: usize| bytes.get(i).and_then(|&b| (b as char).to_digit(16));
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let (Some(high), Some(low)) = (hex(i + 1), hex(i + 2)) {
+                out.push((high * 16 + low) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn png_dimensions(png: &[u8]) -> Option<(u32, u32)> {
+    let img = image::load_from_memory_with_format(png, ImageFormat::Png).ok()?;
+    Some((img.width(), img.height()))
+}
+
+/// Clipboard bitmaps wider or taller than this are not decoded.
+#[cfg(target_os = "windows")]
+const MAX_DIB_SIDE: usize = 16_384;
+
+/// Converts a packed `CF_DIB` (24 or 32 bits per pixel) into PNG bytes.
+#[cfg(target_os = "windows")]
+fn dib_to_png(dib: &[u8]) -> Option<Vec<u8>> {
+    let u32_at = |o: usize| Some(u32::from_le_bytes(dib.get(o..o + 4)?.try_into().ok()?));
+    let header_size = u32_at(0)? as usize;
+    let width = u32_at(4)? as i32;
+    let height = u32_at(8)? as i32;
+    let bit_count = u16::from_le_bytes(dib.get(14..16)?.try_into().ok()?);
+    let compression = u32_at(16)?;
+    if width <= 0 || height == 0 || !(bit_count == 24 || bit_count == 32) {
+        return None;
+    }
+
+    let mut offset = header_size;
+    // BI_BITFIELDS with a plain BITMAPINFOHEADER is followed by three masks
+    if compression == 3 && header_size == 40 {
+        offset = offset.checked_add(12)?;
+    }
+    let width = width as usize;
+    let rows = height.unsigned_abs() as usize;
+    // The header comes from another process; never trust it to size buffers
+    if width > MAX_DIB_SIDE || rows > MAX_DIB_SIDE {
+        return None;
+    }
+    let bytes_per_pixel = bit_count as usize / 8;
+    let stride = width.checked_mul(bit_count as usize)?.div_ceil(32) * 4;
+    let pixels = dib.get(offset..offset.checked_add(stride.checked_mul(rows)?)?)?;
+
+    let mut rgba = Vec::with_capacity(width * rows * 4);
+    let mut any_alpha = false;
+    for y in 0..rows {
+        // Positive heights are stored bottom-up
+        let src_row = if height > 0 { rows - 1 - y } else { y };
+        let start = src_row * stride;
+        let row = pixels.get(start..start + width * bytes_per_pixel)?;
+        for px in row.chunks_exact(bytes_per_pixel) {
+            let alpha = if bytes_per_pixel == 4 { px[3] } else { 255 };
+            any_alpha |= alpha != 0;
+            rgba.extend_from_slice(&[px[2], px[1], px[0], alpha]);
+        }
+    }
+    // Many apps leave the alpha channel of 32-bit DIBs at zero
+    if !any_alpha {
+        for px in rgba.chunks_exact_mut(4) {
+            px[3] = 255;
+        }
+    }
+
+    let img = image::RgbaImage::from_raw(width as u32, rows as u32, rgba)?;
+    let mut png = Vec::new();
+    DynamicImage::ImageRgba8(img)
+        .write_to(&mut io::Cursor::new(&mut png), ImageFormat::Png)
+        .ok()?;
+    Some(png)
+}
+
+/// Converts PNG bytes into a packed 32-bit bottom-up `CF_DIB`.
+#[cfg(target_os = "windows")]
+fn png_to_dib(png: &[u8]) -> Option<Vec<u8>> {
+    let img = image::load_from_memory_with_format(png, ImageFormat::Png)
+        .ok()?
+        .to_rgba8();
+    let (width, height) = img.dimensions();
+    let mut dib = Vec::with_capacity(40 + (width * height * 4) as usize);
+    dib.extend_from_slice(&40u32.to_le_bytes()); // biSize
+    dib.extend_from_slice(&(width as i32).to_le_bytes());
+    dib.extend_from_slice(&(height as i32).to_le_bytes());
+    dib.extend_from_slice(&1u16.to_le_bytes()); // biPlanes
+    dib.extend_from_slice(&32u16.to_le_bytes()); // biBitCount
+    dib.extend_from_slice(&0u32.to_le_bytes()); // BI_RGB
+    dib.extend_from_slice(&(width * height * 4).to_le_bytes());
+    dib.extend_from_slice(&[0u8; 16]); // resolution and palette fields
+    for row in img.rows().rev() {
+        for px in row {
+            dib.extend_from_slice(&[px[2], px[1], px[0], px[3]]);
+        }
+    }
+    Some(dib)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uri_lists_decode_to_paths() {
+        let list = "# copied\r\nfile:///home/me/My%20Notes.md\r\nfile://localhost/tmp/caf%C3%A9\r\nhttps://example.com\r\n";
+        assert_eq!(
+            parse_uri_list(list),
+            vec!["/home/me/My Notes.md", "/tmp/café"]
+        );
+    }
+
+    #[test]
+    fn broken_escapes_are_kept() {
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%zz%41"), "%zzA");
+        // Used to slice through the middle of the multi-byte character
+        assert_eq!(percent_decode("%é1"), "%é1");
+        assert_eq!(percent_decode("%%é"), "%%é");
+    }
+
+    #[test]
+    fn only_latin1_text_is_offered_as_string() {
+        assert_eq!(latin1_bytes("café"), Some(vec![b'c', b'a', b'f', 0xe9]));
+        assert_eq!(latin1_bytes("€5"), None);
+        assert_eq!(latin1_bytes("日本"), None);
+    }
+}
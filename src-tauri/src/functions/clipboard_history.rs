@@ -0,0 +1,612 @@
+//! Clipboard history: a bounded list of recently copied texts with pinning,
+//! fuzzy search and optional encrypted persistence.
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+
+static CLIPBOARD_HISTORY_ENABLED: AtomicBool = AtomicBool::new(false);
+static HISTORY: Mutex<Option<ClipboardHistory>> = Mutex::new(None);
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipboardHistoryItem {
+    pub id: u64,
+    pub text: String,
+    /// Executable name of the window that was active when the text was copied.
+    pub source_app: String,
+    pub source_title: String,
+    /// Milliseconds since the Unix epoch.
+    pub copied_at: u64,
+    pub pinned: bool,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RetentionPolicy {
+    /// Maximum number of unpinned items kept; the oldest are dropped first.
+    pub max_items: usize,
+    /// Unpinned items older than this are dropped. `None` keeps them forever.
+    pub max_age_hours: Option<u64>,
+    /// Save the history to disk so it survives restarts.
+    pub persist: bool,
+    /// Encrypt the saved history for the current user. Only available on
+    /// Windows, where DPAPI does it.
+    pub encrypt: bool,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_items: 200,
+            max_age_hours: Some(24 * 7),
+            persist: false,
+            encrypt: ENCRYPTION_SUPPORTED,
+        }
+    }
+}
+
+/// Whether a saved history can be encrypted on this platform.
+const ENCRYPTION_SUPPORTED: bool = cfg!(target_os = "windows");
+
+impl RetentionPolicy {
+    /// Rejects policies whose history could not be saved as asked.
+    fn check(&self) -> Result<(), String> {
+        if self.encrypt && !ENCRYPTION_SUPPORTED {
+            return Err("Encrypted clipboard history is only supported on Windows".into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct ClipboardHistory {
+    items: VecDeque<ClipboardHistoryItem>,
+    next_id: u64,
+    #[serde(skip)]
+    policy: RetentionPolicy,
+}
+
+impl ClipboardHistory {
+    /// Adds `text` at the front, or moves an identical entry there.
+    fn record(&mut self, text: &str, source_app: String, source_title: String) -> u64 {
+        let now = now_millis();
+        if let Some(pos) = self.items.iter().position(|i| i.text == text) {
+            let mut item = self.items.remove(pos).unwrap();
+            item.copied_at = now;
+            item.source_app = source_app;
+            item.source_title = source_title;
+            let id = item.id;
+            self.items.push_front(item);
+            return id;
+        }
+        self.next_id += 1;
+        self.items.push_front(ClipboardHistoryItem {
+            id: self.next_id,
+            text: text.to_string(),
+            source_app,
+            source_title,
+            copied_at: now,
+            pinned: false,
+        });
+        self.next_id
+    }
+
+    /// Drops expired items and trims the unpinned ones down to `max_items`.
+    fn apply_retention(&mut self) {
+        if let Some(hours) = self.policy.max_age_hours {
+            let cutoff = now_millis().saturating_sub(hours * 60 * 60 * 1000);
+            self.items.retain(|i| i.pinned || i.copied_at >= cutoff);
+        }
+        let mut unpinned = 0;
+        let max_items = self.policy.max_items;
+        self.items.retain(|i| {
+            if i.pinned {
+                return true;
+            }
+            unpinned += 1;
+            unpinned <= max_items
+        });
+    }
+}
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Returns the executable name and title of the foreground window.
+#[cfg(target_os = "windows")]
+pub(crate) fn active_window_source() -> (String, String) {
+    use crate::platform::{exe_path_from_hwnd, get_window_title};
+    use winapi::um::winuser::GetForegroundWindow;
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.is_null() {
+            return (String::new(), String::new());
+        }
+        let app = exe_path_from_hwnd(hwnd)
+            .and_then(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+        (app, get_window_title(hwnd))
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn active_window_source() -> (String, String) {
+    (String::new(), String::new())
+}
+
+fn history_path(app: &AppHandle, encrypted: bool) -> Option<PathBuf> {
+    let dir = app.path().app_data_dir().ok()?;
+    let name = if encrypted {
+        "clipboard_history.bin"
+    } else {
+        "clipboard_history.json"
+    };
+    Some(dir.join(name))
+}
+
+fn policy_path(app: &AppHandle) -> Option<PathBuf> {
+    let dir = app.path().app_data_dir().ok()?;
+    Some(dir.join("clipboard_history_policy.json"))
+}
+
+fn save_policy(app: &AppHandle, policy: &RetentionPolicy) -> Result<(), String> {
+    let path = policy_path(app).ok_or("No app data directory")?;
+    let json = serde_json::to_vec_pretty(policy).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(path, json).map_err(|e| e.to_string())
+}
+
+fn load_policy(app: &AppHandle) -> Option<RetentionPolicy> {
+    let bytes = std::fs::read(policy_path(app)?).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Writes the history to disk when the policy persists it.
+fn save(app: &AppHandle, history: &ClipboardHistory) -> Result<(), String> {
+    if !history.policy.persist {
+        return Ok(());
+    }
+    let encrypt = history.policy.encrypt;
+    let path = history_path(app, encrypt).ok_or("No app data directory")?;
+    let json = serde_json::to_vec(history).map_err(|e| e.to_string())?;
+    let bytes = if encrypt {
+        protect(&json).map_err(|e| format!("Failed to encrypt clipboard history: {}", e))?
+    } else {
+        json
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(&path, bytes).map_err(|e| format!("Failed to save clipboard history: {}", e))
+}
+
+fn load(app: &AppHandle, policy: &RetentionPolicy) -> Option<ClipboardHistory> {
+    let path = history_path(app, policy.encrypt)?;
+    let bytes = std::fs::read(path).ok()?;
+    let json = if policy.encrypt {
+        unprotect(&bytes).ok()?
+    } else {
+        bytes
+    };
+    serde_json::from_slice(&json).ok()
+}
+
+fn delete_saved(app: &AppHandle) {
+    for encrypted in [true, false] {
+        if let Some(path) = history_path(app, encrypted) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn protect(data: &[u8]) -> Result<Vec<u8>, String> {
+    dpapi_transform(data, true)
+}
+
+#[cfg(target_os = "windows")]
+fn unprotect(data: &[u8]) -> Result<Vec<u8>, String> {
+    dpapi_transform(data, false)
+}
+
+/// Encrypts or decrypts `data` with the current user's DPAPI key.
+#[cfg(target_os = "windows")]
+fn dpapi_transform(data: &[u8], encrypt: bool) -> Result<Vec<u8>, String> {
+    use winapi::um::dpapi::{CryptProtectData, CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN};
+    use winapi::um::winbase::LocalFree;
+    use winapi::um::wincrypt::DATA_BLOB;
+    unsafe {
+        let mut input = DATA_BLOB {
+            cbData: data.len() as u32,
+            pbData: data.as_ptr() as *mut u8,
+        };
+        let mut output: DATA_BLOB = std::mem::zeroed();
+        let ok = if encrypt {
+            CryptProtectData(
+                &mut input,
+                std::ptr::null(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+        } else {
+            CryptUnprotectData(
+                &mut input,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+        };
+        if ok == 0 || output.pbData.is_null() {
+            return Err("DPAPI call failed".into());
+        }
+        let bytes = std::slice::from_raw_parts(output.pbData, output.cbData as usize).to_vec();
+        LocalFree(output.pbData as _);
+        Ok(bytes)
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn protect(_data: &[u8]) -> Result<Vec<u8>, String> {
+    Err("Encrypted persistence is only supported on Windows".into())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn unprotect(_data: &[u8]) -> Result<Vec<u8>, String> {
+    Err("Encrypted persistence is only supported on Windows".into())
+}
+
+/// Brings back the policy set in an earlier session and, when it persists
+/// the history, the saved items. Called once at startup.
+pub fn restore_clipboard_history(app: &AppHandle) {
+    // A policy this platform cannot honour, such as one copied over from
+    // Windows with encryption on, is dropped rather than failing every save
+    let Some(policy) = load_policy(app).filter(|p| p.check().is_ok()) else {
+        return;
+    };
+    let mut history = if policy.persist {
+        load(app, &policy).unwrap_or_default()
+    } else {
+        ClipboardHistory::default()
+    };
+    history.policy = policy;
+    history.apply_retention();
+    if let Ok(mut guard) = HISTORY.lock() {
+        *guard = Some(history);
+    }
+}
+
+pub fn is_clipboard_history_enabled() -> bool {
+    CLIPBOARD_HISTORY_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Records a copied text. Called by the clipboard watcher for every new copy.
+pub fn record_clipboard_text(app: &AppHandle, text: &str) {
+    if !is_clipboard_history_enabled() {
+        return;
+    }
+    let (source_app, source_title) = active_window_source();
+    let Ok(mut guard) = HISTORY.lock() else {
+        return;
+    };
+    let history = guard.get_or_insert_with(ClipboardHistory::default);
+    let id = history.record(text, source_app, source_title);
+    history.apply_retention();
+    if let Err(e) = save(app, history) {
+        println!("{}", e);
+    }
+    let _ = app.emit(
+        "clipboard_history_changed",
+        serde_json::json!({ "addedId": id }),
+    );
+}
+
+#[tauri::command]
+pub fn set_clipboard_history_enabled(app: AppHandle, enabled: bool) {
+    CLIPBOARD_HISTORY_ENABLED.store(enabled, Ordering::Relaxed);
+    if enabled {
+        if let Ok(mut guard) = HISTORY.lock() {
+            guard.get_or_insert_with(ClipboardHistory::default);
+        }
+    }
+    super::chat::refresh_clipboard_watcher(&app);
+}
+
+#[tauri::command]
+pub fn get_clipboard_history_enabled() -> bool {
+    is_clipboard_history_enabled()
+}
+
+#[tauri::command]
+pub fn set_clipboard_history_policy(app: AppHandle, policy: RetentionPolicy) -> Result<(), String> {
+    policy.check()?;
+    let mut guard = HISTORY.lock().map_err(|e| e.to_string())?;
+    let history = guard.get_or_insert_with(ClipboardHistory::default);
+    if policy.persist && !history.policy.persist {
+        // Merge what was saved in an earlier session
+        if let Some(saved) = load(&app, &policy) {
+            history.next_id = history.next_id.max(saved.next_id);
+            for item in saved.items {
+                if !history.items.iter().any(|i| i.text == item.text) {
+                    history.items.push_back(item);
+                }
+            }
+        }
+    }
+    if history.policy.persist != policy.persist || history.policy.encrypt != policy.encrypt {
+        delete_saved(&app);
+    }
+    history.policy = policy;
+    history.apply_retention();
+    // Kept unsaved when the history cannot be written, so the next start
+    // does not claim a persistence that never happened
+    save(&app, history)?;
+    save_policy(&app, &history.policy)
+}
+
+#[tauri::command]
+pub fn get_clipboard_history_policy() -> RetentionPolicy {
+    HISTORY
+        .lock()
+        .ok()
+        .and_then(|h| h.as_ref().map(|h| h.policy.clone()))
+        .unwrap_or_default()
+}
+
+/// Lists history items, pinned ones first, then newest first.
+#[tauri::command]
+pub fn list_clipboard_history(limit: Option<usize>) -> Vec<ClipboardHistoryItem> {
+    let Ok(guard) = HISTORY.lock() else {
+        return Vec::new();
+    };
+    let Some(history) = guard.as_ref() else {
+        return Vec::new();
+    };
+    let mut items: Vec<ClipboardHistoryItem> = history.items.iter().cloned().collect();
+    items.sort_by_key(|i| (!i.pinned, std::cmp::Reverse(i.copied_at)));
+    items.truncate(limit.unwrap_or(usize::MAX));
+    items
+}
+
+/// Fuzzy-searches the history text and source app, best matches first.
+#[tauri::command]
+pub fn search_clipboard_history(query: String, limit: Option<usize>) -> Vec<ClipboardHistoryItem> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return list_clipboard_history(limit);
+    }
+    let mut scored: Vec<(i64, ClipboardHistoryItem)> = list_clipboard_history(None)
+        .into_iter()
+        .filter_map(|item| {
+            let haystack = format!("{} {}", item.text, item.source_app).to_lowercase();
+            fuzzy_score(&query, &haystack).map(|score| (score, item))
+        })
+        .collect();
+    scored.sort_by(|a, b| {
+        b.0.cmp(&a.0)
+            .then_with(|| b.1.copied_at.cmp(&a.1.copied_at))
+    });
+    scored
+        .into_iter()
+        .take(limit.unwrap_or(usize::MAX))
+        .map(|(_, item)| item)
+        .collect()
+}
+
+/// Scores how well `query` matches `haystack`. Substring matches always win;
+/// otherwise every query character must appear in order, with bonuses for
+/// consecutive characters and matches at word starts.
+fn fuzzy_score(query: &str, haystack: &str) -> Option<i64> {
+    if let Some(pos) = haystack.find(query) {
+        return Some(10_000 - pos.min(5_000) as i64);
+    }
+    let hay: Vec<char> = haystack.chars().collect();
+    let mut score = 0i64;
+    let mut hay_idx = 0;
+    let mut last_match: Option<usize> = None;
+    for qc in query.chars() {
+        let found = hay[hay_idx..].iter().position(|&c| c == qc)? + hay_idx;
+        score += 1;
+        if last_match.map(|l| l + 1 == found).unwrap_or(false) {
+            score += 5;
+        }
+        if found == 0 || !hay[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        last_match = Some(found);
+        hay_idx = found + 1;
+    }
+    Some(score)
+}
+
+/// Puts a history item back on the clipboard and, when `hwnd` is given,
+/// pastes it into that window.
+#[tauri::command]
+pub fn paste_clipboard_history_item(id: u64, hwnd: Option<isize>) -> Result<(), String> {
+    let text = list_clipboard_history(None)
+        .into_iter()
+        .find(|i| i.id == id)
+        .map(|i| i.text)
+        .ok_or("Clipboard history item not found")?;
+    paste_text(&text, hwnd)
+}
+
+#[cfg(target_os = "windows")]
+fn paste_text(text: &str, hwnd: Option<isize>) -> Result<(), String> {
+    use super::clipboard::write_clipboard_unicode_text;
+    use super::injection::send_key_combo;
+    use winapi::um::winuser::{SetForegroundWindow, VK_CONTROL};
+    unsafe {
+        if !write_clipboard_unicode_text(text) {
+            return Err("Failed to write to clipboard".into());
+        }
+        if let Some(hwnd) = hwnd {
+            if SetForegroundWindow(hwnd as _) == 0 {
+                return Err("Failed to bring target window to foreground".into());
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            send_key_combo(&[VK_CONTROL as u16], b'V' as u16);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn paste_text(_text: &str, _hwnd: Option<isize>) -> Result<(), String> {
+    Err("Pasting from clipboard history is not supported on this platform".into())
+}
+
+#[tauri::command]
+pub fn pin_clipboard_history_item(app: AppHandle, id: u64, pinned: bool) -> Result<(), String> {
+    let mut guard = HISTORY.lock().map_err(|e| e.to_string())?;
+    let history = guard.as_mut().ok_or("Clipboard history is empty")?;
+    let item = history
+        .items
+        .iter_mut()
+        .find(|i| i.id == id)
+        .ok_or("Clipboard history item not found")?;
+    item.pinned = pinned;
+    history.apply_retention();
+    save(&app, history)
+}
+
+#[tauri::command]
+pub fn delete_clipboard_history_item(app: AppHandle, id: u64) -> Result<(), String> {
+    let mut guard = HISTORY.lock().map_err(|e| e.to_string())?;
+    let history = guard.as_mut().ok_or("Clipboard history is empty")?;
+    let before = history.items.len();
+    history.items.retain(|i| i.id != id);
+    if history.items.len() == before {
+        return Err("Clipboard history item not found".into());
+    }
+    save(&app, history)
+}
+
+/// Clears the history. Pinned items are kept unless `include_pinned` is set.
+#[tauri::command]
+pub fn clear_clipboard_history(app: AppHandle, include_pinned: Option<bool>) -> Result<(), String> {
+    let mut guard = HISTORY.lock().map_err(|e| e.to_string())?;
+    let Some(history) = guard.as_mut() else {
+        return Ok(());
+    };
+    if include_pinned.unwrap_or(false) {
+        history.items.clear();
+    } else {
+        history.items.retain(|i| i.pinned);
+    }
+    save(&app, history)?;
+    if history.items.is_empty() {
+        delete_saved(&app);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(policy: RetentionPolicy) -> ClipboardHistory {
+        ClipboardHistory {
+            policy,
+            ..ClipboardHistory::default()
+        }
+    }
+
+    fn texts(history: &ClipboardHistory) -> Vec<&str> {
+        history.items.iter().map(|i| i.text.as_str()).collect()
+    }
+
+    #[test]
+    fn copying_again_moves_the_entry_to_the_front() {
+        let mut h = history(RetentionPolicy::default());
+        let first = h.record("alpha", "code".into(), String::new());
+        h.record("beta", "code".into(), String::new());
+        let again = h.record("alpha", "chrome".into(), "Docs".into());
+        assert_eq!(again, first);
+        assert_eq!(texts(&h), vec!["alpha", "beta"]);
+        assert_eq!(h.items[0].source_app, "chrome");
+    }
+
+    #[test]
+    fn retention_trims_unpinned_items_only() {
+        let mut h = history(RetentionPolicy {
+            max_items: 2,
+            max_age_hours: None,
+            ..RetentionPolicy::default()
+        });
+        for text in ["one", "two", "three", "four"] {
+            h.record(text, String::new(), String::new());
+        }
+        // "one" is the oldest and would go first
+        h.items.back_mut().unwrap().pinned = true;
+        h.apply_retention();
+        assert_eq!(texts(&h), vec!["four", "three", "one"]);
+    }
+
+    #[test]
+    fn retention_drops_expired_unpinned_items() {
+        let mut h = history(RetentionPolicy {
+            max_age_hours: Some(1),
+            ..RetentionPolicy::default()
+        });
+        for text in ["old", "old pinned", "new"] {
+            h.record(text, String::new(), String::new());
+        }
+        let two_hours_ago = now_millis() - 2 * 60 * 60 * 1000;
+        for item in h.items.iter_mut().filter(|i| i.text.starts_with("old")) {
+            item.copied_at = two_hours_ago;
+            item.pinned = item.text == "old pinned";
+        }
+        h.apply_retention();
+        assert_eq!(texts(&h), vec!["new", "old pinned"]);
+    }
+
+    #[test]
+    fn encryption_is_refused_where_it_cannot_happen() {
+        let encrypted = RetentionPolicy {
+            persist: true,
+            encrypt: true,
+            ..RetentionPolicy::default()
+        };
+        assert_eq!(encrypted.check().is_ok(), cfg!(target_os = "windows"));
+        assert!(RetentionPolicy::default().check().is_ok());
+    }
+
+    #[test]
+    fn substring_matches_win_earliest_first() {
+        let early = fuzzy_score("board", "board game").unwrap();
+        let late = fuzzy_score("board", "clipboard").unwrap();
+        let scattered = fuzzy_score("board", "b o a r d").unwrap();
+        assert!(early > late);
+        assert!(late > scattered);
+    }
+
+    #[test]
+    fn fuzzy_matches_need_characters_in_order() {
+        assert!(fuzzy_score("cb", "clipboard").is_some());
+        assert_eq!(fuzzy_score("bc", "clipboard"), None);
+        assert_eq!(fuzzy_score("xyz", "clipboard"), None);
+    }
+
+    #[test]
+    fn word_starts_and_runs_score_higher() {
+        // "b" starts a word in "copy buffer" but sits mid-word in "clipboard"
+        assert!(fuzzy_score("cb", "copy buffer") > fuzzy_score("cb", "clipboard"));
+        // "cl" runs together in "clip" but not in "cool lamp"
+        assert!(fuzzy_score("clp", "clip") > fuzzy_score("clp", "cool lamp"));
+    }
+}
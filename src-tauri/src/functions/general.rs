@@ -1,29 +1,47 @@
+#[cfg(target_os = "windows")]
+use super::injection::start_formatted_injection;
+use super::markdown::OutputFormat;
+#[cfg(target_os = "windows")]
+use super::markdown::format_for_window;
+#[cfg(target_os = "windows")]
 use crate::platform::{
     exe_path_from_hwnd, get_icon_base64_from_exe, get_packaged_app_icon_from_hwnd,
     get_window_icon_base64_from_hwnd, get_window_title,
 };
+#[cfg(target_os = "windows")]
 use base64::encode;
+#[cfg(target_os = "windows")]
 use image::{DynamicImage, ImageFormat};
+#[cfg(target_os = "windows")]
 use std::collections::HashMap;
+#[cfg(target_os = "windows")]
 use std::io;
+#[cfg(target_os = "windows")]
 use std::os::windows::ffi::OsStrExt;
+#[cfg(target_os = "windows")]
 use std::{thread, time::Duration};
-use tauri::{AppHandle, Emitter};
+use tauri::AppHandle;
+#[cfg(target_os = "windows")]
+use tauri::Emitter;
+#[cfg(target_os = "windows")]
 use winapi::ctypes::c_void;
+#[cfg(target_os = "windows")]
 use winapi::shared::windef::HWND as WinHWND;
+#[cfg(target_os = "windows")]
 use winapi::shared::windef::RECT;
+#[cfg(target_os = "windows")]
 use winapi::um::wingdi::{
     BitBlt, CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject, GetDIBits,
     SelectObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, SRCCOPY,
 };
-use winapi::um::winuser::{FindWindowW, SetForegroundWindow};
+#[cfg(target_os = "windows")]
+use winapi::um::winuser::{FindWindowW, GetForegroundWindow};
+#[cfg(target_os = "windows")]
 use winapi::um::winuser::{
     GetDC, GetSystemMetrics, GetWindowRect, PrintWindow, ReleaseDC, SM_CXSCREEN, SM_CYSCREEN,
 };
-use winapi::um::winuser::{
-    GetForegroundWindow, SendInput, INPUT, INPUT_KEYBOARD, KEYBDINPUT, KEYEVENTF_UNICODE,
-};
 
+#[cfg(target_os = "windows")]
 #[tauri::command]
 pub fn start_window_watch(app: AppHandle) {
     thread::spawn(move || {
@@ -89,8 +107,15 @@ pub fn start_window_watch(app: AppHandle) {
         }
     });
 }
+
+#[cfg(target_os = "windows")]
 #[tauri::command]
-pub fn inject_text_to_window_by_title(text: String, window_title: String) -> Result<(), String> {
+pub fn inject_text_to_window_by_title(
+    app: AppHandle,
+    text: String,
+    window_title: String,
+    format: Option<OutputFormat>,
+) -> Result<u64, String> {
     unsafe {
         let wide_title: Vec<u16> = std::ffi::OsStr::new(&window_title)
             .encode_wide()
@@ -101,40 +126,13 @@ pub fn inject_text_to_window_by_title(text: String, window_title: String) -> Res
         if hwnd.is_null() {
             return Err(format!("Window with title '{}' not found", window_title));
         }
-        inject_text_to_window(text, hwnd)
+        // Answers are Markdown; convert them for the target application
+        let format = format.unwrap_or_else(|| format_for_window(hwnd));
+        start_formatted_injection(&app, &text, format, hwnd)
     }
 }
 
-fn inject_text_to_window(text: String, hwnd: WinHWND) -> Result<(), String> {
-    unsafe {
-        if SetForegroundWindow(hwnd) == 0 {
-            return Err("Failed to bring target window to foreground".into());
-        }
-        std::thread::sleep(std::time::Duration::from_millis(50));
-        for ch in text.chars() {
-            let mut input = INPUT {
-                type_: INPUT_KEYBOARD,
-                u: std::mem::zeroed(),
-            };
-            *input.u.ki_mut() = KEYBDINPUT {
-                wVk: 0,
-                wScan: ch as u16,
-                dwFlags: KEYEVENTF_UNICODE,
-                time: 0,
-                dwExtraInfo: 0,
-            };
-            SendInput(1, &mut input, std::mem::size_of::<INPUT>() as i32);
-        }
-        Ok(())
-    }
-}
-
-#[derive(serde::Serialize)]
-pub struct WindowInfo {
-    pub hwnd: isize,
-    pub title: String,
-}
-
+#[cfg(target_os = "windows")]
 #[tauri::command]
 pub fn capture_window_screenshot() -> Result<String, String> {
     unsafe {
@@ -268,6 +266,7 @@ pub fn capture_window_screenshot() -> Result<String, String> {
     }
 }
 
+#[cfg(target_os = "windows")]
 fn capture_hwnd_to_png_base64(hwnd: WinHWND) -> Result<String, String> {
     unsafe {
         let mut window_rect: RECT = std::mem::zeroed();
@@ -393,6 +392,7 @@ fn capture_hwnd_to_png_base64(hwnd: WinHWND) -> Result<String, String> {
     }
 }
 
+#[cfg(target_os = "windows")]
 #[tauri::command]
 pub fn capture_window_screenshot_by_title(window_title: String) -> Result<String, String> {
     unsafe {
@@ -410,6 +410,7 @@ pub fn capture_window_screenshot_by_title(window_title: String) -> Result<String
     }
 }
 
+#[cfg(target_os = "windows")]
 #[tauri::command]
 pub fn capture_window_screenshot_by_hwnd(hwnd: isize) -> Result<String, String> {
     unsafe {
@@ -420,3 +421,57 @@ pub fn capture_window_screenshot_by_hwnd(hwnd: isize) -> Result<String, String>
         capture_hwnd_to_png_base64(hwnd_ptr)
     }
 }
+
+// Window tracking, injection and screenshots are built on the Windows APIs
+// above; elsewhere the commands exist but report that they are unsupported.
+
+#[cfg(not(target_os = "windows"))]
+#[tauri::command]
+pub fn start_window_watch(_app: AppHandle) {}
+
+#[cfg(not(target_os = "windows"))]
+#[tauri::command]
+pub fn inject_text_to_window_by_title(
+    _app: AppHandle,
+    _text: String,
+    _window_title: String,
+    _format: Option<OutputFormat>,
+) -> Result<u64, String> {
+    Err("Text injection is only supported on Windows".into())
+}
+
+/// Captures the whole X screen. Under Wayland, XWayland only sees its own windows.
+#[cfg(target_os = "linux")]
+#[tauri::command]
+pub fn capture_window_screenshot() -> Result<String, String> {
+    use base64::{engine::general_purpose, Engine as _};
+    let (width, height, rgba) = crate::x11::capture_screen()?;
+    let img = image::RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| "Failed to create image".to_string())?;
+    let mut png_data = Vec::new();
+    image::DynamicImage::ImageRgba8(img)
+        .write_to(&mut std::io::Cursor::new(&mut png_data), image::ImageFormat::Png)
+        .map_err(|_| "Failed to encode PNG".to_string())?;
+    Ok(format!(
+        "data:image/png;base64,{}",
+        general_purpose::STANDARD.encode(&png_data)
+    ))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+#[tauri::command]
+pub fn capture_window_screenshot() -> Result<String, String> {
+    Err("Screenshots are only supported on Windows and X11".into())
+}
+
+#[cfg(not(target_os = "windows"))]
+#[tauri::command]
+pub fn capture_window_screenshot_by_title(_window_title: String) -> Result<String, String> {
+    Err("Screenshots are only supported on Windows".into())
+}
+
+#[cfg(not(target_os = "windows"))]
+#[tauri::command]
+pub fn capture_window_screenshot_by_hwnd(_hwnd: isize) -> Result<String, String> {
+    Err("Screenshots are only supported on Windows".into())
+}
@@ -0,0 +1,60 @@
+//! Minimal client for the Rae generation backend, used by Rust-side features
+//! that need to run a prompt without going through the chat UI.
+
+use reqwest::Client;
+use serde_json::Value;
+use std::sync::Mutex;
+
+const GENERATE_URL: &str = "https://quackback-xwhd.onrender.com/api/generate/msg";
+
+/// Account and model used for Rust-initiated prompts. Set by the frontend
+/// once the user is signed in.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationProfile {
+    pub email: String,
+    pub provider: String,
+    pub model_name: String,
+}
+
+static GENERATION_PROFILE: Mutex<Option<GenerationProfile>> = Mutex::new(None);
+
+#[tauri::command]
+pub fn set_generation_profile(profile: GenerationProfile) -> Result<(), String> {
+    *GENERATION_PROFILE.lock().map_err(|e| e.to_string())? = Some(profile);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_generation_profile() -> Option<GenerationProfile> {
+    GENERATION_PROFILE.lock().ok().and_then(|p| p.clone())
+}
+
+/// Sends a single prompt as a new conversation and returns the answer text.
+pub async fn generate(prompt: &str) -> Result<String, String> {
+    let profile = get_generation_profile().ok_or("No generation profile configured")?;
+
+    let body = serde_json::json!({
+        "email": profile.email,
+        "message": prompt,
+        "newConvo": true,
+        "conversationId": -1,
+        "provider": profile.provider,
+        "modelName": profile.model_name,
+        "image": "",
+        "tool": 0
+    });
+
+    let res = Client::new()
+        .post(GENERATE_URL)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let json: Value = res.json().await.map_err(|e| e.to_string())?;
+    json.get("aiResponse")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .ok_or_else(|| "No aiResponse in response".to_string())
+}
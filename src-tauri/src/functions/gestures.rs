@@ -0,0 +1,1005 @@
+//! Summon gestures besides the global shortcuts: hot corners and edges,
+//! double-tapping a modifier, and shaking the cursor.
+//!
+//! [`GestureDetector`] is a pure state machine fed with timestamped pointer,
+//! modifier and key events, like [`super::selection_gesture`]. The watcher
+//! polls the pointer and the modifier keys, takes other key presses from
+//! [`super::keyboard`] while a double-tap is configured, and runs the action
+//! configured for each gesture.
+
+use super::keyboard::{self, KeyKind};
+use super::secure_input::{ensure_secure_input_monitor_started, watchers_paused};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+static GESTURE_WATCHER_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// How often the pointer and the modifiers are sampled.
+const POLL_INTERVAL: Duration = Duration::from_millis(15);
+/// How often the monitor layout is re-read.
+const MONITOR_REFRESH: Duration = Duration::from_secs(2);
+
+/// What a gesture does once detected.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GestureAction {
+    ShowOverlayCenter,
+    ToggleMagicDot,
+    /// Takes a screenshot of the screen, then opens the overlay to ask about it.
+    CaptureAndAsk,
+}
+
+/// A corner or a whole edge of a monitor.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScreenArea {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HotCornerConfig {
+    pub area: ScreenArea,
+    /// Monitor name as the OS reports it; `None` watches every monitor.
+    pub monitor: Option<String>,
+    /// How far from the edge the area reaches, in physical pixels.
+    pub size_px: u32,
+    /// How long the pointer must rest in the area.
+    pub dwell_ms: u64,
+    pub action: GestureAction,
+}
+
+impl Default for HotCornerConfig {
+    fn default() -> Self {
+        Self {
+            area: ScreenArea::TopRight,
+            monitor: None,
+            size_px: 4,
+            dwell_ms: 300,
+            action: GestureAction::ShowOverlayCenter,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Modifier {
+    Control,
+    Alt,
+    Shift,
+    /// The Windows or Super key.
+    Meta,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DoubleTapConfig {
+    pub enabled: bool,
+    pub modifier: Modifier,
+    /// Longest a tap may be held; longer presses are shortcuts in the making.
+    pub max_hold_ms: u64,
+    /// Longest gap between releasing the first tap and pressing the second.
+    pub max_interval_ms: u64,
+    pub action: GestureAction,
+}
+
+impl Default for DoubleTapConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            modifier: Modifier::Control,
+            max_hold_ms: 200,
+            max_interval_ms: 350,
+            action: GestureAction::ToggleMagicDot,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ShakeConfig {
+    pub enabled: bool,
+    /// Direction changes needed within `window_ms`.
+    pub min_reversals: u32,
+    pub window_ms: u64,
+    /// Shortest swing between two direction changes, so jitter does not count.
+    pub min_swing_px: u32,
+    pub action: GestureAction,
+}
+
+impl Default for ShakeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_reversals: 4,
+            window_ms: 700,
+            min_swing_px: 60,
+            action: GestureAction::ShowOverlayCenter,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct GestureConfig {
+    pub hot_corners: Vec<HotCornerConfig>,
+    pub double_tap: DoubleTapConfig,
+    pub shake: ShakeConfig,
+    /// Quiet time after any gesture, so one movement does not fire twice.
+    pub cooldown_ms: u64,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            hot_corners: Vec::new(),
+            double_tap: DoubleTapConfig::default(),
+            shake: ShakeConfig::default(),
+            cooldown_ms: 1000,
+        }
+    }
+}
+
+impl GestureConfig {
+    fn any_enabled(&self) -> bool {
+        !self.hot_corners.is_empty() || self.double_tap.enabled || self.shake.enabled
+    }
+
+    pub fn action_for(&self, gesture: Gesture) -> Option<GestureAction> {
+        match gesture {
+            Gesture::HotCorner { index } => self.hot_corners.get(index).map(|c| c.action),
+            Gesture::DoubleTap { .. } => Some(self.double_tap.action),
+            Gesture::Shake => Some(self.shake.action),
+        }
+    }
+}
+
+fn gesture_config() -> &'static Mutex<GestureConfig> {
+    static CONFIG: OnceLock<Mutex<GestureConfig>> = OnceLock::new();
+    CONFIG.get_or_init(|| Mutex::new(GestureConfig::default()))
+}
+
+#[tauri::command]
+pub fn set_gesture_config(app: AppHandle, config: GestureConfig) -> Result<(), String> {
+    let wanted = config.any_enabled();
+    *gesture_config().lock().map_err(|e| e.to_string())? = config;
+    if wanted {
+        ensure_gesture_watcher_started(&app);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_gesture_config() -> GestureConfig {
+    gesture_config()
+        .lock()
+        .map(|c| c.clone())
+        .unwrap_or_default()
+}
+
+/// A monitor's bounds in physical pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonitorRect {
+    pub name: Option<String>,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl MonitorRect {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    fn area_contains(&self, area: ScreenArea, size: i32, x: i32, y: i32) -> bool {
+        if !self.contains(x, y) {
+            return false;
+        }
+        let top = y < self.y + size;
+        let bottom = y >= self.y + self.height - size;
+        let left = x < self.x + size;
+        let right = x >= self.x + self.width - size;
+        match area {
+            ScreenArea::TopLeft => top && left,
+            ScreenArea::TopRight => top && right,
+            ScreenArea::BottomLeft => bottom && left,
+            ScreenArea::BottomRight => bottom && right,
+            ScreenArea::Top => top,
+            ScreenArea::Bottom => bottom,
+            ScreenArea::Left => left,
+            ScreenArea::Right => right,
+        }
+    }
+}
+
+/// Input for the detector. `at` is a monotonic timestamp in milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GestureEvent {
+    PointerMoved {
+        x: i32,
+        y: i32,
+        at: u64,
+    },
+    ModifierDown {
+        modifier: Modifier,
+        at: u64,
+    },
+    ModifierUp {
+        modifier: Modifier,
+        at: u64,
+    },
+    /// Any other key or a mouse button, which turns a modifier tap into a chord.
+    OtherInput {
+        at: u64,
+    },
+    Tick {
+        at: u64,
+    },
+}
+
+impl GestureEvent {
+    fn at(&self) -> u64 {
+        match *self {
+            GestureEvent::PointerMoved { at, .. }
+            | GestureEvent::ModifierDown { at, .. }
+            | GestureEvent::ModifierUp { at, .. }
+            | GestureEvent::OtherInput { at }
+            | GestureEvent::Tick { at } => at,
+        }
+    }
+}
+
+/// A detected gesture, sent with `gesture_detected`.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum Gesture {
+    /// The pointer rested in `hot_corners[index]`.
+    HotCorner {
+        index: usize,
+    },
+    DoubleTap {
+        modifier: Modifier,
+    },
+    Shake,
+}
+
+/// Where the current modifier tap sequence stands.
+#[derive(Clone, Copy)]
+enum Tap {
+    Idle,
+    FirstDown { at: u64 },
+    FirstUp { at: u64 },
+    SecondDown { at: u64 },
+}
+
+/// Direction changes of the pointer along one axis.
+#[derive(Default)]
+struct Swings {
+    last: Option<i32>,
+    /// +1 or -1 while moving, 0 before the first move.
+    direction: i32,
+    /// Where the current swing started.
+    swing_start: i32,
+    reversals: Vec<u64>,
+}
+
+impl Swings {
+    fn moved(&mut self, position: i32, at: u64, min_swing: i32) {
+        let Some(last) = self.last.replace(position) else {
+            self.swing_start = position;
+            return;
+        };
+        let direction = (position - last).signum();
+        if direction == 0 {
+            return;
+        }
+        if direction != self.direction {
+            if self.direction != 0 {
+                // `last` is the turning point; the swing that ended there must be long
+                if (last - self.swing_start).abs() >= min_swing {
+                    self.reversals.push(at);
+                }
+                self.swing_start = last;
+            }
+            self.direction = direction;
+        }
+    }
+
+    fn count_since(&mut self, since: u64) -> usize {
+        self.reversals.retain(|&at| at >= since);
+        self.reversals.len()
+    }
+}
+
+pub struct GestureDetector {
+    config: GestureConfig,
+    monitors: Vec<MonitorRect>,
+    /// Hot corner the pointer is in and since when; `fired` once it triggered.
+    corner: Option<(usize, u64, bool)>,
+    tap: Tap,
+    /// Modifiers other than the configured one that are held.
+    other_modifiers: Vec<Modifier>,
+    swings_x: Swings,
+    swings_y: Swings,
+    cooldown_until: u64,
+}
+
+impl GestureDetector {
+    pub fn new(config: GestureConfig, monitors: Vec<MonitorRect>) -> Self {
+        Self {
+            config,
+            monitors,
+            corner: None,
+            tap: Tap::Idle,
+            other_modifiers: Vec::new(),
+            swings_x: Swings::default(),
+            swings_y: Swings::default(),
+            cooldown_until: 0,
+        }
+    }
+
+    pub fn set_config(&mut self, config: GestureConfig) {
+        if config != self.config {
+            *self = Self::new(config, std::mem::take(&mut self.monitors));
+        }
+    }
+
+    pub fn set_monitors(&mut self, monitors: Vec<MonitorRect>) {
+        self.monitors = monitors;
+    }
+
+    /// Feeds one event and returns a gesture once one completes.
+    pub fn handle(&mut self, event: GestureEvent) -> Option<Gesture> {
+        let at = event.at();
+        let gesture = match event {
+            GestureEvent::PointerMoved { x, y, at } => {
+                self.enter_corner(x, y, at);
+                self.shake(x, y, at)
+            }
+            GestureEvent::ModifierDown { modifier, at } => {
+                self.modifier_down(modifier, at);
+                None
+            }
+            GestureEvent::ModifierUp { modifier, at } => self.modifier_up(modifier, at),
+            GestureEvent::OtherInput { .. } => {
+                self.tap = Tap::Idle;
+                None
+            }
+            GestureEvent::Tick { .. } => None,
+        };
+        let gesture = gesture.or_else(|| self.dwell(at));
+        if at < self.cooldown_until {
+            return None;
+        }
+        if gesture.is_some() {
+            self.cooldown_until = at + self.config.cooldown_ms;
+        }
+        gesture
+    }
+
+    fn corner_at(&self, x: i32, y: i32) -> Option<usize> {
+        self.config.hot_corners.iter().position(|corner| {
+            let size = corner.size_px.max(1) as i32;
+            self.monitors
+                .iter()
+                .filter(|m| corner.monitor.is_none() || corner.monitor == m.name)
+                .any(|m| m.area_contains(corner.area, size, x, y))
+        })
+    }
+
+    fn enter_corner(&mut self, x: i32, y: i32, at: u64) {
+        let index = self.corner_at(x, y);
+        match (index, self.corner) {
+            (Some(index), Some((current, ..))) if index == current => {}
+            (Some(index), _) => self.corner = Some((index, at, false)),
+            (None, _) => self.corner = None,
+        }
+    }
+
+    fn dwell(&mut self, now: u64) -> Option<Gesture> {
+        let (index, since, fired) = self.corner.as_mut()?;
+        let dwell_ms = self.config.hot_corners.get(*index)?.dwell_ms;
+        if *fired || now < *since + dwell_ms {
+            return None;
+        }
+        // Once per visit; the pointer has to leave before it fires again
+        *fired = true;
+        Some(Gesture::HotCorner { index: *index })
+    }
+
+    fn modifier_down(&mut self, modifier: Modifier, at: u64) {
+        let tap = &self.config.double_tap;
+        if !tap.enabled {
+            return;
+        }
+        if modifier != tap.modifier {
+            self.other_modifiers.push(modifier);
+            self.tap = Tap::Idle;
+            return;
+        }
+        if !self.other_modifiers.is_empty() {
+            self.tap = Tap::Idle;
+            return;
+        }
+        self.tap = match self.tap {
+            Tap::FirstUp { at: released } if at.saturating_sub(released) <= tap.max_interval_ms => {
+                Tap::SecondDown { at }
+            }
+            _ => Tap::FirstDown { at },
+        };
+    }
+
+    fn modifier_up(&mut self, modifier: Modifier, at: u64) -> Option<Gesture> {
+        let tap = self.config.double_tap;
+        if modifier != tap.modifier {
+            self.other_modifiers.retain(|&m| m != modifier);
+            return None;
+        }
+        let (pressed, second) = match self.tap {
+            Tap::FirstDown { at } => (at, false),
+            Tap::SecondDown { at } => (at, true),
+            _ => return None,
+        };
+        if at.saturating_sub(pressed) > tap.max_hold_ms {
+            self.tap = Tap::Idle;
+            return None;
+        }
+        if second {
+            self.tap = Tap::Idle;
+            Some(Gesture::DoubleTap { modifier })
+        } else {
+            self.tap = Tap::FirstUp { at };
+            None
+        }
+    }
+
+    fn shake(&mut self, x: i32, y: i32, at: u64) -> Option<Gesture> {
+        let shake = self.config.shake;
+        if !shake.enabled {
+            return None;
+        }
+        let min_swing = shake.min_swing_px as i32;
+        self.swings_x.moved(x, at, min_swing);
+        self.swings_y.moved(y, at, min_swing);
+        let since = at.saturating_sub(shake.window_ms);
+        let reversals = self
+            .swings_x
+            .count_since(since)
+            .max(self.swings_y.count_since(since));
+        if reversals < shake.min_reversals.max(1) as usize {
+            return None;
+        }
+        self.swings_x.reversals.clear();
+        self.swings_y.reversals.clear();
+        Some(Gesture::Shake)
+    }
+}
+
+/// Runs `action` for a detected gesture.
+fn run_action(app: &AppHandle, action: GestureAction) {
+    match action {
+        GestureAction::ShowOverlayCenter => super::overlay::show_overlay_center(app.clone()),
+        GestureAction::ToggleMagicDot => super::overlay::toggle_magic_dot(app.clone()),
+        GestureAction::CaptureAndAsk => {
+            // Before the overlay shows, so it is not in the picture
+            let (image, error) = match super::general::capture_window_screenshot() {
+                Ok(image) => (Some(image), None),
+                Err(e) => (None, Some(e)),
+            };
+            super::overlay::show_overlay_center(app.clone());
+            let _ = app.emit(
+                "capture_and_ask",
+                serde_json::json!({ "image": image, "error": error }),
+            );
+        }
+    }
+}
+
+fn current_monitors(app: &AppHandle) -> Vec<MonitorRect> {
+    app.available_monitors()
+        .map(|monitors| {
+            monitors
+                .iter()
+                .map(|m| MonitorRect {
+                    name: m.name().cloned(),
+                    x: m.position().x,
+                    y: m.position().y,
+                    width: m.size().width as i32,
+                    height: m.size().height as i32,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Pointer position, held modifiers and whether a mouse button is down.
+struct InputSample {
+    pointer: Option<(i32, i32)>,
+    held: Vec<Modifier>,
+    buttons_down: bool,
+}
+
+#[cfg(target_os = "windows")]
+struct InputPoller;
+
+#[cfg(target_os = "windows")]
+impl InputPoller {
+    fn new() -> Result<Self, String> {
+        Ok(Self)
+    }
+
+    fn poll(&self) -> InputSample {
+        use winapi::shared::windef::POINT;
+        use winapi::um::winuser::{
+            GetAsyncKeyState, GetCursorPos, VK_CONTROL, VK_LBUTTON, VK_LWIN, VK_MBUTTON, VK_MENU,
+            VK_RBUTTON, VK_RWIN, VK_SHIFT,
+        };
+        let is_down = |vk: i32| unsafe { (GetAsyncKeyState(vk) as u16 & 0x8000u16) != 0 };
+        let mut held = Vec::new();
+        for (vks, modifier) in [
+            (&[VK_CONTROL][..], Modifier::Control),
+            (&[VK_MENU][..], Modifier::Alt),
+            (&[VK_SHIFT][..], Modifier::Shift),
+            (&[VK_LWIN, VK_RWIN][..], Modifier::Meta),
+        ] {
+            if vks.iter().any(|&vk| is_down(vk)) {
+                held.push(modifier);
+            }
+        }
+        let buttons_down = [VK_LBUTTON, VK_RBUTTON, VK_MBUTTON]
+            .iter()
+            .any(|&vk| is_down(vk));
+        let mut point = POINT { x: 0, y: 0 };
+        let pointer = (unsafe { GetCursorPos(&mut point) } != 0).then_some((point.x, point.y));
+        InputSample {
+            pointer,
+            held,
+            buttons_down,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+struct InputPoller(crate::x11::PointerPoller);
+
+#[cfg(target_os = "linux")]
+impl InputPoller {
+    fn new() -> Result<Self, String> {
+        crate::x11::PointerPoller::new().map(Self)
+    }
+
+    fn poll(&self) -> InputSample {
+        let Some(sample) = self.0.sample() else {
+            return InputSample {
+                pointer: None,
+                held: Vec::new(),
+                buttons_down: false,
+            };
+        };
+        let held = [
+            (sample.control, Modifier::Control),
+            (sample.alt, Modifier::Alt),
+            (sample.shift, Modifier::Shift),
+            (sample.super_key, Modifier::Meta),
+        ]
+        .into_iter()
+        .filter_map(|(down, modifier)| down.then_some(modifier))
+        .collect();
+        InputSample {
+            pointer: Some((sample.x, sample.y)),
+            held,
+            buttons_down: sample.buttons_down,
+        }
+    }
+}
+
+fn ensure_gesture_watcher_started(app: &AppHandle) {
+    if GESTURE_WATCHER_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    ensure_secure_input_monitor_started(app);
+    let app_handle = app.clone();
+    std::thread::spawn(move || {
+        let input = match InputPoller::new() {
+            Ok(poller) => poller,
+            Err(e) => {
+                println!("Gesture watcher unavailable: {}", e);
+                GESTURE_WATCHER_RUNNING.store(false, Ordering::SeqCst);
+                return;
+            }
+        };
+        // Key presses only matter for telling double-taps from shortcuts
+        let mut keys = None;
+        let started = Instant::now();
+        let mut detector =
+            GestureDetector::new(get_gesture_config(), current_monitors(&app_handle));
+        let mut monitors_read_at = Instant::now();
+        let mut held: Vec<Modifier> = Vec::new();
+        let mut buttons_down = false;
+        let mut last_pointer = None;
+        loop {
+            let config = get_gesture_config();
+            if !config.any_enabled() {
+                break;
+            }
+            detector.set_config(config.clone());
+            if config.double_tap.enabled != keys.is_some() {
+                keys = match keys.take() {
+                    Some((subscription, _)) => {
+                        keyboard::unsubscribe(subscription);
+                        None
+                    }
+                    None => Some(keyboard::subscribe()),
+                };
+            }
+            if monitors_read_at.elapsed() >= MONITOR_REFRESH {
+                detector.set_monitors(current_monitors(&app_handle));
+                monitors_read_at = Instant::now();
+            }
+            let at = started.elapsed().as_millis() as u64;
+            let mut events = Vec::new();
+
+            // Typing between two taps makes them part of a shortcut
+            let typed = keys.as_ref().is_some_and(|(_, keys)| {
+                keys.try_iter()
+                    .filter(|stroke| stroke.key != KeyKind::Modifier)
+                    .count()
+                    > 0
+            });
+            let sample = input.poll();
+            if typed || watchers_paused() || (sample.buttons_down && !buttons_down) {
+                events.push(GestureEvent::OtherInput { at });
+            }
+            buttons_down = sample.buttons_down;
+            for &modifier in held.iter().filter(|m| !sample.held.contains(m)) {
+                events.push(GestureEvent::ModifierUp { modifier, at });
+            }
+            for &modifier in sample.held.iter().filter(|m| !held.contains(m)) {
+                events.push(GestureEvent::ModifierDown { modifier, at });
+            }
+            held = sample.held;
+
+            if let Some((x, y)) = sample.pointer.filter(|&p| last_pointer != Some(p)) {
+                last_pointer = Some((x, y));
+                events.push(GestureEvent::PointerMoved { x, y, at });
+            }
+            events.push(GestureEvent::Tick { at });
+
+            for event in events {
+                let Some(gesture) = detector.handle(event) else {
+                    continue;
+                };
+                let Some(action) = config.action_for(gesture) else {
+                    continue;
+                };
+                let _ = app_handle.emit(
+                    "gesture_detected",
+                    serde_json::json!({ "gesture": gesture, "action": action }),
+                );
+                run_action(&app_handle, action);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        if let Some((subscription, _)) = keys {
+            keyboard::unsubscribe(subscription);
+        }
+        GESTURE_WATCHER_RUNNING.store(false, Ordering::SeqCst);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use GestureEvent::*;
+
+    fn monitors() -> Vec<MonitorRect> {
+        vec![
+            MonitorRect {
+                name: Some("primary".to_string()),
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+            },
+            MonitorRect {
+                name: Some("side".to_string()),
+                x: 1920,
+                y: 0,
+                width: 1280,
+                height: 1024,
+            },
+        ]
+    }
+
+    fn detector(config: GestureConfig) -> GestureDetector {
+        GestureDetector::new(config, monitors())
+    }
+
+    fn corner(area: ScreenArea, monitor: Option<&str>) -> GestureConfig {
+        GestureConfig {
+            hot_corners: vec![HotCornerConfig {
+                area,
+                monitor: monitor.map(str::to_string),
+                ..HotCornerConfig::default()
+            }],
+            ..GestureConfig::default()
+        }
+    }
+
+    fn double_tap() -> GestureConfig {
+        GestureConfig {
+            double_tap: DoubleTapConfig {
+                enabled: true,
+                ..DoubleTapConfig::default()
+            },
+            ..GestureConfig::default()
+        }
+    }
+
+    fn shake() -> GestureConfig {
+        GestureConfig {
+            shake: ShakeConfig {
+                enabled: true,
+                ..ShakeConfig::default()
+            },
+            ..GestureConfig::default()
+        }
+    }
+
+    fn run(detector: &mut GestureDetector, events: &[GestureEvent]) -> Vec<Gesture> {
+        events.iter().filter_map(|e| detector.handle(*e)).collect()
+    }
+
+    /// Pointer positions swinging between `from` and `to` along x, one sample
+    /// every 15 ms with `steps` samples per swing.
+    fn swings(from: i32, to: i32, count: usize, steps: i32, y: i32) -> Vec<GestureEvent> {
+        let mut events = Vec::new();
+        let mut at = 0;
+        for swing in 0..count {
+            let (a, b) = if swing % 2 == 0 {
+                (from, to)
+            } else {
+                (to, from)
+            };
+            for step in 1..=steps {
+                at += 15;
+                let x = a + (b - a) * step / steps;
+                events.push(PointerMoved { x, y, at });
+            }
+        }
+        events
+    }
+
+    fn tap(modifier: Modifier, down: u64, up: u64) -> [GestureEvent; 2] {
+        [
+            ModifierDown { modifier, at: down },
+            ModifierUp { modifier, at: up },
+        ]
+    }
+
+    #[test]
+    fn hot_corner_fires_after_dwell() {
+        let mut d = detector(corner(ScreenArea::TopRight, None));
+        let events = [
+            PointerMoved {
+                x: 1900,
+                y: 40,
+                at: 0,
+            },
+            PointerMoved {
+                x: 1919,
+                y: 0,
+                at: 100,
+            },
+            Tick { at: 300 },
+            Tick { at: 400 },
+            Tick { at: 900 },
+        ];
+        assert_eq!(run(&mut d, &events), vec![Gesture::HotCorner { index: 0 }]);
+    }
+
+    #[test]
+    fn hot_corner_ignores_passing_pointer() {
+        let mut d = detector(corner(ScreenArea::TopRight, None));
+        let events = [
+            PointerMoved {
+                x: 1919,
+                y: 0,
+                at: 0,
+            },
+            PointerMoved {
+                x: 1800,
+                y: 200,
+                at: 150,
+            },
+            Tick { at: 1000 },
+        ];
+        assert!(run(&mut d, &events).is_empty());
+    }
+
+    #[test]
+    fn hot_corner_fires_again_only_after_leaving() {
+        let mut d = detector(GestureConfig {
+            cooldown_ms: 0,
+            ..corner(ScreenArea::TopLeft, None)
+        });
+        let events = [
+            PointerMoved { x: 0, y: 0, at: 0 },
+            Tick { at: 400 },
+            Tick { at: 2000 },
+            PointerMoved {
+                x: 300,
+                y: 300,
+                at: 2100,
+            },
+            PointerMoved {
+                x: 1,
+                y: 1,
+                at: 2200,
+            },
+            Tick { at: 2600 },
+        ];
+        assert_eq!(run(&mut d, &events).len(), 2);
+    }
+
+    #[test]
+    fn hot_corner_works_on_every_monitor() {
+        let mut d = detector(corner(ScreenArea::BottomRight, None));
+        let events = [
+            PointerMoved {
+                x: 3199,
+                y: 1023,
+                at: 0,
+            },
+            Tick { at: 400 },
+        ];
+        assert_eq!(run(&mut d, &events), vec![Gesture::HotCorner { index: 0 }]);
+
+        // Only the side monitor is watched, so the primary one's corner does nothing
+        let mut d = detector(corner(ScreenArea::BottomRight, Some("side")));
+        let events = [
+            PointerMoved {
+                x: 1919,
+                y: 1079,
+                at: 0,
+            },
+            Tick { at: 400 },
+        ];
+        assert!(run(&mut d, &events).is_empty());
+    }
+
+    #[test]
+    fn hot_edge_covers_the_whole_side() {
+        let mut d = detector(corner(ScreenArea::Left, Some("primary")));
+        let events = [
+            PointerMoved {
+                x: 0,
+                y: 540,
+                at: 0,
+            },
+            Tick { at: 400 },
+        ];
+        assert_eq!(run(&mut d, &events), vec![Gesture::HotCorner { index: 0 }]);
+    }
+
+    #[test]
+    fn double_tap_fires_on_second_release() {
+        let mut d = detector(double_tap());
+        let mut events = tap(Modifier::Control, 0, 80).to_vec();
+        events.extend(tap(Modifier::Control, 250, 320));
+        assert_eq!(
+            run(&mut d, &events),
+            vec![Gesture::DoubleTap {
+                modifier: Modifier::Control
+            }]
+        );
+    }
+
+    #[test]
+    fn slow_taps_do_not_count() {
+        let mut d = detector(double_tap());
+        let mut events = tap(Modifier::Control, 0, 80).to_vec();
+        events.extend(tap(Modifier::Control, 800, 880));
+        assert!(run(&mut d, &events).is_empty());
+    }
+
+    #[test]
+    fn held_modifier_is_not_a_tap() {
+        let mut d = detector(double_tap());
+        let mut events = tap(Modifier::Control, 0, 600).to_vec();
+        events.extend(tap(Modifier::Control, 700, 760));
+        assert!(run(&mut d, &events).is_empty());
+    }
+
+    #[test]
+    fn shortcuts_between_taps_cancel() {
+        let mut d = detector(double_tap());
+        let mut events = vec![
+            ModifierDown {
+                modifier: Modifier::Control,
+                at: 0,
+            },
+            OtherInput { at: 40 },
+            ModifierUp {
+                modifier: Modifier::Control,
+                at: 90,
+            },
+        ];
+        events.extend(tap(Modifier::Control, 200, 260));
+        assert!(run(&mut d, &events).is_empty());
+
+        // Ctrl tapped twice while Shift is held is Ctrl+Shift, not a double tap
+        let mut d = detector(double_tap());
+        let mut events = vec![ModifierDown {
+            modifier: Modifier::Shift,
+            at: 0,
+        }];
+        events.extend(tap(Modifier::Control, 50, 100));
+        events.extend(tap(Modifier::Control, 200, 260));
+        assert!(run(&mut d, &events).is_empty());
+    }
+
+    #[test]
+    fn triple_tap_fires_once() {
+        let mut d = detector(double_tap());
+        let mut events = tap(Modifier::Control, 0, 60).to_vec();
+        events.extend(tap(Modifier::Control, 150, 210));
+        events.extend(tap(Modifier::Control, 300, 360));
+        assert_eq!(run(&mut d, &events).len(), 1);
+    }
+
+    #[test]
+    fn other_modifier_taps_do_not_count() {
+        let mut d = detector(double_tap());
+        let mut events = tap(Modifier::Alt, 0, 60).to_vec();
+        events.extend(tap(Modifier::Alt, 150, 210));
+        assert!(run(&mut d, &events).is_empty());
+    }
+
+    #[test]
+    fn quick_shake_fires() {
+        let mut d = detector(shake());
+        let events = swings(500, 700, 6, 4, 500);
+        assert_eq!(run(&mut d, &events), vec![Gesture::Shake]);
+    }
+
+    #[test]
+    fn slow_back_and_forth_is_not_a_shake() {
+        let mut d = detector(shake());
+        let events = swings(500, 700, 6, 20, 500);
+        assert!(run(&mut d, &events).is_empty());
+    }
+
+    #[test]
+    fn jitter_is_not_a_shake() {
+        let mut d = detector(shake());
+        let events = swings(500, 520, 12, 2, 500);
+        assert!(run(&mut d, &events).is_empty());
+    }
+
+    #[test]
+    fn cooldown_swallows_repeats() {
+        let mut d = detector(shake());
+        let events = swings(500, 700, 12, 4, 500);
+        assert_eq!(run(&mut d, &events), vec![Gesture::Shake]);
+    }
+
+    #[test]
+    fn disabled_gestures_never_fire() {
+        let mut d = detector(GestureConfig::default());
+        let mut events = swings(500, 700, 8, 4, 0);
+        events.extend(tap(Modifier::Control, 2000, 2060));
+        events.extend(tap(Modifier::Control, 2150, 2210));
+        assert!(run(&mut d, &events).is_empty());
+    }
+}
@@ -0,0 +1,839 @@
+//! Whether the user is typing into an input method composition.
+//!
+//! While an IME composes (Japanese, Chinese, Korean and similar), key presses
+//! are raw material for the IME rather than text: `r`, `a`, `e` may commit as
+//! `ら` + `え` or nothing at all. Key strokes typed into a composition are
+//! marked as composing so triggers only match text that reaches the field
+//! directly; where the committed text can be read it is published instead.
+//!
+//! Having an input method switched on is not enough: Pinyin in English mode
+//! or Mozc in direct input pass keys straight through. A key only counts as
+//! composing while the IME shows a preedit, and since the preedit appears
+//! after the key that opened it, keys typed while an IME is on are held back
+//! for [`SETTLE_MS`] before they are published (see [`Settler`]).
+//!
+//! On Windows the IME WinEvents tell when a composition is shown and hidden;
+//! they are delivered through the keyboard hook thread's message loop, so the
+//! hook itself never waits on another process. The committed text stays in the
+//! other process. On Linux the Fcitx or IBus daemon (behind both XIM and the
+//! toolkit IM modules) is asked over D-Bus whether an input method is on, and
+//! its preedit and commit signals are followed on the session or IBus bus.
+
+use super::keyboard::{KeyKind, KeyStroke};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How long a key typed while an input method is on waits for the IME to
+/// show the composition it may have opened.
+const SETTLE_MS: u64 = 40;
+
+/// What the keyboard watcher knows about the input method when a key arrives.
+#[cfg_attr(not(any(target_os = "windows", target_os = "linux")), allow(dead_code))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ImeState {
+    /// No composing input method is on; keys are text.
+    Off,
+    /// An input method is on and its preedit is followed.
+    Followed,
+    /// An input method is on but its preedit cannot be followed, so every key
+    /// is taken as composing.
+    Unfollowed,
+}
+
+struct Held {
+    stroke: KeyStroke,
+    /// Clock time from which the stroke may be published.
+    due: u64,
+    /// Whether a composition shown before `due` swallowed the key.
+    may_compose: bool,
+}
+
+/// Puts key strokes and preedit changes into order. Keys that an input method
+/// could take are held until [`SETTLE_MS`] after they arrived; if a preedit
+/// shows up meanwhile, they opened or continued a composition. Keys behind a
+/// held one wait too, so subscribers see strokes in the order typed. Times are
+/// milliseconds on one clock shared by keys and IME events.
+pub(crate) struct Settler {
+    held: VecDeque<Held>,
+    preedit: bool,
+    /// When a preedit was last shown; a commit arriving right after the
+    /// preedit hid still ends that composition.
+    preedit_seen: Option<u64>,
+    /// Key clock of the last stroke, given to committed text.
+    last_at: u64,
+}
+
+impl Settler {
+    pub(crate) const fn new() -> Self {
+        Settler {
+            held: VecDeque::new(),
+            preedit: false,
+            preedit_seen: None,
+            last_at: 0,
+        }
+    }
+
+    /// Takes a key that arrived at `now`. `may_compose` says an input method
+    /// is on and could take the key.
+    pub(crate) fn key(&mut self, mut stroke: KeyStroke, may_compose: bool, now: u64) {
+        let may_compose = may_compose && stroke.key != KeyKind::Modifier;
+        if may_compose && self.preedit {
+            stroke.composing = true;
+        }
+        let due = if may_compose && stroke.key == KeyKind::Character {
+            now + SETTLE_MS
+        } else {
+            now
+        };
+        self.last_at = stroke.at;
+        self.held.push_back(Held {
+            stroke,
+            due,
+            may_compose,
+        });
+    }
+
+    /// The IME showed a non-empty preedit or hid it.
+    pub(crate) fn preedit(&mut self, shown: bool, now: u64) {
+        if shown {
+            // The composition was opened by a key still held back
+            for held in self.held.iter_mut().filter(|h| h.may_compose) {
+                held.stroke.composing = true;
+            }
+        }
+        if shown || self.preedit {
+            self.preedit_seen = Some(now);
+        }
+        self.preedit = shown;
+    }
+
+    /// The IME committed `text`. Only text that ends a composition is
+    /// published; other commits echo a key that was already published.
+    pub(crate) fn commit(&mut self, text: &str, now: u64) {
+        let ends_composition = self.preedit
+            || self
+                .preedit_seen
+                .is_some_and(|seen| now.saturating_sub(seen) <= SETTLE_MS);
+        if !ends_composition || text.is_empty() {
+            return;
+        }
+        self.preedit_seen = None;
+        self.held.push_back(Held {
+            stroke: KeyStroke {
+                key: KeyKind::Character,
+                text: text.to_string(),
+                ctrl: false,
+                alt: false,
+                injected: false,
+                composing: false,
+                at: self.last_at,
+            },
+            due: now,
+            may_compose: false,
+        });
+    }
+
+    /// Strokes that may be published at `now`, in the order they arrived.
+    pub(crate) fn drain(&mut self, now: u64) -> Vec<KeyStroke> {
+        let mut ready = Vec::new();
+        while self.held.front().is_some_and(|held| held.due <= now) {
+            ready.extend(self.held.pop_front().map(|held| held.stroke));
+        }
+        ready
+    }
+
+    fn next_due(&self) -> Option<u64> {
+        self.held.front().map(|held| held.due)
+    }
+}
+
+static SETTLER: Mutex<Settler> = Mutex::new(Settler::new());
+/// Signalled whenever the settler changes, for the thread publishing held keys.
+static SETTLER_CHANGED: Condvar = Condvar::new();
+static CLOCK_START: OnceLock<Instant> = OnceLock::new();
+
+fn clock() -> u64 {
+    CLOCK_START.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
+
+/// Passes a key on to `deliver`, right away unless an input method could
+/// still turn it into a composition. Delivery happens under the settler's
+/// lock, so strokes keep their order across threads.
+#[cfg_attr(not(any(target_os = "windows", target_os = "linux")), allow(dead_code))]
+pub(crate) fn settle(mut stroke: KeyStroke, ime: ImeState, deliver: fn(KeyStroke)) {
+    if ime == ImeState::Unfollowed && stroke.key != KeyKind::Modifier {
+        stroke.composing = true;
+    }
+    if let Ok(mut settler) = SETTLER.lock() {
+        let now = clock();
+        settler.key(stroke, ime == ImeState::Followed, now);
+        for stroke in settler.drain(now) {
+            deliver(stroke);
+        }
+        SETTLER_CHANGED.notify_all();
+    }
+}
+
+#[cfg_attr(not(any(target_os = "windows", target_os = "linux")), allow(dead_code))]
+fn preedit_changed(shown: bool) {
+    if let Ok(mut settler) = SETTLER.lock() {
+        settler.preedit(shown, clock());
+        SETTLER_CHANGED.notify_all();
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn committed(text: &str) {
+    if let Ok(mut settler) = SETTLER.lock() {
+        settler.commit(text, clock());
+        SETTLER_CHANGED.notify_all();
+    }
+}
+
+/// Publishes held keys as they fall due, until dropped.
+#[cfg_attr(not(any(target_os = "windows", target_os = "linux")), allow(dead_code))]
+struct Flusher {
+    stop: Arc<AtomicBool>,
+}
+
+#[cfg_attr(not(any(target_os = "windows", target_os = "linux")), allow(dead_code))]
+impl Flusher {
+    fn start(deliver: fn(KeyStroke)) -> Self {
+        if let Ok(mut settler) = SETTLER.lock() {
+            *settler = Settler::new();
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        std::thread::spawn(move || {
+            let Ok(mut settler) = SETTLER.lock() else {
+                return;
+            };
+            while !stopped.load(Ordering::SeqCst) {
+                let now = clock();
+                for stroke in settler.drain(now) {
+                    deliver(stroke);
+                }
+                let wait = settler.next_due().map_or(Duration::from_secs(60), |due| {
+                    Duration::from_millis(due.saturating_sub(now))
+                });
+                settler = match SETTLER_CHANGED.wait_timeout(settler, wait) {
+                    Ok((settler, _)) => settler,
+                    Err(_) => return,
+                };
+            }
+        });
+        Flusher { stop }
+    }
+}
+
+impl Drop for Flusher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Taken so the notification cannot slip in before the thread waits
+        let _settler = SETTLER.lock();
+        SETTLER_CHANGED.notify_all();
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use super::{preedit_changed, Flusher, ImeState, KeyStroke};
+    use winapi::shared::minwindef::DWORD;
+    use winapi::shared::ntdef::LONG;
+    use winapi::shared::windef::{HKL, HWINEVENTHOOK, HWND};
+    use winapi::um::winuser::{
+        SetWinEventHook, UnhookWinEvent, EVENT_OBJECT_IME_CHANGE, EVENT_OBJECT_IME_HIDE,
+        EVENT_OBJECT_IME_SHOW, EVENT_OBJECT_TEXTEDIT_CONVERSIONTARGETCHANGED,
+        EVENT_SYSTEM_FOREGROUND, WINEVENT_OUTOFCONTEXT, WINEVENT_SKIPOWNPROCESS,
+    };
+
+    /// Whether keys typed with the foreground window's `layout` can go into an
+    /// IME composition. Only Chinese, Japanese and Korean layouts compose.
+    pub(crate) fn ime_state(layout: HKL) -> ImeState {
+        // The primary language of the layout's language identifier
+        match layout as usize & 0x3ff {
+            // LANG_CHINESE, LANG_JAPANESE and LANG_KOREAN
+            0x04 | 0x11 | 0x12 => ImeState::Followed,
+            _ => ImeState::Off,
+        }
+    }
+
+    /// Follows IME compositions until dropped. The calling thread must run a
+    /// message loop, which is where the events are delivered.
+    pub(crate) struct CompositionWatch {
+        hooks: Vec<HWINEVENTHOOK>,
+        _flusher: Flusher,
+    }
+
+    pub(crate) fn watch_compositions(deliver: fn(KeyStroke)) -> CompositionWatch {
+        let ranges = [
+            (EVENT_SYSTEM_FOREGROUND, EVENT_SYSTEM_FOREGROUND),
+            (EVENT_OBJECT_IME_SHOW, EVENT_OBJECT_IME_CHANGE),
+            (
+                EVENT_OBJECT_TEXTEDIT_CONVERSIONTARGETCHANGED,
+                EVENT_OBJECT_TEXTEDIT_CONVERSIONTARGETCHANGED,
+            ),
+        ];
+        let flusher = Flusher::start(deliver);
+        let hooks = ranges
+            .iter()
+            .map(|&(min, max)| unsafe {
+                SetWinEventHook(
+                    min,
+                    max,
+                    std::ptr::null_mut(),
+                    Some(on_ime_event),
+                    0,
+                    0,
+                    WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS,
+                )
+            })
+            .filter(|hook| !hook.is_null())
+            .collect();
+        CompositionWatch {
+            hooks,
+            _flusher: flusher,
+        }
+    }
+
+    impl Drop for CompositionWatch {
+        fn drop(&mut self) {
+            for &hook in &self.hooks {
+                unsafe {
+                    UnhookWinEvent(hook);
+                }
+            }
+        }
+    }
+
+    unsafe extern "system" fn on_ime_event(
+        _hook: HWINEVENTHOOK,
+        event: DWORD,
+        _hwnd: HWND,
+        _object: LONG,
+        _child: LONG,
+        _thread: DWORD,
+        _time: DWORD,
+    ) {
+        let shown = match event {
+            EVENT_OBJECT_IME_SHOW
+            | EVENT_OBJECT_IME_CHANGE
+            | EVENT_OBJECT_TEXTEDIT_CONVERSIONTARGETCHANGED => true,
+            // The composition was committed or cancelled, or focus moved on
+            EVENT_OBJECT_IME_HIDE | EVENT_SYSTEM_FOREGROUND => false,
+            _ => return,
+        };
+        preedit_changed(shown);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::{committed, preedit_changed, Flusher, ImeState, KeyStroke};
+    use std::cell::RefCell;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use zbus::blocking::{connection, Connection, MessageIterator};
+    use zbus::message::{Message, Type};
+    use zbus::zvariant::{OwnedValue, Value};
+
+    /// The input method is asked again at most this often while typing.
+    const CACHE_MS: u64 = 500;
+    /// How long to wait before looking for a daemon again after none answered.
+    const RETRY_MS: u64 = 30_000;
+
+    /// Threads following Fcitx and IBus preedit signals.
+    static FCITX_FOLLOWERS: AtomicUsize = AtomicUsize::new(0);
+    static IBUS_FOLLOWERS: AtomicUsize = AtomicUsize::new(0);
+
+    /// The input method daemon that answered last.
+    enum Daemon {
+        Fcitx5(Connection),
+        /// The session bus and the display-specific service name.
+        Fcitx4(Connection, String),
+        /// IBus runs its own bus rather than using the session bus.
+        IBus(Connection),
+    }
+
+    impl Daemon {
+        fn followers(&self) -> &'static AtomicUsize {
+            match self {
+                Daemon::Fcitx5(_) | Daemon::Fcitx4(..) => &FCITX_FOLLOWERS,
+                Daemon::IBus(_) => &IBUS_FOLLOWERS,
+            }
+        }
+    }
+
+    struct Cached {
+        daemon: Option<Daemon>,
+        checked_at: u64,
+        active: bool,
+    }
+
+    thread_local! {
+        static CACHE: RefCell<Option<Cached>> = const { RefCell::new(None) };
+    }
+
+    /// Whether a composing input method is on and its preedit is followed.
+    /// `now` is the key event's millisecond clock.
+    pub(crate) fn ime_state(now: u64) -> ImeState {
+        CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            let fresh = cache.as_ref().is_some_and(|cached| {
+                let ttl = if cached.daemon.is_some() {
+                    CACHE_MS
+                } else {
+                    RETRY_MS
+                };
+                now.wrapping_sub(cached.checked_at) < ttl
+            });
+            if !fresh {
+                let known = cache.take().and_then(|cached| cached.daemon);
+                // A daemon that stopped answering may have been replaced by another
+                let (daemon, active) = match known.and_then(|d| query(&d).map(|a| (d, a))) {
+                    Some((daemon, active)) => (Some(daemon), active),
+                    None => match find_daemon() {
+                        Some((daemon, active)) => (Some(daemon), active),
+                        None => (None, false),
+                    },
+                };
+                *cache = Some(Cached {
+                    daemon,
+                    checked_at: now,
+                    active,
+                });
+            }
+            match cache.as_ref() {
+                Some(Cached {
+                    daemon: Some(daemon),
+                    active: true,
+                    ..
+                }) if daemon.followers().load(Ordering::SeqCst) > 0 => ImeState::Followed,
+                Some(Cached { active: true, .. }) => ImeState::Unfollowed,
+                _ => ImeState::Off,
+            }
+        })
+    }
+
+    /// Tries Fcitx 5, Fcitx 4 and IBus in turn, returning the first that answers.
+    fn find_daemon() -> Option<(Daemon, bool)> {
+        let answer = |daemon: Daemon| query(&daemon).map(|active| (daemon, active));
+        let session = Connection::session().ok();
+        session
+            .clone()
+            .and_then(|bus| answer(Daemon::Fcitx5(bus)))
+            .or_else(|| {
+                let (_, number) = x_display(&std::env::var("DISPLAY").ok()?)?;
+                answer(Daemon::Fcitx4(
+                    session?,
+                    format!("org.fcitx.Fcitx-{}", number),
+                ))
+            })
+            .or_else(|| answer(Daemon::IBus(ibus_bus()?)))
+    }
+
+    fn ibus_bus() -> Option<Connection> {
+        connection::Builder::address(ibus_address()?.as_str())
+            .and_then(|b| b.build())
+            .ok()
+    }
+
+    /// Asks the daemon whether an input method that can compose is on.
+    /// `None` when it does not answer.
+    fn query(daemon: &Daemon) -> Option<bool> {
+        match daemon {
+            // 0 is closed, 1 a keyboard layout, 2 an input method
+            Daemon::Fcitx5(bus) => bus
+                .call_method(
+                    Some("org.fcitx.Fcitx5"),
+                    "/controller",
+                    Some("org.fcitx.Fcitx.Controller1"),
+                    "State",
+                    &(),
+                )
+                .and_then(|reply| reply.body().deserialize::<i32>())
+                .ok()
+                .map(|state| state == 2),
+            Daemon::Fcitx4(bus, name) => bus
+                .call_method(
+                    Some(name.as_str()),
+                    "/inputmethod",
+                    Some("org.fcitx.Fcitx.InputMethod"),
+                    "GetCurrentState",
+                    &(),
+                )
+                .and_then(|reply| reply.body().deserialize::<i32>())
+                .ok()
+                .map(|state| state == 2),
+            Daemon::IBus(bus) => {
+                let engine = bus
+                    .call_method(
+                        Some("org.freedesktop.IBus"),
+                        "/org/freedesktop/IBus",
+                        Some("org.freedesktop.DBus.Properties"),
+                        "Get",
+                        &("org.freedesktop.IBus", "GlobalEngine"),
+                    )
+                    .and_then(|reply| reply.body().deserialize::<OwnedValue>())
+                    .ok()?;
+                let (name, language) = engine_desc(&engine)?;
+                Some(engine_composes(&name, &language))
+            }
+        }
+    }
+
+    /// Follows preedit and commit signals until dropped.
+    pub(crate) struct CompositionWatch {
+        buses: Vec<Connection>,
+        _flusher: Flusher,
+    }
+
+    /// Starts following the daemons' input contexts. Their signals are sent
+    /// to the focused app only, so the session bus is monitored; IBus runs
+    /// its own bus, which passes every message matching a rule on to the
+    /// connection that added it.
+    pub(crate) fn watch_compositions(deliver: fn(KeyStroke)) -> CompositionWatch {
+        let flusher = Flusher::start(deliver);
+        let mut buses = Vec::new();
+        let fcitx = Connection::session().ok().filter(|bus| {
+            let rules: Vec<String> = [
+                "org.fcitx.Fcitx.InputContext1",
+                "org.fcitx.Fcitx.InputContext",
+            ]
+            .iter()
+            .flat_map(|interface| {
+                ["UpdateFormattedPreedit", "CommitString"].map(|member| {
+                    format!(
+                        "type='signal',interface='{}',member='{}'",
+                        interface, member
+                    )
+                })
+            })
+            .collect();
+            bus.call_method(
+                Some("org.freedesktop.DBus"),
+                "/org/freedesktop/DBus",
+                Some("org.freedesktop.DBus.Monitoring"),
+                "BecomeMonitor",
+                &(rules, 0u32),
+            )
+            .is_ok()
+        });
+        if let Some(bus) = fcitx {
+            follow(bus.clone(), &FCITX_FOLLOWERS);
+            buses.push(bus);
+        }
+        let ibus = ibus_bus().filter(|bus| {
+            bus.call_method(
+                Some("org.freedesktop.DBus"),
+                "/org/freedesktop/DBus",
+                Some("org.freedesktop.DBus"),
+                "AddMatch",
+                &("type='signal',interface='org.freedesktop.IBus.InputContext'"),
+            )
+            .is_ok()
+        });
+        if let Some(bus) = ibus {
+            follow(bus.clone(), &IBUS_FOLLOWERS);
+            buses.push(bus);
+        }
+        CompositionWatch {
+            buses,
+            _flusher: flusher,
+        }
+    }
+
+    impl Drop for CompositionWatch {
+        fn drop(&mut self) {
+            // Ends the followers' message iterators
+            for bus in self.buses.drain(..) {
+                let _ = bus.close();
+            }
+        }
+    }
+
+    fn follow(bus: Connection, followers: &'static AtomicUsize) {
+        followers.fetch_add(1, Ordering::SeqCst);
+        std::thread::spawn(move || {
+            // IBus can hide a preedit and show the same text again
+            let mut text = false;
+            for message in MessageIterator::from(bus) {
+                let Ok(message) = message else {
+                    break;
+                };
+                match ime_event(&message) {
+                    Some(ImeEvent::Preedit { text: t, visible }) => {
+                        text = t;
+                        preedit_changed(text && visible);
+                    }
+                    Some(ImeEvent::ShowPreedit) => preedit_changed(text),
+                    Some(ImeEvent::HidePreedit) => preedit_changed(false),
+                    Some(ImeEvent::Commit(committed_text)) => committed(&committed_text),
+                    None => {}
+                }
+            }
+            followers.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub(super) enum ImeEvent {
+        Preedit { text: bool, visible: bool },
+        ShowPreedit,
+        HidePreedit,
+        Commit(String),
+    }
+
+    /// Reads a Fcitx or IBus input context signal.
+    pub(super) fn ime_event(message: &Message) -> Option<ImeEvent> {
+        let header = message.header();
+        if header.message_type() != Type::Signal {
+            return None;
+        }
+        let body = message.body();
+        match (header.interface()?.as_str(), header.member()?.as_str()) {
+            (
+                "org.fcitx.Fcitx.InputContext1" | "org.fcitx.Fcitx.InputContext",
+                "UpdateFormattedPreedit",
+            ) => {
+                let (segments, _cursor) = body.deserialize::<(Vec<(String, i32)>, i32)>().ok()?;
+                Some(ImeEvent::Preedit {
+                    text: segments.iter().any(|(segment, _)| !segment.is_empty()),
+                    visible: true,
+                })
+            }
+            ("org.fcitx.Fcitx.InputContext1" | "org.fcitx.Fcitx.InputContext", "CommitString") => {
+                Some(ImeEvent::Commit(body.deserialize::<String>().ok()?))
+            }
+            ("org.freedesktop.IBus.InputContext", "UpdatePreeditText") => {
+                let (text, _cursor, visible) =
+                    body.deserialize::<(OwnedValue, u32, bool)>().ok()?;
+                Some(ImeEvent::Preedit {
+                    text: !ibus_text(&text)?.is_empty(),
+                    visible,
+                })
+            }
+            ("org.freedesktop.IBus.InputContext", "ShowPreeditText") => Some(ImeEvent::ShowPreedit),
+            ("org.freedesktop.IBus.InputContext", "HidePreeditText") => Some(ImeEvent::HidePreedit),
+            ("org.freedesktop.IBus.InputContext", "CommitText") => {
+                let text = body.deserialize::<OwnedValue>().ok()?;
+                Some(ImeEvent::Commit(ibus_text(&text)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// The text of a serialized `IBusText`: a struct of the type name,
+    /// attachments, the text and its attributes.
+    pub(super) fn ibus_text(value: &Value) -> Option<String> {
+        match value {
+            Value::Value(inner) => ibus_text(inner),
+            Value::Structure(text) => match text.fields().get(2) {
+                Some(Value::Str(s)) => Some(s.to_string()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Name and language of a serialized `IBusEngineDesc`: a struct of the
+    /// type name, attachments, name, long name, description and language,
+    /// followed by fields not needed here.
+    pub(super) fn engine_desc(value: &Value) -> Option<(String, String)> {
+        match value {
+            Value::Value(inner) => engine_desc(inner),
+            Value::Structure(desc) => {
+                let field = |i: usize| match desc.fields().get(i) {
+                    Some(Value::Str(s)) => Some(s.to_string()),
+                    _ => None,
+                };
+                Some((field(2)?, field(5)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Keyboard layouts (`xkb:us::eng`) type directly. Of the other engines
+    /// only Chinese, Japanese and Korean ones hold keys in a composition;
+    /// others, such as typing-booster or m17n transliteration, type as they go.
+    pub(super) fn engine_composes(name: &str, language: &str) -> bool {
+        !name.starts_with("xkb:")
+            && matches!(language.split(['_', '-']).next(), Some("ja" | "ko" | "zh"))
+    }
+
+    /// Reads the IBus bus address from the environment or the file the daemon
+    /// writes for the current display.
+    fn ibus_address() -> Option<String> {
+        if let Ok(address) = std::env::var("IBUS_ADDRESS") {
+            return Some(address);
+        }
+        let config = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        let machine_id = ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+            .iter()
+            .find_map(|path| std::fs::read_to_string(path).ok())?;
+        // IBus prefers the Wayland display when there is one
+        let display = match std::env::var("WAYLAND_DISPLAY") {
+            Ok(wayland) => format!("unix-{}", wayland),
+            Err(_) => {
+                let (host, number) = x_display(&std::env::var("DISPLAY").ok()?)?;
+                format!("{}-{}", host, number)
+            }
+        };
+        let file = config
+            .join("ibus/bus")
+            .join(format!("{}-{}", machine_id.trim(), display));
+        parse_ibus_address(&std::fs::read_to_string(file).ok()?)
+    }
+
+    pub(super) fn parse_ibus_address(contents: &str) -> Option<String> {
+        contents
+            .lines()
+            .find_map(|line| line.strip_prefix("IBUS_ADDRESS="))
+            .map(|address| address.trim().to_string())
+            .filter(|address| !address.is_empty())
+    }
+
+    /// Host and display number of an X display name such as `:0.0`; a local
+    /// display has the host `unix`, like IBus names it.
+    pub(super) fn x_display(display: &str) -> Option<(String, String)> {
+        let (host, rest) = display.rsplit_once(':')?;
+        let number = rest.split('.').next().filter(|n| !n.is_empty())?;
+        let host = if host.is_empty() { "unix" } else { host };
+        Some((host.to_string(), number.to_string()))
+    }
+}
+
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+pub(crate) use imp::{ime_state, watch_compositions};
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::imp::*;
+    use std::collections::HashMap;
+    use zbus::message::Message;
+    use zbus::zvariant::Value;
+
+    #[test]
+    fn only_cjk_engines_compose() {
+        assert!(engine_composes("mozc-jp", "ja"));
+        assert!(engine_composes("libpinyin", "zh_CN"));
+        assert!(engine_composes("hangul", "ko"));
+        assert!(!engine_composes("xkb:jp::jpn", "ja"));
+        assert!(!engine_composes("typing-booster", "en"));
+        assert!(!engine_composes("m17n:hi:itrans", "hi"));
+    }
+
+    #[test]
+    fn reads_the_engine_desc_inside_variants() {
+        let desc = Value::from((
+            "IBusEngineDesc",
+            HashMap::<String, Value>::new(),
+            "anthy",
+            "Anthy",
+            "Japanese input method",
+            "ja",
+            "GPL",
+        ));
+        let wrapped = Value::Value(Box::new(desc));
+        assert_eq!(
+            engine_desc(&wrapped),
+            Some(("anthy".to_string(), "ja".to_string()))
+        );
+        assert_eq!(engine_desc(&Value::from("anthy")), None);
+    }
+
+    fn signal<B>(interface: &str, member: &str, body: &B) -> Message
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        Message::signal("/org/freedesktop/portal/inputcontext/1", interface, member)
+            .unwrap()
+            .build(body)
+            .unwrap()
+    }
+
+    fn ibus_text(text: &str) -> Value<'_> {
+        Value::Value(Box::new(Value::from((
+            "IBusText",
+            HashMap::<String, Value>::new(),
+            text,
+            Value::from(0u32),
+        ))))
+    }
+
+    #[test]
+    fn reads_fcitx_preedit_and_commits() {
+        let fcitx = "org.fcitx.Fcitx.InputContext1";
+        let preedit = |segments: Vec<(&str, i32)>| {
+            ime_event(&signal(fcitx, "UpdateFormattedPreedit", &(segments, 0i32)))
+        };
+        assert_eq!(
+            preedit(vec![("ら", 8)]),
+            Some(ImeEvent::Preedit {
+                text: true,
+                visible: true
+            })
+        );
+        assert_eq!(
+            preedit(vec![("", 0)]),
+            Some(ImeEvent::Preedit {
+                text: false,
+                visible: true
+            })
+        );
+        assert_eq!(
+            ime_event(&signal(fcitx, "CommitString", &("らえ",))),
+            Some(ImeEvent::Commit("らえ".to_string()))
+        );
+        assert_eq!(ime_event(&signal(fcitx, "ForwardKey", &(1u32,))), None);
+    }
+
+    #[test]
+    fn reads_ibus_preedit_and_commits() {
+        let ibus = "org.freedesktop.IBus.InputContext";
+        assert_eq!(
+            ime_event(&signal(
+                ibus,
+                "UpdatePreeditText",
+                &(ibus_text("らえ"), 2u32, false)
+            )),
+            Some(ImeEvent::Preedit {
+                text: true,
+                visible: false
+            })
+        );
+        assert_eq!(
+            ime_event(&signal(ibus, "HidePreeditText", &())),
+            Some(ImeEvent::HidePreedit)
+        );
+        assert_eq!(
+            ime_event(&signal(ibus, "CommitText", &(ibus_text("らえ"),))),
+            Some(ImeEvent::Commit("らえ".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_ibus_address_files() {
+        let file = "# This file is created by ibus-daemon\n\
+                    IBUS_ADDRESS=unix:path=/tmp/ibus-socket,guid=abc\n\
+                    IBUS_DAEMON_PID=1234\n";
+        assert_eq!(
+            parse_ibus_address(file).as_deref(),
+            Some("unix:path=/tmp/ibus-socket,guid=abc")
+        );
+        assert_eq!(parse_ibus_address("IBUS_ADDRESS=\n"), None);
+    }
+
+    #[test]
+    fn splits_x_display_names() {
+        let pair = |host: &str, number: &str| Some((host.to_string(), number.to_string()));
+        assert_eq!(x_display(":0"), pair("unix", "0"));
+        assert_eq!(x_display(":1.0"), pair("unix", "1"));
+        assert_eq!(x_display("remote:10.0"), pair("remote", "10"));
+        assert_eq!(x_display("wayland-0"), None);
+    }
+}
@@ -0,0 +1,514 @@
+//! Background text injection into another application's window.
+//!
+//! Text is typed with `SendInput` in chunks on a worker thread so long answers
+//! can be cancelled, report progress, and pause when the user switches away
+//! from the target window. Typing relies on `SendInput`, so elsewhere only the
+//! configuration and status commands are available.
+
+#[cfg(target_os = "windows")]
+use super::clipboard::{
+    offer_clipboard_formats, rich_text_formats, snapshot_clipboard, write_clipboard_formats,
+};
+#[cfg(target_os = "windows")]
+use super::markdown::{convert, to_html, to_plain_text, to_rtf, OutputFormat};
+#[cfg(target_os = "windows")]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+#[cfg(target_os = "windows")]
+use std::{thread, time::Duration};
+#[cfg(target_os = "windows")]
+use tauri::{AppHandle, Emitter};
+#[cfg(target_os = "windows")]
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
+#[cfg(target_os = "windows")]
+use winapi::shared::windef::HWND as WinHWND;
+#[cfg(target_os = "windows")]
+use winapi::um::winuser::{
+    GetForegroundWindow, SendInput, SetForegroundWindow, INPUT, INPUT_KEYBOARD, KEYBDINPUT,
+    KEYEVENTF_KEYUP, KEYEVENTF_UNICODE, VK_CONTROL,
+};
+
+static INJECTION_RUNNING: AtomicBool = AtomicBool::new(false);
+static INJECTION_CANCELLED: AtomicBool = AtomicBool::new(false);
+static INJECTION_PAUSED: AtomicBool = AtomicBool::new(false);
+static INJECTION_RESUME_REQUESTED: AtomicBool = AtomicBool::new(false);
+#[cfg(target_os = "windows")]
+static NEXT_INJECTION_ID: AtomicU64 = AtomicU64::new(1);
+/// Job id and UTF-16 units actually inserted by the last finished job.
+#[cfg(target_os = "windows")]
+static LAST_INJECTED: Mutex<(u64, usize)> = Mutex::new((0, 0));
+
+/// Stored in `dwExtraInfo` of every key Rae sends, so the keyboard hook can
+/// tell them apart from input that other software injects.
+#[cfg(target_os = "windows")]
+pub(crate) const RAE_INPUT_MARKER: usize = 0x5241_4500;
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct InjectionConfig {
+    /// Number of UTF-16 units typed per `SendInput` batch.
+    pub chunk_size: usize,
+    /// Pause between two chunks, in milliseconds.
+    pub chunk_delay_ms: u64,
+    /// Global shortcut that cancels the running injection. Ctrl+Shift+Escape
+    /// can't be used, Windows reserves it for the Task Manager.
+    pub panic_hotkey: String,
+    /// Pause when the foreground window is no longer the target window.
+    pub pause_on_focus_loss: bool,
+}
+
+impl Default for InjectionConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: 16,
+            chunk_delay_ms: 10,
+            panic_hotkey: "Ctrl+Alt+Escape".to_string(),
+            pause_on_focus_loss: true,
+        }
+    }
+}
+
+fn injection_config() -> &'static Mutex<InjectionConfig> {
+    static CONFIG: OnceLock<Mutex<InjectionConfig>> = OnceLock::new();
+    CONFIG.get_or_init(|| Mutex::new(InjectionConfig::default()))
+}
+
+#[tauri::command]
+pub fn set_injection_config(config: InjectionConfig) -> Result<(), String> {
+    if config.chunk_size == 0 {
+        return Err("Chunk size must be at least 1".into());
+    }
+    *injection_config().lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_injection_config() -> InjectionConfig {
+    injection_config()
+        .lock()
+        .map(|c| c.clone())
+        .unwrap_or_default()
+}
+
+/// Starts typing `text` into `hwnd` on a background thread and returns the job id.
+///
+/// Only one injection runs at a time; progress is reported through the
+/// `injection_progress`, `injection_paused` and `injection_finished` events,
+/// and `injection_hotkey_failed` when the panic hotkey can't be registered.
+#[cfg(target_os = "windows")]
+pub fn start_injection(app: &AppHandle, text: String, hwnd: WinHWND) -> Result<u64, String> {
+    if INJECTION_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err("Another injection is already running".into());
+    }
+    let config = get_injection_config();
+    let job_id = NEXT_INJECTION_ID.fetch_add(1, Ordering::SeqCst);
+    INJECTION_CANCELLED.store(false, Ordering::SeqCst);
+    INJECTION_PAUSED.store(false, Ordering::SeqCst);
+    INJECTION_RESUME_REQUESTED.store(false, Ordering::SeqCst);
+
+    unsafe {
+        if SetForegroundWindow(hwnd) == 0 {
+            INJECTION_RUNNING.store(false, Ordering::SeqCst);
+            return Err("Failed to bring target window to foreground".into());
+        }
+    }
+
+    arm_panic_hotkey(app, job_id, &config.panic_hotkey);
+
+    // HWND is not Send, so hand the raw value to the worker thread
+    let target = hwnd as isize;
+    let app_handle = app.clone();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        let (status, sent) = run_injection(&app_handle, job_id, &text, target as WinHWND, &config);
+        record_injected(job_id, sent);
+        unregister_panic_hotkey(&app_handle, &config.panic_hotkey);
+        INJECTION_PAUSED.store(false, Ordering::SeqCst);
+        INJECTION_RUNNING.store(false, Ordering::SeqCst);
+        let _ = app_handle.emit(
+            "injection_finished",
+            serde_json::json!({ "jobId": job_id, "status": status }),
+        );
+    });
+
+    Ok(job_id)
+}
+
+/// Pastes rich content into `hwnd` through the clipboard, for targets that
+/// understand HTML or RTF. Every format on the clipboard beforehand, images and
+/// HTML included, is restored afterwards.
+///
+/// Reports the same events as [`start_injection`]. The paste can be cancelled
+/// or paused until it is sent; it finishes as `not_pasted` when the target
+/// never read the clipboard.
+#[cfg(target_os = "windows")]
+pub fn start_rich_paste(
+    app: &AppHandle,
+    text: String,
+    html: String,
+    rtf: String,
+    hwnd: WinHWND,
+) -> Result<u64, String> {
+    if INJECTION_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err("Another injection is already running".into());
+    }
+    let config = get_injection_config();
+    let job_id = NEXT_INJECTION_ID.fetch_add(1, Ordering::SeqCst);
+    INJECTION_CANCELLED.store(false, Ordering::SeqCst);
+    INJECTION_PAUSED.store(false, Ordering::SeqCst);
+    INJECTION_RESUME_REQUESTED.store(false, Ordering::SeqCst);
+
+    unsafe {
+        if SetForegroundWindow(hwnd) == 0 {
+            INJECTION_RUNNING.store(false, Ordering::SeqCst);
+            return Err("Failed to bring target window to foreground".into());
+        }
+    }
+
+    arm_panic_hotkey(app, job_id, &config.panic_hotkey);
+
+    let target = hwnd as isize;
+    let app_handle = app.clone();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        let formats = rich_text_formats(&text, &html, &rtf);
+        let total = text.encode_utf16().count();
+        let status = run_rich_paste(
+            &app_handle,
+            job_id,
+            formats,
+            total,
+            target as WinHWND,
+            &config,
+        );
+        let sent = if status == "completed" { total } else { 0 };
+        record_injected(job_id, sent);
+        unregister_panic_hotkey(&app_handle, &config.panic_hotkey);
+        INJECTION_PAUSED.store(false, Ordering::SeqCst);
+        INJECTION_RUNNING.store(false, Ordering::SeqCst);
+        let _ = app_handle.emit(
+            "injection_finished",
+            serde_json::json!({ "jobId": job_id, "status": status }),
+        );
+    });
+
+    Ok(job_id)
+}
+
+#[cfg(target_os = "windows")]
+fn run_rich_paste(
+    app: &AppHandle,
+    job_id: u64,
+    formats: Vec<(u32, Vec<u8>)>,
+    total: usize,
+    hwnd: WinHWND,
+    config: &InjectionConfig,
+) -> &'static str {
+    if INJECTION_CANCELLED.load(Ordering::SeqCst) {
+        return "cancelled";
+    }
+    if unsafe { GetForegroundWindow() } != hwnd {
+        // Pasting into whatever window has focus now would put the answer
+        // in the wrong place
+        if !config.pause_on_focus_loss {
+            return "cancelled";
+        }
+        INJECTION_PAUSED.store(true, Ordering::SeqCst);
+        let _ = app.emit(
+            "injection_paused",
+            serde_json::json!({
+                "jobId": job_id,
+                "reason": "focus_lost",
+                "sent": 0,
+                "total": total,
+            }),
+        );
+        if !wait_for_resume(hwnd) {
+            return "cancelled";
+        }
+        let _ = app.emit(
+            "injection_resumed",
+            serde_json::json!({ "jobId": job_id, "sent": 0, "total": total }),
+        );
+    }
+
+    let previous = unsafe { snapshot_clipboard() };
+    let pasted = unsafe {
+        offer_clipboard_formats(
+            formats,
+            || send_key_combo(&[VK_CONTROL as u16], b'V' as u16),
+            Duration::from_secs(2),
+        )
+    };
+    // Without a snapshot the clipboard stays empty rather than holding the answer
+    if let Some(previous) = previous {
+        unsafe {
+            write_clipboard_formats(&previous);
+        }
+    }
+    match pasted {
+        Ok(true) => {
+            let _ = app.emit(
+                "injection_progress",
+                serde_json::json!({ "jobId": job_id, "sent": total, "total": total }),
+            );
+            "completed"
+        }
+        Ok(false) => "not_pasted",
+        Err(_) => "failed",
+    }
+}
+
+/// Types or pastes the Markdown answer `markdown` into `hwnd`, converted to
+/// `format`. Rich output goes through the clipboard, everything else is typed.
+#[cfg(target_os = "windows")]
+pub fn start_formatted_injection(
+    app: &AppHandle,
+    markdown: &str,
+    format: OutputFormat,
+    hwnd: WinHWND,
+) -> Result<u64, String> {
+    match format {
+        OutputFormat::Rich => start_rich_paste(
+            app,
+            to_plain_text(markdown),
+            to_html(markdown),
+            to_rtf(markdown),
+            hwnd,
+        ),
+        format => start_injection(app, convert(markdown, format), hwnd),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn record_injected(job_id: u64, units: usize) {
+    if let Ok(mut last) = LAST_INJECTED.lock() {
+        *last = (job_id, units);
+    }
+}
+
+/// Returns how many UTF-16 units job `job_id` inserted, or `None` while it is
+/// still running or once a later job has finished.
+#[cfg(target_os = "windows")]
+pub(crate) fn injected_units(job_id: u64) -> Option<usize> {
+    let (last_job, units) = *LAST_INJECTED.lock().ok()?;
+    (last_job == job_id).then_some(units)
+}
+
+#[cfg(target_os = "windows")]
+fn run_injection(
+    app: &AppHandle,
+    job_id: u64,
+    text: &str,
+    hwnd: WinHWND,
+    config: &InjectionConfig,
+) -> (&'static str, usize) {
+    let units: Vec<u16> = text.encode_utf16().collect();
+    let total = units.len();
+    let mut sent = 0;
+
+    while sent < total {
+        if INJECTION_CANCELLED.load(Ordering::SeqCst) {
+            return ("cancelled", sent);
+        }
+
+        if config.pause_on_focus_loss && unsafe { GetForegroundWindow() } != hwnd {
+            INJECTION_PAUSED.store(true, Ordering::SeqCst);
+            let _ = app.emit(
+                "injection_paused",
+                serde_json::json!({
+                    "jobId": job_id,
+                    "reason": "focus_lost",
+                    "sent": sent,
+                    "total": total,
+                }),
+            );
+            if !wait_for_resume(hwnd) {
+                return ("cancelled", sent);
+            }
+            let _ = app.emit(
+                "injection_resumed",
+                serde_json::json!({ "jobId": job_id, "sent": sent, "total": total }),
+            );
+        }
+
+        let mut end = (sent + config.chunk_size).min(total);
+        // Never split a surrogate pair across two chunks
+        if end < total && (0xD800..0xDC00).contains(&units[end - 1]) {
+            end += 1;
+        }
+        send_unicode_units(&units[sent..end]);
+        sent = end;
+
+        let _ = app.emit(
+            "injection_progress",
+            serde_json::json!({ "jobId": job_id, "sent": sent, "total": total }),
+        );
+        thread::sleep(Duration::from_millis(config.chunk_delay_ms));
+    }
+
+    ("completed", sent)
+}
+
+/// Blocks until the user resumes or cancels a paused injection.
+/// Returns `false` when the job was cancelled while paused.
+#[cfg(target_os = "windows")]
+fn wait_for_resume(hwnd: WinHWND) -> bool {
+    loop {
+        if INJECTION_CANCELLED.load(Ordering::SeqCst) {
+            return false;
+        }
+        if INJECTION_RESUME_REQUESTED.swap(false, Ordering::SeqCst) {
+            unsafe {
+                SetForegroundWindow(hwnd);
+            }
+            thread::sleep(Duration::from_millis(50));
+            if unsafe { GetForegroundWindow() } == hwnd {
+                INJECTION_PAUSED.store(false, Ordering::SeqCst);
+                return true;
+            }
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Types the given UTF-16 units with a single `SendInput` call.
+#[cfg(target_os = "windows")]
+pub(crate) fn send_unicode_units(units: &[u16]) {
+    let mut inputs: Vec<INPUT> = Vec::with_capacity(units.len() * 2);
+    for &unit in units {
+        for flags in [KEYEVENTF_UNICODE, KEYEVENTF_UNICODE | KEYEVENTF_KEYUP] {
+            let mut input = INPUT {
+                type_: INPUT_KEYBOARD,
+                u: unsafe { std::mem::zeroed() },
+            };
+            unsafe {
+                *input.u.ki_mut() = KEYBDINPUT {
+                    wVk: 0,
+                    wScan: unit,
+                    dwFlags: flags,
+                    time: 0,
+                    dwExtraInfo: RAE_INPUT_MARKER,
+                };
+            }
+            inputs.push(input);
+        }
+    }
+    if inputs.is_empty() {
+        return;
+    }
+    unsafe {
+        SendInput(
+            inputs.len() as u32,
+            inputs.as_mut_ptr(),
+            std::mem::size_of::<INPUT>() as i32,
+        );
+    }
+}
+
+/// Presses `vk` while holding `modifiers`, then releases everything in reverse order.
+#[cfg(target_os = "windows")]
+pub(crate) fn send_key_combo(modifiers: &[u16], vk: u16) {
+    let mut inputs: Vec<INPUT> = Vec::with_capacity(modifiers.len() * 2 + 2);
+    let mut push = |key: u16, flags: u32| {
+        let mut input = INPUT {
+            type_: INPUT_KEYBOARD,
+            u: unsafe { std::mem::zeroed() },
+        };
+        unsafe {
+            *input.u.ki_mut() = KEYBDINPUT {
+                wVk: key,
+                wScan: 0,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: RAE_INPUT_MARKER,
+            };
+        }
+        inputs.push(input);
+    };
+    for &m in modifiers {
+        push(m, 0);
+    }
+    push(vk, 0);
+    push(vk, KEYEVENTF_KEYUP);
+    for &m in modifiers.iter().rev() {
+        push(m, KEYEVENTF_KEYUP);
+    }
+    unsafe {
+        SendInput(
+            inputs.len() as u32,
+            inputs.as_mut_ptr(),
+            std::mem::size_of::<INPUT>() as i32,
+        );
+    }
+}
+
+/// Registers the panic hotkey for job `job_id`, telling the UI when that fails.
+#[cfg(target_os = "windows")]
+fn arm_panic_hotkey(app: &AppHandle, job_id: u64, hotkey: &str) {
+    if let Err(error) = register_panic_hotkey(app, hotkey) {
+        // The injection can still be cancelled from the UI
+        let _ = app.emit(
+            "injection_hotkey_failed",
+            serde_json::json!({
+                "jobId": job_id,
+                "hotkey": hotkey,
+                "error": error,
+            }),
+        );
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn register_panic_hotkey(app: &AppHandle, hotkey: &str) -> Result<(), String> {
+    if hotkey.is_empty() {
+        return Ok(());
+    }
+    app.global_shortcut()
+        .on_shortcut(hotkey, |_app, _shortcut, event| {
+            if event.state() == ShortcutState::Pressed {
+                INJECTION_CANCELLED.store(true, Ordering::SeqCst);
+            }
+        })
+        .map_err(|e| format!("Failed to register injection panic hotkey: {}", e))
+}
+
+#[cfg(target_os = "windows")]
+fn unregister_panic_hotkey(app: &AppHandle, hotkey: &str) {
+    if hotkey.is_empty() {
+        return;
+    }
+    let _ = app.global_shortcut().unregister(hotkey);
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn is_injection_running() -> bool {
+    INJECTION_RUNNING.load(Ordering::SeqCst)
+}
+
+#[tauri::command]
+pub fn cancel_injection() -> bool {
+    if !INJECTION_RUNNING.load(Ordering::SeqCst) {
+        return false;
+    }
+    INJECTION_CANCELLED.store(true, Ordering::SeqCst);
+    true
+}
+
+#[tauri::command]
+pub fn resume_injection() -> bool {
+    if !INJECTION_PAUSED.load(Ordering::SeqCst) {
+        return false;
+    }
+    INJECTION_RESUME_REQUESTED.store(true, Ordering::SeqCst);
+    true
+}
+
+#[tauri::command]
+pub fn get_injection_status() -> serde_json::Value {
+    serde_json::json!({
+        "running": INJECTION_RUNNING.load(Ordering::SeqCst),
+        "paused": INJECTION_PAUSED.load(Ordering::SeqCst),
+    })
+}
@@ -0,0 +1,522 @@
+//! Inline `@rae` commands, text-expander style.
+//!
+//! After a trigger phrase the typed prompt is captured up to a terminator. The
+//! prompt then runs with the active window as context while the overlay shows
+//! a thinking indicator. Once the answer arrives, the trigger and prompt are
+//! erased from the target field and the answer is typed or pasted in their
+//! place. Escape, or any other typing before the answer arrives, aborts.
+//!
+//! Erasing and typing into the target field needs Windows, so prompts are
+//! only captured there.
+#![cfg_attr(not(target_os = "windows"), allow(dead_code))]
+
+#[cfg(target_os = "windows")]
+use super::clipboard_history::active_window_source;
+#[cfg(target_os = "windows")]
+use super::generate::generate;
+#[cfg(target_os = "windows")]
+use super::injection::{send_key_combo, start_formatted_injection};
+use super::keyboard::{KeyKind, KeyStroke};
+#[cfg(target_os = "windows")]
+use super::markdown::format_for_window;
+use super::triggers::TriggerMatch;
+use std::sync::atomic::AtomicU64;
+#[cfg(target_os = "windows")]
+use std::sync::atomic::Ordering;
+use std::sync::{Mutex, OnceLock};
+use tauri::{AppHandle, Emitter};
+#[cfg(target_os = "windows")]
+use winapi::shared::windef::HWND as WinHWND;
+#[cfg(target_os = "windows")]
+use winapi::um::winuser::{GetForegroundWindow, VK_BACK};
+
+static NEXT_INLINE_PROMPT_ID: AtomicU64 = AtomicU64::new(1);
+/// The prompt waiting for its answer, if any.
+static PENDING: Mutex<Option<PendingPrompt>> = Mutex::new(None);
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct InlinePromptConfig {
+    pub enabled: bool,
+    /// Typed after the prompt to run it.
+    pub terminator: String,
+    /// Enter runs the prompt too. The newline it typed is erased with the prompt.
+    pub submit_on_enter: bool,
+    /// Longer prompts are abandoned; the user is probably not writing one.
+    pub max_prompt_chars: usize,
+}
+
+impl Default for InlinePromptConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            terminator: ";;".to_string(),
+            submit_on_enter: false,
+            max_prompt_chars: 500,
+        }
+    }
+}
+
+fn inline_prompt_config() -> &'static Mutex<InlinePromptConfig> {
+    static CONFIG: OnceLock<Mutex<InlinePromptConfig>> = OnceLock::new();
+    CONFIG.get_or_init(|| Mutex::new(InlinePromptConfig::default()))
+}
+
+#[tauri::command]
+pub fn set_inline_prompt_config(config: InlinePromptConfig) -> Result<(), String> {
+    if config.terminator.is_empty() && !config.submit_on_enter {
+        return Err("Inline prompts need a terminator or Enter to submit".into());
+    }
+    *inline_prompt_config().lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_inline_prompt_config() -> InlinePromptConfig {
+    inline_prompt_config()
+        .lock()
+        .map(|c| c.clone())
+        .unwrap_or_default()
+}
+
+/// Aborts the inline prompt that is waiting for its answer.
+#[tauri::command]
+pub fn cancel_inline_prompt(app: AppHandle) -> bool {
+    abort_pending(&app, "cancelled")
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum CaptureStep {
+    Continue,
+    /// Run `prompt`; `erase_chars` characters were typed into the field since
+    /// the trigger started, the trigger included.
+    Submit {
+        prompt: String,
+        erase_chars: usize,
+    },
+    Abort(&'static str),
+}
+
+/// Collects the prompt typed after a trigger phrase.
+pub(crate) struct InlineCapture {
+    trigger_chars: usize,
+    typed: String,
+    config: InlinePromptConfig,
+}
+
+impl InlineCapture {
+    pub(crate) fn new(trigger: &TriggerMatch, config: InlinePromptConfig) -> Self {
+        Self {
+            trigger_chars: trigger.typed_chars,
+            typed: String::new(),
+            config,
+        }
+    }
+
+    pub(crate) fn feed(&mut self, stroke: &KeyStroke) -> CaptureStep {
+        if stroke.injected {
+            return CaptureStep::Continue;
+        }
+        // We cannot tell what a composition will commit, so the count of
+        // characters to erase would be wrong
+        if stroke.composing {
+            return CaptureStep::Abort("ime_composition");
+        }
+        match stroke.key {
+            KeyKind::Modifier => CaptureStep::Continue,
+            // Dead keys type nothing until the next key
+            KeyKind::Character if stroke.text.is_empty() => CaptureStep::Continue,
+            KeyKind::Character => {
+                // Ctrl shortcuts type control characters; Alt without Ctrl is a
+                // menu shortcut, while AltGr (Ctrl+Alt) types real text
+                if stroke.text.chars().any(char::is_control) || (stroke.alt && !stroke.ctrl) {
+                    return CaptureStep::Abort("shortcut");
+                }
+                self.typed.push_str(&stroke.text);
+                let terminator = &self.config.terminator;
+                if !terminator.is_empty() && self.typed.ends_with(terminator.as_str()) {
+                    let erase_chars = self.trigger_chars + self.typed.chars().count();
+                    let prompt = self.typed[..self.typed.len() - terminator.len()].trim();
+                    return self.submit(prompt.to_string(), erase_chars);
+                }
+                if self.typed.chars().count() > self.config.max_prompt_chars {
+                    return CaptureStep::Abort("too_long");
+                }
+                CaptureStep::Continue
+            }
+            KeyKind::Backspace if !stroke.ctrl => {
+                // Backspacing into the trigger means the user changed their mind
+                match self.typed.pop() {
+                    Some(_) => CaptureStep::Continue,
+                    None => CaptureStep::Abort("erased"),
+                }
+            }
+            KeyKind::Enter if self.config.submit_on_enter => {
+                // The newline reached the field as well
+                let erase_chars = self.trigger_chars + self.typed.chars().count() + 1;
+                let prompt = self.typed.trim().to_string();
+                self.submit(prompt, erase_chars)
+            }
+            KeyKind::Escape => CaptureStep::Abort("cancelled"),
+            // The caret moved or the text changed in ways we cannot follow
+            _ => CaptureStep::Abort("caret_moved"),
+        }
+    }
+
+    fn submit(&self, prompt: String, erase_chars: usize) -> CaptureStep {
+        if prompt.is_empty() {
+            return CaptureStep::Abort("empty");
+        }
+        CaptureStep::Submit {
+            prompt,
+            erase_chars,
+        }
+    }
+}
+
+struct PendingPrompt {
+    id: u64,
+    hwnd: isize,
+    erase_chars: usize,
+}
+
+/// Inline prompt handling for the trigger watcher's key stream.
+#[cfg(target_os = "windows")]
+#[derive(Default)]
+pub(crate) struct InlinePrompts {
+    capture: Option<InlineCapture>,
+}
+
+#[cfg(target_os = "windows")]
+impl InlinePrompts {
+    /// Starts capturing a prompt after `trigger`, if inline prompts are on.
+    pub(crate) fn start(&mut self, app: &AppHandle, trigger: &TriggerMatch) {
+        let config = get_inline_prompt_config();
+        if !config.enabled {
+            return;
+        }
+        self.capture = Some(InlineCapture::new(trigger, config));
+        let _ = app.emit(
+            "inline_prompt_capturing",
+            serde_json::json!({ "phrase": trigger.phrase }),
+        );
+    }
+
+    /// Drops the prompt being captured and the one waiting for its answer,
+    /// e.g. because focus moved to a password field.
+    pub(crate) fn interrupt(&mut self, app: &AppHandle, reason: &str) {
+        if self.capture.take().is_some() {
+            let _ = app.emit(
+                "inline_prompt_aborted",
+                serde_json::json!({ "id": null, "reason": reason }),
+            );
+        }
+        abort_pending(app, reason);
+    }
+
+    /// Handles a stroke that belongs to an inline prompt. Returns `false` when
+    /// the stroke should go to the trigger matcher instead.
+    pub(crate) fn handle_key(&mut self, app: &AppHandle, stroke: &KeyStroke) -> bool {
+        if let Some(capture) = self.capture.as_mut() {
+            match capture.feed(stroke) {
+                CaptureStep::Continue => {}
+                CaptureStep::Submit {
+                    prompt,
+                    erase_chars,
+                } => {
+                    self.capture = None;
+                    submit(app, prompt, erase_chars);
+                }
+                CaptureStep::Abort(reason) => {
+                    self.capture = None;
+                    let _ = app.emit(
+                        "inline_prompt_aborted",
+                        serde_json::json!({ "id": null, "reason": reason }),
+                    );
+                }
+            }
+            return true;
+        }
+        if stroke.injected || stroke.key == KeyKind::Modifier {
+            return false;
+        }
+        // Typing while the answer is pending would shift what we erase later
+        let reason = if stroke.key == KeyKind::Escape {
+            "cancelled"
+        } else {
+            "typing"
+        };
+        abort_pending(app, reason) && stroke.key == KeyKind::Escape
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn submit(app: &AppHandle, prompt: String, erase_chars: usize) {
+    let id = NEXT_INLINE_PROMPT_ID.fetch_add(1, Ordering::SeqCst);
+    let hwnd = unsafe { GetForegroundWindow() } as isize;
+    let (source_app, window_title) = active_window_source();
+    if let Ok(mut pending) = PENDING.lock() {
+        *pending = Some(PendingPrompt {
+            id,
+            hwnd,
+            erase_chars,
+        });
+    }
+    set_thinking(app, id, true);
+    let _ = app.emit(
+        "inline_prompt_started",
+        serde_json::json!({ "id": id, "prompt": prompt }),
+    );
+
+    let full_prompt = format!(
+        "The user is typing in {} (window \"{}\") and asked the following. Reply with only the text to insert at their cursor.\n\n{}",
+        if source_app.is_empty() { "an application" } else { &source_app },
+        window_title,
+        prompt
+    );
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let result = generate(&full_prompt).await;
+        let pending = PENDING
+            .lock()
+            .ok()
+            .and_then(|mut p| p.take_if(|p| p.id == id));
+        // Aborted while thinking; the typed prompt stays where it is
+        let Some(pending) = pending else {
+            return;
+        };
+        set_thinking(&app_handle, id, false);
+        let outcome = result.and_then(|answer| apply_answer(&app_handle, &pending, &answer));
+        match outcome {
+            Ok(answer) => {
+                let _ = app_handle.emit(
+                    "inline_prompt_finished",
+                    serde_json::json!({ "id": id, "answer": answer }),
+                );
+            }
+            Err(e) => {
+                let _ = app_handle.emit(
+                    "inline_prompt_failed",
+                    serde_json::json!({ "id": id, "error": e }),
+                );
+            }
+        }
+    });
+}
+
+/// Erases the trigger and prompt, then puts the answer in their place.
+#[cfg(target_os = "windows")]
+fn apply_answer(app: &AppHandle, pending: &PendingPrompt, answer: &str) -> Result<String, String> {
+    let hwnd = pending.hwnd as WinHWND;
+    if unsafe { GetForegroundWindow() } != hwnd {
+        return Err("The target window lost focus".into());
+    }
+    let answer = answer.trim();
+    // Backspaces are injected, so the trigger matcher ignores them
+    for _ in 0..pending.erase_chars {
+        send_key_combo(&[], VK_BACK as u16);
+    }
+    start_formatted_injection(app, answer, format_for_window(hwnd), hwnd)?;
+    Ok(answer.to_string())
+}
+
+/// Drops the pending prompt. Returns whether there was one.
+fn abort_pending(app: &AppHandle, reason: &str) -> bool {
+    let Some(pending) = PENDING.lock().ok().and_then(|mut p| p.take()) else {
+        return false;
+    };
+    set_thinking(app, pending.id, false);
+    let _ = app.emit(
+        "inline_prompt_aborted",
+        serde_json::json!({ "id": pending.id, "reason": reason }),
+    );
+    true
+}
+
+/// Shows or hides the thinking indicator on the magic dot. The dot must not
+/// take focus, the answer is typed into the window in front.
+fn set_thinking(app: &AppHandle, id: u64, thinking: bool) {
+    if thinking {
+        crate::functions::overlay::show_magic_dot_unfocused(app);
+    }
+    let _ = app.emit(
+        "inline_prompt_thinking",
+        serde_json::json!({ "id": id, "thinking": thinking }),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(config: InlinePromptConfig) -> InlineCapture {
+        let trigger = TriggerMatch {
+            phrase: "@rae".to_string(),
+            typed_chars: 4,
+        };
+        InlineCapture::new(&trigger, config)
+    }
+
+    fn config() -> InlinePromptConfig {
+        InlinePromptConfig {
+            enabled: true,
+            ..InlinePromptConfig::default()
+        }
+    }
+
+    fn stroke(key: KeyKind, text: &str) -> KeyStroke {
+        KeyStroke {
+            key,
+            text: text.to_string(),
+            ctrl: false,
+            alt: false,
+            injected: false,
+            composing: false,
+            at: 0,
+        }
+    }
+
+    /// Feeds `text` one character at a time and returns the last step.
+    fn type_text(capture: &mut InlineCapture, text: &str) -> CaptureStep {
+        let mut step = CaptureStep::Continue;
+        for c in text.chars() {
+            step = capture.feed(&stroke(KeyKind::Character, &c.to_string()));
+        }
+        step
+    }
+
+    #[test]
+    fn defaults_are_off_and_do_not_take_enter() {
+        let config = InlinePromptConfig::default();
+        assert!(!config.enabled);
+        assert!(!config.submit_on_enter);
+        assert_eq!(config.terminator, ";;");
+    }
+
+    #[test]
+    fn terminator_submits_the_trimmed_prompt() {
+        let mut c = capture(config());
+        assert_eq!(
+            type_text(&mut c, " what time is it;;"),
+            CaptureStep::Submit {
+                prompt: "what time is it".to_string(),
+                // The trigger, the prompt and the terminator
+                erase_chars: 4 + 18,
+            }
+        );
+    }
+
+    #[test]
+    fn enter_submits_only_when_configured() {
+        let mut c = capture(InlinePromptConfig {
+            submit_on_enter: true,
+            ..config()
+        });
+        type_text(&mut c, " hi");
+        assert_eq!(
+            c.feed(&stroke(KeyKind::Enter, "")),
+            CaptureStep::Submit {
+                prompt: "hi".to_string(),
+                // The newline Enter typed goes too
+                erase_chars: 4 + 3 + 1,
+            }
+        );
+
+        let mut c = capture(config());
+        type_text(&mut c, " hi");
+        assert_eq!(
+            c.feed(&stroke(KeyKind::Enter, "")),
+            CaptureStep::Abort("caret_moved")
+        );
+    }
+
+    #[test]
+    fn empty_prompt_is_not_submitted() {
+        let mut c = capture(config());
+        assert_eq!(type_text(&mut c, " ;;"), CaptureStep::Abort("empty"));
+    }
+
+    #[test]
+    fn backspace_edits_until_the_trigger() {
+        let mut c = capture(config());
+        type_text(&mut c, "ab");
+        let backspace = stroke(KeyKind::Backspace, "");
+        assert_eq!(c.feed(&backspace), CaptureStep::Continue);
+        assert_eq!(c.feed(&backspace), CaptureStep::Continue);
+        assert_eq!(c.feed(&backspace), CaptureStep::Abort("erased"));
+    }
+
+    #[test]
+    fn long_prompts_are_abandoned() {
+        let mut c = capture(InlinePromptConfig {
+            max_prompt_chars: 5,
+            ..config()
+        });
+        assert_eq!(type_text(&mut c, "12345"), CaptureStep::Continue);
+        assert_eq!(type_text(&mut c, "6"), CaptureStep::Abort("too_long"));
+    }
+
+    #[test]
+    fn escape_navigation_and_composition_abort() {
+        let mut c = capture(config());
+        assert_eq!(
+            c.feed(&stroke(KeyKind::Escape, "")),
+            CaptureStep::Abort("cancelled")
+        );
+        let mut c = capture(config());
+        assert_eq!(
+            c.feed(&stroke(KeyKind::Navigation, "")),
+            CaptureStep::Abort("caret_moved")
+        );
+        let mut c = capture(config());
+        let mut composing = stroke(KeyKind::Character, "a");
+        composing.composing = true;
+        assert_eq!(c.feed(&composing), CaptureStep::Abort("ime_composition"));
+    }
+
+    #[test]
+    fn shortcuts_abort_but_altgr_types() {
+        let mut c = capture(config());
+        let mut ctrl_a = stroke(KeyKind::Character, "\u{1}");
+        ctrl_a.ctrl = true;
+        assert_eq!(c.feed(&ctrl_a), CaptureStep::Abort("shortcut"));
+
+        let mut c = capture(config());
+        let mut alt_f = stroke(KeyKind::Character, "f");
+        alt_f.alt = true;
+        assert_eq!(c.feed(&alt_f), CaptureStep::Abort("shortcut"));
+
+        // German layout: AltGr+Q types @
+        let mut c = capture(config());
+        let mut altgr_q = stroke(KeyKind::Character, "@");
+        altgr_q.ctrl = true;
+        altgr_q.alt = true;
+        assert_eq!(c.feed(&altgr_q), CaptureStep::Continue);
+        assert_eq!(
+            type_text(&mut c, ";;"),
+            CaptureStep::Submit {
+                prompt: "@".to_string(),
+                erase_chars: 4 + 3,
+            }
+        );
+    }
+
+    #[test]
+    fn injected_and_dead_keys_are_skipped() {
+        let mut c = capture(config());
+        let mut injected = stroke(KeyKind::Character, "x");
+        injected.injected = true;
+        assert_eq!(c.feed(&injected), CaptureStep::Continue);
+        assert_eq!(
+            c.feed(&stroke(KeyKind::Character, "")),
+            CaptureStep::Continue
+        );
+        assert_eq!(
+            type_text(&mut c, "é;;"),
+            CaptureStep::Submit {
+                prompt: "é".to_string(),
+                erase_chars: 4 + 3,
+            }
+        );
+    }
+}
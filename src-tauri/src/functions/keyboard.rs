@@ -0,0 +1,734 @@
+//! System-wide key events translated into typed text.
+//!
+//! On Windows a low-level keyboard hook runs on its own thread while anyone is
+//! subscribed. Each key press is turned into characters with the keyboard
+//! layout of the foreground window (`ToUnicodeEx`), so `@` and non-US layouts
+//! work without guessing from virtual-key codes. Keys that go into an IME
+//! composition are marked as such (see [`super::ime`]); the text the IME
+//! commits is only seen on Linux. Nothing is published while the watchers are
+//! paused for a password field (see [`super::secure_input`]).
+//!
+//! On X11 the same strokes come from XInput2 raw key events (see
+//! [`crate::x11::KeyWatcher`]), translated with xkbcommon under the server's
+//! current keymap and compose table. Under Wayland only keys typed into
+//! XWayland windows are seen.
+
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+use super::secure_input::stop_unused_secure_input_monitor;
+use super::ime::ImeState;
+use super::secure_input::watchers_paused;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+static SUBSCRIBERS: Mutex<Vec<(u64, Sender<KeyStroke>)>> = Mutex::new(Vec::new());
+static NEXT_SUBSCRIBER_ID: AtomicU64 = AtomicU64::new(1);
+/// Bumped when the last subscriber leaves; a hook thread exits as soon as the
+/// generation it was started for is no longer current.
+static HOOK_GENERATION: AtomicU64 = AtomicU64::new(1);
+/// Generation of the live hook thread, 0 when none runs.
+static HOOK_ACTIVE: AtomicU64 = AtomicU64::new(0);
+/// Why the hook could not be installed or keeps running on an old keymap.
+static HOOK_ERROR: Mutex<Option<String>> = Mutex::new(None);
+/// Thread running the hook's message loop, for posting `WM_QUIT`.
+#[cfg(target_os = "windows")]
+static HOOK_THREAD: Mutex<Option<(u64, u32)>> = Mutex::new(None);
+/// Stops the X event loop of the hook's generation.
+#[cfg(target_os = "linux")]
+static HOOK_STOP_HANDLE: Mutex<Option<(u64, crate::x11::StopHandle)>> = Mutex::new(None);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyKind {
+    /// A key that produces text in the active layout, including dead keys.
+    Character,
+    Backspace,
+    Enter,
+    Escape,
+    Tab,
+    /// Arrows, Home/End and Page Up/Down.
+    Navigation,
+    /// Shift, Ctrl, Alt, the Windows keys and Caps Lock.
+    Modifier,
+    /// Function keys, Delete and everything else that types nothing.
+    Other,
+}
+
+/// One key press as seen by the system, before the focused app handles it.
+#[derive(Clone, Debug)]
+pub struct KeyStroke {
+    pub key: KeyKind,
+    /// Text the key produces with the active layout; empty for dead keys,
+    /// which only take effect with the next key.
+    pub text: String,
+    pub ctrl: bool,
+    pub alt: bool,
+    /// Sent by Rae's own text injection. Text other software injects, such as
+    /// on-screen keyboards or the emoji panel, counts as typed.
+    pub injected: bool,
+    /// Typed into an input method composition, so it is not text yet.
+    pub composing: bool,
+    /// Milliseconds from an arbitrary start; only differences are meaningful.
+    pub at: u64,
+}
+
+/// Starts receiving key strokes. The hook is installed on the first
+/// subscription and removed again with the last [`unsubscribe`].
+pub(crate) fn subscribe() -> (u64, Receiver<KeyStroke>) {
+    let id = NEXT_SUBSCRIBER_ID.fetch_add(1, Ordering::SeqCst);
+    let (sender, receiver) = channel();
+    if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+        subscribers.push((id, sender));
+    }
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    ensure_hook_started();
+    (id, receiver)
+}
+
+pub(crate) fn unsubscribe(id: u64) {
+    let remaining = match SUBSCRIBERS.lock() {
+        Ok(mut subscribers) => {
+            subscribers.retain(|(sid, _)| *sid != id);
+            // Bumped under the lock, so a subscriber joining right after
+            // starts a hook of the new generation instead of relying on this one
+            if subscribers.is_empty() {
+                HOOK_GENERATION.fetch_add(1, Ordering::SeqCst);
+            }
+            subscribers.len()
+        }
+        Err(_) => return,
+    };
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    if remaining == 0 {
+        wake_hook();
+        stop_unused_secure_input_monitor();
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    let _ = remaining;
+}
+
+pub(crate) fn has_subscribers() -> bool {
+    SUBSCRIBERS.lock().is_ok_and(|s| !s.is_empty())
+}
+
+/// Hands a stroke to the subscribers once the input method had its say.
+#[cfg_attr(not(any(target_os = "windows", target_os = "linux")), allow(dead_code))]
+fn publish(stroke: KeyStroke, ime: ImeState) {
+    // Nothing typed into a password field leaves the hook
+    if watchers_paused() {
+        return;
+    }
+    super::ime::settle(stroke, ime, deliver);
+}
+
+/// Hands a stroke to every subscriber, dropping the ones that went away.
+#[cfg_attr(not(any(target_os = "windows", target_os = "linux")), allow(dead_code))]
+fn deliver(stroke: KeyStroke) {
+    if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+        subscribers.retain(|(_, sender)| sender.send(stroke.clone()).is_ok());
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct KeyboardWatcherStatus {
+    pub running: bool,
+    pub error: Option<String>,
+}
+
+#[tauri::command]
+pub fn get_keyboard_watcher_status() -> KeyboardWatcherStatus {
+    KeyboardWatcherStatus {
+        running: HOOK_ACTIVE.load(Ordering::SeqCst) != 0,
+        error: HOOK_ERROR.lock().ok().and_then(|e| e.clone()),
+    }
+}
+
+#[cfg_attr(not(any(target_os = "windows", target_os = "linux")), allow(dead_code))]
+fn set_hook_error(error: Option<String>) {
+    if let Ok(mut current) = HOOK_ERROR.lock() {
+        *current = error;
+    }
+}
+
+/// Whether the hook of `generation` should keep running and publishing.
+#[cfg_attr(not(any(target_os = "windows", target_os = "linux")), allow(dead_code))]
+fn hook_current(generation: u64) -> bool {
+    HOOK_GENERATION.load(Ordering::SeqCst) == generation && has_subscribers()
+}
+
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+fn ensure_hook_started() {
+    let generation = HOOK_GENERATION.load(Ordering::SeqCst);
+    // A hook of an older generation may still be winding down; it no longer counts
+    if HOOK_ACTIVE.swap(generation, Ordering::SeqCst) == generation {
+        return;
+    }
+    std::thread::spawn(move || {
+        run_hook(generation);
+        let _ = HOOK_ACTIVE.compare_exchange(generation, 0, Ordering::SeqCst, Ordering::SeqCst);
+    });
+}
+
+#[cfg(target_os = "windows")]
+fn run_hook(generation: u64) {
+    use winapi::um::libloaderapi::GetModuleHandleW;
+    use winapi::um::processthreadsapi::GetCurrentThreadId;
+    use winapi::um::winuser::{
+        DispatchMessageW, GetKeyState, GetMessageW, SetWindowsHookExW, TranslateMessage,
+        UnhookWindowsHookEx, MSG, VK_CAPITAL, WH_KEYBOARD_LL,
+    };
+    unsafe {
+        // Published before the generation is checked, so a stop request
+        // either reaches the thread or is noticed by the loop condition
+        if let Ok(mut thread) = HOOK_THREAD.lock() {
+            *thread = Some((generation, GetCurrentThreadId()));
+        }
+        HOOK_THREAD_GENERATION.with(|g| g.set(generation));
+        // Best guess until the first Caps Lock press is seen by the hook
+        CAPS_LOCK.with(|c| c.set((GetKeyState(VK_CAPITAL) & 1 != 0, false)));
+        let hook = SetWindowsHookExW(
+            WH_KEYBOARD_LL,
+            Some(low_level_keyboard_proc),
+            GetModuleHandleW(std::ptr::null()),
+            0,
+        );
+        if hook.is_null() {
+            set_hook_error(Some("Failed to install keyboard hook".to_string()));
+        } else {
+            set_hook_error(None);
+            // Low-level hooks and the IME events are called through this
+            // thread's message loop
+            let _compositions = super::ime::watch_compositions(deliver);
+            let mut msg: MSG = std::mem::zeroed();
+            while hook_current(generation) && GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) > 0
+            {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+            UnhookWindowsHookEx(hook);
+        }
+        if let Ok(mut thread) = HOOK_THREAD.lock() {
+            if thread.is_some_and(|(g, _)| g == generation) {
+                *thread = None;
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn wake_hook() {
+    use winapi::um::winuser::{PostThreadMessageW, WM_QUIT};
+    if let Ok(thread) = HOOK_THREAD.lock() {
+        if let Some((_, thread_id)) = *thread {
+            unsafe {
+                PostThreadMessageW(thread_id, WM_QUIT, 0, 0);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+unsafe extern "system" fn low_level_keyboard_proc(
+    code: i32,
+    wparam: winapi::shared::minwindef::WPARAM,
+    lparam: winapi::shared::minwindef::LPARAM,
+) -> winapi::shared::minwindef::LRESULT {
+    use winapi::um::winuser::{
+        CallNextHookEx, HC_ACTION, KBDLLHOOKSTRUCT, VK_CAPITAL, WM_KEYDOWN, WM_SYSKEYDOWN,
+    };
+    let message = wparam as u32;
+    if code == HC_ACTION as i32 {
+        let info = &*(lparam as *const KBDLLHOOKSTRUCT);
+        let pressed = message == WM_KEYDOWN || message == WM_SYSKEYDOWN;
+        if info.vkCode as i32 == VK_CAPITAL {
+            // Toggles on the first press only; held keys repeat key-downs
+            CAPS_LOCK.with(|c| {
+                let (on, down) = c.get();
+                c.set((on ^ (pressed && !down), pressed));
+            });
+        }
+        // A hook of an older generation may still be winding down
+        if pressed && !watchers_paused() && HOOK_THREAD_GENERATION.with(|g| hook_current(g.get())) {
+            let (stroke, ime) = translate_key(info);
+            publish(stroke, ime);
+        }
+    }
+    CallNextHookEx(std::ptr::null_mut(), code, wparam, lparam)
+}
+
+#[cfg(target_os = "windows")]
+thread_local! {
+    /// High surrogate of a `VK_PACKET` character waiting for its low half.
+    static PACKET_HIGH_SURROGATE: std::cell::Cell<Option<u16>> = const { std::cell::Cell::new(None) };
+    /// Generation of the hook installed on this thread.
+    static HOOK_THREAD_GENERATION: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+    /// Caps Lock toggle and whether the key is held, tracked from the hook
+    /// because the hook thread's own key state is never updated.
+    static CAPS_LOCK: std::cell::Cell<(bool, bool)> = const { std::cell::Cell::new((false, false)) };
+}
+
+/// Turns a hook event into a [`KeyStroke`] using the foreground window's
+/// layout, which also tells whether an IME could take the key.
+#[cfg(target_os = "windows")]
+unsafe fn translate_key(info: &winapi::um::winuser::KBDLLHOOKSTRUCT) -> (KeyStroke, ImeState) {
+    use super::injection::RAE_INPUT_MARKER;
+    use winapi::um::winuser::{
+        GetAsyncKeyState, GetForegroundWindow, GetKeyboardLayout, GetWindowThreadProcessId,
+        ToUnicodeEx, LLKHF_ALTDOWN, LLKHF_INJECTED, VK_BACK, VK_CAPITAL, VK_CONTROL, VK_DOWN,
+        VK_END, VK_ESCAPE, VK_HOME, VK_LCONTROL, VK_LEFT, VK_LMENU, VK_LSHIFT, VK_LWIN, VK_MENU,
+        VK_NEXT, VK_PACKET, VK_PRIOR, VK_RCONTROL, VK_RETURN, VK_RIGHT, VK_RMENU, VK_RSHIFT,
+        VK_RWIN, VK_SHIFT, VK_TAB, VK_UP,
+    };
+    let vk = info.vkCode as i32;
+    let down = |vk: i32| (GetAsyncKeyState(vk) as u16 & 0x8000u16) != 0;
+    let ctrl = down(VK_CONTROL);
+    let alt = down(VK_MENU) || info.flags & LLKHF_ALTDOWN != 0;
+
+    let mut key = match vk {
+        VK_BACK => KeyKind::Backspace,
+        VK_RETURN => KeyKind::Enter,
+        VK_ESCAPE => KeyKind::Escape,
+        VK_TAB => KeyKind::Tab,
+        VK_LEFT | VK_RIGHT | VK_UP | VK_DOWN | VK_HOME | VK_END | VK_PRIOR | VK_NEXT => {
+            KeyKind::Navigation
+        }
+        VK_SHIFT | VK_LSHIFT | VK_RSHIFT | VK_CONTROL | VK_LCONTROL | VK_RCONTROL | VK_MENU
+        | VK_LMENU | VK_RMENU | VK_LWIN | VK_RWIN | VK_CAPITAL => KeyKind::Modifier,
+        _ => KeyKind::Character,
+    };
+    let foreground = GetForegroundWindow();
+    let layout = GetKeyboardLayout(GetWindowThreadProcessId(foreground, std::ptr::null_mut()));
+    let mut text = String::new();
+    if vk == VK_PACKET {
+        // `SendInput` with `KEYEVENTF_UNICODE` carries one UTF-16 unit in the
+        // scan code; characters outside the BMP arrive as two packets
+        let unit = info.scanCode as u16;
+        let pending = PACKET_HIGH_SURROGATE.with(|p| p.take());
+        if (0xD800..0xDC00).contains(&unit) {
+            PACKET_HIGH_SURROGATE.with(|p| p.set(Some(unit)));
+        } else {
+            let units: Vec<u16> = pending.into_iter().chain(std::iter::once(unit)).collect();
+            text = String::from_utf16_lossy(&units);
+        }
+    } else if key == KeyKind::Character {
+        // The hook thread's own key state is stale, so rebuild it from the
+        // physical state of the keys that change what a key types
+        let mut state = [0u8; 256];
+        for vk in [
+            VK_SHIFT,
+            VK_LSHIFT,
+            VK_RSHIFT,
+            VK_CONTROL,
+            VK_LCONTROL,
+            VK_RCONTROL,
+            VK_MENU,
+            VK_LMENU,
+            VK_RMENU,
+        ] {
+            if down(vk) {
+                state[vk as usize] = 0x80;
+            }
+        }
+        if CAPS_LOCK.with(|c| c.get().0) {
+            state[VK_CAPITAL as usize] = 0x01;
+        }
+        let mut buffer = [0u16; 8];
+        // Flag 0x4 leaves the dead-key state alone so the app still sees it
+        let written = ToUnicodeEx(
+            vk as u32,
+            info.scanCode,
+            state.as_ptr(),
+            buffer.as_mut_ptr(),
+            buffer.len() as i32,
+            0x4,
+            layout,
+        );
+        match written {
+            n if n > 0 => text = String::from_utf16_lossy(&buffer[..n as usize]),
+            0 => key = KeyKind::Other,
+            _ => {}
+        }
+    }
+    // Unicode packets are committed text even while an IME is open
+    let ime = if vk == VK_PACKET {
+        ImeState::Off
+    } else {
+        super::ime::ime_state(layout)
+    };
+    let stroke = KeyStroke {
+        key,
+        text,
+        ctrl,
+        alt,
+        injected: info.flags & LLKHF_INJECTED != 0 && info.dwExtraInfo == RAE_INPUT_MARKER,
+        composing: false,
+        at: info.time as u64,
+    };
+    (stroke, ime)
+}
+
+#[cfg(target_os = "linux")]
+fn run_hook(generation: u64) {
+    use crate::x11::{KeyEvent, KeyWatcher};
+    let context = xkbcommon::xkb::Context::new(xkbcommon::xkb::CONTEXT_NO_FLAGS);
+    let started = KeyWatcher::new().and_then(|watcher| {
+        let translator = XkbTranslator::new(&context, &watcher.keymap(&context)?);
+        Ok((watcher, translator))
+    });
+    let (watcher, mut translator) = match started {
+        Ok(started) => started,
+        Err(e) => {
+            set_hook_error(Some(format!("Keyboard watcher unavailable: {}", e)));
+            return;
+        }
+    };
+    set_hook_error(None);
+    if let Some(state) = watcher.keyboard_state() {
+        translator.set_state(&state);
+    }
+    // Published before the generation is checked, so a stop request either
+    // reaches the loop or is noticed right here
+    if let Ok(mut stop) = HOOK_STOP_HANDLE.lock() {
+        *stop = Some((generation, watcher.stop_handle()));
+    }
+    if hook_current(generation) {
+        let _compositions = super::ime::watch_compositions(deliver);
+        let _ = watcher.run(|event| match event {
+            KeyEvent::Key {
+                keycode,
+                pressed: true,
+                time,
+            } => {
+                let stroke = translator.key_down(keycode, time as u64);
+                // A hook of an older generation may still be winding down
+                if hook_current(generation) {
+                    let ime = super::ime::ime_state(stroke.at);
+                    publish(stroke, ime);
+                }
+            }
+            KeyEvent::Key { keycode, .. } => translator.key_up(keycode),
+            KeyEvent::State(state) => translator.set_state(&state),
+            KeyEvent::KeymapChanged => match watcher.keymap(&context) {
+                Ok(keymap) => {
+                    translator = XkbTranslator::new(&context, &keymap);
+                    if let Some(state) = watcher.keyboard_state() {
+                        translator.set_state(&state);
+                    }
+                    set_hook_error(None);
+                }
+                Err(e) => set_hook_error(Some(format!("Keeping the previous keymap: {}", e))),
+            },
+        });
+    }
+    if let Ok(mut stop) = HOOK_STOP_HANDLE.lock() {
+        if stop.as_ref().is_some_and(|(g, _)| *g == generation) {
+            *stop = None;
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn wake_hook() {
+    if let Ok(stop) = HOOK_STOP_HANDLE.lock() {
+        if let Some((_, handle)) = stop.as_ref() {
+            handle.stop();
+        }
+    }
+}
+
+/// Keysyms that are sorted into [`KeyKind`]s other than text.
+#[cfg(target_os = "linux")]
+mod keysym {
+    pub const BACKSPACE: u32 = 0xff08;
+    pub const TAB: u32 = 0xff09;
+    pub const RETURN: u32 = 0xff0d;
+    pub const ESCAPE: u32 = 0xff1b;
+    pub const DELETE: u32 = 0xffff;
+    pub const ISO_LEFT_TAB: u32 = 0xfe20;
+    pub const KP_ENTER: u32 = 0xff8d;
+    /// Home, Left, Up, Right, Down, Prior, Next, End.
+    pub const NAVIGATION: std::ops::RangeInclusive<u32> = 0xff50..=0xff57;
+    /// The keypad's navigation keys with Num Lock off.
+    pub const KP_NAVIGATION: std::ops::RangeInclusive<u32> = 0xff95..=0xff9c;
+    /// Shift_L up to Hyper_R, Caps Lock included.
+    pub const MODIFIERS: std::ops::RangeInclusive<u32> = 0xffe1..=0xffee;
+    /// ISO_Lock up to ISO_Level5_Lock, AltGr (ISO_Level3_Shift) included.
+    pub const ISO_MODIFIERS: std::ops::RangeInclusive<u32> = 0xfe01..=0xfe13;
+    pub const MODE_SWITCH: u32 = 0xff7e;
+    pub const NUM_LOCK: u32 = 0xff7f;
+}
+
+#[cfg(target_os = "linux")]
+fn key_kind(sym: u32) -> KeyKind {
+    match sym {
+        keysym::BACKSPACE => KeyKind::Backspace,
+        keysym::RETURN | keysym::KP_ENTER => KeyKind::Enter,
+        keysym::ESCAPE => KeyKind::Escape,
+        keysym::TAB | keysym::ISO_LEFT_TAB => KeyKind::Tab,
+        keysym::DELETE => KeyKind::Other,
+        s if keysym::NAVIGATION.contains(&s) || keysym::KP_NAVIGATION.contains(&s) => {
+            KeyKind::Navigation
+        }
+        s if keysym::MODIFIERS.contains(&s)
+            || keysym::ISO_MODIFIERS.contains(&s)
+            || s == keysym::MODE_SWITCH
+            || s == keysym::NUM_LOCK =>
+        {
+            KeyKind::Modifier
+        }
+        _ => KeyKind::Character,
+    }
+}
+
+/// Turns X keycodes into [`KeyStroke`]s with xkbcommon, tracking modifiers,
+/// the active layout and dead-key sequences the way the focused app does.
+#[cfg(target_os = "linux")]
+struct XkbTranslator {
+    state: xkbcommon::xkb::State,
+    compose: Option<xkbcommon::xkb::compose::State>,
+}
+
+#[cfg(target_os = "linux")]
+impl XkbTranslator {
+    fn new(context: &xkbcommon::xkb::Context, keymap: &xkbcommon::xkb::Keymap) -> Self {
+        use xkbcommon::xkb;
+        // Dead keys and the Compose key follow the user's locale, as in apps
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(std::env::var_os)
+            .find(|v| !v.is_empty())
+            .unwrap_or_else(|| "C".into());
+        let compose =
+            xkb::compose::Table::new_from_locale(context, &locale, xkb::compose::COMPILE_NO_FLAGS)
+                .ok()
+                .map(|table| xkb::compose::State::new(&table, xkb::compose::STATE_NO_FLAGS));
+        Self {
+            state: xkb::State::new(keymap),
+            compose,
+        }
+    }
+
+    fn set_state(&mut self, state: &crate::x11::KeyboardState) {
+        self.state.update_mask(
+            state.depressed_mods,
+            state.latched_mods,
+            state.locked_mods,
+            state.depressed_group,
+            state.latched_group,
+            state.locked_group,
+        );
+    }
+
+    fn key_down(&mut self, keycode: u32, at: u64) -> KeyStroke {
+        use xkbcommon::xkb;
+        let code = xkb::Keycode::from(keycode);
+        let sym = self.state.key_get_one_sym(code);
+        let ctrl = self
+            .state
+            .mod_name_is_active(xkb::MOD_NAME_CTRL, xkb::STATE_MODS_EFFECTIVE);
+        let alt = self
+            .state
+            .mod_name_is_active(xkb::MOD_NAME_ALT, xkb::STATE_MODS_EFFECTIVE);
+        let mut key = key_kind(u32::from(sym));
+        let mut text = String::new();
+        if key == KeyKind::Character {
+            // With Ctrl held this is a control character, as on Windows
+            text = self.state.key_get_utf8(code);
+            if let Some(compose) = self.compose.as_mut().filter(|_| !ctrl) {
+                if compose.feed(sym) == xkb::compose::FeedResult::Accepted {
+                    match compose.status() {
+                        // Like a dead key: nothing typed until the sequence ends
+                        xkb::compose::Status::Composing => text.clear(),
+                        xkb::compose::Status::Composed => {
+                            text = compose.utf8().unwrap_or_default();
+                            compose.reset();
+                        }
+                        xkb::compose::Status::Cancelled => {
+                            text.clear();
+                            compose.reset();
+                        }
+                        xkb::compose::Status::Nothing => {}
+                    }
+                } else if text.is_empty() {
+                    key = KeyKind::Other;
+                }
+            } else if text.is_empty() {
+                key = KeyKind::Other;
+            }
+        }
+        self.state.update_key(code, xkb::KeyDirection::Down);
+        KeyStroke {
+            key,
+            text,
+            ctrl,
+            alt,
+            // Rae does not inject keys on Linux
+            injected: false,
+            composing: false,
+            at,
+        }
+    }
+
+    fn key_up(&mut self, keycode: u32) {
+        use xkbcommon::xkb;
+        self.state
+            .update_key(xkb::Keycode::from(keycode), xkb::KeyDirection::Up);
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::functions::triggers::{TriggerConfig, TriggerMatcher};
+    use crate::x11::KeyboardState;
+    use xkbcommon::xkb;
+
+    // evdev keycodes, as X servers number them
+    const SHIFT_L: u32 = 50;
+    const CONTROL_L: u32 = 37;
+    const ALTGR: u32 = 108;
+    const KEY_2: u32 = 11;
+    const KEY_Q: u32 = 24;
+    const KEY_E: u32 = 26;
+    const KEY_R: u32 = 27;
+    const KEY_A: u32 = 38;
+
+    fn translator(layout: &str) -> XkbTranslator {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let keymap = xkb::Keymap::new_from_names(
+            &context,
+            "evdev",
+            "pc105",
+            layout,
+            "",
+            None,
+            xkb::KEYMAP_COMPILE_NO_FLAGS,
+        )
+        .expect("keymap compiles");
+        XkbTranslator::new(&context, &keymap)
+    }
+
+    /// Presses `keys` in order and releases them in reverse, like a chord.
+    fn chord(translator: &mut XkbTranslator, keys: &[u32]) -> Vec<KeyStroke> {
+        let strokes = keys.iter().map(|&k| translator.key_down(k, 0)).collect();
+        keys.iter().rev().for_each(|&k| translator.key_up(k));
+        strokes
+    }
+
+    /// Types Shift+2, r, a, e and returns the strokes that type something.
+    fn type_at_rae(translator: &mut XkbTranslator) -> Vec<KeyStroke> {
+        let mut strokes = chord(translator, &[SHIFT_L, KEY_2]);
+        for key in [KEY_R, KEY_A, KEY_E] {
+            strokes.extend(chord(translator, &[key]));
+        }
+        strokes.retain(|s| s.key != KeyKind::Modifier);
+        strokes
+    }
+
+    fn matches(strokes: &[KeyStroke]) -> Vec<String> {
+        let mut matcher = TriggerMatcher::new(&TriggerConfig {
+            phrases: vec!["@rae".to_string()],
+            ..TriggerConfig::default()
+        });
+        strokes
+            .iter()
+            .filter_map(|s| matcher.feed(s))
+            .map(|m| m.phrase)
+            .collect()
+    }
+
+    fn text(strokes: &[KeyStroke]) -> String {
+        strokes.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    #[test]
+    fn us_layout_types_trigger() {
+        let strokes = type_at_rae(&mut translator("us"));
+        assert_eq!(text(&strokes), "@rae");
+        assert_eq!(matches(&strokes), vec!["@rae"]);
+    }
+
+    #[test]
+    fn other_layouts_type_their_own_characters() {
+        let strokes = type_at_rae(&mut translator("ru"));
+        assert_eq!(text(&strokes), "\"кфу");
+        assert!(matches(&strokes).is_empty());
+    }
+
+    #[test]
+    fn altgr_types_third_level() {
+        let mut translator = translator("de");
+        let strokes = chord(&mut translator, &[ALTGR, KEY_Q]);
+        assert_eq!(strokes[0].key, KeyKind::Modifier);
+        assert_eq!(strokes[1].text, "@");
+        assert!(!strokes[1].ctrl);
+    }
+
+    #[test]
+    fn ctrl_gives_control_characters() {
+        let mut translator = translator("us");
+        let strokes = chord(&mut translator, &[CONTROL_L, KEY_A]);
+        assert!(strokes[1].ctrl);
+        assert_eq!(strokes[1].text, "\u{1}");
+    }
+
+    #[test]
+    fn server_state_selects_layout() {
+        let mut translator = translator("us,ru");
+        translator.set_state(&KeyboardState {
+            locked_group: 1,
+            ..KeyboardState::default()
+        });
+        assert_eq!(text(&chord(&mut translator, &[KEY_A])), "ф");
+    }
+
+    /// Types through a real X server with XTest, so the XInput2 watcher,
+    /// the keymap it reads and the translator are all exercised.
+    #[test]
+    #[ignore = "needs Xvfb and the XTEST extension"]
+    fn xvfb_typing_reaches_subscribers() {
+        use std::time::Duration;
+        use x11rb::connection::Connection;
+        use x11rb::protocol::xproto::{KEY_PRESS_EVENT, KEY_RELEASE_EVENT};
+        use x11rb::protocol::xtest::ConnectionExt as _;
+
+        let display = (90..200)
+            .find(|n| !std::path::Path::new(&format!("/tmp/.X11-unix/X{}", n)).exists())
+            .expect("a free display number");
+        let mut xvfb = std::process::Command::new("Xvfb")
+            .arg(format!(":{}", display))
+            .spawn()
+            .expect("Xvfb runs");
+        std::env::set_var("DISPLAY", format!(":{}", display));
+        std::thread::sleep(Duration::from_secs(1));
+
+        let (id, keys) = subscribe();
+        // Give the watcher time to select raw events
+        std::thread::sleep(Duration::from_millis(500));
+        let (conn, screen) = x11rb::connect(None).expect("Xvfb accepts connections");
+        let root = conn.setup().roots[screen].root;
+        let fake = |kind: u8, keycode: u32| {
+            conn.xtest_fake_input(kind, keycode as u8, 0, root, 0, 0, 0)
+                .expect("XTEST is available");
+        };
+        fake(KEY_PRESS_EVENT, SHIFT_L);
+        fake(KEY_PRESS_EVENT, KEY_2);
+        fake(KEY_RELEASE_EVENT, KEY_2);
+        fake(KEY_RELEASE_EVENT, SHIFT_L);
+        for key in [KEY_R, KEY_A, KEY_E] {
+            fake(KEY_PRESS_EVENT, key);
+            fake(KEY_RELEASE_EVENT, key);
+        }
+        conn.flush().expect("requests reach Xvfb");
+
+        let mut strokes = Vec::new();
+        while let Ok(stroke) = keys.recv_timeout(Duration::from_secs(2)) {
+            strokes.push(stroke);
+        }
+        unsubscribe(id);
+        let _ = xvfb.kill();
+        let _ = xvfb.wait();
+        strokes.retain(|s| s.key != KeyKind::Modifier);
+        assert_eq!(text(&strokes), "@rae");
+        assert_eq!(matches(&strokes), vec!["@rae"]);
+    }
+}
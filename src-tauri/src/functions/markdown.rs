@@ -0,0 +1,488 @@
+//! Converts Markdown answers into the representation that fits the target
+//! application: plain text, HTML/RTF for rich editors, or bare code.
+
+#[cfg(target_os = "windows")]
+use crate::platform::{exe_path_from_hwnd, get_window_title};
+use pulldown_cmark::{html, Event, HeadingLevel, Options, Parser, Tag};
+use std::sync::{Mutex, OnceLock};
+#[cfg(target_os = "windows")]
+use winapi::shared::windef::HWND as WinHWND;
+
+#[derive(Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// Leave the Markdown untouched.
+    Markdown,
+    /// Strip Markdown syntax, keeping list markers and code indentation.
+    PlainText,
+    /// HTML and RTF clipboard formats, with plain text as a fallback.
+    Rich,
+    /// Only the contents of fenced code blocks.
+    CodeOnly,
+}
+
+/// Maps an application to an output format. `app` is matched case-insensitively
+/// against the executable name (without extension) or as a substring of the
+/// window title, so browser-based editors like Google Docs can be targeted.
+/// An `app` ending in `.exe` only matches the executable file name, for apps
+/// whose name is too common a word to look for in titles.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatRule {
+    pub app: String,
+    pub format: OutputFormat,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatMapping {
+    pub rules: Vec<FormatRule>,
+    pub default_format: OutputFormat,
+}
+
+impl Default for FormatMapping {
+    fn default() -> Self {
+        let rule = |app: &str, format| FormatRule {
+            app: app.to_string(),
+            format,
+        };
+        Self {
+            rules: vec![
+                rule("winword", OutputFormat::Rich),
+                rule("outlook", OutputFormat::Rich),
+                rule("olk", OutputFormat::Rich),
+                rule("onenote", OutputFormat::Rich),
+                rule("google docs", OutputFormat::Rich),
+                rule("code.exe", OutputFormat::CodeOnly),
+                rule("devenv", OutputFormat::CodeOnly),
+                rule("idea64", OutputFormat::CodeOnly),
+                rule("sublime_text", OutputFormat::CodeOnly),
+                rule("windowsterminal", OutputFormat::CodeOnly),
+            ],
+            default_format: OutputFormat::PlainText,
+        }
+    }
+}
+
+fn format_mapping() -> &'static Mutex<FormatMapping> {
+    static MAPPING: OnceLock<Mutex<FormatMapping>> = OnceLock::new();
+    MAPPING.get_or_init(|| Mutex::new(FormatMapping::default()))
+}
+
+#[tauri::command]
+pub fn set_output_format_mapping(mapping: FormatMapping) -> Result<(), String> {
+    *format_mapping().lock().map_err(|e| e.to_string())? = mapping;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_output_format_mapping() -> FormatMapping {
+    format_mapping()
+        .lock()
+        .map(|m| m.clone())
+        .unwrap_or_default()
+}
+
+/// Picks the output format for the application that owns `hwnd`.
+#[cfg(target_os = "windows")]
+pub fn format_for_window(hwnd: WinHWND) -> OutputFormat {
+    let exe = exe_path_from_hwnd(hwnd)
+        .and_then(|p| p.file_name().map(|s| s.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+    format_for_app(&get_output_format_mapping(), &exe, &get_window_title(hwnd))
+}
+
+/// Picks the output format for an app given its executable file name and
+/// window title.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn format_for_app(mapping: &FormatMapping, exe: &str, title: &str) -> OutputFormat {
+    let exe = exe.to_lowercase();
+    let stem = exe.strip_suffix(".exe").unwrap_or(&exe);
+    let title = title.to_lowercase();
+    mapping
+        .rules
+        .iter()
+        .find(|r| {
+            let app = r.app.to_lowercase();
+            if app.is_empty() {
+                false
+            } else if app.ends_with(".exe") {
+                exe == app
+            } else {
+                stem == app || title.contains(&app)
+            }
+        })
+        .map(|r| r.format)
+        .unwrap_or(mapping.default_format)
+}
+
+fn parser(markdown: &str) -> Parser<'_, '_> {
+    Parser::new_ext(
+        markdown,
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS,
+    )
+}
+
+pub fn to_html(markdown: &str) -> String {
+    let mut out = String::new();
+    html::push_html(&mut out, parser(markdown));
+    out
+}
+
+/// Renders Markdown as readable plain text. Lists keep `-`/`1.` markers with
+/// two-space indentation per level and code blocks are indented by four spaces.
+pub fn to_plain_text(markdown: &str) -> String {
+    let mut out = String::new();
+    // One entry per open list: the next ordered number, or None for bullets
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut in_code_block = false;
+    let mut quote_depth = 0;
+    let mut link_urls: Vec<String> = Vec::new();
+
+    for event in parser(markdown) {
+        match event {
+            Event::Start(Tag::Paragraph) | Event::Start(Tag::Heading(..)) => {
+                if lists.is_empty() {
+                    start_block(&mut out);
+                }
+                push_quote_prefix(&mut out, quote_depth);
+            }
+            Event::End(Tag::Paragraph) | Event::End(Tag::Heading(..)) => newline(&mut out),
+            Event::Start(Tag::BlockQuote) => quote_depth += 1,
+            Event::End(Tag::BlockQuote) => quote_depth -= 1,
+            Event::Start(Tag::List(start)) => {
+                if lists.is_empty() {
+                    start_block(&mut out);
+                } else {
+                    newline(&mut out);
+                }
+                lists.push(start);
+            }
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+                newline(&mut out);
+            }
+            Event::Start(Tag::Item) => {
+                newline(&mut out);
+                out.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(n)) => {
+                        out.push_str(&format!("{}. ", n));
+                        *n += 1;
+                    }
+                    _ => out.push_str("- "),
+                }
+            }
+            Event::End(Tag::Item) => {}
+            Event::Start(Tag::CodeBlock(_)) => {
+                start_block(&mut out);
+                in_code_block = true;
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                in_code_block = false;
+                newline(&mut out);
+            }
+            Event::Start(Tag::Link(_, url, _)) => link_urls.push(url.to_string()),
+            Event::End(Tag::Link(..)) => {
+                if let Some(url) = link_urls.pop() {
+                    if !url.is_empty() && !out.ends_with(url.as_str()) {
+                        out.push_str(&format!(" ({})", url));
+                    }
+                }
+            }
+            Event::Start(Tag::TableRow) | Event::Start(Tag::TableHead) => newline(&mut out),
+            Event::End(Tag::TableCell) => out.push('\t'),
+            Event::End(Tag::TableRow) | Event::End(Tag::TableHead) => {
+                while out.ends_with('\t') {
+                    out.pop();
+                }
+            }
+            Event::Start(Tag::Table(_)) => start_block(&mut out),
+            Event::Text(text) if in_code_block => {
+                for line in text.split_inclusive('\n') {
+                    out.push_str("    ");
+                    out.push_str(line);
+                }
+            }
+            Event::Text(text) | Event::Code(text) => out.push_str(&text),
+            Event::SoftBreak => out.push(' '),
+            Event::HardBreak => {
+                out.push('\n');
+                push_quote_prefix(&mut out, quote_depth);
+            }
+            Event::Rule => {
+                start_block(&mut out);
+                out.push_str("----------\n");
+            }
+            Event::TaskListMarker(checked) => out.push_str(if checked { "[x] " } else { "[ ] " }),
+            _ => {}
+        }
+    }
+
+    out.trim_start_matches('\n').trim_end().to_string()
+}
+
+/// Returns only the contents of the code blocks, separated by blank lines.
+/// Falls back to plain text when the answer has no code block at all.
+pub fn to_code_only(markdown: &str) -> String {
+    let mut blocks: Vec<String> = Vec::new();
+    let mut current: Option<String> = None;
+    for event in parser(markdown) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => current = Some(String::new()),
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some(block) = current.take() {
+                    blocks.push(block.trim_end_matches('\n').to_string());
+                }
+            }
+            Event::Text(text) => {
+                if let Some(block) = current.as_mut() {
+                    block.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+    if blocks.is_empty() {
+        return to_plain_text(markdown);
+    }
+    blocks.join("\n\n")
+}
+
+/// Renders Markdown as a minimal RTF document understood by Word and Outlook.
+pub fn to_rtf(markdown: &str) -> String {
+    let mut out = String::from(
+        "{\\rtf1\\ansi\\deff0{\\fonttbl{\\f0\\fswiss Calibri;}{\\f1\\fmodern Consolas;}}\\f0\\fs22\n",
+    );
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut in_code_block = false;
+
+    for event in parser(markdown) {
+        match event {
+            Event::Start(Tag::Paragraph) => {}
+            Event::End(Tag::Paragraph) => out.push_str("\\par\\par\n"),
+            Event::Start(Tag::Heading(level, ..)) => {
+                let size = match level {
+                    HeadingLevel::H1 => 36,
+                    HeadingLevel::H2 => 30,
+                    HeadingLevel::H3 => 26,
+                    _ => 24,
+                };
+                out.push_str(&format!("{{\\b\\fs{} ", size));
+            }
+            Event::End(Tag::Heading(..)) => out.push_str("}\\par\\par\n"),
+            Event::Start(Tag::Emphasis) => out.push_str("{\\i "),
+            Event::Start(Tag::Strong) => out.push_str("{\\b "),
+            Event::Start(Tag::Strikethrough) => out.push_str("{\\strike "),
+            Event::End(Tag::Emphasis)
+            | Event::End(Tag::Strong)
+            | Event::End(Tag::Strikethrough) => out.push('}'),
+            Event::Start(Tag::Link(_, url, _)) => out.push_str(&format!(
+                "{{\\field{{\\*\\fldinst HYPERLINK \"{}\"}}{{\\fldrslt\\ul ",
+                rtf_escape(&url)
+            )),
+            Event::End(Tag::Link(..)) => out.push_str("}}"),
+            Event::Start(Tag::List(start)) => {
+                if !lists.is_empty() {
+                    out.push_str("\\par\n");
+                }
+                lists.push(start);
+            }
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    out.push_str("\\par\n");
+                }
+            }
+            Event::Start(Tag::Item) => {
+                let indent = 360 * lists.len();
+                out.push_str(&format!("\\pard\\li{}\\fi-360 ", indent));
+                match lists.last_mut() {
+                    Some(Some(n)) => {
+                        out.push_str(&format!("{}.\\tab ", n));
+                        *n += 1;
+                    }
+                    _ => out.push_str("\\bullet\\tab "),
+                }
+            }
+            Event::End(Tag::Item) => out.push_str("\\par\\pard\n"),
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code_block = true;
+                out.push_str("{\\f1\\fs20 ");
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                in_code_block = false;
+                out.push_str("}\\par\n");
+            }
+            Event::Start(Tag::BlockQuote) => out.push_str("{\\li720\\i "),
+            Event::End(Tag::BlockQuote) => out.push_str("}\\pard\n"),
+            Event::End(Tag::TableCell) => out.push_str("\\tab "),
+            Event::End(Tag::TableRow) | Event::End(Tag::TableHead) => out.push_str("\\par\n"),
+            Event::Text(text) if in_code_block => {
+                out.push_str(&rtf_escape(&text).replace('\n', "\\line\n"));
+            }
+            Event::Text(text) => out.push_str(&rtf_escape(&text)),
+            Event::Code(text) => out.push_str(&format!("{{\\f1 {}}}", rtf_escape(&text))),
+            Event::SoftBreak => out.push(' '),
+            Event::HardBreak => out.push_str("\\line\n"),
+            Event::Rule => out.push_str("\\pard\\brdrb\\brdrs\\brdrw10\\brsp20 \\par\\pard\n"),
+            Event::TaskListMarker(checked) => out.push_str(if checked { "[x] " } else { "[ ] " }),
+            _ => {}
+        }
+    }
+
+    out.push('}');
+    out
+}
+
+fn rtf_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '{' => out.push_str("\\{"),
+            '}' => out.push_str("\\}"),
+            '\t' => out.push_str("\\tab "),
+            c if c.is_ascii() => out.push(c),
+            c => {
+                // RTF wants signed 16-bit code units followed by an ASCII fallback
+                let mut buf = [0u16; 2];
+                for unit in c.encode_utf16(&mut buf) {
+                    out.push_str(&format!("\\u{}?", *unit as i16));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn newline(out: &mut String) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// Ensures the next block starts after a blank line.
+fn start_block(out: &mut String) {
+    newline(out);
+    if !out.is_empty() && !out.ends_with("\n\n") {
+        out.push('\n');
+    }
+}
+
+fn push_quote_prefix(out: &mut String, depth: usize) {
+    for _ in 0..depth {
+        out.push_str("> ");
+    }
+}
+
+/// Converts Markdown for the given format. For `Rich` this returns plain text;
+/// use [`to_html`] and [`to_rtf`] for the rich clipboard representations.
+pub fn convert(markdown: &str, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Markdown => markdown.to_string(),
+        OutputFormat::PlainText | OutputFormat::Rich => to_plain_text(markdown),
+        OutputFormat::CodeOnly => to_code_only(markdown),
+    }
+}
+
+#[tauri::command]
+pub fn convert_markdown(markdown: String, format: OutputFormat) -> serde_json::Value {
+    match format {
+        OutputFormat::Rich => serde_json::json!({
+            "text": to_plain_text(&markdown),
+            "html": to_html(&markdown),
+            "rtf": to_rtf(&markdown),
+        }),
+        _ => serde_json::json!({ "text": convert(&markdown, format) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANSWER: &str = "# Title\n\nSome **bold** text and [a link](https://example.com).\n\n\
+- one\n- two\n  1. nested\n  2. again\n\n```rust\nfn main() {}\n```\n";
+
+    #[test]
+    fn plain_text_keeps_list_markers_and_indents_code() {
+        assert_eq!(
+            to_plain_text(ANSWER),
+            "Title\n\nSome bold text and a link (https://example.com).\n\n\
+- one\n- two\n  1. nested\n  2. again\n\n    fn main() {}"
+        );
+    }
+
+    #[test]
+    fn plain_text_does_not_repeat_bare_urls() {
+        assert_eq!(
+            to_plain_text("<https://example.com>"),
+            "https://example.com"
+        );
+    }
+
+    #[test]
+    fn plain_text_renders_quotes_tasks_and_tables() {
+        assert_eq!(to_plain_text("> quoted"), "> quoted");
+        assert_eq!(
+            to_plain_text("- [x] done\n- [ ] todo"),
+            "- [x] done\n- [ ] todo"
+        );
+        assert_eq!(
+            to_plain_text("| a | b |\n|---|---|\n| 1 | 2 |"),
+            "a\tb\n1\t2"
+        );
+    }
+
+    #[test]
+    fn code_only_joins_blocks_and_falls_back_to_plain_text() {
+        let markdown = "Intro\n\n```\nfirst\n```\n\nMiddle\n\n```\nsecond\n```";
+        assert_eq!(to_code_only(markdown), "first\n\nsecond");
+        assert_eq!(to_code_only("No *code* here"), "No code here");
+    }
+
+    #[test]
+    fn rtf_escapes_braces_and_non_ascii() {
+        let rtf = to_rtf("{x} \\ é 😀");
+        assert!(rtf.starts_with("{\\rtf1"));
+        assert!(rtf.ends_with('}'));
+        assert!(rtf.contains("\\{x\\} \\\\ \\u233? \\u-10179?\\u-8704?"));
+    }
+
+    #[test]
+    fn rtf_formats_emphasis_and_lists() {
+        let rtf = to_rtf("**b** *i*\n\n1. one\n2. two");
+        assert!(rtf.contains("{\\b b} {\\i i}"));
+        assert!(rtf.contains("1.\\tab one"));
+        assert!(rtf.contains("2.\\tab two"));
+    }
+
+    #[test]
+    fn html_renders_markdown() {
+        assert_eq!(to_html("*hi*"), "<p><em>hi</em></p>\n");
+    }
+
+    #[test]
+    fn convert_leaves_markdown_untouched() {
+        assert_eq!(convert("**x**", OutputFormat::Markdown), "**x**");
+        assert_eq!(convert("**x**", OutputFormat::Rich), "x");
+    }
+
+    #[test]
+    fn exe_rules_do_not_match_titles() {
+        let mapping = FormatMapping::default();
+        let pick = |exe, title| format_for_app(&mapping, exe, title);
+        assert!(pick("Code.exe", "main.rs - Visual Studio Code") == OutputFormat::CodeOnly);
+        assert!(pick("notepad.exe", "qrcode.txt - Notepad") == OutputFormat::PlainText);
+        assert!(pick("chrome.exe", "Promo code - Gmail") == OutputFormat::PlainText);
+    }
+
+    #[test]
+    fn name_rules_match_stem_or_title() {
+        let mapping = FormatMapping::default();
+        let pick = |exe, title| format_for_app(&mapping, exe, title);
+        assert!(pick("WINWORD.EXE", "Document1") == OutputFormat::Rich);
+        assert!(pick("chrome.exe", "Notes - Google Docs") == OutputFormat::Rich);
+    }
+}
@@ -1,5 +1,25 @@
 pub mod chat;
+pub mod classify;
+pub mod clipboard;
+pub mod clipboard_history;
 pub mod general;
+pub mod generate;
+pub mod gestures;
+pub mod ime;
+pub mod injection;
+pub mod inline_prompt;
+pub mod keyboard;
+pub mod markdown;
 pub mod overlay;
+pub mod overlay_state;
+pub mod quick_actions;
+pub mod secure_input;
+pub mod selection_context;
+pub mod selection_copy;
+pub mod selection_gesture;
+pub mod selection_text;
+pub mod sensitive;
+pub mod snippets;
 pub mod stealth;
 pub mod supermemory;
+pub mod triggers;
@@ -1,10 +1,19 @@
-use crate::utils::{smooth_move, smooth_resize};
+use crate::utils::{
+    animate_window, animate_window_from, cancel_window_animation, jump_window, Easing,
+    WindowSnapshot, WindowTransform,
+};
 use enigo::{Enigo, MouseControllable};
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
 use tauri::{AppHandle, Emitter, Manager, WebviewUrl, WebviewWindowBuilder, Monitor};
 
 // Import stealth functions
 use super::stealth::apply_stealth_mode_to_window;
+use super::overlay_state::{
+    commit_overlay_state, current_overlay_state, next_state, OverlayState, OverlayTransition,
+};
 
 // Controls whether toggle_magic_dot is allowed to create the window
 static ALLOW_MAGIC_DOT_CREATE: AtomicBool = AtomicBool::new(true);
@@ -29,48 +38,284 @@ fn get_monitor_for_position(app: &AppHandle, position: &tauri::PhysicalPosition<
     None
 }
 
+/// Requests a change of the overlay's mode. Returns the state the overlay is
+/// in afterwards.
 #[tauri::command]
-pub fn follow_magic_dot(app: AppHandle) {
+pub fn request_overlay_transition(
+    app: AppHandle,
+    transition: OverlayTransition,
+) -> Result<OverlayState, String> {
+    apply_overlay_transition(&app, transition, None)
+}
+
+// Transitions commit one at a time, so the window ends up in the state recorded
+static OVERLAY_TRANSITION: Mutex<()> = Mutex::new(());
+
+// How often a transition is planned again when others keep committing first
+const TRANSITION_ATTEMPTS: usize = 3;
+
+/// Moves the overlay into the state `transition` leads to. `anchor` places
+/// the dot when it starts following; without one it goes next to the cursor.
+///
+/// Window getters wait for the main thread, and main-thread commands run
+/// transitions too, so the geometry is read before taking the lock. Under it
+/// the transition only commits if no other one changed the state meanwhile.
+pub fn apply_overlay_transition(
+    app: &AppHandle,
+    transition: OverlayTransition,
+    anchor: Option<tauri::PhysicalPosition<i32>>,
+) -> Result<OverlayState, String> {
+    run_overlay_transition(app, transition, anchor, true)
+}
+
+/// [`apply_overlay_transition`]; `focus` is whether showing the overlay may
+/// take focus from the app in front.
+fn run_overlay_transition(
+    app: &AppHandle,
+    transition: OverlayTransition,
+    anchor: Option<tauri::PhysicalPosition<i32>>,
+    focus: bool,
+) -> Result<OverlayState, String> {
+    for _ in 0..TRANSITION_ATTEMPTS {
+        let current = current_overlay_state();
+        let Some(next) = next_state(current, transition)? else {
+            return Ok(current);
+        };
+        let placement = plan_overlay_state(app, current, next, anchor, focus)?;
+        let _transition = OVERLAY_TRANSITION.lock().map_err(|e| e.to_string())?;
+        if current_overlay_state() != current {
+            continue;
+        }
+        enter_overlay_state(current, next, placement);
+        commit_overlay_state(app, next);
+        return Ok(next);
+    }
+    Err(format!(
+        "Overlay state kept changing, {:?} was dropped",
+        transition
+    ))
+}
+
+/// What entering a state needs, read from the window up front.
+struct Placement {
+    window: Option<tauri::WebviewWindow>,
+    focus: bool,
+    /// Where the window is now and where it goes.
+    target: Option<(WindowSnapshot, tauri::PhysicalPosition<i32>)>,
+}
+
+fn plan_overlay_state(
+    app: &AppHandle,
+    previous: OverlayState,
+    next: OverlayState,
+    anchor: Option<tauri::PhysicalPosition<i32>>,
+    focus: bool,
+) -> Result<Placement, String> {
+    if next == OverlayState::Hidden {
+        return Ok(Placement {
+            window: app.get_webview_window("overlay"),
+            focus,
+            target: None,
+        });
+    }
+    let window = overlay_window(app).ok_or("Overlay window could not be created")?;
+    let target = match next {
+        OverlayState::Pinned if previous == OverlayState::Notch => None,
+        OverlayState::Pinned => WindowSnapshot::read(&window)
+            .and_then(|snapshot| Some((snapshot, top_center_position(app, &snapshot)?))),
+        OverlayState::Following => WindowSnapshot::read(&window)
+            .and_then(|snapshot| Some((snapshot, anchor.or_else(|| cursor_anchor(&window))?))),
+        OverlayState::Centered => WindowSnapshot::read(&window)
+            .and_then(|snapshot| Some((snapshot, center_position(app, &snapshot)?))),
+        _ => None,
+    };
+    Ok(Placement {
+        window: Some(window),
+        focus,
+        target,
+    })
+}
+
+/// Arranges the overlay window for `next`. Only uses window setters, which do
+/// not wait for the event loop. The events emitted here are the ones the
+/// frontend listened to before `overlay_state_changed` existed.
+fn enter_overlay_state(previous: OverlayState, next: OverlayState, placement: Placement) {
+    let Some(window) = placement.window else {
+        return;
+    };
+    let move_to = |duration: Duration| {
+        if let Some((snapshot, target_pos)) = placement.target {
+            animate_window_from(
+                &window,
+                snapshot,
+                WindowTransform::position(target_pos),
+                duration,
+                Easing::EaseOutCubic,
+            );
+        }
+    };
+    match next {
+        OverlayState::Hidden => {
+            cancel_window_animation(&window);
+            let _ = window.hide();
+        }
+        OverlayState::Notch => {
+            let _ = window.set_ignore_cursor_events(true);
+            NotchWatcher::start(window.clone());
+        }
+        OverlayState::Pinned if previous == OverlayState::Notch => {
+            // The bar grows out of the notch where it is, without taking focus
+            let _ = window.set_ignore_cursor_events(false);
+            let _ = window.emit("notch-hover", ());
+        }
+        OverlayState::Pinned if previous == OverlayState::Pinned => {
+            // Realigned after a resize: snap back without taking focus
+            move_to(Duration::ZERO);
+        }
+        OverlayState::Pinned => {
+            show_overlay_window(&window, placement.focus);
+            let duration = if previous == OverlayState::Hidden {
+                Duration::ZERO
+            } else {
+                Duration::from_millis(96)
+            };
+            move_to(duration);
+            NotchWatcher::start(window.clone());
+        }
+        OverlayState::Following => {
+            show_overlay_window(&window, placement.focus);
+            // Emit events to prevent notch and pinning
+            let _ = window.emit("disable_notch_on_show", ());
+            let _ = window.emit("disable_pin_on_show", ());
+            // Place window at the anchor or near the mouse cursor with smooth animation
+            move_to(Duration::from_millis(64));
+        }
+        OverlayState::Centered => {
+            show_overlay_window(&window, placement.focus);
+            // Emit notch-hover event to expand from notch to bar if currently in notch mode
+            let _ = window.emit("notch-hover", ());
+            // Emit event to unpin the overlay so it doesn't auto-collapse back to notch
+            let _ = window.emit("unpin_for_center", ());
+            move_to(Duration::from_millis(96));
+            NotchWatcher::start(window.clone());
+        }
+        OverlayState::ExpandedChat => show_overlay_window(&window, placement.focus),
+    }
+}
+
+/// The overlay window, created if it does not exist yet.
+fn overlay_window(app: &AppHandle) -> Option<tauri::WebviewWindow> {
     if let Some(window) = app.get_webview_window("overlay") {
-        if let (Ok(current_size), Ok(current_pos)) =
-            (window.outer_size(), window.outer_position())
-        {
-            // Use the helper function to get the correct monitor for the current position
-            if let Some(monitor) = get_monitor_for_position(&app, &current_pos) {
-                let monitor_pos = monitor.position();
-                let screen_size = monitor.size();
-                let center_x = monitor_pos.x + ((screen_size.width as i32 - current_size.width as i32) / 2).max(0);
-                let target_pos = tauri::PhysicalPosition { x: center_x, y: monitor_pos.y };
-                let _ = window.set_position(tauri::Position::Physical(target_pos));
-            }
+        return Some(window);
+    }
+    let window = WebviewWindowBuilder::new(app, "overlay", WebviewUrl::App("/overlay".into()))
+        .title("overlay")
+        .transparent(true)
+        .decorations(false)
+        .resizable(false)
+        .maximizable(false)
+        .skip_taskbar(true)
+        .shadow(false)
+        .always_on_top(true)
+        .inner_size(500.0, 60.0)
+        .build();
+    match window {
+        Ok(window) => {
+            // Apply stealth mode to the newly created window
+            apply_stealth_mode_to_window(app.clone(), "overlay");
+            Some(window)
         }
+        // Another transition created it first
+        Err(_) => app.get_webview_window("overlay"),
+    }
+}
+
+fn show_overlay_window(window: &tauri::WebviewWindow, focus: bool) {
+    if focus {
         let _ = window.show();
         let _ = window.set_focus();
-        let _ = window.set_always_on_top(true);
-        let _ = window.set_ignore_cursor_events(false);
+    } else {
+        show_without_activating(window);
     }
+    let _ = window.set_always_on_top(true);
+    let _ = window.set_ignore_cursor_events(false);
 }
 
-#[tauri::command]
-pub fn pin_magic_dot(app: AppHandle) {
-    if let Some(window) = app.get_webview_window("overlay") {
-        if let (Ok(current_pos), Ok(current_size)) = (
-            window.outer_position(),
-            window.outer_size(),
-        ) {
-            // Use the helper function to get the correct monitor for the current position
-            if let Some(monitor) = get_monitor_for_position(&app, &current_pos) {
-                let monitor_pos = monitor.position();
-                let screen_size = monitor.size();
-                let center_x = monitor_pos.x + ((screen_size.width as i32 - current_size.width as i32) / 2).max(0);
-                let target_pos = tauri::PhysicalPosition { x: center_x, y: monitor_pos.y };
-                NotchWatcher::start(window.clone());
-                smooth_move(&window, current_pos, target_pos, 8, 12);
-            }
+/// Shows `window` while the app in front keeps focus and its caret.
+#[cfg(target_os = "windows")]
+fn show_without_activating(window: &tauri::WebviewWindow) {
+    use winapi::um::winuser::{ShowWindow, SW_SHOWNOACTIVATE};
+    // `show` activates the window on Windows
+    match window.hwnd() {
+        Ok(hwnd) => unsafe {
+            ShowWindow(hwnd.0 as _, SW_SHOWNOACTIVATE);
+        },
+        Err(_) => {
+            let _ = window.show();
         }
     }
 }
 
+#[cfg(not(target_os = "windows"))]
+fn show_without_activating(window: &tauri::WebviewWindow) {
+    let _ = window.show();
+}
+
+/// Where a window of the snapshot's size sits at the top center of its monitor.
+fn top_center_position(
+    app: &AppHandle,
+    snapshot: &WindowSnapshot,
+) -> Option<tauri::PhysicalPosition<i32>> {
+    // Use the helper function to get the correct monitor for the current position
+    let monitor = get_monitor_for_position(app, &snapshot.position())?;
+    let monitor_pos = monitor.position();
+    let screen_size = monitor.size();
+    let current_size = snapshot.size();
+    let center_x =
+        monitor_pos.x + ((screen_size.width as i32 - current_size.width as i32) / 2).max(0);
+    Some(tauri::PhysicalPosition {
+        x: center_x,
+        y: monitor_pos.y,
+    })
+}
+
+/// Where a window of the snapshot's size sits in the middle of its monitor.
+fn center_position(
+    app: &AppHandle,
+    snapshot: &WindowSnapshot,
+) -> Option<tauri::PhysicalPosition<i32>> {
+    let monitor = get_monitor_for_position(app, &snapshot.position())?;
+    let monitor_pos = monitor.position();
+    let screen_size = monitor.size();
+    let current_size = snapshot.size();
+    let center_x =
+        monitor_pos.x + ((screen_size.width as i32 - current_size.width as i32) / 2).max(0);
+    let center_y =
+        monitor_pos.y + ((screen_size.height as i32 - current_size.height as i32) / 2).max(0);
+    Some(tauri::PhysicalPosition {
+        x: center_x,
+        y: center_y,
+    })
+}
+
+#[tauri::command]
+pub fn follow_magic_dot(app: AppHandle) {
+    // Despite the name this has always put the bar at the top center
+    let _ = apply_overlay_transition(&app, OverlayTransition::Pin, None);
+}
+
+#[tauri::command]
+pub fn pin_magic_dot(app: AppHandle) {
+    let _ = apply_overlay_transition(&app, OverlayTransition::Pin, None);
+}
+
+/// Puts the bar back at the top center after the frontend resized it. Only
+/// applies while the overlay is pinned; other states keep their place.
+#[tauri::command]
+pub fn force_top_center_magic_dot(app: AppHandle) {
+    let _ = apply_overlay_transition(&app, OverlayTransition::Realign, None);
+}
+
 #[tauri::command]
 pub fn stick_chat_to_dot(app: AppHandle) {
     std::thread::spawn(move || {
@@ -104,11 +349,7 @@ pub fn stick_chat_to_dot(app: AppHandle) {
 
                     let tx = x.max(0);
                     let ty = y;
-                    if last_sent
-                        .map(|(lx, ly)| lx == tx && ly == ty)
-                        .unwrap_or(false)
-                        == false
-                    {
+                    if last_sent != Some((tx, ty)) {
                         let _ = chat.set_position(tauri::Position::Physical(tauri::PhysicalPosition {
                             x: tx,
                             y: ty,
@@ -125,247 +366,339 @@ pub fn stick_chat_to_dot(app: AppHandle) {
 #[tauri::command]
 pub fn animate_chat_expand(app: AppHandle, to_width: u32, to_height: u32) {
     if let Some(chat) = app.get_webview_window("chat") {
-        if let Ok(current) = chat.outer_size() {
-            smooth_resize(
-                &chat,
-                current,
-                tauri::PhysicalSize {
-                    width: to_width,
-                    height: to_height,
-                },
-                8,
-                12,
-            );
-        }
+        let size = tauri::PhysicalSize {
+            width: to_width,
+            height: to_height,
+        };
+        animate_window(
+            &chat,
+            WindowTransform::size(size),
+            Duration::from_millis(96),
+            Easing::EaseOutCubic,
+        );
     }
 }
 
 #[tauri::command]
 pub fn center_magic_dot(app: AppHandle) {
-    if let Some(window) = app.get_webview_window("overlay") {
-        if let (Ok(size), Ok(current_pos)) = (
-            window.outer_size(),
-            window.outer_position(),
-        ) {
-            // Use the helper function to get the correct monitor for the current position
-            if let Some(monitor) = get_monitor_for_position(&app, &current_pos) {
-                let monitor_pos = monitor.position();
-                let screen_size = monitor.size();
-                window.set_ignore_cursor_events(false).ok();
-                let x = monitor_pos.x + ((screen_size.width as i32 - size.width as i32) / 2).max(0);
-                let y = monitor_pos.y + ((screen_size.height as i32 - size.height as i32) / 2).max(0);
-                smooth_move(
-                    &window,
-                    current_pos,
-                    tauri::PhysicalPosition { x, y },
-                    8,
-                    12,
-                );
-            }
-        }
-    }
+    let _ = apply_overlay_transition(&app, OverlayTransition::Center, None);
 }
 
 #[tauri::command]
 pub fn close_magic_dot(app: AppHandle) {
+    let _transition = OVERLAY_TRANSITION.lock();
     if let Some(window) = app.get_webview_window("overlay") {
+        cancel_window_animation(&window);
         let _ = window.close();
     }
+    commit_overlay_state(&app, OverlayState::Hidden);
 }
 
 #[tauri::command]
 pub fn close_magic_chat(app: AppHandle) {
     if let Some(window) = app.get_webview_window("chat") {
+        cancel_window_animation(&window);
         let _ = window.close();
     }
+    let _ = apply_overlay_transition(&app, OverlayTransition::CloseChat, None);
 }
 
 #[tauri::command]
 pub fn show_overlay_center(app: AppHandle) {
-    if let Some(dot) = app.get_webview_window("overlay") {
-        let _ = dot.show();
-        let _ = dot.set_focus();
-        let _ = dot.set_always_on_top(true);
-        let _ = dot.set_ignore_cursor_events(false);
+    let _ = apply_overlay_transition(&app, OverlayTransition::Center, None);
+}
 
-        // Emit notch-hover event to expand from notch to bar if currently in notch mode
-        let _ = dot.emit("notch-hover", ());
+#[tauri::command]
+pub fn toggle_magic_dot(app: AppHandle) {
+    let window = app.get_webview_window("overlay");
+    let transition = match &window {
+        Some(dot) if dot.is_visible().unwrap_or(false) => OverlayTransition::Hide,
+        Some(_) => OverlayTransition::Pin,
+        None if ALLOW_MAGIC_DOT_CREATE.load(Ordering::Relaxed) => OverlayTransition::Pin,
+        None => return,
+    };
+    let _ = apply_overlay_transition(&app, transition, None);
+}
 
-        // Emit event to unpin the overlay so it doesn't auto-collapse back to notch
-        let _ = dot.emit("unpin_for_center", ());
+// Default notch size (customize with set_notch_config)
+const NOTCH_WIDTH: u32 = 200; // Width of notch
+const NOTCH_HEIGHT: u32 = 20; // Height of notch
+const NOTCH_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Where a notch sits along the top edge of its monitor.
+#[derive(Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotchAnchor {
+    Left,
+    Center,
+    Right,
+}
 
-        if let (Ok(current_size), Ok(current_pos)) =
-            (dot.outer_size(), dot.outer_position())
-        {
-            // Use the helper function to get the correct monitor for the current position
-            if let Some(monitor) = get_monitor_for_position(&app, &current_pos) {
-                let monitor_pos = monitor.position();
-                let screen_size = monitor.size();
-                let center_x = monitor_pos.x +
-                    ((screen_size.width as i32 - current_size.width as i32) / 2).max(0);
-                let center_y = monitor_pos.y +
-                    ((screen_size.height as i32 - current_size.height as i32) / 2).max(0);
-                let target_pos = tauri::PhysicalPosition { x: center_x, y: center_y };
-                smooth_move(&dot, current_pos, target_pos, 12, 8);
-            }
-        }
-        NotchWatcher::start(dot.clone());
-    } else {
-        // If overlay doesn't exist, create it
-        let overlay_window = WebviewWindowBuilder::new(&app, "overlay", WebviewUrl::App("/overlay".into()))
-            .title("rae")
-            .inner_size(500.0, 60.0)
-            .decorations(false)
-            .transparent(true)
-            .always_on_top(true)
-            .resizable(false)
-            .shadow(false)
-            .fullscreen(false)
-            .maximizable(false)
-            .skip_taskbar(true) //that's all
-            .build()
-            .unwrap();
-
-        let _ = overlay_window.show();
-        let _ = overlay_window.set_focus();
-        let _ = overlay_window.set_always_on_top(true);
-
-        // Position in center with smooth animation
-        if let (Ok(current_size), Ok(current_pos)) =
-            (overlay_window.outer_size(), overlay_window.outer_position())
-        {
-            // Use the helper function to get the correct monitor for the current position
-            if let Some(monitor) = get_monitor_for_position(&app, &current_pos) {
-                let monitor_pos = monitor.position();
-                let screen_size = monitor.size();
-                let center_x = monitor_pos.x +
-                    ((screen_size.width as i32 - current_size.width as i32) / 2).max(0);
-                let center_y = monitor_pos.y +
-                    ((screen_size.height as i32 - current_size.height as i32) / 2).max(0);
-                let target_pos = tauri::PhysicalPosition { x: center_x, y: center_y };
-                smooth_move(&overlay_window, current_pos, target_pos, 12, 8);
-            }
-        }
+#[derive(Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct NotchGeometry {
+    pub width: u32,
+    pub height: u32,
+    pub anchor: NotchAnchor,
+    /// Shift from the anchor in physical pixels; positive moves right.
+    pub offset_x: i32,
+}
 
-        NotchWatcher::start(overlay_window);
+impl Default for NotchGeometry {
+    fn default() -> Self {
+        Self {
+            width: NOTCH_WIDTH,
+            height: NOTCH_HEIGHT,
+            anchor: NotchAnchor::Center,
+            offset_x: 0,
+        }
     }
 }
 
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct NotchConfig {
+    /// Geometry of notches on monitors without their own entry.
+    pub geometry: NotchGeometry,
+    /// Geometry by monitor name, as the OS reports it.
+    pub monitors: HashMap<String, NotchGeometry>,
+    /// Put a notch on every monitor instead of only the overlay's.
+    pub all_monitors: bool,
+}
+
+fn notch_config() -> &'static Mutex<NotchConfig> {
+    static CONFIG: OnceLock<Mutex<NotchConfig>> = OnceLock::new();
+    CONFIG.get_or_init(|| Mutex::new(NotchConfig::default()))
+}
+
 #[tauri::command]
-pub fn toggle_magic_dot(app: AppHandle) {
-    if let Some(dot) = app.get_webview_window("overlay") {
-        match dot.is_visible() {
-            Ok(true) => {
-                let _ = dot.hide();
-            }
-            Ok(false) => {
-                let _ = dot.show();
-                let _ = dot.set_focus();
-                let _ = dot.set_always_on_top(true);
-                let _ = dot.set_ignore_cursor_events(false);
-                NotchWatcher::start(dot.clone());
-                if let (Ok(current_size), Ok(current_pos)) =
-                    (dot.outer_size(), dot.outer_position())
-                {
-                    // Use the helper function to get the correct monitor for the current position
-                    if let Some(monitor) = get_monitor_for_position(&app, &current_pos) {
-                        let monitor_pos = monitor.position();
-                        let screen_size = monitor.size();
-                        let center_x = monitor_pos.x +
-                            ((screen_size.width as i32 - current_size.width as i32) / 2).max(0);
-                        let target_pos = tauri::PhysicalPosition { x: center_x, y: monitor_pos.y };
-                        let _ = dot.set_position(tauri::Position::Physical(target_pos));
-                    }
-                }
-            }
-            Err(_) => {
-                let _ = dot.show();
-                let _ = dot.set_ignore_cursor_events(false);
-                NotchWatcher::start(dot.clone());
-            }
+pub fn set_notch_config(config: NotchConfig) -> Result<(), String> {
+    // Running watchers read the config on every check
+    *notch_config().lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_notch_config() -> NotchConfig {
+    notch_config().lock().map(|c| c.clone()).unwrap_or_default()
+}
+
+/// A notch's bounds in physical pixels.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotchArea {
+    pub monitor: Option<String>,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl NotchArea {
+    /// The notch `geometry` gives on the monitor named `monitor`, whose top
+    /// left corner is at `monitor_pos` and which is `screen_width` wide.
+    fn new(
+        monitor: Option<String>,
+        monitor_pos: tauri::PhysicalPosition<i32>,
+        screen_width: u32,
+        geometry: &NotchGeometry,
+    ) -> Self {
+        let width = geometry.width.min(screen_width);
+        let free = (screen_width - width) as i32;
+        let anchor_x = match geometry.anchor {
+            NotchAnchor::Left => 0,
+            NotchAnchor::Center => free / 2,
+            NotchAnchor::Right => free,
+        };
+        Self {
+            monitor,
+            x: monitor_pos.x + anchor_x + geometry.offset_x,
+            y: monitor_pos.y,
+            width,
+            height: geometry.height,
         }
-        return;
     }
-    if !ALLOW_MAGIC_DOT_CREATE.load(Ordering::Relaxed) {
-        return;
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x
+            && x < self.x + self.width as i32
+            && y >= self.y
+            && y < self.y + self.height as i32
     }
-    let _ = WebviewWindowBuilder::new(&app, "overlay", WebviewUrl::App("/overlay".into()))
-        .title("overlay")
-        .transparent(true)
-        .decorations(false)
-        .resizable(false)
-        .shadow(false)
-        .always_on_top(true)
-        .inner_size(500.0, 60.0)
-        .build()
-        .and_then(|w| {
-            let _ = w.show();
-            let _ = w.set_focus();
-            let _ = w.set_ignore_cursor_events(false);
+}
 
-            // Apply stealth mode to the newly created window
-            apply_stealth_mode_to_window(app.clone(), "overlay");
+/// The notches watched for `window`: one per monitor, or only the one on the
+/// window's monitor. The primary monitor stands in while the window has no
+/// position yet.
+fn notch_areas(app: &AppHandle, window: &tauri::WebviewWindow) -> Vec<NotchArea> {
+    let config = get_notch_config();
+    let monitors: Vec<Monitor> = if config.all_monitors {
+        app.available_monitors().unwrap_or_default()
+    } else {
+        window
+            .outer_position()
+            .ok()
+            .and_then(|pos| get_monitor_for_position(app, &pos))
+            .or_else(|| app.primary_monitor().ok().flatten())
+            .into_iter()
+            .collect()
+    };
+    monitors
+        .iter()
+        .map(|monitor| {
+            let geometry = monitor
+                .name()
+                .and_then(|name| config.monitors.get(name))
+                .unwrap_or(&config.geometry);
+            NotchArea::new(
+                monitor.name().cloned(),
+                *monitor.position(),
+                monitor.size().width,
+                geometry,
+            )
+        })
+        .collect()
+}
 
-            NotchWatcher::start(w.clone());
-            Ok(())
-        });
+/// Moves `window` under a notch on another monitor, so it expands there.
+fn move_to_notch(app: &AppHandle, window: &tauri::WebviewWindow, area: &NotchArea) {
+    let (Ok(current_pos), Ok(current_size)) = (window.outer_position(), window.outer_size()) else {
+        return;
+    };
+    let current_monitor =
+        get_monitor_for_position(app, &current_pos).and_then(|m| m.name().cloned());
+    if current_monitor == area.monitor {
+        return;
+    }
+    let target_pos = tauri::PhysicalPosition {
+        x: area.x + (area.width as i32 - current_size.width as i32) / 2,
+        y: area.y,
+    };
+    jump_window(window, WindowTransform::position(target_pos));
 }
 
-/// Continuously records and prints the mouse position in a background thread.
+struct RunningNotchWatcher {
+    label: String,
+    stop: Arc<AtomicBool>,
+}
 
-// Notch area constants (customize as needed)
-const NOTCH_WIDTH: i32 = 200; // Width of notch
-const NOTCH_HEIGHT: i32 = 20; // Height of notch
+// At most one watcher per overlay window, by window label
+static NOTCH_WATCHERS: Mutex<Vec<RunningNotchWatcher>> = Mutex::new(Vec::new());
+// Set by stop_notch_watcher so overlay state changes don't restart the watcher
+static NOTCH_WATCHER_DISABLED: AtomicBool = AtomicBool::new(false);
 
 pub struct NotchWatcher;
 impl NotchWatcher {
+    /// Watches the notch for `window` until stopped or the window closes.
+    /// Does nothing if the window already has a watcher, or while the user
+    /// has the watcher turned off.
     pub fn start(window: tauri::WebviewWindow) {
+        let label = window.label().to_string();
+        let stop = Arc::new(AtomicBool::new(false));
+        {
+            let Ok(mut watchers) = NOTCH_WATCHERS.lock() else {
+                return;
+            };
+            // Checked under the lock so a concurrent stop_notch_watcher wins
+            if NOTCH_WATCHER_DISABLED.load(Ordering::SeqCst)
+                || watchers.iter().any(|w| w.label == label)
+            {
+                return;
+            }
+            watchers.push(RunningNotchWatcher {
+                label: label.clone(),
+                stop: stop.clone(),
+            });
+        }
         std::thread::spawn(move || {
             let enigo = Enigo::new();
-            let app = window.app_handle();
-            loop {
-                // Get current window position and monitor info in each iteration
-                // This ensures we always have the correct monitor info even if window moved
-                let (screen_width, monitor_x) = if let Ok(current_pos) = window.outer_position() {
-                    get_monitor_for_position(&app, &current_pos)
-                        .map(|m| (m.size().width as i32, m.position().x))
-                        .unwrap_or((1920, 0))
-                } else {
-                    (1920, 0)
-                };
-                let notch_x = monitor_x + (screen_width - NOTCH_WIDTH) / 2;
-                let notch_y = 0;
-
+            let app = window.app_handle().clone();
+            while !stop.load(Ordering::SeqCst) && app.get_webview_window(&label).is_some() {
                 let (mouse_x, mouse_y) = enigo.mouse_location();
-                // Check if mouse is inside the centered notch area
-                if mouse_x >= notch_x
-                    && mouse_x < notch_x + NOTCH_WIDTH
-                    && mouse_y >= notch_y
-                    && mouse_y < notch_y + NOTCH_HEIGHT
+                // Areas are re-read every time, the window may have moved to another monitor
+                let hovered = notch_areas(&app, &window)
+                    .into_iter()
+                    .find(|area| area.contains(mouse_x, mouse_y));
+                if let Some(area) =
+                    hovered.filter(|_| current_overlay_state() == OverlayState::Notch)
                 {
-                    let _ = window.set_ignore_cursor_events(false);
-                    // println!("Mouse hovered over notch");
-                    let _ = window.emit("notch-hover", ());
+                    move_to_notch(&app, &window, &area);
+                    let _ = apply_overlay_transition(&app, OverlayTransition::HoverNotch, None);
                 }
-                std::thread::sleep(std::time::Duration::from_millis(100));
+                std::thread::sleep(NOTCH_POLL_INTERVAL);
+            }
+            if let Ok(mut watchers) = NOTCH_WATCHERS.lock() {
+                watchers.retain(|w| !Arc::ptr_eq(&w.stop, &stop));
             }
         });
     }
+
+    /// Stops the watcher of the window labelled `label`. Returns whether one ran.
+    pub fn stop(label: &str) -> bool {
+        let Ok(mut watchers) = NOTCH_WATCHERS.lock() else {
+            return false;
+        };
+        let running = watchers.len();
+        watchers.retain(|w| {
+            if w.label == label {
+                w.stop.store(true, Ordering::SeqCst);
+            }
+            w.label != label
+        });
+        watchers.len() != running
+    }
+
+    pub fn is_running(label: &str) -> bool {
+        NOTCH_WATCHERS
+            .lock()
+            .map(|watchers| watchers.iter().any(|w| w.label == label))
+            .unwrap_or(false)
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotchWatcherStatus {
+    pub running: bool,
+    /// The notches the watcher checks right now.
+    pub areas: Vec<NotchArea>,
 }
 
 #[tauri::command]
-pub fn start_notch_watcher(app: AppHandle) {
-    NotchWatcher::start(app.get_webview_window("overlay").unwrap());
+pub fn start_notch_watcher(app: AppHandle) -> Result<(), String> {
+    let window = app
+        .get_webview_window("overlay")
+        .ok_or("Overlay window not found")?;
+    NOTCH_WATCHER_DISABLED.store(false, Ordering::SeqCst);
+    NotchWatcher::start(window);
+    Ok(())
 }
 
+/// Stops the watcher and keeps it off until `start_notch_watcher` is called.
 #[tauri::command]
-pub fn enable_notch(app: AppHandle) {
-    if let Some(window) = app.get_webview_window("overlay") {
-        // println!("Enabling notch");
-        let _ = window.set_ignore_cursor_events(true);
+pub fn stop_notch_watcher() -> bool {
+    NOTCH_WATCHER_DISABLED.store(true, Ordering::SeqCst);
+    NotchWatcher::stop("overlay")
+}
+
+#[tauri::command]
+pub fn get_notch_watcher_status(app: AppHandle) -> NotchWatcherStatus {
+    let areas = app
+        .get_webview_window("overlay")
+        .map(|window| notch_areas(&app, &window))
+        .unwrap_or_default();
+    NotchWatcherStatus {
+        running: NotchWatcher::is_running("overlay"),
+        areas,
     }
 }
 
+#[tauri::command]
+pub fn enable_notch(app: AppHandle) {
+    // println!("Enabling notch");
+    let _ = apply_overlay_transition(&app, OverlayTransition::Collapse, None);
+}
+
 #[tauri::command]
 pub fn enable_mouse_events(app: AppHandle) {
     if let Some(window) = app.get_webview_window("overlay") {
@@ -375,68 +708,104 @@ pub fn enable_mouse_events(app: AppHandle) {
 }
 #[tauri::command]
 pub fn show_magic_dot(app: AppHandle) {
-    if let Some(dot) = app.get_webview_window("overlay") {
-        let _ = dot.show();
-        let _ = dot.set_focus();
-        let _ = dot.set_always_on_top(true);
-        let _ = dot.set_ignore_cursor_events(false);
-
-        // Emit events to prevent notch and pinning
-        let _ = dot.emit("disable_notch_on_show", ());
-        let _ = dot.emit("disable_pin_on_show", ());
-
-        // Place window near mouse cursor with smooth animation
-        if let Ok(cursor_pos) = dot.cursor_position() {
-            if let Ok(current_pos) = dot.outer_position() {
-                let offset_x = 10.0;
-                let offset_y = 10.0;
-                let target_pos = tauri::PhysicalPosition::new(
-                    (cursor_pos.x + offset_x) as i32,
-                    (cursor_pos.y + offset_y) as i32,
-                );
-                smooth_move(&dot, current_pos, target_pos, 8, 8);
-            }
-        }
-        return;
-    }
+    show_magic_dot_at(app, None);
+}
 
-    let _ = WebviewWindowBuilder::new(&app, "overlay", WebviewUrl::App("/overlay".into()))
-        .title("overlay")
-        .transparent(true)
-        .decorations(false)
-        .resizable(false)
-        .shadow(false)
-        .always_on_top(true)
-        .inner_size(500.0, 60.0)
-        .build()
-        .and_then(|w| {
-            let _ = w.show();
-            let _ = w.set_focus();
-            let _ = w.set_ignore_cursor_events(false);
+/// Shows the magic dot next to the mouse cursor without taking focus, so the
+/// user can keep typing in the app in front.
+pub fn show_magic_dot_unfocused(app: &AppHandle) {
+    let _ = run_overlay_transition(app, OverlayTransition::Follow, None, false);
+}
 
-            // Apply stealth mode to the newly created window
-            apply_stealth_mode_to_window(app.clone(), "overlay");
+/// Shows the magic dot at `anchor`, or next to the mouse cursor when no anchor
+/// is given.
+pub fn show_magic_dot_at(app: AppHandle, anchor: Option<tauri::PhysicalPosition<i32>>) {
+    let _ = apply_overlay_transition(&app, OverlayTransition::Follow, anchor);
+}
 
-            // Emit events to prevent notch and pinning
-            let _ = w.emit("disable_notch_on_show", ());
-            let _ = w.emit("disable_pin_on_show", ());
-
-            // Place new window near mouse cursor with smooth animation
-            if let Ok(cursor_pos) = w.cursor_position() {
-                if let Ok(current_pos) = w.outer_position() {
-                    let offset_x = 10.0;
-                    let offset_y = 10.0;
-                    let target_pos = tauri::PhysicalPosition::new(
-                        (cursor_pos.x + offset_x) as i32,
-                        (cursor_pos.y + offset_y) as i32,
-                    );
-                    smooth_move(&w, current_pos, target_pos, 8, 8);
-                }
-            }
-            Ok(())
-        });
+// Default magic dot position: slightly below and right of the mouse cursor
+fn cursor_anchor(window: &tauri::WebviewWindow) -> Option<tauri::PhysicalPosition<i32>> {
+    let cursor_pos = window.cursor_position().ok()?;
+    let offset_x = 10.0;
+    let offset_y = 10.0;
+    Some(tauri::PhysicalPosition::new(
+        (cursor_pos.x + offset_x) as i32,
+        (cursor_pos.y + offset_y) as i32,
+    ))
 }
 #[tauri::command]
 pub fn set_magic_dot_creation_enabled(enabled: bool) {
     ALLOW_MAGIC_DOT_CREATE.store(enabled, Ordering::Relaxed);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONITOR_X: i32 = 1920;
+
+    fn area(screen_width: u32, geometry: NotchGeometry) -> NotchArea {
+        NotchArea::new(
+            Some("side".to_string()),
+            tauri::PhysicalPosition {
+                x: MONITOR_X,
+                y: -200,
+            },
+            screen_width,
+            &geometry,
+        )
+    }
+
+    /// Where the default-sized notch starts, relative to the monitor's left edge.
+    fn x_on_monitor(anchor: NotchAnchor, offset_x: i32) -> i32 {
+        let geometry = NotchGeometry {
+            anchor,
+            offset_x,
+            ..NotchGeometry::default()
+        };
+        area(1280, geometry).x - MONITOR_X
+    }
+
+    #[test]
+    fn default_notch_is_centered_at_the_top() {
+        let area = area(1280, NotchGeometry::default());
+        assert_eq!(
+            area,
+            NotchArea {
+                monitor: Some("side".to_string()),
+                x: MONITOR_X + 540,
+                y: -200,
+                width: NOTCH_WIDTH,
+                height: NOTCH_HEIGHT,
+            }
+        );
+    }
+
+    #[test]
+    fn anchors_follow_the_monitor_edges() {
+        assert_eq!(x_on_monitor(NotchAnchor::Left, 0), 0);
+        assert_eq!(x_on_monitor(NotchAnchor::Right, 0), 1080);
+        assert_eq!(x_on_monitor(NotchAnchor::Left, 30), 30);
+        assert_eq!(x_on_monitor(NotchAnchor::Right, -30), 1050);
+    }
+
+    #[test]
+    fn notch_wider_than_the_monitor_is_clamped() {
+        let geometry = NotchGeometry {
+            width: 4000,
+            ..NotchGeometry::default()
+        };
+        let area = area(1280, geometry);
+        assert_eq!((area.x, area.width), (MONITOR_X, 1280));
+    }
+
+    #[test]
+    fn contains_excludes_the_far_edges() {
+        let area = area(1280, NotchGeometry::default());
+        assert!(area.contains(area.x, area.y));
+        assert!(area.contains(area.x + 199, area.y + 19));
+        assert!(!area.contains(area.x + 200, area.y));
+        assert!(!area.contains(area.x, area.y + 20));
+        assert!(!area.contains(area.x - 1, area.y));
+    }
+}
@@ -0,0 +1,154 @@
+//! The overlay's mode as an explicit state machine.
+//!
+//! Commands request an [`OverlayTransition`]; [`next_state`] decides what it
+//! leads to from the current [`OverlayState`], and [`super::overlay`] moves the
+//! window accordingly. Every change is announced once with
+//! `overlay_state_changed`.
+
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter};
+
+static OVERLAY_STATE: Mutex<OverlayState> = Mutex::new(OverlayState::Hidden);
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayState {
+    /// No overlay window, or a hidden one.
+    Hidden,
+    /// Collapsed into the notch at the top of the screen; clicks pass through.
+    Notch,
+    /// The bar at the top center of the screen.
+    Pinned,
+    /// The dot next to the cursor or a selection.
+    Following,
+    /// The bar in the middle of the screen.
+    Centered,
+    /// The chat is open below the bar.
+    ExpandedChat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayTransition {
+    Hide,
+    /// Shrink into the notch.
+    Collapse,
+    /// The pointer reached the notch.
+    HoverNotch,
+    Pin,
+    /// Put the pinned bar back at the top center after it was resized.
+    Realign,
+    Follow,
+    Center,
+    OpenChat,
+    CloseChat,
+}
+
+/// Where `transition` leads from `current`. `Ok(None)` means the request does
+/// not apply in this state and is ignored, such as a notch hover while the
+/// bar is already out.
+pub fn next_state(
+    current: OverlayState,
+    transition: OverlayTransition,
+) -> Result<Option<OverlayState>, String> {
+    use OverlayState::*;
+    use OverlayTransition::*;
+    let next = match (transition, current) {
+        (Hide, _) => Hidden,
+        (Collapse | OpenChat, Hidden) => {
+            return Err(format!(
+                "Cannot {:?} while the overlay is hidden",
+                transition
+            ))
+        }
+        (Collapse, _) => Notch,
+        (HoverNotch, Notch) => Pinned,
+        (HoverNotch, _) => return Ok(None),
+        (Pin, _) => Pinned,
+        (Realign, Pinned) => Pinned,
+        (Realign, _) => return Ok(None),
+        (Follow, _) => Following,
+        (Center, _) => Centered,
+        (OpenChat, _) => ExpandedChat,
+        (CloseChat, ExpandedChat) => Pinned,
+        (CloseChat, _) => return Ok(None),
+    };
+    Ok(Some(next))
+}
+
+pub(crate) fn current_overlay_state() -> OverlayState {
+    OVERLAY_STATE
+        .lock()
+        .map(|s| *s)
+        .unwrap_or(OverlayState::Hidden)
+}
+
+/// Records `state` and emits `overlay_state_changed` if it differs from the
+/// current one.
+pub(crate) fn commit_overlay_state(app: &AppHandle, state: OverlayState) {
+    let Ok(mut current) = OVERLAY_STATE.lock() else {
+        return;
+    };
+    if *current == state {
+        return;
+    }
+    let previous = std::mem::replace(&mut *current, state);
+    let _ = app.emit(
+        "overlay_state_changed",
+        serde_json::json!({ "state": state, "previous": previous }),
+    );
+}
+
+#[tauri::command]
+pub fn get_overlay_state() -> OverlayState {
+    current_overlay_state()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use OverlayState::*;
+    use OverlayTransition::*;
+
+    const STATES: [OverlayState; 6] = [Hidden, Notch, Pinned, Following, Centered, ExpandedChat];
+
+    #[test]
+    fn hide_pin_follow_and_center_work_from_anywhere() {
+        for state in STATES {
+            assert_eq!(next_state(state, Hide), Ok(Some(Hidden)));
+            assert_eq!(next_state(state, Pin), Ok(Some(Pinned)));
+            assert_eq!(next_state(state, Follow), Ok(Some(Following)));
+            assert_eq!(next_state(state, Center), Ok(Some(Centered)));
+        }
+    }
+
+    #[test]
+    fn hidden_overlay_cannot_collapse_or_open_chat() {
+        assert!(next_state(Hidden, Collapse).is_err());
+        assert!(next_state(Hidden, OpenChat).is_err());
+        assert_eq!(next_state(Centered, Collapse), Ok(Some(Notch)));
+        assert_eq!(next_state(Following, OpenChat), Ok(Some(ExpandedChat)));
+    }
+
+    #[test]
+    fn notch_hover_only_expands_the_notch() {
+        assert_eq!(next_state(Notch, HoverNotch), Ok(Some(Pinned)));
+        for state in [Hidden, Pinned, Following, Centered, ExpandedChat] {
+            assert_eq!(next_state(state, HoverNotch), Ok(None));
+        }
+    }
+
+    #[test]
+    fn realigning_only_applies_to_the_pinned_bar() {
+        assert_eq!(next_state(Pinned, Realign), Ok(Some(Pinned)));
+        for state in [Hidden, Notch, Following, Centered, ExpandedChat] {
+            assert_eq!(next_state(state, Realign), Ok(None));
+        }
+    }
+
+    #[test]
+    fn closing_chat_returns_to_the_bar() {
+        assert_eq!(next_state(ExpandedChat, CloseChat), Ok(Some(Pinned)));
+        assert_eq!(next_state(Notch, CloseChat), Ok(None));
+    }
+}
@@ -0,0 +1,430 @@
+//! Quick actions that run a prompt against the current selection and put the
+//! result back into the source application. Typing into the source app needs
+//! Windows; elsewhere only results copied to the clipboard are supported.
+#![cfg_attr(not(target_os = "windows"), allow(dead_code))]
+
+use super::chat::last_selection_hwnd;
+#[cfg(not(target_os = "windows"))]
+use super::clipboard::{clipboard_write, ClipboardWrite};
+#[cfg(target_os = "windows")]
+use super::clipboard::{snapshot_clipboard, write_clipboard_formats, write_clipboard_unicode_text};
+use super::generate::generate;
+#[cfg(target_os = "windows")]
+use super::injection::{
+    injected_units, is_injection_running, send_key_combo, send_unicode_units,
+    start_formatted_injection, start_injection,
+};
+#[cfg(target_os = "windows")]
+use super::markdown::{convert, format_for_window, OutputFormat};
+use std::sync::{Mutex, OnceLock};
+#[cfg(target_os = "windows")]
+use std::{thread, time::Duration};
+use tauri::{AppHandle, Emitter};
+use unicode_segmentation::UnicodeSegmentation;
+#[cfg(target_os = "windows")]
+use winapi::shared::windef::HWND as WinHWND;
+#[cfg(target_os = "windows")]
+use winapi::um::winuser::{SetForegroundWindow, VK_BACK, VK_LEFT, VK_RIGHT, VK_SHIFT};
+
+/// Where the result of a quick action ends up.
+#[derive(Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuickActionOutput {
+    /// Type the result over the selection in the source app.
+    Replace,
+    /// Type the result right after the selection.
+    Append,
+    /// Put the result on the clipboard.
+    Copy,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuickAction {
+    pub id: String,
+    pub label: String,
+    /// Prompt template; `{text}` is replaced with the selected text.
+    pub prompt: String,
+    pub output: QuickActionOutput,
+}
+
+impl QuickAction {
+    fn new(id: &str, label: &str, prompt: &str, output: QuickActionOutput) -> Self {
+        Self {
+            id: id.to_string(),
+            label: label.to_string(),
+            prompt: prompt.to_string(),
+            output,
+        }
+    }
+
+    fn render_prompt(&self, text: &str) -> String {
+        if self.prompt.contains("{text}") {
+            self.prompt.replace("{text}", text)
+        } else {
+            format!("{}\n\n{}", self.prompt, text)
+        }
+    }
+}
+
+fn default_quick_actions() -> Vec<QuickAction> {
+    use QuickActionOutput::*;
+    vec![
+        QuickAction::new(
+            "rewrite",
+            "Rewrite",
+            "Rewrite the following text to be clearer. Reply with the rewritten text only.\n\n{text}",
+            Replace,
+        ),
+        QuickAction::new(
+            "fix_grammar",
+            "Fix grammar",
+            "Fix spelling and grammar in the following text without changing its meaning. Reply with the corrected text only.\n\n{text}",
+            Replace,
+        ),
+        QuickAction::new(
+            "translate_en",
+            "Translate to English",
+            "Translate the following text to English. Reply with the translation only.\n\n{text}",
+            Replace,
+        ),
+        QuickAction::new(
+            "summarize",
+            "Summarize",
+            "Summarize the following text in a few sentences.\n\n{text}",
+            Copy,
+        ),
+        QuickAction::new(
+            "explain",
+            "Explain",
+            "Explain the following text in simple terms.\n\n{text}",
+            Copy,
+        ),
+    ]
+}
+
+fn quick_actions() -> &'static Mutex<Vec<QuickAction>> {
+    static ACTIONS: OnceLock<Mutex<Vec<QuickAction>>> = OnceLock::new();
+    ACTIONS.get_or_init(|| Mutex::new(default_quick_actions()))
+}
+
+/// What the last applied action changed, so it can be undone.
+struct AppliedQuickAction {
+    hwnd: isize,
+    output: QuickActionOutput,
+    original: String,
+    /// Injection job that inserted the result, if any.
+    job_id: Option<u64>,
+    /// Text the job typed. `None` when it pasted rich text, whose length in
+    /// the target app is unknown.
+    typed: Option<String>,
+    /// Characters typed before the job started, such as Append's separator.
+    typed_before: usize,
+    /// Every clipboard format from before Copy replaced it.
+    previous_clipboard: Option<Vec<(u32, Vec<u8>)>>,
+}
+
+impl AppliedQuickAction {
+    /// How many characters to select leftwards to cover what was inserted,
+    /// given the UTF-16 units the injection job reports typing.
+    fn inserted_chars(&self, injected_units: usize) -> Result<usize, String> {
+        let typed = match (self.job_id, &self.typed) {
+            (None, _) => return Ok(self.typed_before),
+            (Some(_), Some(typed)) => typed,
+            (Some(_), None) => return Err("A result pasted as rich text cannot be undone".into()),
+        };
+        Ok(graphemes_in_units(typed, injected_units) + self.typed_before)
+    }
+}
+
+/// User-perceived characters in the first `units` UTF-16 units of `text`.
+/// The caret moves a whole grapheme per arrow key, so an emoji or a letter
+/// with a combining accent is one step however many units it takes.
+fn graphemes_in_units(text: &str, units: usize) -> usize {
+    let mut end = 0;
+    let mut counted = 0;
+    for c in text.chars() {
+        if counted + c.len_utf16() > units {
+            break;
+        }
+        counted += c.len_utf16();
+        end += c.len_utf8();
+    }
+    text[..end].graphemes(true).count()
+}
+
+static LAST_APPLIED: Mutex<Option<AppliedQuickAction>> = Mutex::new(None);
+
+#[tauri::command]
+pub fn get_quick_actions() -> Vec<QuickAction> {
+    quick_actions()
+        .lock()
+        .map(|a| a.clone())
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+pub fn set_quick_actions(actions: Vec<QuickAction>) -> Result<(), String> {
+    *quick_actions().lock().map_err(|e| e.to_string())? = actions;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn reset_quick_actions() -> Result<(), String> {
+    set_quick_actions(default_quick_actions())
+}
+
+/// Runs the quick action `action_id` against `text` and applies the result.
+///
+/// `hwnd` defaults to the window of the last `text_selected` event.
+#[tauri::command]
+pub async fn run_quick_action(
+    app: AppHandle,
+    action_id: String,
+    text: String,
+    hwnd: Option<isize>,
+) -> Result<String, String> {
+    let action = get_quick_actions()
+        .into_iter()
+        .find(|a| a.id == action_id)
+        .ok_or_else(|| format!("Unknown quick action '{}'", action_id))?;
+    let hwnd = hwnd
+        .or_else(last_selection_hwnd)
+        .ok_or("No source window for quick action")?;
+
+    let _ = app.emit(
+        "quick_action_started",
+        serde_json::json!({ "actionId": action.id }),
+    );
+
+    let result = match generate(&action.render_prompt(&text)).await {
+        Ok(result) => result.trim().to_string(),
+        Err(e) => {
+            let _ = app.emit(
+                "quick_action_failed",
+                serde_json::json!({ "actionId": action.id, "error": e }),
+            );
+            return Err(e);
+        }
+    };
+
+    let applied = apply_output(&app, action.output, hwnd, &text, &result)?;
+    if let Ok(mut last) = LAST_APPLIED.lock() {
+        *last = Some(applied);
+    }
+
+    let _ = app.emit(
+        "quick_action_finished",
+        serde_json::json!({
+            "actionId": action.id,
+            "output": action.output,
+            "result": result,
+        }),
+    );
+    Ok(result)
+}
+
+#[cfg(target_os = "windows")]
+fn apply_output(
+    app: &AppHandle,
+    output: QuickActionOutput,
+    hwnd: isize,
+    original: &str,
+    result: &str,
+) -> Result<AppliedQuickAction, String> {
+    let mut applied = AppliedQuickAction {
+        hwnd,
+        output,
+        original: original.to_string(),
+        job_id: None,
+        typed: None,
+        typed_before: 0,
+        previous_clipboard: None,
+    };
+    // Results are Markdown; convert them like any other answer for the target
+    let format = format_for_window(hwnd as WinHWND);
+    if format != OutputFormat::Rich {
+        applied.typed = Some(convert(result, format));
+    }
+    match output {
+        QuickActionOutput::Replace => {
+            // Typing over the still-active selection replaces it
+            applied.job_id = Some(start_formatted_injection(
+                app,
+                result,
+                format,
+                hwnd as WinHWND,
+            )?);
+        }
+        QuickActionOutput::Append => {
+            focus_window(hwnd)?;
+            // Collapse the selection to its end before typing
+            send_key_combo(&[], VK_RIGHT as u16);
+            send_unicode_units(&[b' ' as u16]);
+            applied.typed_before = 1;
+            applied.job_id = Some(start_formatted_injection(
+                app,
+                result,
+                format,
+                hwnd as WinHWND,
+            )?);
+        }
+        QuickActionOutput::Copy => unsafe {
+            applied.previous_clipboard = snapshot_clipboard();
+            if !write_clipboard_unicode_text(result) {
+                return Err("Failed to write to clipboard".into());
+            }
+        },
+    }
+    Ok(applied)
+}
+
+/// Reverts the last applied quick action, putting the original text back.
+#[cfg(target_os = "windows")]
+#[tauri::command]
+pub fn undo_quick_action(app: AppHandle) -> Result<(), String> {
+    if is_injection_running() {
+        return Err("Quick action is still being typed".into());
+    }
+    let mut last = LAST_APPLIED.lock().map_err(|e| e.to_string())?;
+    let applied = last.as_ref().ok_or("Nothing to undo")?;
+    // Only what was actually typed can be selected again, which may be less
+    // than the result when the injection was cancelled
+    let injected = match applied.job_id {
+        Some(job_id) => {
+            injected_units(job_id).ok_or("The quick action was followed by another injection")?
+        }
+        None => 0,
+    };
+    let inserted = applied.inserted_chars(injected)?;
+    let applied = last.take().ok_or("Nothing to undo")?;
+    drop(last);
+
+    match applied.output {
+        QuickActionOutput::Replace => {
+            focus_window(applied.hwnd)?;
+            select_backwards(inserted);
+            start_injection(&app, applied.original, applied.hwnd as WinHWND)?;
+        }
+        QuickActionOutput::Append => {
+            focus_window(applied.hwnd)?;
+            select_backwards(inserted);
+            send_key_combo(&[], VK_BACK as u16);
+        }
+        QuickActionOutput::Copy => {
+            let previous = applied
+                .previous_clipboard
+                .ok_or("The clipboard from before the quick action was not saved")?;
+            if !unsafe { write_clipboard_formats(&previous) } {
+                return Err("Failed to restore the clipboard".into());
+            }
+        }
+    }
+
+    let _ = app.emit("quick_action_undone", serde_json::json!({}));
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn focus_window(hwnd: isize) -> Result<(), String> {
+    unsafe {
+        if SetForegroundWindow(hwnd as WinHWND) == 0 {
+            return Err("Failed to bring source window to foreground".into());
+        }
+    }
+    thread::sleep(Duration::from_millis(50));
+    Ok(())
+}
+
+/// Extends the selection leftwards over the last `count` characters typed.
+#[cfg(target_os = "windows")]
+fn select_backwards(count: usize) {
+    for _ in 0..count {
+        send_key_combo(&[VK_SHIFT as u16], VK_LEFT as u16);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn apply_output(
+    _app: &AppHandle,
+    output: QuickActionOutput,
+    hwnd: isize,
+    original: &str,
+    result: &str,
+) -> Result<AppliedQuickAction, String> {
+    if output != QuickActionOutput::Copy {
+        return Err("Typing into other apps is only supported on Windows".into());
+    }
+    clipboard_write(ClipboardWrite {
+        text: Some(result.to_string()),
+        ..ClipboardWrite::default()
+    })?;
+    Ok(AppliedQuickAction {
+        hwnd,
+        output,
+        original: original.to_string(),
+        job_id: None,
+        typed: None,
+        typed_before: 0,
+        previous_clipboard: None,
+    })
+}
+
+#[cfg(not(target_os = "windows"))]
+#[tauri::command]
+pub fn undo_quick_action(_app: AppHandle) -> Result<(), String> {
+    Err("Undoing quick actions is only supported on Windows".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(typed: Option<&str>, typed_before: usize) -> AppliedQuickAction {
+        AppliedQuickAction {
+            hwnd: 0,
+            output: QuickActionOutput::Append,
+            original: "original".into(),
+            job_id: Some(1),
+            typed: typed.map(str::to_string),
+            typed_before,
+            previous_clipboard: None,
+        }
+    }
+
+    #[test]
+    fn prompt_puts_the_text_in_place_or_after() {
+        let action = QuickAction::new("a", "A", "Fix: {text}!", QuickActionOutput::Replace);
+        assert_eq!(action.render_prompt("teh"), "Fix: teh!");
+        let action = QuickAction::new("a", "A", "Summarize", QuickActionOutput::Copy);
+        assert_eq!(action.render_prompt("long"), "Summarize\n\nlong");
+    }
+
+    #[test]
+    fn undo_counts_graphemes_not_utf16_units() {
+        // e + combining acute, a thumbs up with a skin tone, and a CRLF
+        let typed = "ne\u{301} 👍🏽\r\n";
+        assert_eq!(typed.encode_utf16().count(), 10);
+        assert_eq!(applied(Some(typed), 0).inserted_chars(10), Ok(5));
+        assert_eq!(applied(Some(typed), 1).inserted_chars(10), Ok(6));
+    }
+
+    #[test]
+    fn undo_covers_only_what_a_cancelled_job_typed() {
+        let typed = "ab👍🏽cd";
+        assert_eq!(applied(Some(typed), 0).inserted_chars(2), Ok(2));
+        // Stopped inside the emoji: only whole characters count
+        assert_eq!(applied(Some(typed), 0).inserted_chars(3), Ok(2));
+        assert_eq!(applied(Some(typed), 0).inserted_chars(6), Ok(3));
+    }
+
+    #[test]
+    fn rich_pastes_cannot_be_undone() {
+        assert!(applied(None, 1).inserted_chars(4).is_err());
+        let copied = AppliedQuickAction {
+            job_id: None,
+            ..applied(None, 0)
+        };
+        assert_eq!(copied.inserted_chars(0), Ok(0));
+    }
+}
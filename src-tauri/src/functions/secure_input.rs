@@ -0,0 +1,519 @@
+//! Pauses the watchers while the user types a password.
+//!
+//! A monitor follows keyboard focus and pauses key, clipboard and selection
+//! watching while it sits in a password field or in a known credential prompt.
+//! On Windows focus WinEvents trigger a check of the focused UI Automation
+//! element's `IsPassword`. On Linux AT-SPI focus events are followed and
+//! checked for the password-text role. Each change emits `watchers_paused` or
+//! `watchers_resumed`.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tauri::{AppHandle, Emitter};
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+/// Bumped on every pause so watchers can drop state that spans one.
+static PAUSE_EPOCH: AtomicU64 = AtomicU64::new(0);
+/// Bumped by every stop request; a monitor thread exits as soon as the
+/// generation it was started for is no longer current.
+static MONITOR_GENERATION: AtomicU64 = AtomicU64::new(1);
+/// Generation of the live monitor thread, 0 when none runs.
+static MONITOR_ACTIVE: AtomicU64 = AtomicU64::new(0);
+/// Thread running the monitor's message loop, for posting `WM_QUIT`.
+#[cfg(target_os = "windows")]
+static MONITOR_THREAD: Mutex<Option<(u64, u32)>> = Mutex::new(None);
+/// Connection the monitor listens on, for waking it up with a signal.
+#[cfg(target_os = "linux")]
+static MONITOR_BUS: Mutex<Option<(u64, zbus::blocking::Connection)>> = Mutex::new(None);
+static PAUSE_REASON: Mutex<Option<SecureInputReason>> = Mutex::new(None);
+
+#[derive(Clone, Copy, PartialEq, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecureInputReason {
+    PasswordField,
+    CredentialPrompt,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SecureInputConfig {
+    pub enabled: bool,
+    /// Apps that only ask for credentials, by executable name (with or
+    /// without `.exe`) on Windows and application name on Linux.
+    pub credential_apps: Vec<String>,
+}
+
+impl Default for SecureInputConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            credential_apps: [
+                // Windows Security credential dialog, UAC and sign-in screen
+                "CredentialUIBroker",
+                "consent",
+                "LogonUI",
+                // GnuPG, GNOME Keyring, polkit and SSH passphrase prompts
+                "pinentry",
+                "pinentry-gnome3",
+                "pinentry-gtk-2",
+                "pinentry-qt",
+                "gcr-prompter",
+                "polkit-gnome-authentication-agent-1",
+                "polkit-kde-authentication-agent-1",
+                "ssh-askpass",
+                "ksshaskpass",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+        }
+    }
+}
+
+fn secure_input_config() -> &'static Mutex<SecureInputConfig> {
+    static CONFIG: OnceLock<Mutex<SecureInputConfig>> = OnceLock::new();
+    CONFIG.get_or_init(|| Mutex::new(SecureInputConfig::default()))
+}
+
+#[tauri::command]
+pub fn set_secure_input_config(app: AppHandle, config: SecureInputConfig) -> Result<(), String> {
+    let enabled = config.enabled;
+    *secure_input_config().lock().map_err(|e| e.to_string())? = config;
+    if !enabled {
+        set_pause(&app, None, "");
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_secure_input_config() -> SecureInputConfig {
+    secure_input_config()
+        .lock()
+        .map(|c| c.clone())
+        .unwrap_or_default()
+}
+
+/// Why the watchers are paused, or `None` while they run.
+#[tauri::command]
+pub fn get_watchers_paused() -> Option<SecureInputReason> {
+    PAUSE_REASON.lock().ok().and_then(|r| *r)
+}
+
+/// Whether watchers must leave keys, the clipboard and selections alone.
+pub(crate) fn watchers_paused() -> bool {
+    PAUSED.load(Ordering::SeqCst)
+}
+
+/// Changes whenever a pause starts.
+pub(crate) fn pause_epoch() -> u64 {
+    PAUSE_EPOCH.load(Ordering::SeqCst)
+}
+
+fn is_credential_app(credential_apps: &[String], app: &str) -> bool {
+    !app.is_empty()
+        && credential_apps.iter().any(|c| {
+            let c = c.trim();
+            let c = c
+                .strip_suffix(".exe")
+                .or_else(|| c.strip_suffix(".EXE"))
+                .unwrap_or(c);
+            c.eq_ignore_ascii_case(app)
+        })
+}
+
+/// What focus on a field of `app` means for the watchers.
+fn classify_focus(
+    config: &SecureInputConfig,
+    is_password: bool,
+    app: &str,
+) -> Option<SecureInputReason> {
+    if !config.enabled {
+        None
+    } else if is_password {
+        Some(SecureInputReason::PasswordField)
+    } else if is_credential_app(&config.credential_apps, app) {
+        Some(SecureInputReason::CredentialPrompt)
+    } else {
+        None
+    }
+}
+
+fn set_pause(app: &AppHandle, reason: Option<SecureInputReason>, source_app: &str) {
+    let Ok(mut current) = PAUSE_REASON.lock() else {
+        return;
+    };
+    if *current == reason {
+        return;
+    }
+    *current = reason;
+    match reason {
+        Some(reason) => {
+            PAUSE_EPOCH.fetch_add(1, Ordering::SeqCst);
+            PAUSED.store(true, Ordering::SeqCst);
+            let _ = app.emit(
+                "watchers_paused",
+                serde_json::json!({ "reason": reason, "app": source_app }),
+            );
+        }
+        None => {
+            PAUSED.store(false, Ordering::SeqCst);
+            let _ = app.emit("watchers_resumed", serde_json::json!({}));
+        }
+    }
+}
+
+/// The monitor runs while anything watches keys, the clipboard or selections.
+fn monitor_wanted() -> bool {
+    super::chat::any_watcher_enabled() || super::keyboard::has_subscribers()
+}
+
+/// Starts following focus. Watchers call [`stop_unused_secure_input_monitor`]
+/// when they stop, which ends the monitor and any pause once none runs.
+pub(crate) fn ensure_secure_input_monitor_started(app: &AppHandle) {
+    let generation = MONITOR_GENERATION.load(Ordering::SeqCst);
+    // A monitor of an older generation may still be winding down; it no longer counts
+    if MONITOR_ACTIVE.swap(generation, Ordering::SeqCst) == generation {
+        return;
+    }
+    let app_handle = app.clone();
+    std::thread::spawn(move || {
+        run_monitor(&app_handle, generation);
+        // A newer monitor owns the pause now; leave it in place until it decides
+        if MONITOR_ACTIVE
+            .compare_exchange(generation, 0, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            set_pause(&app_handle, None, "");
+        }
+    });
+}
+
+/// Stops the monitor when no watcher needs it any more.
+pub(crate) fn stop_unused_secure_input_monitor() {
+    if monitor_wanted() {
+        return;
+    }
+    MONITOR_GENERATION.fetch_add(1, Ordering::SeqCst);
+    wake_monitor();
+}
+
+fn monitor_current(generation: u64) -> bool {
+    MONITOR_GENERATION.load(Ordering::SeqCst) == generation && monitor_wanted()
+}
+
+#[cfg(target_os = "windows")]
+fn wake_monitor() {
+    use winapi::um::winuser::{PostThreadMessageW, WM_QUIT};
+    if let Ok(thread) = MONITOR_THREAD.lock() {
+        if let Some((_, thread_id)) = *thread {
+            unsafe {
+                PostThreadMessageW(thread_id, WM_QUIT, 0, 0);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+thread_local! {
+    /// What the focus callback needs, set while the monitor runs on this thread.
+    static FOCUS_MONITOR: std::cell::RefCell<
+        Option<(AppHandle, windows::Win32::UI::Accessibility::IUIAutomation)>,
+    > = const { std::cell::RefCell::new(None) };
+}
+
+#[cfg(target_os = "windows")]
+fn run_monitor(app: &AppHandle, generation: u64) {
+    use winapi::um::processthreadsapi::GetCurrentThreadId;
+    use winapi::um::winuser::{
+        DispatchMessageW, GetMessageW, SetWinEventHook, TranslateMessage, UnhookWinEvent,
+        EVENT_OBJECT_FOCUS, MSG, WINEVENT_OUTOFCONTEXT, WINEVENT_SKIPOWNPROCESS,
+    };
+    use windows::Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_INPROC_SERVER,
+        COINIT_APARTMENTTHREADED,
+    };
+    use windows::Win32::UI::Accessibility::{CUIAutomation, IUIAutomation};
+    unsafe {
+        let _hr = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+        let automation: Option<IUIAutomation> =
+            CoCreateInstance(&CUIAutomation, None, CLSCTX_INPROC_SERVER).ok();
+        if let Some(automation) = automation {
+            // Published before the generation is checked, so a stop request
+            // either reaches the thread or is noticed by the loop condition
+            if let Ok(mut thread) = MONITOR_THREAD.lock() {
+                *thread = Some((generation, GetCurrentThreadId()));
+            }
+            FOCUS_MONITOR.with(|m| *m.borrow_mut() = Some((app.clone(), automation)));
+            check_focus();
+            // Out-of-context events are delivered through this thread's message loop
+            let hook = SetWinEventHook(
+                EVENT_OBJECT_FOCUS,
+                EVENT_OBJECT_FOCUS,
+                std::ptr::null_mut(),
+                Some(on_focus_event),
+                0,
+                0,
+                WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS,
+            );
+            if hook.is_null() {
+                println!("Secure input monitor unavailable: focus events could not be hooked");
+            } else {
+                let mut msg: MSG = std::mem::zeroed();
+                while monitor_current(generation)
+                    && GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) > 0
+                {
+                    TranslateMessage(&msg);
+                    DispatchMessageW(&msg);
+                }
+                UnhookWinEvent(hook);
+            }
+            FOCUS_MONITOR.with(|m| m.borrow_mut().take());
+            if let Ok(mut thread) = MONITOR_THREAD.lock() {
+                if thread.is_some_and(|(g, _)| g == generation) {
+                    *thread = None;
+                }
+            }
+        } else {
+            println!("Secure input monitor unavailable: UI Automation failed to start");
+        }
+        CoUninitialize();
+    }
+}
+
+#[cfg(target_os = "windows")]
+unsafe extern "system" fn on_focus_event(
+    _hook: winapi::shared::windef::HWINEVENTHOOK,
+    _event: winapi::shared::minwindef::DWORD,
+    _hwnd: winapi::shared::windef::HWND,
+    _object: winapi::shared::ntdef::LONG,
+    _child: winapi::shared::ntdef::LONG,
+    _thread: winapi::shared::minwindef::DWORD,
+    _time: winapi::shared::minwindef::DWORD,
+) {
+    check_focus();
+}
+
+/// Pauses or resumes the watchers for the element that has focus now.
+#[cfg(target_os = "windows")]
+fn check_focus() {
+    use super::clipboard_history::active_window_source;
+    let Some((app, automation)) = FOCUS_MONITOR.with(|m| m.borrow().clone()) else {
+        return;
+    };
+    let is_password = unsafe {
+        automation
+            .GetFocusedElement()
+            .and_then(|e| e.CurrentIsPassword())
+            .map(|b| b.as_bool())
+            .unwrap_or(false)
+    };
+    let source_app = active_window_source().0;
+    let reason = classify_focus(&get_secure_input_config(), is_password, &source_app);
+    set_pause(&app, reason, &source_app);
+}
+
+/// AT-SPI role of password entries.
+#[cfg(target_os = "linux")]
+const ATSPI_ROLE_PASSWORD_TEXT: u32 = 40;
+#[cfg(target_os = "linux")]
+const ATSPI_REGISTRY: &str = "org.a11y.atspi.Registry";
+#[cfg(target_os = "linux")]
+const ATSPI_REGISTRY_PATH: &str = "/org/a11y/atspi/registry";
+
+#[cfg(target_os = "linux")]
+fn run_monitor(app: &AppHandle, generation: u64) {
+    if let Err(e) = follow_atspi_focus(app, generation) {
+        println!("Secure input monitor unavailable: {}", e);
+    }
+    if let Ok(mut bus) = MONITOR_BUS.lock() {
+        if bus.as_ref().is_some_and(|(g, _)| *g == generation) {
+            *bus = None;
+        }
+    }
+}
+
+/// Sends the monitor's own connection an event it listens for, so the loop
+/// wakes up and sees that it should stop.
+#[cfg(target_os = "linux")]
+fn wake_monitor() {
+    if let Ok(bus) = MONITOR_BUS.lock() {
+        if let Some((_, bus)) = bus.as_ref() {
+            let _ = bus.emit_signal(
+                bus.unique_name(),
+                "/",
+                "org.a11y.atspi.Event.Object",
+                "StateChanged",
+                &("",),
+            );
+        }
+    }
+}
+
+/// Listens for focus changes on the accessibility bus and checks the role and
+/// application of every newly focused object.
+#[cfg(target_os = "linux")]
+fn follow_atspi_focus(app: &AppHandle, generation: u64) -> Result<(), String> {
+    use zbus::blocking::{connection, Connection, MessageIterator};
+    use zbus::zvariant::{OwnedObjectPath, OwnedValue, Structure, Value};
+    use zbus::MatchRule;
+
+    // The accessibility bus is separate from the session bus, which knows its address
+    let session = Connection::session().map_err(|e| e.to_string())?;
+    let address: String = session
+        .call_method(
+            Some("org.a11y.Bus"),
+            "/org/a11y/bus",
+            Some("org.a11y.Bus"),
+            "GetAddress",
+            &(),
+        )
+        .and_then(|reply| reply.body().deserialize())
+        .map_err(|e| e.to_string())?;
+    let bus = connection::Builder::address(address.as_str())
+        .and_then(|b| b.build())
+        .map_err(|e| e.to_string())?;
+
+    // Toolkits only send the events somebody registered for. Newer registries
+    // also take the properties to cache and the listener's bus name
+    let event = "object:state-changed:focused";
+    bus.call_method(
+        Some(ATSPI_REGISTRY),
+        ATSPI_REGISTRY_PATH,
+        Some(ATSPI_REGISTRY),
+        "RegisterEvent",
+        &(event, Vec::<String>::new(), ""),
+    )
+    .or_else(|_| {
+        bus.call_method(
+            Some(ATSPI_REGISTRY),
+            ATSPI_REGISTRY_PATH,
+            Some(ATSPI_REGISTRY),
+            "RegisterEvent",
+            &(event,),
+        )
+    })
+    .map_err(|e| e.to_string())?;
+
+    let rule = MatchRule::builder()
+        .msg_type(zbus::message::Type::Signal)
+        .interface("org.a11y.atspi.Event.Object")
+        .and_then(|b| b.member("StateChanged"))
+        .map_err(|e| e.to_string())?
+        .build();
+    let events = MessageIterator::for_match_rule(rule, &bus, None).map_err(|e| e.to_string())?;
+    // Published once events are matched, so a wake-up signal can't be missed
+    if let Ok(mut wake) = MONITOR_BUS.lock() {
+        *wake = Some((generation, bus.clone()));
+    }
+    if !monitor_current(generation) {
+        return Ok(());
+    }
+
+    let accessible = |destination: &str, path: &str, method: &str| {
+        bus.call_method(
+            Some(destination),
+            path,
+            Some("org.a11y.atspi.Accessible"),
+            method,
+            &(),
+        )
+    };
+    // The object whose focus paused the watchers. Signals from different
+    // applications may arrive out of order, so only its own focus loss resumes
+    let mut paused_by: Option<(String, String)> = None;
+    for message in events {
+        if !monitor_current(generation) {
+            break;
+        }
+        let Ok(message) = message else {
+            continue;
+        };
+        // (detail, detail1, detail2, any_data, ...); detail1 is 1 on focus gain
+        let body = message.body();
+        let Ok(body) = body.deserialize::<Structure>() else {
+            continue;
+        };
+        let fields = body.fields();
+        if !matches!(fields.first(), Some(Value::Str(detail)) if detail.as_str() == "focused") {
+            continue;
+        }
+        let header = message.header();
+        let (Some(sender), Some(path)) = (header.sender(), header.path()) else {
+            continue;
+        };
+        let object = (sender.to_string(), path.to_string());
+        if !matches!(fields.get(1), Some(Value::I32(1))) {
+            if paused_by.as_ref() == Some(&object) {
+                paused_by = None;
+                set_pause(app, None, "");
+            }
+            continue;
+        }
+        let (sender, path) = (&object.0, &object.1);
+        let is_password = accessible(sender, path, "GetRole")
+            .and_then(|reply| reply.body().deserialize::<u32>())
+            .is_ok_and(|role| role == ATSPI_ROLE_PASSWORD_TEXT);
+        // The application object's name is the program name, e.g. `pinentry-gnome3`
+        let source_app = accessible(sender, path, "GetApplication")
+            .and_then(|reply| reply.body().deserialize::<(String, OwnedObjectPath)>())
+            .and_then(|(app_bus, app_path)| {
+                bus.call_method(
+                    Some(app_bus.as_str()),
+                    app_path.as_str(),
+                    Some("org.freedesktop.DBus.Properties"),
+                    "Get",
+                    &("org.a11y.atspi.Accessible", "Name"),
+                )
+            })
+            .and_then(|reply| reply.body().deserialize::<OwnedValue>())
+            .ok()
+            .and_then(|name| String::try_from(name).ok())
+            .unwrap_or_default();
+        let reason = classify_focus(&get_secure_input_config(), is_password, &source_app);
+        paused_by = reason.map(|_| object);
+        set_pause(app, reason, &source_app);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credential_apps_match_with_or_without_exe() {
+        let apps = vec!["consent.exe".to_string(), " pinentry-qt ".to_string()];
+        assert!(is_credential_app(&apps, "consent"));
+        assert!(is_credential_app(&apps, "Consent"));
+        assert!(is_credential_app(&apps, "pinentry-qt"));
+        assert!(!is_credential_app(&apps, "pinentry"));
+        assert!(!is_credential_app(&apps, ""));
+    }
+
+    #[test]
+    fn password_fields_pause_before_credential_apps() {
+        let config = SecureInputConfig::default();
+        assert_eq!(
+            classify_focus(&config, true, "firefox"),
+            Some(SecureInputReason::PasswordField)
+        );
+        assert_eq!(
+            classify_focus(&config, true, "pinentry"),
+            Some(SecureInputReason::PasswordField)
+        );
+        assert_eq!(
+            classify_focus(&config, false, "CredentialUIBroker"),
+            Some(SecureInputReason::CredentialPrompt)
+        );
+        assert_eq!(classify_focus(&config, false, "firefox"), None);
+    }
+
+    #[test]
+    fn disabled_config_never_pauses() {
+        let config = SecureInputConfig {
+            enabled: false,
+            ..SecureInputConfig::default()
+        };
+        assert_eq!(classify_focus(&config, true, "pinentry"), None);
+    }
+}
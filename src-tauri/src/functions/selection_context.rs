@@ -0,0 +1,171 @@
+//! Text around a selection, sent with `text_selected` so prompts like
+//! "explain this" can see the paragraph the selection came from.
+
+use std::sync::{Mutex, OnceLock};
+
+#[derive(Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextUnit {
+    /// The rest of the paragraphs the selection starts and ends in.
+    Paragraph,
+    /// A fixed number of characters on each side.
+    Characters,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SelectionContextConfig {
+    pub enabled: bool,
+    pub unit: ContextUnit,
+    /// Characters captured on each side; also caps paragraph context.
+    pub max_chars: usize,
+    /// Executable names (with or without `.exe`) whose selections never carry context.
+    pub disabled_apps: Vec<String>,
+}
+
+impl Default for SelectionContextConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            unit: ContextUnit::Paragraph,
+            max_chars: 1000,
+            disabled_apps: Vec::new(),
+        }
+    }
+}
+
+fn context_config() -> &'static Mutex<SelectionContextConfig> {
+    static CONFIG: OnceLock<Mutex<SelectionContextConfig>> = OnceLock::new();
+    CONFIG.get_or_init(|| Mutex::new(SelectionContextConfig::default()))
+}
+
+#[tauri::command]
+pub fn set_selection_context_config(config: SelectionContextConfig) -> Result<(), String> {
+    *context_config().lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_selection_context_config() -> SelectionContextConfig {
+    context_config()
+        .lock()
+        .map(|c| c.clone())
+        .unwrap_or_default()
+}
+
+/// Text surrounding a selection. The selection itself is never repeated here,
+/// so `before + selection + after` rebuilds the passage.
+#[derive(Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+pub(crate) struct SelectionContext {
+    pub before: String,
+    pub after: String,
+    /// Name of the document or web page the selection belongs to.
+    pub document_title: Option<String>,
+}
+
+/// Whether context should be captured for selections made in `app`.
+#[cfg(target_os = "windows")]
+pub(crate) fn context_wanted(app: &str) -> bool {
+    let config = get_selection_context_config();
+    config.enabled
+        && !config.disabled_apps.iter().any(|c| {
+            let c = c.trim();
+            let c = c
+                .strip_suffix(".exe")
+                .or_else(|| c.strip_suffix(".EXE"))
+                .unwrap_or(c);
+            c.eq_ignore_ascii_case(app)
+        })
+}
+
+/// Keeps the last `max_chars` characters of `text`.
+#[cfg(target_os = "windows")]
+fn tail_chars(text: &str, max_chars: usize) -> String {
+    let count = text.chars().count();
+    text.chars().skip(count.saturating_sub(max_chars)).collect()
+}
+
+/// Expands the first and last selected ranges outwards and reads the text
+/// around the selection, plus the title of the enclosing document.
+#[cfg(target_os = "windows")]
+pub(crate) unsafe fn uia_selection_context(
+    automation: &windows::Win32::UI::Accessibility::IUIAutomation,
+    element: &windows::Win32::UI::Accessibility::IUIAutomationElement,
+    ranges: &windows::Win32::UI::Accessibility::IUIAutomationTextRangeArray,
+) -> Option<SelectionContext> {
+    use windows::Win32::UI::Accessibility::{
+        TextPatternRangeEndpoint_End, TextPatternRangeEndpoint_Start, TextUnit_Character,
+        TextUnit_Paragraph,
+    };
+    let config = get_selection_context_config();
+    let count = ranges.Length().ok()?;
+    if count == 0 {
+        return None;
+    }
+    let first = ranges.GetElement(0).ok()?;
+    let last = ranges.GetElement(count - 1).ok()?;
+    let (unit, steps) = match config.unit {
+        ContextUnit::Paragraph => (TextUnit_Paragraph, 1),
+        ContextUnit::Characters => (TextUnit_Character, config.max_chars as i32),
+    };
+    let limit = config.max_chars as i32;
+
+    // Collapse a copy onto the selection start, then grow it backwards
+    let before = first.Clone().ok().and_then(|r| {
+        r.MoveEndpointByRange(
+            TextPatternRangeEndpoint_End,
+            &first,
+            TextPatternRangeEndpoint_Start,
+        )
+        .ok()?;
+        r.MoveEndpointByUnit(TextPatternRangeEndpoint_Start, unit, -steps)
+            .ok()?;
+        // GetText keeps the start of the range; the end is what touches the selection
+        r.GetText(-1)
+            .ok()
+            .map(|t| tail_chars(&t.to_string(), config.max_chars))
+    });
+    let after = last.Clone().ok().and_then(|r| {
+        r.MoveEndpointByRange(
+            TextPatternRangeEndpoint_Start,
+            &last,
+            TextPatternRangeEndpoint_End,
+        )
+        .ok()?;
+        r.MoveEndpointByUnit(TextPatternRangeEndpoint_End, unit, steps)
+            .ok()?;
+        r.GetText(limit).ok().map(|t| t.to_string())
+    });
+
+    Some(SelectionContext {
+        before: before.unwrap_or_default(),
+        after: after.unwrap_or_default(),
+        document_title: uia_document_title(automation, element),
+    })
+}
+
+/// Name of the closest document element at or above `element`, e.g. the page
+/// title in a browser.
+#[cfg(target_os = "windows")]
+unsafe fn uia_document_title(
+    automation: &windows::Win32::UI::Accessibility::IUIAutomation,
+    element: &windows::Win32::UI::Accessibility::IUIAutomationElement,
+) -> Option<String> {
+    use windows::Win32::UI::Accessibility::UIA_DocumentControlTypeId;
+    let walker = automation.ControlViewWalker().ok()?;
+    let mut current = element.clone();
+    // Bounded so a broken provider cannot keep us walking forever
+    for _ in 0..32 {
+        if current.CurrentControlType().ok() == Some(UIA_DocumentControlTypeId) {
+            return current
+                .CurrentName()
+                .ok()
+                .map(|name| name.to_string())
+                .filter(|name| !name.is_empty());
+        }
+        current = walker.GetParentElement(&current).ok()?;
+    }
+    None
+}
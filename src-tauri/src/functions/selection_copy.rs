@@ -0,0 +1,227 @@
+//! Fallback selection capture for controls that do not expose their selection
+//! through UI Automation (browser content areas, Electron apps, terminals).
+//!
+//! The copy shortcut is sent to the source window, the copied text is read and
+//! the previous clipboard contents are put back. The clipboard watcher ignores
+//! every update it sees until it has caught up with the state the capture left,
+//! including a copy the app only makes after the capture gave up waiting.
+
+#[cfg(target_os = "windows")]
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+#[cfg(target_os = "windows")]
+static SIMULATED_COPY_ACTIVE: AtomicBool = AtomicBool::new(false);
+// Clipboard sequence number left behind by the last capture, 0 once the
+// watcher has seen a later update
+#[cfg(target_os = "windows")]
+static SIMULATED_COPY_SEQUENCE: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CopyFallbackConfig {
+    pub enabled: bool,
+    /// Executable names (with or without `.exe`) where the copy shortcut must
+    /// never be sent, e.g. consoles where Ctrl+C interrupts the running program.
+    pub disabled_apps: Vec<String>,
+    /// How long to wait for the source app to update the clipboard.
+    pub timeout_ms: u64,
+}
+
+impl Default for CopyFallbackConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            // Consoles interrupt on Ctrl+C and Explorer copies files instead
+            disabled_apps: [
+                "cmd",
+                "powershell",
+                "pwsh",
+                "WindowsTerminal",
+                "OpenConsole",
+                "conhost",
+                "mintty",
+                "ConEmu64",
+                "ConEmu",
+                "alacritty",
+                "wezterm-gui",
+                "Hyper",
+                "Tabby",
+                "putty",
+                "MobaXterm",
+                "explorer",
+            ]
+            .iter()
+            .map(|app| app.to_string())
+            .collect(),
+            timeout_ms: 300,
+        }
+    }
+}
+
+fn copy_fallback_config() -> &'static Mutex<CopyFallbackConfig> {
+    static CONFIG: OnceLock<Mutex<CopyFallbackConfig>> = OnceLock::new();
+    CONFIG.get_or_init(|| Mutex::new(CopyFallbackConfig::default()))
+}
+
+#[tauri::command]
+pub fn set_copy_fallback_config(config: CopyFallbackConfig) -> Result<(), String> {
+    *copy_fallback_config().lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_copy_fallback_config() -> CopyFallbackConfig {
+    copy_fallback_config()
+        .lock()
+        .map(|c| c.clone())
+        .unwrap_or_default()
+}
+
+/// Whether a clipboard update the watcher is handling came from a simulated
+/// copy. `WM_CLIPBOARDUPDATE` arrives after the capture has finished, so an
+/// update counts as Rae's own until the clipboard has moved past the state the
+/// capture left behind.
+#[cfg(target_os = "windows")]
+pub(crate) fn is_simulated_copy_update() -> bool {
+    use winapi::um::winuser::GetClipboardSequenceNumber;
+    if SIMULATED_COPY_ACTIVE.load(Ordering::SeqCst) {
+        return true;
+    }
+    let left = SIMULATED_COPY_SEQUENCE.load(Ordering::SeqCst);
+    if left == 0 {
+        return false;
+    }
+    if unsafe { GetClipboardSequenceNumber() } == left {
+        return true;
+    }
+    let _ = SIMULATED_COPY_SEQUENCE.compare_exchange(left, 0, Ordering::SeqCst, Ordering::SeqCst);
+    false
+}
+
+#[cfg(target_os = "windows")]
+fn app_matches(configured: &str, app: &str) -> bool {
+    let configured = configured.trim();
+    let configured = configured
+        .strip_suffix(".exe")
+        .or_else(|| configured.strip_suffix(".EXE"))
+        .unwrap_or(configured);
+    configured.eq_ignore_ascii_case(app)
+}
+
+/// How long after the timeout a late clipboard update from the source app is
+/// still taken for the simulated copy and undone.
+#[cfg(target_os = "windows")]
+const LATE_COPY_GRACE: std::time::Duration = std::time::Duration::from_millis(1000);
+
+/// Copies the selection of the foreground window `hwnd` and restores the
+/// clipboard afterwards. Returns `None` when the fallback is disabled for the
+/// app, another capture is running, the user keeps holding a modifier or
+/// nothing was copied in time.
+#[cfg(target_os = "windows")]
+pub(crate) fn capture_selection_by_copy(hwnd: isize) -> Option<String> {
+    use super::clipboard::{read_clipboard_unicode_text, snapshot_clipboard};
+    use super::clipboard_history::active_window_source;
+    use super::injection::send_key_combo;
+    use std::time::{Duration, Instant};
+    use winapi::um::winuser::{GetClipboardSequenceNumber, GetForegroundWindow, VK_CONTROL};
+
+    let config = get_copy_fallback_config();
+    if !config.enabled {
+        return None;
+    }
+    let (app, _) = active_window_source();
+    if config.disabled_apps.iter().any(|c| app_matches(c, &app)) {
+        return None;
+    }
+    if unsafe { GetForegroundWindow() } as isize != hwnd {
+        return None;
+    }
+    // Shift still held from a Shift+click would turn the copy into Ctrl+Shift+C
+    if !wait_for_modifiers_released(Duration::from_millis(500)) {
+        return None;
+    }
+    if SIMULATED_COPY_ACTIVE.swap(true, Ordering::SeqCst) {
+        return None;
+    }
+
+    let (text, changed) = unsafe {
+        let snapshot = snapshot_clipboard();
+        let before = GetClipboardSequenceNumber();
+        send_key_combo(&[VK_CONTROL as u16], 0x43);
+
+        let started = Instant::now();
+        let timeout = Duration::from_millis(config.timeout_ms);
+        let changed = wait_for_clipboard_change(before, started + timeout);
+        let text = if changed {
+            // Some apps publish delayed-rendered formats right after the update
+            std::thread::sleep(Duration::from_millis(20));
+            read_clipboard_unicode_text()
+        } else {
+            None
+        };
+        if changed {
+            restore_clipboard(snapshot);
+        } else {
+            // The app may still copy after the timeout; until the grace runs
+            // out the watcher keeps ignoring updates and a late copy is undone
+            std::thread::spawn(move || {
+                if wait_for_clipboard_change(before, started + timeout + LATE_COPY_GRACE) {
+                    restore_clipboard(snapshot);
+                }
+                SIMULATED_COPY_ACTIVE.store(false, Ordering::SeqCst);
+            });
+        }
+        (text, changed)
+    };
+
+    if changed {
+        SIMULATED_COPY_ACTIVE.store(false, Ordering::SeqCst);
+    }
+    text.map(|t| t.trim().to_string()).filter(|t| !t.is_empty())
+}
+
+/// Polls until the clipboard sequence number moves past `before` or
+/// `deadline` passes. Returns whether it changed.
+#[cfg(target_os = "windows")]
+fn wait_for_clipboard_change(before: u32, deadline: std::time::Instant) -> bool {
+    use winapi::um::winuser::GetClipboardSequenceNumber;
+    while std::time::Instant::now() < deadline {
+        if unsafe { GetClipboardSequenceNumber() } != before {
+            return true;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    false
+}
+
+/// Puts back the clipboard from before a simulated copy, or empties it when
+/// no snapshot could be taken, and remembers the state this leaves.
+#[cfg(target_os = "windows")]
+unsafe fn restore_clipboard(snapshot: Option<Vec<(u32, Vec<u8>)>>) {
+    use super::clipboard::write_clipboard_formats;
+    use winapi::um::winuser::GetClipboardSequenceNumber;
+    // The restore is tagged, so the watcher skips it as Rae's own write
+    write_clipboard_formats(snapshot.as_deref().unwrap_or_default());
+    // Published before the capture ends, so the watcher never sees a gap
+    SIMULATED_COPY_SEQUENCE.store(GetClipboardSequenceNumber(), Ordering::SeqCst);
+}
+
+/// Waits up to `timeout` for Shift, Alt and the Windows keys to be released.
+#[cfg(target_os = "windows")]
+fn wait_for_modifiers_released(timeout: std::time::Duration) -> bool {
+    use winapi::um::winuser::{GetAsyncKeyState, VK_LWIN, VK_MENU, VK_RWIN, VK_SHIFT};
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        let held = [VK_SHIFT, VK_MENU, VK_LWIN, VK_RWIN]
+            .iter()
+            .any(|&vk| unsafe { GetAsyncKeyState(vk) as u16 & 0x8000 != 0 });
+        if !held {
+            return true;
+        }
+        if std::time::Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,604 @@
+//! Decides when the user has finished making a selection. The detector is a
+//! pure state machine fed with timestamped mouse, keyboard and selection
+//! events, so the platform watchers only translate OS input into [`InputEvent`]s.
+//! Only the Windows watcher polls input; X11 reports finished selections itself.
+#![cfg_attr(not(target_os = "windows"), allow(dead_code))]
+
+use std::sync::{Mutex, OnceLock};
+
+#[derive(Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SelectionGestureConfig {
+    /// Distance the mouse must travel while pressed to count as a drag.
+    pub drag_min_distance_px: u32,
+    /// Minimum time the button must be held for a drag.
+    pub drag_min_duration_ms: u64,
+    /// Maximum gap between clicks of a double or triple click.
+    pub multi_click_interval_ms: u64,
+    /// Maximum distance between clicks of a double or triple click.
+    pub multi_click_max_distance_px: u32,
+    /// Quiet time after a gesture before it is reported, so the selection has
+    /// settled and a double click can still grow into a triple click.
+    pub debounce_ms: u64,
+    /// Selections shorter than this many characters are ignored.
+    pub min_length: usize,
+}
+
+impl Default for SelectionGestureConfig {
+    fn default() -> Self {
+        Self {
+            drag_min_distance_px: 12,
+            drag_min_duration_ms: 120,
+            multi_click_interval_ms: 500,
+            multi_click_max_distance_px: 4,
+            debounce_ms: 100,
+            min_length: 1,
+        }
+    }
+}
+
+fn gesture_config() -> &'static Mutex<SelectionGestureConfig> {
+    static CONFIG: OnceLock<Mutex<SelectionGestureConfig>> = OnceLock::new();
+    CONFIG.get_or_init(|| Mutex::new(SelectionGestureConfig::default()))
+}
+
+pub fn current_selection_gesture_config() -> SelectionGestureConfig {
+    gesture_config().lock().map(|c| *c).unwrap_or_default()
+}
+
+#[tauri::command]
+pub fn set_selection_gesture_config(config: SelectionGestureConfig) -> Result<(), String> {
+    *gesture_config().lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_selection_gesture_config() -> SelectionGestureConfig {
+    current_selection_gesture_config()
+}
+
+/// Keys the detector cares about.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    Shift,
+    Control,
+    /// Arrows, Home/End and Page Up/Down.
+    Navigation,
+    A,
+    /// Anything else; typing replaces the selection.
+    Other,
+}
+
+/// Input for the detector. `at` is a monotonic timestamp in milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEvent {
+    MouseDown {
+        x: i32,
+        y: i32,
+        at: u64,
+    },
+    MouseMove {
+        x: i32,
+        y: i32,
+        at: u64,
+    },
+    MouseUp {
+        x: i32,
+        y: i32,
+        at: u64,
+    },
+    KeyDown {
+        key: Key,
+        at: u64,
+    },
+    KeyUp {
+        key: Key,
+        at: u64,
+    },
+    /// The selected text changed to `len` characters.
+    SelectionChanged {
+        len: usize,
+        at: u64,
+    },
+    /// The focused element does not expose its selection.
+    SelectionUnavailable {
+        at: u64,
+    },
+    Tick {
+        at: u64,
+    },
+}
+
+impl InputEvent {
+    fn at(&self) -> u64 {
+        match *self {
+            InputEvent::MouseDown { at, .. }
+            | InputEvent::MouseMove { at, .. }
+            | InputEvent::MouseUp { at, .. }
+            | InputEvent::KeyDown { at, .. }
+            | InputEvent::KeyUp { at, .. }
+            | InputEvent::SelectionChanged { at, .. }
+            | InputEvent::SelectionUnavailable { at }
+            | InputEvent::Tick { at } => at,
+        }
+    }
+}
+
+/// How a reported selection was made.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionTrigger {
+    Drag,
+    DoubleClick,
+    TripleClick,
+    /// Shift with arrows, Home/End or Page Up/Down.
+    Keyboard,
+    /// Ctrl+A.
+    SelectAll,
+}
+
+impl SelectionTrigger {
+    fn is_mouse(self) -> bool {
+        matches!(
+            self,
+            SelectionTrigger::Drag | SelectionTrigger::DoubleClick | SelectionTrigger::TripleClick
+        )
+    }
+}
+
+struct Press {
+    x: i32,
+    y: i32,
+    at: u64,
+    dragged: bool,
+}
+
+struct Click {
+    x: i32,
+    y: i32,
+    at: u64,
+    count: u32,
+}
+
+pub struct SelectionDetector {
+    config: SelectionGestureConfig,
+    press: Option<Press>,
+    last_click: Option<Click>,
+    shift_down: bool,
+    control_down: bool,
+    /// Length of the current selection; `None` when the app does not expose it.
+    selection_len: Option<usize>,
+    pending: Option<(SelectionTrigger, u64)>,
+}
+
+impl SelectionDetector {
+    pub fn new(config: SelectionGestureConfig) -> Self {
+        Self {
+            config,
+            press: None,
+            last_click: None,
+            shift_down: false,
+            control_down: false,
+            selection_len: None,
+            pending: None,
+        }
+    }
+
+    pub fn set_config(&mut self, config: SelectionGestureConfig) {
+        self.config = config;
+    }
+
+    /// Feeds one event and returns a trigger once a finished selection is due.
+    pub fn handle(&mut self, event: InputEvent) -> Option<SelectionTrigger> {
+        match event {
+            InputEvent::MouseDown { x, y, at } => {
+                self.pending = None;
+                self.press = Some(Press {
+                    x,
+                    y,
+                    at,
+                    dragged: false,
+                });
+            }
+            InputEvent::MouseMove { x, y, .. } => {
+                let threshold = self.config.drag_min_distance_px as i64;
+                if let Some(press) = self.press.as_mut() {
+                    if distance_sq(press.x, press.y, x, y) > threshold * threshold {
+                        press.dragged = true;
+                    }
+                }
+            }
+            InputEvent::MouseUp { x, y, at } => self.mouse_up(x, y, at),
+            InputEvent::KeyDown { key, at } => self.key_down(key, at),
+            InputEvent::KeyUp { key, .. } => match key {
+                Key::Shift => self.shift_down = false,
+                Key::Control => self.control_down = false,
+                _ => {}
+            },
+            InputEvent::SelectionChanged { len, .. } => self.selection_len = Some(len),
+            InputEvent::SelectionUnavailable { .. } => self.selection_len = None,
+            InputEvent::Tick { .. } => {}
+        }
+        self.poll(event.at())
+    }
+
+    fn mouse_up(&mut self, x: i32, y: i32, at: u64) {
+        let Some(press) = self.press.take() else {
+            return;
+        };
+        let moved = press.dragged || {
+            let threshold = self.config.drag_min_distance_px as i64;
+            distance_sq(press.x, press.y, x, y) > threshold * threshold
+        };
+        if moved {
+            self.last_click = None;
+            if at.saturating_sub(press.at) >= self.config.drag_min_duration_ms {
+                self.schedule(SelectionTrigger::Drag, at);
+            }
+            return;
+        }
+
+        let max = self.config.multi_click_max_distance_px as i64;
+        let count = match &self.last_click {
+            Some(click)
+                if press.at.saturating_sub(click.at) <= self.config.multi_click_interval_ms
+                    && distance_sq(click.x, click.y, x, y) <= max * max =>
+            {
+                click.count + 1
+            }
+            _ => 1,
+        };
+        self.last_click = Some(Click { x, y, at, count });
+        match count {
+            1 => {}
+            2 => self.schedule(SelectionTrigger::DoubleClick, at),
+            _ => self.schedule(SelectionTrigger::TripleClick, at),
+        }
+    }
+
+    fn key_down(&mut self, key: Key, at: u64) {
+        match key {
+            Key::Shift => self.shift_down = true,
+            Key::Control => self.control_down = true,
+            Key::Navigation if self.shift_down => self.schedule(SelectionTrigger::Keyboard, at),
+            Key::A if self.control_down => self.schedule(SelectionTrigger::SelectAll, at),
+            // Shortcuts such as Ctrl+C leave the selection alone
+            Key::Other if self.control_down => {}
+            // Plain navigation collapses the selection; typing replaces it
+            Key::Navigation | Key::A | Key::Other => self.pending = None,
+        }
+    }
+
+    fn schedule(&mut self, trigger: SelectionTrigger, at: u64) {
+        self.pending = Some((trigger, at + self.config.debounce_ms));
+    }
+
+    /// Reports the pending gesture once its debounce time has passed and the
+    /// selection is long enough.
+    fn poll(&mut self, now: u64) -> Option<SelectionTrigger> {
+        let (trigger, due) = self.pending?;
+        if now < due {
+            return None;
+        }
+        self.pending = None;
+        match self.selection_len {
+            Some(len) if len >= self.config.min_length.max(1) => Some(trigger),
+            // Without selection info only mouse gestures are worth a guess
+            None if trigger.is_mouse() => Some(trigger),
+            _ => None,
+        }
+    }
+}
+
+fn distance_sq(x1: i32, y1: i32, x2: i32, y2: i32) -> i64 {
+    let dx = (x2 - x1) as i64;
+    let dy = (y2 - y1) as i64;
+    dx * dx + dy * dy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use InputEvent::*;
+
+    fn detector() -> SelectionDetector {
+        SelectionDetector::new(SelectionGestureConfig::default())
+    }
+
+    /// Feeds all events, then ticks well past the debounce, collecting triggers.
+    fn run(detector: &mut SelectionDetector, events: &[InputEvent]) -> Vec<SelectionTrigger> {
+        let mut triggers: Vec<_> = events.iter().filter_map(|e| detector.handle(*e)).collect();
+        let last = events.last().map(|e| e.at()).unwrap_or(0);
+        triggers.extend(detector.handle(Tick { at: last + 10_000 }));
+        triggers
+    }
+
+    fn click(x: i32, y: i32, at: u64) -> [InputEvent; 2] {
+        [MouseDown { x, y, at }, MouseUp { x, y, at: at + 50 }]
+    }
+
+    #[test]
+    fn drag_past_thresholds_selects() {
+        let mut d = detector();
+        let events = [
+            MouseDown { x: 0, y: 0, at: 0 },
+            MouseMove {
+                x: 40,
+                y: 0,
+                at: 100,
+            },
+            SelectionChanged { len: 8, at: 110 },
+            MouseUp {
+                x: 80,
+                y: 0,
+                at: 200,
+            },
+        ];
+        assert_eq!(run(&mut d, &events), vec![SelectionTrigger::Drag]);
+    }
+
+    #[test]
+    fn short_or_quick_drags_are_ignored() {
+        let mut d = detector();
+        let short = [
+            MouseDown { x: 0, y: 0, at: 0 },
+            MouseMove {
+                x: 5,
+                y: 5,
+                at: 100,
+            },
+            SelectionChanged { len: 3, at: 100 },
+            MouseUp {
+                x: 5,
+                y: 5,
+                at: 300,
+            },
+        ];
+        assert!(run(&mut d, &short).is_empty());
+
+        let quick = [
+            MouseDown {
+                x: 0,
+                y: 0,
+                at: 1000,
+            },
+            MouseMove {
+                x: 100,
+                y: 0,
+                at: 1040,
+            },
+            MouseUp {
+                x: 100,
+                y: 0,
+                at: 1080,
+            },
+        ];
+        assert!(run(&mut d, &quick).is_empty());
+    }
+
+    #[test]
+    fn thresholds_are_configurable() {
+        let mut d = SelectionDetector::new(SelectionGestureConfig {
+            drag_min_distance_px: 3,
+            drag_min_duration_ms: 0,
+            ..Default::default()
+        });
+        let events = [
+            MouseDown { x: 0, y: 0, at: 0 },
+            SelectionChanged { len: 2, at: 10 },
+            MouseUp { x: 5, y: 0, at: 20 },
+        ];
+        assert_eq!(run(&mut d, &events), vec![SelectionTrigger::Drag]);
+    }
+
+    #[test]
+    fn double_click_selects_word() {
+        let mut d = detector();
+        let mut events = vec![SelectionChanged { len: 0, at: 0 }];
+        events.extend(click(10, 10, 0));
+        events.extend(click(11, 10, 200));
+        events.push(SelectionChanged { len: 5, at: 260 });
+        assert_eq!(run(&mut d, &events), vec![SelectionTrigger::DoubleClick]);
+    }
+
+    #[test]
+    fn triple_click_replaces_pending_double_click() {
+        let mut d = detector();
+        let mut events = vec![SelectionChanged { len: 40, at: 0 }];
+        events.extend(click(10, 10, 0));
+        events.extend(click(10, 10, 150));
+        events.extend(click(10, 10, 230));
+        assert_eq!(run(&mut d, &events), vec![SelectionTrigger::TripleClick]);
+    }
+
+    #[test]
+    fn slow_or_distant_clicks_are_single_clicks() {
+        let mut d = detector();
+        let mut events = vec![SelectionChanged { len: 4, at: 0 }];
+        events.extend(click(10, 10, 0));
+        events.extend(click(10, 10, 900));
+        events.extend(click(60, 10, 1000));
+        assert!(run(&mut d, &events).is_empty());
+    }
+
+    #[test]
+    fn shift_arrow_selects_after_debounce() {
+        let mut d = detector();
+        let events = [
+            KeyDown {
+                key: Key::Shift,
+                at: 0,
+            },
+            KeyDown {
+                key: Key::Navigation,
+                at: 10,
+            },
+            SelectionChanged { len: 1, at: 15 },
+            KeyDown {
+                key: Key::Navigation,
+                at: 60,
+            },
+            SelectionChanged { len: 2, at: 65 },
+        ];
+        for event in events {
+            assert_eq!(d.handle(event), None);
+        }
+        // Still within the debounce window of the last key press
+        assert_eq!(d.handle(Tick { at: 150 }), None);
+        assert_eq!(d.handle(Tick { at: 160 }), Some(SelectionTrigger::Keyboard));
+        assert_eq!(d.handle(Tick { at: 500 }), None);
+    }
+
+    #[test]
+    fn arrows_without_shift_do_not_select() {
+        let mut d = detector();
+        let events = [
+            SelectionChanged { len: 10, at: 0 },
+            KeyDown {
+                key: Key::Navigation,
+                at: 10,
+            },
+            KeyUp {
+                key: Key::Navigation,
+                at: 20,
+            },
+        ];
+        assert!(run(&mut d, &events).is_empty());
+    }
+
+    #[test]
+    fn ctrl_a_selects_all() {
+        let mut d = detector();
+        let events = [
+            KeyDown {
+                key: Key::Control,
+                at: 0,
+            },
+            KeyDown {
+                key: Key::A,
+                at: 20,
+            },
+            SelectionChanged { len: 300, at: 40 },
+            KeyUp {
+                key: Key::A,
+                at: 60,
+            },
+            KeyUp {
+                key: Key::Control,
+                at: 70,
+            },
+        ];
+        assert_eq!(run(&mut d, &events), vec![SelectionTrigger::SelectAll]);
+    }
+
+    #[test]
+    fn typing_cancels_pending_keyboard_selection() {
+        let mut d = detector();
+        let events = [
+            SelectionChanged { len: 3, at: 0 },
+            KeyDown {
+                key: Key::Shift,
+                at: 0,
+            },
+            KeyDown {
+                key: Key::Navigation,
+                at: 10,
+            },
+            KeyUp {
+                key: Key::Shift,
+                at: 30,
+            },
+            KeyDown {
+                key: Key::Other,
+                at: 40,
+            },
+        ];
+        assert!(run(&mut d, &events).is_empty());
+    }
+
+    #[test]
+    fn keyboard_needs_known_selection() {
+        let mut d = detector();
+        let events = [
+            SelectionUnavailable { at: 0 },
+            KeyDown {
+                key: Key::Shift,
+                at: 0,
+            },
+            KeyDown {
+                key: Key::Navigation,
+                at: 10,
+            },
+        ];
+        assert!(run(&mut d, &events).is_empty());
+    }
+
+    #[test]
+    fn mouse_gestures_fall_back_without_selection_info() {
+        let mut d = detector();
+        let events = [
+            SelectionUnavailable { at: 0 },
+            MouseDown { x: 0, y: 0, at: 0 },
+            MouseMove {
+                x: 50,
+                y: 0,
+                at: 100,
+            },
+            MouseUp {
+                x: 50,
+                y: 0,
+                at: 200,
+            },
+        ];
+        assert_eq!(run(&mut d, &events), vec![SelectionTrigger::Drag]);
+    }
+
+    #[test]
+    fn min_length_filters_short_selections() {
+        let mut d = SelectionDetector::new(SelectionGestureConfig {
+            min_length: 4,
+            ..Default::default()
+        });
+        let mut events = vec![SelectionChanged { len: 3, at: 0 }];
+        events.extend(click(0, 0, 0));
+        events.extend(click(0, 0, 100));
+        assert!(run(&mut d, &events).is_empty());
+
+        let mut events = vec![SelectionChanged { len: 4, at: 5000 }];
+        events.extend(click(0, 0, 5000));
+        events.extend(click(0, 0, 5100));
+        assert_eq!(run(&mut d, &events), vec![SelectionTrigger::DoubleClick]);
+    }
+
+    #[test]
+    fn new_press_cancels_pending_gesture() {
+        let mut d = detector();
+        let events = [
+            SelectionChanged { len: 5, at: 0 },
+            MouseDown { x: 0, y: 0, at: 0 },
+            MouseMove {
+                x: 50,
+                y: 0,
+                at: 100,
+            },
+            MouseUp {
+                x: 50,
+                y: 0,
+                at: 200,
+            },
+            // Clicked away before the debounce elapsed
+            MouseDown {
+                x: 300,
+                y: 300,
+                at: 250,
+            },
+            SelectionChanged { len: 0, at: 260 },
+            MouseUp {
+                x: 300,
+                y: 300,
+                at: 280,
+            },
+        ];
+        assert!(run(&mut d, &events).is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+//! Size limits for selected text, and a store for selections too large to go
+//! over IPC in a single `text_selected` event.
+//!
+//! Large selections are kept here and referenced by id; the frontend reads
+//! them back in chunks with `read_selection_blob`.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// Oldest blobs are dropped first once this many are stored.
+const MAX_STORED_BLOBS: usize = 4;
+
+static SELECTION_BLOBS: Mutex<VecDeque<(u64, String)>> = Mutex::new(VecDeque::new());
+static NEXT_BLOB_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SelectionLimits {
+    /// Characters kept from a selection; the rest is dropped and the event is
+    /// marked `truncated`.
+    pub max_chars: usize,
+    /// Selections longer than this are stored as a blob and the event only
+    /// carries a preview of this many characters.
+    pub inline_chars: usize,
+}
+
+impl Default for SelectionLimits {
+    fn default() -> Self {
+        Self {
+            max_chars: 1_000_000,
+            inline_chars: 16_384,
+        }
+    }
+}
+
+fn selection_limits() -> &'static Mutex<SelectionLimits> {
+    static LIMITS: OnceLock<Mutex<SelectionLimits>> = OnceLock::new();
+    LIMITS.get_or_init(|| Mutex::new(SelectionLimits::default()))
+}
+
+#[tauri::command]
+pub fn set_selection_limits(limits: SelectionLimits) -> Result<(), String> {
+    if limits.max_chars == 0 || limits.inline_chars == 0 {
+        return Err("Selection limits must be at least 1".into());
+    }
+    *selection_limits().lock().map_err(|e| e.to_string())? = limits;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_selection_limits() -> SelectionLimits {
+    selection_limits().lock().map(|l| *l).unwrap_or_default()
+}
+
+/// How many characters to request from sources that can cut the text
+/// themselves: one more than `max_chars`, so truncation is still detected.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+pub(crate) fn selection_read_limit() -> i32 {
+    get_selection_limits()
+        .max_chars
+        .saturating_add(1)
+        .min(i32::MAX as usize) as i32
+}
+
+/// Selected text as reported by `text_selected`. Lengths are in characters.
+#[derive(Default)]
+pub(crate) struct SelectedText {
+    /// The whole selection, or a trimmed preview of it when `blob_id` is set.
+    pub text: String,
+    /// Length of the selection before truncation.
+    pub total_length: usize,
+    pub truncated: bool,
+    /// Length of each selected range, for multi-cursor and table selections.
+    /// Ranges are kept as the source reported them, empty ones included, so
+    /// the n-th length belongs to the n-th range.
+    pub range_lengths: Vec<usize>,
+    pub blob_id: Option<u64>,
+}
+
+impl SelectedText {
+    pub(crate) fn single(text: String) -> Self {
+        Self::from_ranges(vec![text])
+    }
+
+    /// Joins the ranges with newlines, as they are, and applies the configured
+    /// limits.
+    pub(crate) fn from_ranges(ranges: Vec<String>) -> Self {
+        Self::from_ranges_with(ranges, get_selection_limits())
+    }
+
+    fn from_ranges_with(ranges: Vec<String>, limits: SelectionLimits) -> Self {
+        let range_lengths: Vec<usize> = ranges.iter().map(|r| r.chars().count()).collect();
+        let mut text = ranges.join("\n");
+        let total_length = text.chars().count();
+
+        let truncated = total_length > limits.max_chars;
+        if truncated {
+            text.truncate(byte_offset(&text, limits.max_chars));
+        }
+        let mut blob_id = None;
+        if total_length.min(limits.max_chars) > limits.inline_chars {
+            let preview = text[..byte_offset(&text, limits.inline_chars)]
+                .trim()
+                .to_string();
+            blob_id = Some(store_blob(std::mem::replace(&mut text, preview)));
+        }
+        Self {
+            text,
+            total_length,
+            truncated,
+            range_lengths,
+            blob_id,
+        }
+    }
+}
+
+/// Byte offset of the `chars`-th character, or the string length.
+fn byte_offset(text: &str, chars: usize) -> usize {
+    text.char_indices()
+        .nth(chars)
+        .map(|(i, _)| i)
+        .unwrap_or(text.len())
+}
+
+fn store_blob(text: String) -> u64 {
+    let id = NEXT_BLOB_ID.fetch_add(1, Ordering::SeqCst);
+    if let Ok(mut blobs) = SELECTION_BLOBS.lock() {
+        blobs.push_back((id, text));
+        while blobs.len() > MAX_STORED_BLOBS {
+            blobs.pop_front();
+        }
+    }
+    id
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectionBlobChunk {
+    pub text: String,
+    /// Offset to pass for the next chunk, or `None` after the last one.
+    pub next_offset: Option<usize>,
+    pub total_length: usize,
+}
+
+/// Reads `length` characters of a stored selection starting at `offset`.
+#[tauri::command]
+pub fn read_selection_blob(
+    blob_id: u64,
+    offset: usize,
+    length: usize,
+) -> Result<SelectionBlobChunk, String> {
+    let blobs = SELECTION_BLOBS.lock().map_err(|e| e.to_string())?;
+    let (_, text) = blobs
+        .iter()
+        .find(|(id, _)| *id == blob_id)
+        .ok_or("Selection is no longer available")?;
+    let total_length = text.chars().count();
+    let start = byte_offset(text, offset);
+    let end = start + byte_offset(&text[start..], length.max(1));
+    let next = offset
+        .checked_add(length.max(1))
+        .filter(|next| *next < total_length);
+    Ok(SelectionBlobChunk {
+        text: text[start..end].to_string(),
+        next_offset: next,
+        total_length,
+    })
+}
+
+#[tauri::command]
+pub fn release_selection_blob(blob_id: u64) -> bool {
+    let Ok(mut blobs) = SELECTION_BLOBS.lock() else {
+        return false;
+    };
+    let before = blobs.len();
+    blobs.retain(|(id, _)| *id != blob_id);
+    blobs.len() != before
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_chars: usize, inline_chars: usize) -> SelectionLimits {
+        SelectionLimits {
+            max_chars,
+            inline_chars,
+        }
+    }
+
+    #[test]
+    fn byte_offset_counts_characters() {
+        assert_eq!(byte_offset("héllo", 0), 0);
+        assert_eq!(byte_offset("héllo", 2), 3);
+        assert_eq!(byte_offset("a😀b", 2), 5);
+        assert_eq!(byte_offset("abc", 10), 3);
+    }
+
+    #[test]
+    fn ranges_are_joined_and_measured_verbatim() {
+        let selected = SelectedText::from_ranges_with(
+            vec!["    indented ".into(), "".into(), "twö\n".into()],
+            limits(100, 100),
+        );
+        assert_eq!(selected.text, "    indented \n\ntwö\n");
+        assert_eq!(selected.total_length, 19);
+        assert_eq!(selected.range_lengths, vec![13, 0, 4]);
+        assert!(!selected.truncated);
+        assert!(selected.blob_id.is_none());
+    }
+
+    #[test]
+    fn long_selections_are_truncated_on_a_character_boundary() {
+        let selected = SelectedText::from_ranges_with(vec!["ééééé".into()], limits(3, 100));
+        assert_eq!(selected.text, "ééé");
+        assert_eq!(selected.total_length, 5);
+        assert!(selected.truncated);
+    }
+
+    #[test]
+    fn only_the_preview_of_a_blob_is_trimmed() {
+        let selected = SelectedText::from_ranges_with(vec!["  ab  cd".into()], limits(100, 5));
+        assert_eq!(selected.text, "ab");
+        assert_eq!(selected.total_length, 8);
+        let blob_id = selected.blob_id.expect("blob");
+        let stored = read_selection_blob(blob_id, 0, 100).unwrap();
+        assert_eq!(stored.text, "  ab  cd");
+        assert!(release_selection_blob(blob_id));
+    }
+
+    #[test]
+    fn large_selections_are_stored_as_blobs() {
+        let selected = SelectedText::from_ranges_with(vec!["abcdef".into()], limits(5, 2));
+        assert_eq!(selected.text, "ab");
+        assert!(selected.truncated);
+        let blob_id = selected.blob_id.expect("blob");
+
+        let first = read_selection_blob(blob_id, 0, 3).unwrap();
+        assert_eq!(first.text, "abc");
+        assert_eq!(first.next_offset, Some(3));
+        assert_eq!(first.total_length, 5);
+        let last = read_selection_blob(blob_id, 3, 3).unwrap();
+        assert_eq!(last.text, "de");
+        assert_eq!(last.next_offset, None);
+        assert_eq!(
+            read_selection_blob(blob_id, 1, usize::MAX)
+                .unwrap()
+                .next_offset,
+            None
+        );
+        assert!(release_selection_blob(blob_id));
+    }
+}
@@ -0,0 +1,614 @@
+//! User-defined abbreviations such as `;sig` that expand into text while
+//! typing in any application.
+//!
+//! Snippets are saved as JSON in the app data directory. Templates may use
+//! variables in braces: `{date}`, `{time}`, `{datetime}` (all accept a format
+//! such as `{date:%d/%m/%Y}`), `{clipboard}`, `{selection}`, `{app}` and
+//! `{prompt:...}`, which runs its text through the generation backend. `{{`
+//! and `}}` type literal braces.
+//!
+//! Expansion types into other apps and so only runs on Windows; snippets can
+//! still be edited everywhere.
+#![cfg_attr(not(target_os = "windows"), allow(dead_code))]
+
+#[cfg(target_os = "windows")]
+use super::chat::last_selected_text;
+#[cfg(target_os = "windows")]
+use super::clipboard::read_clipboard_unicode_text;
+#[cfg(target_os = "windows")]
+use super::clipboard_history::active_window_source;
+use super::generate::generate;
+#[cfg(target_os = "windows")]
+use super::injection::{send_key_combo, start_injection};
+#[cfg(target_os = "windows")]
+use super::keyboard;
+#[cfg(target_os = "windows")]
+use super::secure_input::{ensure_secure_input_monitor_started, watchers_paused};
+#[cfg(target_os = "windows")]
+use super::triggers::TriggerMatch;
+use super::triggers::{TriggerConfig, TriggerMatcher};
+use std::path::PathBuf;
+#[cfg(target_os = "windows")]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+#[cfg(target_os = "windows")]
+use tauri::Emitter;
+use tauri::{AppHandle, Manager};
+#[cfg(target_os = "windows")]
+use winapi::shared::windef::HWND as WinHWND;
+#[cfg(target_os = "windows")]
+use winapi::um::winuser::{GetForegroundWindow, VK_BACK};
+
+/// `{selection}` only uses a selection made in the same window this recently.
+#[cfg(target_os = "windows")]
+const SELECTION_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(60);
+
+static SNIPPETS_ENABLED: AtomicBool = AtomicBool::new(false);
+static SNIPPET_WATCHER_RUNNING: AtomicBool = AtomicBool::new(false);
+static SNIPPETS_CHANGED: AtomicBool = AtomicBool::new(false);
+/// Keys the user typed while snippets are watched; an expansion is dropped
+/// when more were typed after its abbreviation.
+#[cfg(target_os = "windows")]
+static KEYS_TYPED: AtomicU64 = AtomicU64::new(0);
+/// `None` until loaded from disk.
+static SNIPPETS: Mutex<Option<Vec<Snippet>>> = Mutex::new(None);
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snippet {
+    pub id: String,
+    /// Typed text that expands, e.g. `;sig`. Matched case-sensitively.
+    pub abbreviation: String,
+    pub template: String,
+    /// Executable names (with or without `.exe`) the snippet is limited to.
+    /// Empty means everywhere; a scoped snippet wins over a global one with
+    /// the same abbreviation.
+    #[serde(default)]
+    pub apps: Vec<String>,
+}
+
+impl Snippet {
+    fn applies_to(&self, app: &str) -> bool {
+        self.apps.is_empty() || self.apps.iter().any(|a| app_matches(a, app))
+    }
+}
+
+fn app_matches(configured: &str, app: &str) -> bool {
+    let configured = configured.trim();
+    let configured = configured
+        .strip_suffix(".exe")
+        .or_else(|| configured.strip_suffix(".EXE"))
+        .unwrap_or(configured);
+    configured.eq_ignore_ascii_case(app)
+}
+
+fn snippets_path(app: &AppHandle) -> Option<PathBuf> {
+    let dir = app.path().app_data_dir().ok()?;
+    Some(dir.join("snippets.json"))
+}
+
+/// Writes the list to a temporary file and moves it over the old one, so a
+/// crash halfway never leaves a truncated file behind.
+fn save(app: &AppHandle, snippets: &[Snippet]) -> Result<(), String> {
+    let path = snippets_path(app).ok_or("No app data directory")?;
+    let json = serde_json::to_vec_pretty(snippets).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, json).map_err(|e| e.to_string())?;
+    std::fs::rename(&temp, &path).map_err(|e| e.to_string())
+}
+
+/// Reads the saved list. A missing file is an empty list; a file that does
+/// not parse is moved aside, so the next save cannot overwrite it.
+fn load(path: &std::path::Path) -> Result<Vec<Snippet>, String> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read snippets: {}", e)),
+    };
+    serde_json::from_slice(&bytes).map_err(|e| {
+        let aside = path.with_extension("json.bad");
+        match std::fs::rename(path, &aside) {
+            Ok(()) => format!(
+                "Snippets file is invalid ({}); it was moved to {}",
+                e,
+                aside.display()
+            ),
+            Err(_) => format!("Snippets file is invalid: {}", e),
+        }
+    })
+}
+
+/// Runs `f` on the snippet list, loading it from disk on first use.
+fn with_snippets<T>(app: &AppHandle, f: impl FnOnce(&mut Vec<Snippet>) -> T) -> Result<T, String> {
+    let mut guard = SNIPPETS.lock().map_err(|e| e.to_string())?;
+    let snippets = match guard.as_mut() {
+        Some(snippets) => snippets,
+        None => guard.insert(load(&snippets_path(app).ok_or("No app data directory")?)?),
+    };
+    Ok(f(snippets))
+}
+
+#[tauri::command]
+pub fn list_snippets(app: AppHandle) -> Result<Vec<Snippet>, String> {
+    with_snippets(&app, |snippets| snippets.clone())
+}
+
+/// Adds `snippet`, or replaces the one with the same id.
+#[tauri::command]
+pub fn save_snippet(app: AppHandle, snippet: Snippet) -> Result<(), String> {
+    if snippet.id.trim().is_empty() {
+        return Err("Snippet id cannot be empty".into());
+    }
+    if snippet.abbreviation.trim().is_empty() {
+        return Err("Snippet abbreviation cannot be empty".into());
+    }
+    if snippet.abbreviation.chars().any(char::is_whitespace) {
+        return Err("Snippet abbreviation cannot contain spaces".into());
+    }
+    let snippets = with_snippets(&app, |snippets| {
+        if let Some(other) = prefix_conflict(snippets, &snippet) {
+            return Err(format!(
+                "Abbreviation '{}' overlaps with '{}'; one would expand before the other can be typed",
+                snippet.abbreviation, other.abbreviation
+            ));
+        }
+        match snippets.iter_mut().find(|s| s.id == snippet.id) {
+            Some(existing) => *existing = snippet,
+            None => snippets.push(snippet),
+        }
+        Ok(snippets.clone())
+    })??;
+    SNIPPETS_CHANGED.store(true, Ordering::SeqCst);
+    save(&app, &snippets)
+}
+
+/// Another snippet whose abbreviation is a proper prefix of `snippet`'s or
+/// the other way round.
+fn prefix_conflict<'a>(snippets: &'a [Snippet], snippet: &Snippet) -> Option<&'a Snippet> {
+    let abbreviation = snippet.abbreviation.as_str();
+    snippets.iter().find(|other| {
+        other.id != snippet.id
+            && other.abbreviation != abbreviation
+            && (other.abbreviation.starts_with(abbreviation)
+                || abbreviation.starts_with(other.abbreviation.as_str()))
+    })
+}
+
+#[tauri::command]
+pub fn delete_snippet(app: AppHandle, id: String) -> Result<(), String> {
+    let (removed, snippets) = with_snippets(&app, |snippets| {
+        let before = snippets.len();
+        snippets.retain(|s| s.id != id);
+        (snippets.len() != before, snippets.clone())
+    })?;
+    if !removed {
+        return Err(format!("Unknown snippet '{}'", id));
+    }
+    SNIPPETS_CHANGED.store(true, Ordering::SeqCst);
+    save(&app, &snippets)
+}
+
+#[tauri::command]
+pub fn set_snippets_enabled(app: AppHandle, enabled: bool) {
+    SNIPPETS_ENABLED.store(enabled, Ordering::Relaxed);
+    #[cfg(target_os = "windows")]
+    if enabled {
+        ensure_snippet_watcher_started(&app);
+    }
+    #[cfg(not(target_os = "windows"))]
+    let _ = app;
+}
+
+#[tauri::command]
+pub fn get_snippets_enabled() -> bool {
+    SNIPPETS_ENABLED.load(Ordering::Relaxed)
+}
+
+fn snippet_matcher(snippets: &[Snippet]) -> TriggerMatcher {
+    TriggerMatcher::new(&TriggerConfig {
+        phrases: snippets.iter().map(|s| s.abbreviation.clone()).collect(),
+        case_sensitive: true,
+        ..TriggerConfig::default()
+    })
+}
+
+#[cfg(target_os = "windows")]
+fn ensure_snippet_watcher_started(app: &AppHandle) {
+    if SNIPPET_WATCHER_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    // Abbreviations typed into password fields are never expanded
+    ensure_secure_input_monitor_started(app);
+    let app_handle = app.clone();
+    std::thread::spawn(move || {
+        let (subscription, keys) = keyboard::subscribe();
+        let mut snippets = with_snippets(&app_handle, |s| s.clone()).unwrap_or_default();
+        let mut matcher = snippet_matcher(&snippets);
+        while SNIPPETS_ENABLED.load(Ordering::Relaxed) {
+            let stroke = match keys.recv_timeout(std::time::Duration::from_millis(250)) {
+                Ok(stroke) => stroke,
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            if stroke.key != keyboard::KeyKind::Modifier && !stroke.injected {
+                KEYS_TYPED.fetch_add(1, Ordering::SeqCst);
+            }
+            if SNIPPETS_CHANGED.swap(false, Ordering::SeqCst) {
+                snippets = with_snippets(&app_handle, |s| s.clone()).unwrap_or_default();
+                matcher = snippet_matcher(&snippets);
+            }
+            if let Some(found) = matcher.feed(&stroke) {
+                let (source_app, _) = active_window_source();
+                let candidates = snippets
+                    .iter()
+                    .filter(|s| s.abbreviation == found.phrase && s.applies_to(&source_app));
+                // Prefer a snippet scoped to this app over a global one
+                let snippet = candidates
+                    .clone()
+                    .find(|s| !s.apps.is_empty())
+                    .or_else(|| candidates.clone().next());
+                if let Some(snippet) = snippet {
+                    expand(&app_handle, snippet.clone(), &found, source_app);
+                }
+            }
+        }
+        keyboard::unsubscribe(subscription);
+        SNIPPET_WATCHER_RUNNING.store(false, Ordering::SeqCst);
+    });
+}
+
+/// Renders the snippet, then erases the abbreviation and types the result.
+#[cfg(target_os = "windows")]
+fn expand(app: &AppHandle, snippet: Snippet, found: &TriggerMatch, source_app: String) {
+    let hwnd = unsafe { GetForegroundWindow() } as isize;
+    let erase_chars = found.typed_chars;
+    let typed = KEYS_TYPED.load(Ordering::SeqCst);
+    let values = TemplateValues {
+        now: local_time(),
+        clipboard: unsafe { read_clipboard_unicode_text() },
+        selection: last_selected_text(hwnd, SELECTION_MAX_AGE),
+        app: source_app,
+    };
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let result = render_template(&snippet.template, &values)
+            .await
+            .and_then(|text| type_expansion(&app_handle, hwnd, typed, erase_chars, text));
+        match result {
+            Ok(()) => {
+                let _ = app_handle.emit(
+                    "snippet_expanded",
+                    serde_json::json!({ "id": snippet.id, "abbreviation": snippet.abbreviation }),
+                );
+            }
+            Err(e) => {
+                let _ = app_handle.emit(
+                    "snippet_failed",
+                    serde_json::json!({ "id": snippet.id, "error": e }),
+                );
+            }
+        }
+    });
+}
+
+/// Erases the abbreviation and types `text`, unless the user switched windows
+/// or kept typing while the template was rendering. `typed` is the key count
+/// when the abbreviation matched.
+#[cfg(target_os = "windows")]
+fn type_expansion(
+    app: &AppHandle,
+    hwnd: isize,
+    typed: u64,
+    erase_chars: usize,
+    text: String,
+) -> Result<(), String> {
+    if unsafe { GetForegroundWindow() } as isize != hwnd {
+        return Err("The target window lost focus".into());
+    }
+    // The backspaces would erase what was typed since instead of the abbreviation
+    if KEYS_TYPED.load(Ordering::SeqCst) != typed {
+        return Err("Typing continued while the snippet was rendering".into());
+    }
+    if watchers_paused() {
+        return Err("Focus is in a password field".into());
+    }
+    // Backspaces are injected, so the matchers ignore them
+    for _ in 0..erase_chars {
+        send_key_combo(&[], VK_BACK as u16);
+    }
+    start_injection(app, text, hwnd as WinHWND).map(|_| ())
+}
+
+/// Local wall-clock time, broken down.
+#[derive(Clone, Copy)]
+struct LocalTime {
+    year: u16,
+    month: u16,
+    day: u16,
+    hour: u16,
+    minute: u16,
+    second: u16,
+}
+
+#[cfg(target_os = "windows")]
+fn local_time() -> LocalTime {
+    use winapi::um::minwinbase::SYSTEMTIME;
+    use winapi::um::sysinfoapi::GetLocalTime;
+    let mut time: SYSTEMTIME = unsafe { std::mem::zeroed() };
+    unsafe { GetLocalTime(&mut time) };
+    LocalTime {
+        year: time.wYear,
+        month: time.wMonth,
+        day: time.wDay,
+        hour: time.wHour,
+        minute: time.wMinute,
+        second: time.wSecond,
+    }
+}
+
+/// Formats `time` with the `%Y %m %d %H %M %S` placeholders; `%%` is a
+/// literal `%`.
+fn format_time(time: LocalTime, format: &str) -> String {
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", time.year)),
+            Some('m') => out.push_str(&format!("{:02}", time.month)),
+            Some('d') => out.push_str(&format!("{:02}", time.day)),
+            Some('H') => out.push_str(&format!("{:02}", time.hour)),
+            Some('M') => out.push_str(&format!("{:02}", time.minute)),
+            Some('S') => out.push_str(&format!("{:02}", time.second)),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+struct TemplateValues {
+    now: LocalTime,
+    clipboard: Option<String>,
+    selection: Option<String>,
+    app: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum TemplatePart {
+    Text(String),
+    /// Variable name and the argument after `:`, if any.
+    Variable(String, Option<String>),
+}
+
+/// Splits a template into text and `{name}` / `{name:argument}` variables.
+/// Braces nest inside arguments, so `{prompt:Greet {app}}` is one variable.
+fn parse_template(template: &str) -> Vec<TemplatePart> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut depth = 1;
+                let mut inner = String::new();
+                for c in chars.by_ref() {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    inner.push(c);
+                }
+                if depth != 0 {
+                    // Unclosed brace: keep it as typed
+                    text.push('{');
+                    text.push_str(&inner);
+                    continue;
+                }
+                if !text.is_empty() {
+                    parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                }
+                let (name, argument) = match inner.split_once(':') {
+                    Some((name, argument)) => (name, Some(argument.to_string())),
+                    None => (inner.as_str(), None),
+                };
+                parts.push(TemplatePart::Variable(name.trim().to_lowercase(), argument));
+            }
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        parts.push(TemplatePart::Text(text));
+    }
+    parts
+}
+
+/// Renders everything but `{prompt:...}`, which needs the network.
+fn render_local(template: &str, values: &TemplateValues) -> String {
+    let mut out = String::new();
+    for part in parse_template(template) {
+        match part {
+            TemplatePart::Text(text) => out.push_str(&text),
+            TemplatePart::Variable(name, argument) => {
+                out.push_str(&render_variable(&name, argument.as_deref(), values))
+            }
+        }
+    }
+    out
+}
+
+fn render_variable(name: &str, argument: Option<&str>, values: &TemplateValues) -> String {
+    match name {
+        "date" => format_time(values.now, argument.unwrap_or("%Y-%m-%d")),
+        "time" => format_time(values.now, argument.unwrap_or("%H:%M")),
+        "datetime" => format_time(values.now, argument.unwrap_or("%Y-%m-%d %H:%M")),
+        "clipboard" => values.clipboard.clone().unwrap_or_default(),
+        "selection" => values.selection.clone().unwrap_or_default(),
+        "app" => values.app.clone(),
+        // Unknown variables are typed back unchanged
+        _ => match argument {
+            Some(argument) => format!("{{{}:{}}}", name, argument),
+            None => format!("{{{}}}", name),
+        },
+    }
+}
+
+async fn render_template(template: &str, values: &TemplateValues) -> Result<String, String> {
+    let mut out = String::new();
+    for part in parse_template(template) {
+        match part {
+            TemplatePart::Text(text) => out.push_str(&text),
+            TemplatePart::Variable(name, Some(argument)) if name == "prompt" => {
+                let prompt = render_local(&argument, values);
+                out.push_str(generate(&prompt).await?.trim());
+            }
+            TemplatePart::Variable(name, argument) => {
+                out.push_str(&render_variable(&name, argument.as_deref(), values))
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TemplatePart::*;
+
+    fn snippet(id: &str, abbreviation: &str) -> Snippet {
+        Snippet {
+            id: id.to_string(),
+            abbreviation: abbreviation.to_string(),
+            template: String::new(),
+            apps: Vec::new(),
+        }
+    }
+
+    fn values() -> TemplateValues {
+        TemplateValues {
+            now: LocalTime {
+                year: 2024,
+                month: 3,
+                day: 7,
+                hour: 9,
+                minute: 5,
+                second: 2,
+            },
+            clipboard: Some("copied".into()),
+            selection: None,
+            app: "notepad".into(),
+        }
+    }
+
+    #[test]
+    fn abbreviations_must_not_prefix_each_other() {
+        let snippets = vec![snippet("a", ";sig"), snippet("b", ";addr")];
+        assert_eq!(
+            prefix_conflict(&snippets, &snippet("c", ";si")).map(|s| s.id.as_str()),
+            Some("a")
+        );
+        assert_eq!(
+            prefix_conflict(&snippets, &snippet("c", ";signature")).map(|s| s.id.as_str()),
+            Some("a")
+        );
+        assert!(prefix_conflict(&snippets, &snippet("c", ";date")).is_none());
+        // Same abbreviation for another app, and editing a snippet in place
+        assert!(prefix_conflict(&snippets, &snippet("c", ";sig")).is_none());
+        assert!(prefix_conflict(&snippets, &snippet("a", ";s")).is_none());
+    }
+
+    #[test]
+    fn parses_text_variables_and_escaped_braces() {
+        assert_eq!(
+            parse_template("Hi {{x}} {date:%d/%m} and {App}"),
+            vec![
+                Text("Hi {x} ".into()),
+                Variable("date".into(), Some("%d/%m".into())),
+                Text(" and ".into()),
+                Variable("app".into(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_braces_stay_in_the_argument() {
+        assert_eq!(
+            parse_template("{prompt:Greet {app}}!"),
+            vec![
+                Variable("prompt".into(), Some("Greet {app}".into())),
+                Text("!".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_braces_are_kept_as_typed() {
+        assert_eq!(parse_template("a {date"), vec![Text("a {date".into())]);
+    }
+
+    #[test]
+    fn formats_time_placeholders() {
+        let now = values().now;
+        assert_eq!(format_time(now, "%Y-%m-%d %H:%M:%S"), "2024-03-07 09:05:02");
+        assert_eq!(format_time(now, "100%% %x %"), "100% %x %");
+    }
+
+    #[test]
+    fn missing_snippets_file_is_an_empty_list() {
+        let dir = std::env::temp_dir().join(format!("rae-snippets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("missing.json");
+        assert!(load(&path).unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_snippets_file_is_moved_aside() {
+        let dir = std::env::temp_dir().join(format!("rae-snippets-bad-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("snippets.json");
+        std::fs::write(&path, "[{\"id\": ").unwrap();
+        assert!(load(&path).is_err());
+        assert!(!path.exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("snippets.json.bad")).unwrap(),
+            "[{\"id\": "
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn renders_local_variables() {
+        assert_eq!(
+            render_local(
+                "{date} {time} in {app}: {clipboard}{selection} {nope:x}",
+                &values()
+            ),
+            "2024-03-07 09:05 in notepad: copied {nope:x}"
+        );
+        assert_eq!(render_local("{datetime:%H%M}", &values()), "0905");
+    }
+}
@@ -20,7 +20,7 @@ pub async fn create_connection(
     let body_str = serde_json::to_string(&body).map_err(|e| e.to_string())?;
 
     let res = client
-        .post(&format!(
+        .post(format!(
             "https://api.supermemory.ai/v3/connections/{}",
             provider
         ))
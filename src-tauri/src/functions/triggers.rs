@@ -0,0 +1,549 @@
+//! Trigger phrases such as `@rae` typed anywhere on the system.
+//!
+//! Key strokes from [`super::keyboard`] are fed into a [`TriggerMatcher`], a
+//! trie walked by every position where a phrase could have started. Backspace
+//! steps the matcher back, and shortcuts, navigation keys or a long pause
+//! reset it, so only phrases actually typed as text fire. Keys typed into an
+//! IME composition reset it as well. Phrases only start at a word boundary,
+//! so the `@rae` in an address like `x@raelynn.dev` does not fire.
+
+use super::keyboard::{KeyKind, KeyStroke};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// Matcher states remembered for backspace; older ones are forgotten.
+const MAX_HISTORY: usize = 64;
+
+static TRIGGERS_CHANGED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TriggerConfig {
+    pub phrases: Vec<String>,
+    pub case_sensitive: bool,
+    /// A pause longer than this between two keys starts over.
+    pub reset_after_ms: u64,
+}
+
+impl Default for TriggerConfig {
+    fn default() -> Self {
+        Self {
+            phrases: vec!["@rae".to_string()],
+            case_sensitive: false,
+            reset_after_ms: 3000,
+        }
+    }
+}
+
+fn trigger_config() -> &'static Mutex<TriggerConfig> {
+    static CONFIG: OnceLock<Mutex<TriggerConfig>> = OnceLock::new();
+    CONFIG.get_or_init(|| Mutex::new(TriggerConfig::default()))
+}
+
+#[tauri::command]
+pub fn set_trigger_config(config: TriggerConfig) -> Result<(), String> {
+    if config.phrases.iter().any(|p| p.trim().is_empty()) {
+        return Err("Trigger phrases cannot be empty".into());
+    }
+    *trigger_config().lock().map_err(|e| e.to_string())? = config;
+    TRIGGERS_CHANGED.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_trigger_config() -> TriggerConfig {
+    trigger_config()
+        .lock()
+        .map(|c| c.clone())
+        .unwrap_or_default()
+}
+
+/// Whether the trigger config changed since the last call; matchers rebuild
+/// themselves when it did.
+pub(crate) fn take_triggers_changed() -> bool {
+    TRIGGERS_CHANGED.swap(false, Ordering::SeqCst)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TriggerMatch {
+    /// The phrase as configured.
+    pub phrase: String,
+    /// Characters of the phrase, i.e. how many backspaces erase it.
+    pub typed_chars: usize,
+}
+
+#[derive(Default)]
+struct TrieNode {
+    children: HashMap<char, usize>,
+    /// Index into `phrases` when a phrase ends here.
+    phrase: Option<usize>,
+}
+
+pub struct TriggerMatcher {
+    nodes: Vec<TrieNode>,
+    phrases: Vec<String>,
+    case_sensitive: bool,
+    reset_after_ms: u64,
+    /// Trie nodes reached by partial matches ending at the last typed character.
+    active: Vec<usize>,
+    /// The last typed character; `None` when unknown, e.g. after a reset.
+    previous: Option<char>,
+    history: Vec<(Vec<usize>, Option<char>)>,
+    last_at: Option<u64>,
+}
+
+impl TriggerMatcher {
+    pub fn new(config: &TriggerConfig) -> Self {
+        let mut matcher = Self {
+            nodes: vec![TrieNode::default()],
+            phrases: Vec::new(),
+            case_sensitive: config.case_sensitive,
+            reset_after_ms: config.reset_after_ms,
+            active: Vec::new(),
+            previous: None,
+            history: Vec::new(),
+            last_at: None,
+        };
+        for phrase in &config.phrases {
+            matcher.insert(phrase);
+        }
+        matcher
+    }
+
+    fn insert(&mut self, phrase: &str) {
+        let mut node = 0;
+        for c in phrase.chars() {
+            let c = self.normalize(c);
+            node = match self.nodes[node].children.get(&c) {
+                Some(&next) => next,
+                None => {
+                    self.nodes.push(TrieNode::default());
+                    let next = self.nodes.len() - 1;
+                    self.nodes[node].children.insert(c, next);
+                    next
+                }
+            };
+        }
+        if node != 0 && self.nodes[node].phrase.is_none() {
+            self.phrases.push(phrase.to_string());
+            self.nodes[node].phrase = Some(self.phrases.len() - 1);
+        }
+    }
+
+    fn normalize(&self, c: char) -> char {
+        if self.case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.active.clear();
+        self.previous = None;
+        self.history.clear();
+    }
+
+    /// Feeds one key stroke and returns the phrase it completed, if any.
+    /// When phrases overlap, the first one to complete wins.
+    pub fn feed(&mut self, stroke: &KeyStroke) -> Option<TriggerMatch> {
+        // Text typed by software is not the user typing a trigger
+        if stroke.injected {
+            return None;
+        }
+        if let Some(last) = self.last_at {
+            if stroke.at.saturating_sub(last) > self.reset_after_ms {
+                self.reset();
+            }
+        }
+        self.last_at = Some(stroke.at);
+        // Keys going into an IME composition are not text yet; only what
+        // reaches the field directly can spell a phrase
+        if stroke.composing {
+            self.reset();
+            return None;
+        }
+
+        match stroke.key {
+            KeyKind::Modifier => None,
+            KeyKind::Backspace if !stroke.ctrl => {
+                match self.history.pop() {
+                    Some((active, previous)) => {
+                        self.active = active;
+                        self.previous = previous;
+                    }
+                    None => self.reset(),
+                }
+                None
+            }
+            KeyKind::Character if !stroke.text.is_empty() => {
+                // Ctrl or Alt shortcuts type control characters; AltGr (Ctrl+Alt)
+                // types printable ones and is real text
+                if stroke.text.chars().any(char::is_control) {
+                    self.reset();
+                    return None;
+                }
+                let mut found = None;
+                for c in stroke.text.chars() {
+                    found = found.or(self.push_char(c));
+                }
+                found
+            }
+            // Dead keys type nothing until the next key
+            KeyKind::Character => None,
+            _ => {
+                self.reset();
+                None
+            }
+        }
+    }
+
+    fn push_char(&mut self, c: char) -> Option<TriggerMatch> {
+        let c = self.normalize(c);
+        // A new match may only start after something that ends a word
+        let at_boundary = self.previous.is_none_or(|p| !p.is_alphanumeric());
+        let mut next: Vec<usize> = Vec::with_capacity(self.active.len() + 1);
+        for &node in self.active.iter().chain(at_boundary.then_some(&0)) {
+            if let Some(&child) = self.nodes[node].children.get(&c) {
+                if !next.contains(&child) {
+                    next.push(child);
+                }
+            }
+        }
+        if let Some(phrase) = next.iter().find_map(|&n| self.nodes[n].phrase) {
+            self.reset();
+            let phrase = self.phrases[phrase].clone();
+            return Some(TriggerMatch {
+                typed_chars: phrase.chars().count(),
+                phrase,
+            });
+        }
+        let previous = self.previous.replace(c);
+        self.history
+            .push((std::mem::replace(&mut self.active, next), previous));
+        if self.history.len() > MAX_HISTORY {
+            self.history.remove(0);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ime::Settler;
+    use super::*;
+    use std::path::Path;
+
+    fn stroke(key: KeyKind, text: &str, at: u64) -> KeyStroke {
+        KeyStroke {
+            key,
+            text: text.to_string(),
+            ctrl: false,
+            alt: false,
+            injected: false,
+            composing: false,
+            at,
+        }
+    }
+
+    /// Builds a key stream from a string, 40 ms apart. `\u{8}` is backspace
+    /// and `\n` is enter.
+    fn typed(text: &str) -> Vec<KeyStroke> {
+        text.chars()
+            .enumerate()
+            .map(|(i, c)| {
+                let at = i as u64 * 40;
+                match c {
+                    '\u{8}' => stroke(KeyKind::Backspace, "", at),
+                    '\n' => stroke(KeyKind::Enter, "", at),
+                    c => stroke(KeyKind::Character, &c.to_string(), at),
+                }
+            })
+            .collect()
+    }
+
+    fn matcher(phrases: &[&str]) -> TriggerMatcher {
+        TriggerMatcher::new(&TriggerConfig {
+            phrases: phrases.iter().map(|p| p.to_string()).collect(),
+            ..TriggerConfig::default()
+        })
+    }
+
+    fn run(matcher: &mut TriggerMatcher, keys: &[KeyStroke]) -> Vec<String> {
+        keys.iter()
+            .filter_map(|k| matcher.feed(k))
+            .map(|m| m.phrase)
+            .collect()
+    }
+
+    #[test]
+    fn matches_phrase_typed_inside_text() {
+        let mut m = matcher(&["@rae"]);
+        assert_eq!(run(&mut m, &typed("hey @rae what")), vec!["@rae"]);
+    }
+
+    #[test]
+    fn reports_phrase_length() {
+        let mut m = matcher(&["@rae"]);
+        let found = typed("@rae").iter().find_map(|k| m.feed(k)).unwrap();
+        assert_eq!(found.typed_chars, 4);
+    }
+
+    #[test]
+    fn restarts_inside_a_partial_match() {
+        let mut m = matcher(&["@rae"]);
+        assert_eq!(run(&mut m, &typed("@r@@rae")), vec!["@rae"]);
+    }
+
+    #[test]
+    fn phrases_start_at_a_word_boundary() {
+        let mut m = matcher(&["@rae"]);
+        assert!(run(&mut m, &typed("mail x@raelynn.dev")).is_empty());
+        let mut m = matcher(&["@rae"]);
+        assert_eq!(run(&mut m, &typed("(@rae")), vec!["@rae"]);
+        // Erasing the word in front makes it a boundary again
+        let mut m = matcher(&["@rae"]);
+        assert_eq!(run(&mut m, &typed("x\u{8}@rae")), vec!["@rae"]);
+    }
+
+    #[test]
+    fn backspace_steps_back() {
+        let mut m = matcher(&["@rae"]);
+        assert_eq!(run(&mut m, &typed("@rx\u{8}ae")), vec!["@rae"]);
+        let mut m = matcher(&["@rae"]);
+        assert!(run(&mut m, &typed("@ra\u{8}\u{8}ae")).is_empty());
+    }
+
+    #[test]
+    fn enter_and_navigation_reset() {
+        let mut m = matcher(&["@rae"]);
+        assert!(run(&mut m, &typed("@r\nae")).is_empty());
+        let mut m = matcher(&["@rae"]);
+        let mut keys = typed("@ra");
+        keys.push(stroke(KeyKind::Navigation, "", 130));
+        keys.push(stroke(KeyKind::Character, "e", 170));
+        assert!(run(&mut m, &keys).is_empty());
+    }
+
+    #[test]
+    fn modifiers_alone_do_not_reset() {
+        let mut m = matcher(&["@rae"]);
+        let keys = vec![
+            stroke(KeyKind::Modifier, "", 0),
+            stroke(KeyKind::Character, "@", 10),
+            stroke(KeyKind::Modifier, "", 20),
+            stroke(KeyKind::Character, "r", 30),
+            stroke(KeyKind::Character, "a", 40),
+            stroke(KeyKind::Character, "e", 50),
+        ];
+        assert_eq!(run(&mut m, &keys), vec!["@rae"]);
+    }
+
+    #[test]
+    fn shortcuts_do_not_type() {
+        // Ctrl+R types a control character instead of "r"
+        let mut m = matcher(&["@rae"]);
+        let mut keys = typed("@");
+        let mut ctrl_r = stroke(KeyKind::Character, "\u{12}", 40);
+        ctrl_r.ctrl = true;
+        keys.push(ctrl_r);
+        keys.extend(typed("ae").into_iter().map(|mut k| {
+            k.at += 80;
+            k
+        }));
+        assert!(run(&mut m, &keys).is_empty());
+    }
+
+    #[test]
+    fn altgr_characters_count_as_text() {
+        // German layout: @ is AltGr+Q, reported with Ctrl and Alt held
+        let mut m = matcher(&["@rae"]);
+        let mut at = stroke(KeyKind::Character, "@", 0);
+        at.ctrl = true;
+        at.alt = true;
+        let mut keys = vec![at];
+        keys.extend(typed("rae").into_iter().map(|mut k| {
+            k.at += 40;
+            k
+        }));
+        assert_eq!(run(&mut m, &keys), vec!["@rae"]);
+    }
+
+    #[test]
+    fn dead_keys_wait_for_the_composed_character() {
+        let mut m = matcher(&["@café"]);
+        let mut keys = typed("@caf");
+        keys.push(stroke(KeyKind::Character, "", 200));
+        keys.push(stroke(KeyKind::Character, "é", 240));
+        assert_eq!(run(&mut m, &keys), vec!["@café"]);
+    }
+
+    #[test]
+    fn long_pause_resets() {
+        let mut m = matcher(&["@rae"]);
+        let keys = vec![
+            stroke(KeyKind::Character, "@", 0),
+            stroke(KeyKind::Character, "r", 40),
+            stroke(KeyKind::Character, "a", 5000),
+            stroke(KeyKind::Character, "e", 5040),
+        ];
+        assert!(run(&mut m, &keys).is_empty());
+    }
+
+    #[test]
+    fn injected_keys_are_ignored() {
+        let mut m = matcher(&["@rae"]);
+        let keys: Vec<KeyStroke> = typed("@rae")
+            .into_iter()
+            .map(|mut k| {
+                k.injected = true;
+                k
+            })
+            .collect();
+        assert!(run(&mut m, &keys).is_empty());
+    }
+
+    #[test]
+    fn matches_several_phrases_case_insensitively() {
+        let mut m = matcher(&["@rae", ";sig", "@re"]);
+        assert_eq!(
+            run(&mut m, &typed("@RAE then ;Sig and @re")),
+            vec!["@rae", ";sig", "@re"]
+        );
+    }
+
+    #[test]
+    fn case_sensitive_phrases() {
+        let mut m = TriggerMatcher::new(&TriggerConfig {
+            phrases: vec!["@Rae".into()],
+            case_sensitive: true,
+            ..TriggerConfig::default()
+        });
+        assert_eq!(run(&mut m, &typed("@rae @Rae")), vec!["@Rae"]);
+    }
+
+    #[test]
+    fn shorter_phrase_completing_first_wins() {
+        let mut m = matcher(&["@rae", "ra"]);
+        assert_eq!(run(&mut m, &typed("@rae")), vec!["ra"]);
+    }
+
+    #[test]
+    fn composing_keys_reset() {
+        let mut m = matcher(&["@rae"]);
+        let mut keys = typed("@rae");
+        keys[1].composing = true;
+        assert!(run(&mut m, &keys).is_empty());
+    }
+
+    enum TraceEvent {
+        /// A key, and whether an input method was on when it was typed.
+        Key(KeyStroke, bool),
+        Preedit(bool),
+        Commit(String),
+    }
+
+    /// Parses one trace line: `<ms> <kind>[:<text>] [ctrl] [alt] [composing]
+    /// [injected] [ime]`. In the text `\s` is a space and `\xNN` a control
+    /// character. IME events are `<ms> preedit:<text>` with the preedit shown,
+    /// `<ms> preedit` when it is hidden and `<ms> commit:<text>`.
+    fn parse_trace_line(line: &str) -> (u64, TraceEvent) {
+        let mut fields = line.split_whitespace();
+        let at = fields.next().unwrap().parse().unwrap();
+        let token = fields.next().unwrap();
+        let (kind, text) = token.split_once(':').unwrap_or((token, ""));
+        let key = match kind {
+            "char" => KeyKind::Character,
+            "backspace" => KeyKind::Backspace,
+            "enter" => KeyKind::Enter,
+            "escape" => KeyKind::Escape,
+            "tab" => KeyKind::Tab,
+            "nav" => KeyKind::Navigation,
+            "mod" => KeyKind::Modifier,
+            "other" => KeyKind::Other,
+            "preedit" => return (at, TraceEvent::Preedit(!text.is_empty())),
+            "commit" => return (at, TraceEvent::Commit(text.to_string())),
+            other => panic!("unknown key kind {}", other),
+        };
+        let text = match text.strip_prefix("\\x") {
+            Some(hex) => char::from(u8::from_str_radix(hex, 16).unwrap()).to_string(),
+            None => text.replace("\\s", " "),
+        };
+        let mut stroke = stroke(key, &text, at);
+        let mut ime = false;
+        for flag in fields {
+            match flag {
+                "ctrl" => stroke.ctrl = true,
+                "alt" => stroke.alt = true,
+                "composing" => stroke.composing = true,
+                "injected" => stroke.injected = true,
+                "ime" => ime = true,
+                other => panic!("unknown flag {}", other),
+            }
+        }
+        (at, TraceEvent::Key(stroke, ime))
+    }
+
+    /// Puts a trace through the input method settling the keyboard watcher
+    /// does, giving the strokes subscribers would see.
+    fn settle(events: Vec<(u64, TraceEvent)>) -> Vec<KeyStroke> {
+        let mut settler = Settler::new();
+        let mut keys = Vec::new();
+        for (at, event) in events {
+            keys.extend(settler.drain(at));
+            match event {
+                TraceEvent::Key(stroke, ime) => settler.key(stroke, ime, at),
+                TraceEvent::Preedit(shown) => settler.preedit(shown, at),
+                TraceEvent::Commit(text) => settler.commit(&text, at),
+            }
+            keys.extend(settler.drain(at));
+        }
+        keys.extend(settler.drain(u64::MAX));
+        keys
+    }
+
+    /// Traces live in `fixtures/keyboard/`. A `# expect:` header lists the
+    /// phrases the trace should fire, comma separated, or `none`.
+    #[test]
+    fn replays_keyboard_traces() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/keyboard");
+        let mut files: Vec<_> = std::fs::read_dir(&root)
+            .expect("keyboard traces missing")
+            .map(|e| e.unwrap().path())
+            .collect();
+        files.sort();
+        let mut failures = Vec::new();
+        for file in &files {
+            let trace = std::fs::read_to_string(file).unwrap();
+            let mut expected = None;
+            let mut events = Vec::new();
+            for line in trace.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                match line.strip_prefix('#') {
+                    Some(comment) => {
+                        if let Some(list) = comment.trim().strip_prefix("expect:") {
+                            expected = Some(match list.trim() {
+                                "none" => Vec::new(),
+                                list => list.split(',').map(|p| p.trim().to_string()).collect(),
+                            });
+                        }
+                    }
+                    None => events.push(parse_trace_line(line)),
+                }
+            }
+            let expected: Vec<String> =
+                expected.unwrap_or_else(|| panic!("{}: no expect header", file.display()));
+            let got = run(&mut matcher(&["@rae"]), &settle(events));
+            if got != expected {
+                failures.push(format!(
+                    "{}: expected {:?}, got {:?}",
+                    file.display(),
+                    expected,
+                    got
+                ));
+            }
+        }
+        assert!(!files.is_empty(), "no keyboard traces");
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+}
@@ -2,13 +2,21 @@
 
 // Declare the modules that make up the application logic.
 mod functions;
+#[cfg(target_os = "windows")]
 mod platform;
 mod utils;
+#[cfg(target_os = "linux")]
+mod x11;
 
 fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
+        .setup(|app| {
+            // Bring back the clipboard history saved in an earlier session
+            functions::clipboard_history::restore_clipboard_history(app.handle());
+            Ok(())
+        })
         // Register all the invokable commands from the `commands` module.
         .invoke_handler(tauri::generate_handler![
             functions::overlay::enable_notch,
@@ -38,10 +46,75 @@ fn main() {
             functions::stealth::get_stealth_mode_enabled,
             functions::stealth::apply_stealth_mode_to_window,
             functions::general::inject_text_to_window_by_title,
+            functions::injection::cancel_injection,
+            functions::injection::resume_injection,
+            functions::injection::get_injection_status,
+            functions::injection::set_injection_config,
+            functions::injection::get_injection_config,
             functions::general::capture_window_screenshot,
             functions::general::capture_window_screenshot_by_title,
             functions::general::capture_window_screenshot_by_hwnd,
-            functions::supermemory::create_connection
+            functions::supermemory::create_connection,
+            functions::generate::set_generation_profile,
+            functions::generate::get_generation_profile,
+            functions::quick_actions::get_quick_actions,
+            functions::quick_actions::set_quick_actions,
+            functions::quick_actions::reset_quick_actions,
+            functions::quick_actions::run_quick_action,
+            functions::quick_actions::undo_quick_action,
+            functions::markdown::convert_markdown,
+            functions::markdown::set_output_format_mapping,
+            functions::markdown::get_output_format_mapping,
+            functions::clipboard::copy_markdown_to_clipboard,
+            functions::clipboard::get_clipboard_image,
+            functions::clipboard::clipboard_write,
+            functions::clipboard_history::set_clipboard_history_enabled,
+            functions::clipboard_history::get_clipboard_history_enabled,
+            functions::clipboard_history::set_clipboard_history_policy,
+            functions::clipboard_history::get_clipboard_history_policy,
+            functions::clipboard_history::list_clipboard_history,
+            functions::clipboard_history::search_clipboard_history,
+            functions::clipboard_history::paste_clipboard_history_item,
+            functions::clipboard_history::pin_clipboard_history_item,
+            functions::clipboard_history::delete_clipboard_history_item,
+            functions::clipboard_history::clear_clipboard_history,
+            functions::sensitive::set_sensitive_filter_config,
+            functions::sensitive::get_sensitive_filter_config,
+            functions::sensitive::list_secret_detectors,
+            functions::classify::classify_text,
+            functions::selection_gesture::set_selection_gesture_config,
+            functions::selection_gesture::get_selection_gesture_config,
+            functions::selection_copy::set_copy_fallback_config,
+            functions::selection_copy::get_copy_fallback_config,
+            functions::selection_text::set_selection_limits,
+            functions::selection_text::get_selection_limits,
+            functions::selection_text::read_selection_blob,
+            functions::selection_text::release_selection_blob,
+            functions::selection_context::set_selection_context_config,
+            functions::selection_context::get_selection_context_config,
+            functions::triggers::set_trigger_config,
+            functions::triggers::get_trigger_config,
+            functions::inline_prompt::set_inline_prompt_config,
+            functions::inline_prompt::get_inline_prompt_config,
+            functions::inline_prompt::cancel_inline_prompt,
+            functions::snippets::list_snippets,
+            functions::snippets::save_snippet,
+            functions::snippets::delete_snippet,
+            functions::snippets::set_snippets_enabled,
+            functions::snippets::get_snippets_enabled,
+            functions::secure_input::set_secure_input_config,
+            functions::secure_input::get_secure_input_config,
+            functions::secure_input::get_watchers_paused,
+            functions::keyboard::get_keyboard_watcher_status,
+            functions::gestures::set_gesture_config,
+            functions::gestures::get_gesture_config,
+            functions::overlay::stop_notch_watcher,
+            functions::overlay::get_notch_watcher_status,
+            functions::overlay::set_notch_config,
+            functions::overlay::get_notch_config,
+            functions::overlay::request_overlay_transition,
+            functions::overlay::force_top_center_magic_dot,
+            functions::overlay_state::get_overlay_state
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
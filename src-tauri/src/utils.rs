@@ -1,72 +1,475 @@
 //! This module contains general-purpose utility functions, like window animations.
+//!
+//! Window animations run on a single scheduler thread, so starting one never
+//! blocks the caller. Each window has at most one animation: starting another
+//! retargets it from wherever the window is mid-flight, and
+//! [`cancel_window_animation`] stops it in place. Frames are paced by the
+//! refresh rate of the monitor the window is on.
 
-use std::{thread, time::Duration};
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use tauri::WebviewWindow;
 
-/// Animates a window's size from a starting to an ending size over a series of steps.
-pub fn smooth_resize(
-    window: &WebviewWindow,
-    from: tauri::PhysicalSize<u32>,
-    to: tauri::PhysicalSize<u32>,
-    steps: u32,
-    delay: u64,
-) {
-    if steps == 0 {
-        let _ = window.set_size(tauri::Size::Physical(to));
-        return;
+/// Used when the monitor's refresh rate cannot be read.
+const DEFAULT_REFRESH_HZ: u32 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    EaseInQuad,
+    EaseOutQuad,
+    EaseInOutQuad,
+    EaseOutCubic,
+    EaseInOutCubic,
+    /// Overshoots the target slightly before settling.
+    EaseOutBack,
+}
+
+impl Easing {
+    /// Maps linear progress `t` in `0.0..=1.0` to eased progress. Every curve
+    /// starts at 0 and ends at 1.
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInQuad => t * t,
+            Easing::EaseOutQuad => 1.0 - (1.0 - t).powi(2),
+            Easing::EaseInOutQuad if t < 0.5 => 2.0 * t * t,
+            Easing::EaseInOutQuad => 1.0 - (-2.0 * t + 2.0).powi(2) / 2.0,
+            Easing::EaseOutCubic => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOutCubic if t < 0.5 => 4.0 * t.powi(3),
+            Easing::EaseInOutCubic => 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0,
+            Easing::EaseOutBack => {
+                let c1 = 1.70158;
+                let c3 = c1 + 1.0;
+                1.0 + c3 * (t - 1.0).powi(3) + c1 * (t - 1.0).powi(2)
+            }
+        }
+    }
+}
+
+/// Where an animation takes a window. Parts left `None` are not animated.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WindowTransform {
+    pub position: Option<tauri::PhysicalPosition<i32>>,
+    pub size: Option<tauri::PhysicalSize<u32>>,
+}
+
+impl WindowTransform {
+    pub fn position(position: tauri::PhysicalPosition<i32>) -> Self {
+        Self {
+            position: Some(position),
+            size: None,
+        }
     }
 
-    let from_w = from.width as f64;
-    let from_h = from.height as f64;
-    let to_w = to.width as f64;
-    let to_h = to.height as f64;
+    pub fn size(size: tauri::PhysicalSize<u32>) -> Self {
+        Self {
+            position: None,
+            size: Some(size),
+        }
+    }
+}
 
-    for i in 1..=steps {
-        // Progress from 0.0 to 1.0
-        let t = i as f64 / steps as f64;
+/// Window bounds in physical pixels, kept fractional between frames so
+/// rounding never accumulates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Frame {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
 
-        // Easing function (ease-out cubic)
-        let ease = 1.0 - (1.0 - t).powi(3);
+impl Frame {
+    fn lerp(from: Frame, to: Frame, progress: f64) -> Frame {
+        let mix = |a: f64, b: f64| a + (b - a) * progress;
+        Frame {
+            x: mix(from.x, to.x),
+            y: mix(from.y, to.y),
+            width: mix(from.width, to.width),
+            height: mix(from.height, to.height),
+        }
+    }
+}
 
-        let new_w = from_w + (to_w - from_w) * ease;
-        let new_h = from_h + (to_h - from_h) * ease;
+/// The bounds an animation runs between and which of them it changes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Path {
+    from: Frame,
+    to: Frame,
+    moves: bool,
+    resizes: bool,
+}
 
+/// The path of an animation to `to` that starts at `current`. When it
+/// replaces one still `running` from there, parts `to` leaves out keep heading
+/// for the old target. `None` when nothing would change.
+fn retarget(current: Frame, running: Option<Path>, to: WindowTransform) -> Option<Path> {
+    let mut path = running.unwrap_or(Path {
+        from: current,
+        to: current,
+        moves: false,
+        resizes: false,
+    });
+    path.from = current;
+    if let Some(position) = to.position {
+        path.to.x = position.x as f64;
+        path.to.y = position.y as f64;
+        path.moves = true;
+    }
+    if let Some(size) = to.size {
+        path.to.width = size.width as f64;
+        path.to.height = size.height as f64;
+        path.resizes = true;
+    }
+    (path.moves || path.resizes).then_some(path)
+}
+
+struct Animation {
+    window: WebviewWindow,
+    path: Path,
+    started: Instant,
+    duration: Duration,
+    easing: Easing,
+    frame_interval: Duration,
+}
+
+impl Animation {
+    /// The frame due at `now` and whether it is the last one.
+    fn frame_at(&self, now: Instant) -> (Frame, bool) {
+        let Path { from, to, .. } = self.path;
+        if self.duration.is_zero() {
+            return (to, true);
+        }
+        let t =
+            now.saturating_duration_since(self.started).as_secs_f64() / self.duration.as_secs_f64();
+        if t >= 1.0 {
+            (to, true)
+        } else {
+            (Frame::lerp(from, to, self.easing.apply(t)), false)
+        }
+    }
+}
+
+fn apply_frame(window: &WebviewWindow, frame: Frame, path: &Path) {
+    if path.moves {
+        let _ = window.set_position(tauri::Position::Physical(tauri::PhysicalPosition {
+            x: frame.x.round() as i32,
+            y: frame.y.round() as i32,
+        }));
+    }
+    if path.resizes {
         let _ = window.set_size(tauri::Size::Physical(tauri::PhysicalSize {
-            width: new_w.round().max(1.0) as u32,
-            height: new_h.round().max(1.0) as u32,
+            width: frame.width.round().max(1.0) as u32,
+            height: frame.height.round().max(1.0) as u32,
         }));
+    }
+}
+
+struct Scheduler {
+    /// Running animations by window label.
+    animations: Mutex<HashMap<String, Animation>>,
+    wake: Condvar,
+}
 
-        thread::sleep(Duration::from_millis(delay));
+fn scheduler() -> &'static Scheduler {
+    static SCHEDULER: OnceLock<Scheduler> = OnceLock::new();
+    SCHEDULER.get_or_init(|| {
+        std::thread::spawn(run_scheduler);
+        Scheduler {
+            animations: Mutex::new(HashMap::new()),
+            wake: Condvar::new(),
+        }
+    })
+}
+
+fn run_scheduler() {
+    let scheduler = scheduler();
+    loop {
+        let Ok(mut animations) = scheduler.animations.lock() else {
+            return;
+        };
+        while animations.is_empty() {
+            animations = match scheduler.wake.wait(animations) {
+                Ok(animations) => animations,
+                Err(_) => return,
+            };
+        }
+        // Setters do not wait for the event loop, so frames go out under the
+        // lock and a cancel can never be overtaken by one already computed
+        let now = Instant::now();
+        animations.retain(|_, animation| {
+            let (frame, done) = animation.frame_at(now);
+            apply_frame(&animation.window, frame, &animation.path);
+            !done
+        });
+        let interval = animations
+            .values()
+            .map(|a| a.frame_interval)
+            .min()
+            .unwrap_or_default();
+        drop(animations);
+        std::thread::sleep(interval);
+    }
+}
+
+/// Where a window is and how fast its monitor refreshes. Reading one waits for
+/// the event loop, so callers holding a lock the main thread may wait on read
+/// it first and start the animation with [`animate_window_from`].
+#[derive(Clone, Copy, Debug)]
+pub struct WindowSnapshot {
+    frame: Frame,
+    frame_interval: Duration,
+}
+
+impl WindowSnapshot {
+    pub fn read(window: &WebviewWindow) -> Option<Self> {
+        let (Ok(position), Ok(size)) = (window.outer_position(), window.outer_size()) else {
+            return None;
+        };
+        Some(Self {
+            frame: Frame {
+                x: position.x as f64,
+                y: position.y as f64,
+                width: size.width as f64,
+                height: size.height as f64,
+            },
+            frame_interval: refresh_interval(window),
+        })
     }
 
-    // Ensure final size is exact
-    let _ = window.set_size(tauri::Size::Physical(to));
+    pub fn position(&self) -> tauri::PhysicalPosition<i32> {
+        tauri::PhysicalPosition {
+            x: self.frame.x.round() as i32,
+            y: self.frame.y.round() as i32,
+        }
+    }
+
+    pub fn size(&self) -> tauri::PhysicalSize<u32> {
+        tauri::PhysicalSize {
+            width: self.frame.width.round() as u32,
+            height: self.frame.height.round() as u32,
+        }
+    }
 }
 
-/// Animates a window's position from a starting to an ending position over a series of steps.
-pub fn smooth_move(
+/// Animates `window` to `to` over `duration` without blocking. An animation
+/// already running on the window is retargeted: the new one starts from the
+/// current frame, and parts `to` leaves out keep heading for the old target.
+pub fn animate_window(
     window: &WebviewWindow,
-    from: tauri::PhysicalPosition<i32>,
-    to: tauri::PhysicalPosition<i32>,
-    steps: u32,
-    delay: u64,
+    to: WindowTransform,
+    duration: Duration,
+    easing: Easing,
 ) {
-    if steps == 0 {
-        let _ = window.set_position(tauri::Position::Physical(to));
+    if let Some(snapshot) = WindowSnapshot::read(window) {
+        animate_window_from(window, snapshot, to, duration, easing);
+    }
+}
+
+/// [`animate_window`] with the window's bounds read beforehand. Never waits
+/// for the event loop.
+pub fn animate_window_from(
+    window: &WebviewWindow,
+    snapshot: WindowSnapshot,
+    to: WindowTransform,
+    duration: Duration,
+    easing: Easing,
+) {
+    let frame_interval = snapshot.frame_interval;
+
+    let scheduler = scheduler();
+    let Ok(mut animations) = scheduler.animations.lock() else {
         return;
+    };
+    let now = Instant::now();
+    let label = window.label().to_string();
+    let (current, running) = match animations.remove(&label) {
+        Some(running) => (running.frame_at(now).0, Some(running.path)),
+        None => (snapshot.frame, None),
+    };
+    let Some(path) = retarget(current, running, to) else {
+        return;
+    };
+    animations.insert(
+        label,
+        Animation {
+            window: window.clone(),
+            path,
+            started: now,
+            duration,
+            easing,
+            frame_interval,
+        },
+    );
+    scheduler.wake.notify_one();
+}
+
+/// Puts `window` at `to` on the next frame, replacing any running animation.
+/// Goes through the scheduler so a frame still in flight cannot land after it.
+pub fn jump_window(window: &WebviewWindow, to: WindowTransform) {
+    animate_window(window, to, Duration::ZERO, Easing::Linear);
+}
+
+/// Stops the animation running on `window`, leaving it where it is.
+pub fn cancel_window_animation(window: &WebviewWindow) {
+    if let Ok(mut animations) = scheduler().animations.lock() {
+        animations.remove(window.label());
     }
+}
 
-    let dx = (to.x - from.x) / steps as i32;
-    let dy = (to.y - from.y) / steps as i32;
+/// Time between frames on the monitor `window` is on.
+fn refresh_interval(window: &WebviewWindow) -> Duration {
+    let hz = window
+        .current_monitor()
+        .ok()
+        .flatten()
+        .and_then(|monitor| monitor.name().and_then(|name| monitor_refresh_hz(name)))
+        .unwrap_or(DEFAULT_REFRESH_HZ);
+    Duration::from_secs_f64(1.0 / hz.clamp(24, 480) as f64)
+}
 
-    for i in 1..=steps {
-        let new_x = from.x + dx * i as i32;
-        let new_y = from.y + dy * i as i32;
-        let _ = window.set_position(tauri::Position::Physical(tauri::PhysicalPosition {
-            x: new_x,
-            y: new_y,
-        }));
-        thread::sleep(Duration::from_millis(delay));
+/// The refresh rate of the display named `device`, e.g. `\\.\DISPLAY1`.
+#[cfg(target_os = "windows")]
+fn monitor_refresh_hz(device: &str) -> Option<u32> {
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::um::wingdi::DEVMODEW;
+    use winapi::um::winuser::{EnumDisplaySettingsW, ENUM_CURRENT_SETTINGS};
+    let device: Vec<u16> = std::ffi::OsStr::new(device)
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    unsafe {
+        let mut mode: DEVMODEW = std::mem::zeroed();
+        mode.dmSize = std::mem::size_of::<DEVMODEW>() as u16;
+        if EnumDisplaySettingsW(device.as_ptr(), ENUM_CURRENT_SETTINGS, &mut mode) == 0 {
+            return None;
+        }
+        // 0 and 1 stand for the hardware default
+        (mode.dmDisplayFrequency > 1).then_some(mode.dmDisplayFrequency)
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn monitor_refresh_hz(_device: &str) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURVES: [Easing; 7] = [
+        Easing::Linear,
+        Easing::EaseInQuad,
+        Easing::EaseOutQuad,
+        Easing::EaseInOutQuad,
+        Easing::EaseOutCubic,
+        Easing::EaseInOutCubic,
+        Easing::EaseOutBack,
+    ];
+
+    #[test]
+    fn curves_start_at_zero_and_end_at_one() {
+        for easing in CURVES {
+            assert!(easing.apply(0.0).abs() < 1e-9, "{:?}", easing);
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-9, "{:?}", easing);
+            // Progress outside the animation is clamped
+            assert_eq!(easing.apply(2.0), easing.apply(1.0));
+        }
+    }
+
+    #[test]
+    fn in_out_curves_are_symmetric() {
+        for easing in [Easing::EaseInOutQuad, Easing::EaseInOutCubic] {
+            assert!((easing.apply(0.5) - 0.5).abs() < 1e-9);
+            let (a, b) = (easing.apply(0.2), easing.apply(0.8));
+            assert!((a + b - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn ease_out_back_overshoots() {
+        assert!((0..100).any(|i| Easing::EaseOutBack.apply(i as f64 / 100.0) > 1.0));
+    }
+
+    fn frame(x: f64, y: f64, width: f64, height: f64) -> Frame {
+        Frame {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn moved_to(x: i32, y: i32) -> WindowTransform {
+        WindowTransform::position(tauri::PhysicalPosition { x, y })
+    }
+
+    fn resized_to(width: u32, height: u32) -> WindowTransform {
+        WindowTransform::size(tauri::PhysicalSize { width, height })
+    }
+
+    #[test]
+    fn fresh_animation_keeps_what_it_does_not_change() {
+        let current = frame(10.0, 20.0, 300.0, 200.0);
+        let path = retarget(current, None, moved_to(50, 60)).unwrap();
+        assert_eq!(path.from, current);
+        assert_eq!(path.to, frame(50.0, 60.0, 300.0, 200.0));
+        assert!(path.moves && !path.resizes);
+    }
+
+    #[test]
+    fn retarget_starts_mid_flight_and_keeps_the_old_target() {
+        let running = retarget(frame(0.0, 0.0, 100.0, 100.0), None, moved_to(200, 0)).unwrap();
+        let mid_flight = frame(80.0, 0.0, 100.0, 100.0);
+        let path = retarget(mid_flight, Some(running), resized_to(400, 300)).unwrap();
+        assert_eq!(path.from, mid_flight);
+        // Still heading for the position the running animation was going to
+        assert_eq!(path.to, frame(200.0, 0.0, 400.0, 300.0));
+        assert!(path.moves && path.resizes);
+    }
+
+    #[test]
+    fn new_target_replaces_the_running_one() {
+        let running = retarget(frame(0.0, 0.0, 100.0, 100.0), None, moved_to(200, 0)).unwrap();
+        let path = retarget(
+            frame(80.0, 0.0, 100.0, 100.0),
+            Some(running),
+            moved_to(-50, 40),
+        );
+        assert_eq!(path.unwrap().to, frame(-50.0, 40.0, 100.0, 100.0));
+    }
+
+    #[test]
+    fn empty_transform_animates_nothing() {
+        let current = frame(0.0, 0.0, 100.0, 100.0);
+        assert_eq!(retarget(current, None, WindowTransform::default()), None);
+    }
+
+    #[test]
+    fn interpolation_does_not_drift() {
+        // 7 px in 3 steps used to end up 1 px short before the final snap
+        let from = Frame {
+            x: 0.0,
+            y: 0.0,
+            width: 100.0,
+            height: 100.0,
+        };
+        let to = Frame {
+            x: 7.0,
+            y: -7.0,
+            width: 107.0,
+            height: 93.0,
+        };
+        let frames: Vec<_> = (1..=3)
+            .map(|i| Frame::lerp(from, to, Easing::Linear.apply(i as f64 / 3.0)))
+            .collect();
+        assert_eq!(frames[2], to);
+        assert_eq!(frames[0].x.round(), 2.0);
+        assert_eq!(frames[1].x.round(), 5.0);
     }
-    let _ = window.set_position(tauri::Position::Physical(to));
 }
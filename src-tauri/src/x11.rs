@@ -0,0 +1,753 @@
+//! X11 backends built on x11rb. Selection changes are observed through XFixes
+//! owner-change notifications instead of polling. Key presses come from
+//! XInput2 raw events on the root window, which any client may select without
+//! grabbing the keyboard or root privileges.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use x11rb::connection::{Connection, RequestConnection as _};
+use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+use x11rb::protocol::xinput::{self, ConnectionExt as _, XIEventMask};
+use x11rb::protocol::xkb::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ConnectionExt as _,
+    CreateWindowAux, EventMask, GetPropertyReply, KeyButMask, PropMode, Property,
+    SelectionNotifyEvent, Window, WindowClass, SELECTION_NOTIFY_EVENT,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::xcb_ffi::XCBConnection;
+use x11rb::{COPY_DEPTH_FROM_PARENT, CURRENT_TIME, NONE};
+
+/// How long to wait for the selection owner to answer a conversion request,
+/// or to send the next chunk of an INCR transfer.
+const CONVERT_TIMEOUT: Duration = Duration::from_millis(500);
+
+struct Atoms {
+    utf8_string: Atom,
+    targets: Atom,
+    incr: Atom,
+    property: Atom,
+    stop: Atom,
+}
+
+impl Atoms {
+    fn intern(conn: &RustConnection) -> Result<Self, String> {
+        let intern = |name: &[u8]| -> Result<Atom, String> {
+            conn.intern_atom(false, name)
+                .map_err(|e| e.to_string())?
+                .reply()
+                .map(|r| r.atom)
+                .map_err(|e| e.to_string())
+        };
+        Ok(Self {
+            utf8_string: intern(b"UTF8_STRING")?,
+            targets: intern(b"TARGETS")?,
+            incr: intern(b"INCR")?,
+            property: intern(b"RAE_SELECTION")?,
+            stop: intern(b"RAE_STOP_WATCHER")?,
+        })
+    }
+}
+
+/// Watches one selection (`CLIPBOARD` or `PRIMARY`) for owner changes.
+pub struct SelectionWatcher {
+    conn: Arc<RustConnection>,
+    root: Window,
+    window: Window,
+    selection: Atom,
+    atoms: Atoms,
+    /// Events read while waiting for a conversion, handed to `run` next.
+    pending: Mutex<VecDeque<Event>>,
+}
+
+/// Stops a running [`SelectionWatcher`] or [`KeyWatcher`] from any thread.
+#[derive(Clone)]
+pub struct StopHandle {
+    conn: Arc<RustConnection>,
+    window: Window,
+    stop: Atom,
+}
+
+impl StopHandle {
+    pub fn stop(&self) {
+        let event = ClientMessageEvent::new(32, self.window, self.stop, [0u32; 5]);
+        let _ = self
+            .conn
+            .send_event(false, self.window, EventMask::NO_EVENT, event);
+        let _ = self.conn.flush();
+    }
+}
+
+impl SelectionWatcher {
+    pub fn new(selection_name: &str) -> Result<Self, String> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(|e| e.to_string())?;
+        conn.xfixes_query_version(5, 0)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| format!("XFixes not available: {}", e))?;
+
+        let root = conn.setup().roots[screen_num].root;
+        let window = conn.generate_id().map_err(|e| e.to_string())?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            // INCR transfers announce each chunk with a property change
+            &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )
+        .map_err(|e| e.to_string())?;
+
+        let atoms = Atoms::intern(&conn)?;
+        let selection = conn
+            .intern_atom(false, selection_name.as_bytes())
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?
+            .atom;
+
+        conn.xfixes_select_selection_input(
+            window,
+            selection,
+            SelectionEventMask::SET_SELECTION_OWNER,
+        )
+        .map_err(|e| e.to_string())?;
+        conn.flush().map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            conn: Arc::new(conn),
+            root,
+            window,
+            selection,
+            atoms,
+            pending: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle {
+            conn: self.conn.clone(),
+            window: self.window,
+            stop: self.atoms.stop,
+        }
+    }
+
+    /// Blocks until stopped, calling `on_change` every time the selection gets
+    /// a new owner. Owner changes made by this process are reported too.
+    pub fn run(&self, mut on_change: impl FnMut(&Self)) -> Result<(), String> {
+        loop {
+            let queued = self.pending.lock().ok().and_then(|mut p| p.pop_front());
+            let event = match queued {
+                Some(event) => event,
+                None => self.conn.wait_for_event().map_err(|e| e.to_string())?,
+            };
+            match event {
+                Event::XfixesSelectionNotify(ev) if ev.selection == self.selection => {
+                    on_change(self);
+                }
+                Event::ClientMessage(ev) if ev.type_ == self.atoms.stop => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Reads the current selection contents as UTF-8 text.
+    pub fn read_text(&self) -> Option<String> {
+        let bytes = self.read_target(self.atoms.utf8_string)?;
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+        Some(text)
+    }
+
+    /// Whether any mouse button is currently held, e.g. while a PRIMARY
+    /// selection is still being dragged out.
+    pub fn pointer_buttons_down(&self) -> bool {
+        let Some(reply) = self
+            .conn
+            .query_pointer(self.root)
+            .ok()
+            .and_then(|c| c.reply().ok())
+        else {
+            return false;
+        };
+        let buttons = KeyButMask::BUTTON1 | KeyButMask::BUTTON2 | KeyButMask::BUTTON3;
+        u16::from(reply.mask) & u16::from(buttons) != 0
+    }
+
+    /// The window the window manager reports as active (`_NET_ACTIVE_WINDOW`).
+    pub fn active_window(&self) -> Option<Window> {
+        let property = self.atom("_NET_ACTIVE_WINDOW")?;
+        let reply = self
+            .conn
+            .get_property(false, self.root, property, AtomEnum::WINDOW, 0, 1)
+            .ok()?
+            .reply()
+            .ok()?;
+        let window = reply.value32()?.next();
+        window.filter(|&w| w != NONE)
+    }
+
+    /// Lists the targets (MIME types and X11 formats) the owner can convert to.
+    pub fn targets(&self) -> Vec<String> {
+        let Some(bytes) = self.read_target(self.atoms.targets) else {
+            return Vec::new();
+        };
+        bytes
+            .chunks_exact(4)
+            .map(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+            .filter_map(|atom| {
+                let reply = self.conn.get_atom_name(atom).ok()?.reply().ok()?;
+                String::from_utf8(reply.name).ok()
+            })
+            .collect()
+    }
+
+    /// Like [`Self::read_target`], with the target given by name.
+    pub fn read_target_named(&self, target: &str) -> Option<Vec<u8>> {
+        let atom = self.atom(target)?;
+        self.read_target(atom)
+    }
+
+    fn atom(&self, name: &str) -> Option<Atom> {
+        intern_atom(&self.conn, name).ok()
+    }
+
+    /// Converts the selection to `target` and returns the raw property bytes.
+    /// Large values arrive in chunks through the INCR protocol.
+    pub fn read_target(&self, target: Atom) -> Option<Vec<u8>> {
+        self.conn
+            .convert_selection(
+                self.window,
+                self.selection,
+                target,
+                self.atoms.property,
+                CURRENT_TIME,
+            )
+            .ok()?;
+        self.conn.flush().ok()?;
+
+        let notify = self.wait_for_event(
+            |event| matches!(event, Event::SelectionNotify(ev) if ev.requestor == self.window),
+        )?;
+        match notify {
+            Event::SelectionNotify(ev) if ev.property != NONE => {}
+            _ => return None,
+        }
+        let reply = self.take_property()?;
+        if reply.type_ != self.atoms.incr {
+            return Some(reply.value);
+        }
+        // Deleting the INCR property asked the owner for the first chunk. Each
+        // chunk replaces the property; an empty one ends the transfer
+        let mut value = Vec::new();
+        loop {
+            self.wait_for_event(|event| {
+                matches!(event, Event::PropertyNotify(ev)
+                    if ev.window == self.window
+                        && ev.atom == self.atoms.property
+                        && ev.state == Property::NEW_VALUE)
+            })?;
+            let chunk = self.take_property()?;
+            if chunk.value.is_empty() {
+                return Some(value);
+            }
+            value.extend_from_slice(&chunk.value);
+        }
+    }
+
+    /// Reads the conversion property and deletes it, which tells an INCR
+    /// owner to send the next chunk.
+    fn take_property(&self) -> Option<GetPropertyReply> {
+        self.conn
+            .get_property(
+                true,
+                self.window,
+                self.atoms.property,
+                AtomEnum::ANY,
+                0,
+                u32::MAX / 4,
+            )
+            .ok()?
+            .reply()
+            .ok()
+    }
+
+    /// Waits up to [`CONVERT_TIMEOUT`] for an event `wanted` accepts. Other
+    /// events are queued for `run`, so owner changes during a read aren't
+    /// lost; a stop request ends the wait.
+    fn wait_for_event(&self, wanted: impl Fn(&Event) -> bool) -> Option<Event> {
+        let deadline = Instant::now() + CONVERT_TIMEOUT;
+        loop {
+            match self.conn.poll_for_event().ok()? {
+                Some(event) if wanted(&event) => return Some(event),
+                Some(event) => {
+                    let stop =
+                        matches!(&event, Event::ClientMessage(ev) if ev.type_ == self.atoms.stop);
+                    if let Ok(mut pending) = self.pending.lock() {
+                        pending.push_back(event);
+                    }
+                    if stop {
+                        return None;
+                    }
+                }
+                None => {
+                    if Instant::now() > deadline {
+                        return None;
+                    }
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for SelectionWatcher {
+    fn drop(&mut self) {
+        let _ = self.conn.destroy_window(self.window);
+        let _ = self.conn.flush();
+    }
+}
+
+/// Keyboard modifier and layout state as XKB reports it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct KeyboardState {
+    pub depressed_mods: u32,
+    pub latched_mods: u32,
+    pub locked_mods: u32,
+    /// Relative to the locked group and possibly negative; xkbcommon takes
+    /// these as the same bits in a `u32`.
+    pub depressed_group: u32,
+    pub latched_group: u32,
+    pub locked_group: u32,
+}
+
+#[derive(Debug)]
+pub enum KeyEvent {
+    /// A key went down or up. `keycode` is the X keycode and `time` the
+    /// server time in milliseconds.
+    Key {
+        keycode: u32,
+        pressed: bool,
+        time: u32,
+    },
+    /// Modifiers or the active layout changed.
+    State(KeyboardState),
+    /// The keymap was replaced, e.g. by `setxkbmap`; fetch it again with
+    /// [`KeyWatcher::keymap`].
+    KeymapChanged,
+}
+
+/// Watches every key press on the display through XInput2 raw events. Raw
+/// events report keycodes only; turning them into text is up to the caller.
+pub struct KeyWatcher {
+    conn: Arc<RustConnection>,
+    /// libxcb connection for xkbcommon, which reads the keymap itself.
+    keymap_conn: XCBConnection,
+    keyboard_id: i32,
+    window: Window,
+    stop: Atom,
+}
+
+impl KeyWatcher {
+    pub fn new() -> Result<Self, String> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(|e| e.to_string())?;
+        // Raw events reach root-window listeners regardless of grabs from 2.1 on
+        conn.xinput_xi_query_version(2, 2)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| format!("XInput2 not available: {}", e))?;
+        let xkb_version = conn
+            .xkb_use_extension(1, 0)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?;
+        if !xkb_version.supported {
+            return Err("XKB not available".into());
+        }
+        let (keymap_conn, _) = XCBConnection::connect(None).map_err(|e| e.to_string())?;
+        let xkb_ready = xkbcommon::xkb::x11::setup_xkb_extension(
+            &keymap_conn,
+            xkbcommon::xkb::x11::MIN_MAJOR_XKB_VERSION,
+            xkbcommon::xkb::x11::MIN_MINOR_XKB_VERSION,
+            xkbcommon::xkb::x11::SetupXkbExtensionFlags::NoFlags,
+            &mut 0,
+            &mut 0,
+            &mut 0,
+            &mut 0,
+        );
+        let keyboard_id = xkbcommon::xkb::x11::get_core_keyboard_device_id(&keymap_conn);
+        if !xkb_ready || keyboard_id < 0 {
+            return Err("XKB keyboard not available".into());
+        }
+
+        let root = conn.setup().roots[screen_num].root;
+        let window = conn.generate_id().map_err(|e| e.to_string())?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &CreateWindowAux::new(),
+        )
+        .map_err(|e| e.to_string())?;
+
+        conn.xinput_xi_select_events(
+            root,
+            &[xinput::EventMask {
+                deviceid: xinput::Device::ALL_MASTER.into(),
+                mask: vec![XIEventMask::RAW_KEY_PRESS | XIEventMask::RAW_KEY_RELEASE],
+            }],
+        )
+        .map_err(|e| e.to_string())?;
+        let xkb_events = xkb::EventType::STATE_NOTIFY | xkb::EventType::NEW_KEYBOARD_NOTIFY;
+        conn.xkb_select_events(
+            xkb::ID::USE_CORE_KBD.into(),
+            xkb::EventType::from(0u16),
+            xkb_events,
+            xkb::MapPart::from(0u16),
+            xkb::MapPart::from(0u16),
+            &xkb::SelectEventsAux::new(),
+        )
+        .map_err(|e| e.to_string())?;
+
+        let stop = intern_atom(&conn, "RAE_STOP_WATCHER")?;
+        conn.flush().map_err(|e| e.to_string())?;
+        Ok(Self {
+            conn: Arc::new(conn),
+            keymap_conn,
+            keyboard_id,
+            window,
+            stop,
+        })
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle {
+            conn: self.conn.clone(),
+            window: self.window,
+            stop: self.stop,
+        }
+    }
+
+    /// The keymap the server uses for the core keyboard, compiled by
+    /// xkbcommon exactly as the server has it, custom xkb files included.
+    pub fn keymap(
+        &self,
+        context: &xkbcommon::xkb::Context,
+    ) -> Result<xkbcommon::xkb::Keymap, String> {
+        let keymap = xkbcommon::xkb::x11::keymap_new_from_device(
+            context,
+            &self.keymap_conn,
+            self.keyboard_id,
+            xkbcommon::xkb::KEYMAP_COMPILE_NO_FLAGS,
+        );
+        if keymap.get_raw_ptr().is_null() {
+            return Err("Could not read the keymap from the X server".into());
+        }
+        Ok(keymap)
+    }
+
+    /// The current modifiers and layout, for starting in sync with the server.
+    pub fn keyboard_state(&self) -> Option<KeyboardState> {
+        let reply = self
+            .conn
+            .xkb_get_state(xkb::ID::USE_CORE_KBD.into())
+            .ok()?
+            .reply()
+            .ok()?;
+        Some(KeyboardState {
+            depressed_mods: u16::from(reply.base_mods).into(),
+            latched_mods: u16::from(reply.latched_mods).into(),
+            locked_mods: u16::from(reply.locked_mods).into(),
+            depressed_group: i32::from(reply.base_group) as u32,
+            latched_group: i32::from(reply.latched_group) as u32,
+            locked_group: u32::from(reply.locked_group),
+        })
+    }
+
+    /// Blocks until stopped, calling `on_event` for every key press and
+    /// release, state change and keymap change.
+    pub fn run(&self, mut on_event: impl FnMut(KeyEvent)) -> Result<(), String> {
+        loop {
+            let event = self.conn.wait_for_event().map_err(|e| e.to_string())?;
+            match event {
+                Event::XinputRawKeyPress(ev) => on_event(KeyEvent::Key {
+                    keycode: ev.detail,
+                    pressed: true,
+                    time: ev.time,
+                }),
+                Event::XinputRawKeyRelease(ev) => on_event(KeyEvent::Key {
+                    keycode: ev.detail,
+                    pressed: false,
+                    time: ev.time,
+                }),
+                Event::XkbStateNotify(ev) => on_event(KeyEvent::State(KeyboardState {
+                    depressed_mods: u16::from(ev.base_mods).into(),
+                    latched_mods: u16::from(ev.latched_mods).into(),
+                    locked_mods: u16::from(ev.locked_mods).into(),
+                    depressed_group: i32::from(ev.base_group) as u32,
+                    latched_group: i32::from(ev.latched_group) as u32,
+                    locked_group: u32::from(ev.locked_group),
+                })),
+                Event::XkbNewKeyboardNotify(_) => on_event(KeyEvent::KeymapChanged),
+                Event::ClientMessage(ev) if ev.type_ == self.stop => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for KeyWatcher {
+    fn drop(&mut self) {
+        let _ = self.conn.destroy_window(self.window);
+        let _ = self.conn.flush();
+    }
+}
+
+fn intern_atom(conn: &RustConnection, name: &str) -> Result<Atom, String> {
+    conn.intern_atom(false, name.as_bytes())
+        .map_err(|e| e.to_string())?
+        .reply()
+        .map(|r| r.atom)
+        .map_err(|e| e.to_string())
+}
+
+/// Pointer position, held buttons and held modifiers at one moment.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PointerSample {
+    pub x: i32,
+    pub y: i32,
+    pub buttons_down: bool,
+    pub shift: bool,
+    pub control: bool,
+    /// Mod1, where every common keymap puts Alt.
+    pub alt: bool,
+    /// Mod4, where every common keymap puts Super.
+    pub super_key: bool,
+}
+
+/// Reads the pointer and modifier state on demand, for detectors that poll.
+pub struct PointerPoller {
+    conn: RustConnection,
+    root: Window,
+}
+
+impl PointerPoller {
+    pub fn new() -> Result<Self, String> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(|e| e.to_string())?;
+        let root = conn.setup().roots[screen_num].root;
+        Ok(Self { conn, root })
+    }
+
+    pub fn sample(&self) -> Option<PointerSample> {
+        let reply = self.conn.query_pointer(self.root).ok()?.reply().ok()?;
+        let mask = u16::from(reply.mask);
+        let held = |m: KeyButMask| mask & u16::from(m) != 0;
+        Some(PointerSample {
+            x: reply.root_x.into(),
+            y: reply.root_y.into(),
+            buttons_down: held(KeyButMask::BUTTON1 | KeyButMask::BUTTON2 | KeyButMask::BUTTON3),
+            shift: held(KeyButMask::SHIFT),
+            control: held(KeyButMask::CONTROL),
+            alt: held(KeyButMask::MOD1),
+            super_key: held(KeyButMask::MOD4),
+        })
+    }
+}
+
+/// Grabs the whole screen as RGBA rows, returned with its width and height.
+/// Only the usual 24-bit TrueColor layout in 32-bit pixels is supported.
+pub fn capture_screen() -> Result<(u32, u32, Vec<u8>), String> {
+    use x11rb::protocol::xproto::{ImageFormat, ImageOrder};
+    let (conn, screen_num) = x11rb::connect(None).map_err(|e| e.to_string())?;
+    let setup = conn.setup();
+    let screen = &setup.roots[screen_num];
+    let (width, height) = (screen.width_in_pixels, screen.height_in_pixels);
+    let reply = conn
+        .get_image(ImageFormat::Z_PIXMAP, screen.root, 0, 0, width, height, !0)
+        .map_err(|e| e.to_string())?
+        .reply()
+        .map_err(|e| e.to_string())?;
+    let bits_per_pixel = setup
+        .pixmap_formats
+        .iter()
+        .find(|f| f.depth == reply.depth)
+        .map(|f| f.bits_per_pixel);
+    let visual = screen
+        .allowed_depths
+        .iter()
+        .flat_map(|d| &d.visuals)
+        .find(|v| v.visual_id == reply.visual);
+    let bgrx = visual.is_some_and(|v| v.red_mask == 0xff0000 && v.blue_mask == 0xff);
+    if bits_per_pixel != Some(32) || setup.image_byte_order != ImageOrder::LSB_FIRST || !bgrx {
+        return Err(format!("Unsupported screen format (depth {})", reply.depth));
+    }
+    let rgba = reply
+        .data
+        .chunks_exact(4)
+        .flat_map(|p| [p[2], p[1], p[0], 0xff])
+        .collect();
+    Ok((width.into(), height.into(), rgba))
+}
+
+/// Takes ownership of `selection_name` and serves `offers` (`(target, bytes)`
+/// pairs) on a background thread until another client takes it over.
+/// Transfers too large for a single property (INCR) are not supported, so
+/// offers that do not fit in one request are refused up front.
+pub fn own_selection(selection_name: &str, offers: Vec<(String, Vec<u8>)>) -> Result<(), String> {
+    /// Size of a ChangeProperty request without its data.
+    const CHANGE_PROPERTY_HEADER: usize = 24;
+
+    let (conn, screen_num) = x11rb::connect(None).map_err(|e| e.to_string())?;
+    let max_data = conn
+        .maximum_request_bytes()
+        .saturating_sub(CHANGE_PROPERTY_HEADER);
+    if let Some((name, data)) = offers.iter().find(|(_, data)| data.len() > max_data) {
+        return Err(format!(
+            "{} is too large for the clipboard ({} bytes, at most {})",
+            name,
+            data.len(),
+            max_data
+        ));
+    }
+    let root = conn.setup().roots[screen_num].root;
+    let window = conn.generate_id().map_err(|e| e.to_string())?;
+    conn.create_window(
+        COPY_DEPTH_FROM_PARENT,
+        window,
+        root,
+        0,
+        0,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        0,
+        &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let selection = intern_atom(&conn, selection_name)?;
+    let targets = intern_atom(&conn, "TARGETS")?;
+    let timestamp = intern_atom(&conn, "TIMESTAMP")?;
+    let offers = offers
+        .into_iter()
+        .map(|(name, data)| Ok((intern_atom(&conn, &name)?, data)))
+        .collect::<Result<Vec<(Atom, Vec<u8>)>, String>>()?;
+
+    // ICCCM asks for a real server timestamp rather than CurrentTime; an empty
+    // property append produces a PropertyNotify carrying one
+    conn.change_property8(
+        PropMode::APPEND,
+        window,
+        AtomEnum::WM_NAME,
+        AtomEnum::STRING,
+        &[],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.flush().map_err(|e| e.to_string())?;
+    let time = loop {
+        match conn.wait_for_event().map_err(|e| e.to_string())? {
+            Event::PropertyNotify(ev) if ev.window == window => break ev.time,
+            _ => {}
+        }
+    };
+    conn.change_window_attributes(
+        window,
+        &ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT),
+    )
+    .map_err(|e| e.to_string())?;
+
+    conn.set_selection_owner(window, selection, time)
+        .map_err(|e| e.to_string())?;
+    let owner = conn
+        .get_selection_owner(selection)
+        .map_err(|e| e.to_string())?
+        .reply()
+        .map_err(|e| e.to_string())?
+        .owner;
+    if owner != window {
+        let _ = conn.destroy_window(window);
+        let _ = conn.flush();
+        return Err("Could not take ownership of the selection".into());
+    }
+
+    std::thread::spawn(move || {
+        let mut available: Vec<Atom> = vec![targets, timestamp];
+        available.extend(offers.iter().map(|(atom, _)| *atom));
+        while let Ok(event) = conn.wait_for_event() {
+            match event {
+                Event::SelectionRequest(req) => {
+                    // Obsolete clients leave the property unset
+                    let property = if req.property == NONE {
+                        req.target
+                    } else {
+                        req.property
+                    };
+                    let written = if req.target == targets {
+                        conn.change_property32(
+                            PropMode::REPLACE,
+                            req.requestor,
+                            property,
+                            AtomEnum::ATOM,
+                            &available,
+                        )
+                        .is_ok()
+                    } else if req.target == timestamp {
+                        conn.change_property32(
+                            PropMode::REPLACE,
+                            req.requestor,
+                            property,
+                            AtomEnum::INTEGER,
+                            &[time],
+                        )
+                        .is_ok()
+                    } else if let Some((atom, data)) =
+                        offers.iter().find(|(atom, _)| *atom == req.target)
+                    {
+                        conn.change_property8(
+                            PropMode::REPLACE,
+                            req.requestor,
+                            property,
+                            *atom,
+                            data,
+                        )
+                        .is_ok()
+                    } else {
+                        false
+                    };
+                    let notify = SelectionNotifyEvent {
+                        response_type: SELECTION_NOTIFY_EVENT,
+                        sequence: 0,
+                        time: req.time,
+                        requestor: req.requestor,
+                        selection: req.selection,
+                        target: req.target,
+                        property: if written { property } else { NONE },
+                    };
+                    let _ = conn.send_event(false, req.requestor, EventMask::NO_EVENT, notify);
+                    let _ = conn.flush();
+                }
+                Event::SelectionClear(ev) if ev.selection == selection => break,
+                _ => {}
+            }
+        }
+        let _ = conn.destroy_window(window);
+        let _ = conn.flush();
+    });
+    Ok(())
+}
@@ -121,11 +121,9 @@ function App() {
     };
   }, []);
 
-  // Listen for events and conditionally start watchers based on saved settings
+  // Conditionally start watchers based on saved settings. The watchers show
+  // the magic dot themselves, at the selection when they know where it is.
   useEffect(() => {
-    let unlisten: undefined | (() => void);
-    let unlistenSel: undefined | (() => void);
-    let unlistenRae: undefined | (() => void);
     async function setup() {
       try {
         // If previously enabled, ensure watcher thread is running after reload
@@ -153,40 +151,8 @@ function App() {
           await invoke("set_rae_watcher_enabled", { enabled: true });
         }
       } catch (_) {}
-      try {
-        const { listen } = await import("@tauri-apps/api/event");
-        unlisten = await listen<{ text: string }>(
-          "clipboard_text_copied",
-          async () => {
-            try {
-              await invoke("show_magic_dot");
-            } catch (_) {}
-          },
-        );
-        unlistenSel = await listen<{ text: string }>(
-          "text_selected",
-          async () => {
-            try {
-              await invoke("show_magic_dot");
-            } catch (_) {}
-          },
-        );
-        unlistenRae = await listen("rae_mentioned", async () => {
-          try {
-            console.log("@rae detected! Showing magic dot...");
-            await invoke("show_magic_dot");
-          } catch (e) {
-            console.error("Failed to show magic dot on @rae", e);
-          }
-        });
-      } catch (_) {}
     }
     setup();
-    return () => {
-      if (unlisten) unlisten();
-      if (unlistenSel) unlistenSel();
-      if (unlistenRae) unlistenRae();
-    };
   }, []);
 
   // Actively update the root html class when darkTheme changes
@@ -5,7 +5,9 @@ import {
   pinMagicDot,
   resize,
   refreshStyles,
+  requestOverlayTransition,
 } from "@/utils/windowUtils";
+import type { OverlayState } from "@/utils/windowUtils";
 import { AnimatePresence, motion } from "framer-motion";
 import { OverlayButton } from "./OverlayComponents";
 import { ChatView } from "./chatView";
@@ -88,6 +90,7 @@ const Overlay = () => {
   const [windowHwnd, setWindowHwnd] = useState<number | null>(null);
   const [isNotch, setIsNotch] = useState(false);
   const [inputActive, setInputActive] = useState(false);
+  const [inlineThinking, setInlineThinking] = useState(false);
   const [showGradient, setShowGradient] = useState<boolean>(
     localStorage.getItem("gradient") === "true"
   );
@@ -97,6 +100,9 @@ const Overlay = () => {
   const [initialChatMessage, setInitialChatMessage] = useState<
     string | undefined
   >();
+  const [initialChatImage, setInitialChatImage] = useState<
+    string | undefined
+  >();
 
   // Refs for dragging and notch timeout
   const notchTimeoutRef = useRef<NodeJS.Timeout | null>(null);
@@ -344,6 +350,42 @@ const Overlay = () => {
     };
   }, []);
 
+  // An inline @rae prompt typed in another app is waiting for its answer
+  useEffect(() => {
+    const unlisten = listen<{ id: number; thinking: boolean }>(
+      "inline_prompt_thinking",
+      (event) => {
+        setInlineThinking(event.payload.thinking);
+      }
+    );
+    return () => {
+      unlisten.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  // Follow the overlay state kept in Rust, whoever changed it
+  useEffect(() => {
+    const unlisten = listen<{ state: OverlayState; previous: OverlayState }>(
+      "overlay_state_changed",
+      (event) => {
+        const { state } = event.payload;
+        if (state !== "notch" && notchTimeoutRef.current) {
+          clearTimeout(notchTimeoutRef.current);
+          notchTimeoutRef.current = null;
+        }
+        setIsNotch(state === "notch");
+        if (state === "pinned" || state === "notch") {
+          setIsPinned(true);
+        } else if (state === "following" || state === "centered") {
+          setIsPinned(false);
+        }
+      }
+    );
+    return () => {
+      unlisten.then((unlisten) => unlisten());
+    };
+  }, []);
+
   // Setup event listeners for overlay controls
   useEffect(() => {
     const eventListeners = [
@@ -376,6 +418,23 @@ const Overlay = () => {
         const gradient = event.payload as { gradient: boolean };
         console.log("OverlayCard: Setting showGradient to:", gradient.gradient);
         setShowGradient(gradient.gradient);
+      }),
+
+      // A capture gesture opens the chat with the screenshot attached
+      listen<{ image: string | null; error: string | null }>("capture_and_ask", (event) => {
+        if (event.payload.error) {
+          console.error("Gesture capture failed:", event.payload.error);
+        }
+        setInitialChatMessage(undefined);
+        setInitialChatImage(event.payload.image ?? undefined);
+        setShowChat(true);
+        setChatOpen(true);
+        setIsNotch(false);
+        if (notchTimeoutRef.current) {
+          clearTimeout(notchTimeoutRef.current);
+          notchTimeoutRef.current = null;
+        }
+        requestOverlayTransition("open_chat");
       })
     ];
 
@@ -529,16 +588,19 @@ const Overlay = () => {
     setInputActive(false);
     fetchNotes();
     setInitialChatMessage(userMsg);
+    requestOverlayTransition("open_chat");
   };
 
   const handleOpenChat = () => {
     setInitialChatMessage(undefined); // Ensure no old message is passed
+    setInitialChatImage(undefined);
     setShowChat(true);
     setChatOpen(true);
     setIsNotch(false);
     // Reset the disable notch flag when user manually opens chat
     DISABLE_NOTCH_ON_SHOW.current = false;
     if (notchTimeoutRef.current) clearTimeout(notchTimeoutRef.current);
+    requestOverlayTransition("open_chat");
   };
 
   const handleCloseChatClick = () => {
@@ -548,6 +610,8 @@ const Overlay = () => {
     }, animations.overlayChat * 1000);
     // Clear screenshot when chat is closed
     setWindowScreenshot("");
+    setInitialChatImage(undefined);
+    requestOverlayTransition("close_chat");
   };
 
   const [expandedChat, setExpandedChat] = useState(false);
@@ -823,7 +887,9 @@ const Overlay = () => {
                       inputText ? "text-foreground" : "text-gray-500"
                     }`}
                   >
-                    {inputText || "Ask Rae anything..."}
+                    {inlineThinking
+                      ? "Rae is thinking..."
+                      : inputText || "Ask Rae anything..."}
                   </span>
                 </div>
               )
@@ -1016,6 +1082,7 @@ const Overlay = () => {
             <ChatView
               onClose={handleCloseChatClick}
               initialMessage={initialChatMessage}
+              initialImage={initialChatImage}
               smoothResize={smoothResize}
               showChat={showChat}
               setShowChat={setShowChat}
@@ -2,63 +2,31 @@
 // import { Window } from "@tauri-apps/api/window";
 import { WebviewWindow } from "@tauri-apps/api/webviewWindow";
 import { LogicalSize } from "@tauri-apps/api/dpi";
-// Creates (or reuses) the magic dot window and ensures it is visible and focused.
+import { invoke } from "@tauri-apps/api/core";
+import { emitTo } from "@tauri-apps/api/event";
+// Pins the magic dot through the overlay state machine, which creates the window if needed.
 // Emits `collapse_to_dot` so the UI starts in the small dot state.
 export const LaunchOverlayWindow = async () => {
   try {
     const WIDTH = 500; // broadened bar
     const HEIGHT = 60; // buffer to avoid clipping
 
-    // If window already exists, just resize and focus it
+    // If window already exists, resize it before it is put back in place
     const existing = await WebviewWindow.getByLabel("overlay");
     if (existing) {
       try {
         await existing.setSize(new LogicalSize(WIDTH, HEIGHT));
       } catch (_) {}
-      await existing.show();
-      await existing.setFocus();
-      await existing.setAlwaysOnTop(true);
-      // Ensuring
-      // it starts collapsed dot state
-      try {
-        await existing.emit("collapse_to_dot");
-      } catch (_) {}
-      return existing;
     }
 
-    // Create a new pre-configured window
-    const overlayWindow = new WebviewWindow("overlay", {
-      url: "/overlay",
-      width: WIDTH,
-      height: HEIGHT,
-      decorations: false,
-      transparent: true,
-      alwaysOnTop: true,
-      resizable: false,
-      shadow: false,
-      fullscreen: false,
-      maximizable: false,
-      skipTaskbar: true, //that's all
-    });
-
-    // Set up event listeners
-    overlayWindow.once("tauri://created", function () {
-      console.log("Magic dot window successfully created");
-    });
-
-    overlayWindow.once("tauri://error", function (e) {
-      console.error("Error creating magic dot window:", e);
-    });
-
-    await overlayWindow.show();
-    await overlayWindow.setFocus();
-    await overlayWindow.setAlwaysOnTop(true);
+    // Rust shows, focuses and positions the window and records the new state
+    await invoke("request_overlay_transition", { transition: "pin" });
     try {
-      await overlayWindow.emit("collapse_to_dot");
+      await emitTo("overlay", "collapse_to_dot");
     } catch (_) {}
 
     console.log("Magic dot window shown");
-    return overlayWindow;
+    return await WebviewWindow.getByLabel("overlay");
   } catch (error) {
     console.error("Failed to create magic dot window:", error);
     throw error;
@@ -35,6 +35,8 @@ const MODELS = [
 interface ChatViewProps {
   onClose: () => void;
   initialMessage?: string;
+  /** Screenshot to attach to the next message, e.g. from a capture gesture. */
+  initialImage?: string;
   showChat?: boolean;
   setShowChat?: (show: boolean) => void;
   smoothResize: (width: number, height: number) => void;
@@ -92,6 +94,7 @@ const performSmoothResize = async (
 export const ChatView = ({
   onClose,
   initialMessage,
+  initialImage,
   showChat,
   setShowChat,
   smoothResize,
@@ -223,6 +226,14 @@ export const ChatView = ({
     }
   }, [initialMessage]);
 
+  // Effect to attach an image passed from the overlay, e.g. a gesture capture
+  useEffect(() => {
+    if (initialImage) {
+      setAttachedImage(initialImage);
+      setImagePreview(initialImage);
+    }
+  }, [initialImage]);
+
   // Scroll to bottom when new messages appear
   useEffect(() => {
     bottomRef.current?.scrollIntoView({ behavior: "smooth" });
@@ -56,3 +56,37 @@ export const pinMagicDot = async () => {
     console.error("Failed to pin magic dot:", err);
   }
 };
+
+// Mirrors OverlayState / OverlayTransition in src-tauri/src/functions/overlay_state.rs
+export type OverlayState =
+  | "hidden"
+  | "notch"
+  | "pinned"
+  | "following"
+  | "centered"
+  | "expanded_chat";
+
+export type OverlayTransition =
+  | "hide"
+  | "collapse"
+  | "hover_notch"
+  | "pin"
+  | "realign"
+  | "follow"
+  | "center"
+  | "open_chat"
+  | "close_chat";
+
+// Moves and hides of the overlay go through Rust so its state stays in sync
+export const requestOverlayTransition = async (
+  transition: OverlayTransition
+): Promise<OverlayState | null> => {
+  try {
+    return await invoke<OverlayState>("request_overlay_transition", {
+      transition,
+    });
+  } catch (err) {
+    console.error(`Overlay transition "${transition}" failed:`, err);
+    return null;
+  }
+};